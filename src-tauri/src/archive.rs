@@ -0,0 +1,183 @@
+// 打包输出
+// 把传输计划中的文件按第一级目标文件夹写入 ZIP（存储模式，不压缩），而不是复制为单独的文件
+use chrono::{Datelike, Local, Timelike};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, DateTime, ZipWriter};
+
+/// 传输的输出方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputMode {
+    /// 逐个复制为单独的文件
+    #[default]
+    Files,
+    /// 每个第一级目标文件夹打包为一个 ZIP（如 2024-03.zip），压缩包内保留下级文件夹
+    Zip,
+}
+
+/// 直接位于目标文件夹根目录的文件所在的压缩包名
+pub const ROOT_ARCHIVE_NAME: &str = "照片";
+
+/// 目标路径对应的（压缩包名，压缩包内的路径）：第一级文件夹为压缩包名，其余部分以 `/` 分隔；
+/// 直接位于 target_dir 下的文件放入 ROOT_ARCHIVE_NAME
+pub fn archive_location(target_dir: &Path, target_path: &Path) -> (String, String) {
+    let relative = target_path.strip_prefix(target_dir).unwrap_or(target_path);
+    let mut parts: Vec<String> = relative
+        .components()
+        .filter_map(|c| match c {
+            std::path::Component::Normal(part) => Some(part.to_string_lossy().to_string()),
+            _ => None,
+        })
+        .collect();
+    if parts.len() > 1 {
+        let archive = parts.remove(0);
+        (archive, parts.join("/"))
+    } else {
+        (ROOT_ARCHIVE_NAME.to_string(), parts.join("/"))
+    }
+}
+
+/// 不与已有文件重名的压缩包路径：name.zip 已存在时依次尝试 name_1.zip、name_2.zip……
+fn unique_archive_path(target_dir: &Path, name: &str) -> PathBuf {
+    let mut path = target_dir.join(format!("{}.zip", name));
+    let mut counter = 1;
+    while path.exists() {
+        path = target_dir.join(format!("{}_{}.zip", name, counter));
+        counter += 1;
+    }
+    path
+}
+
+/// 文件修改时间对应的 ZIP 时间（本地时间），超出 ZIP 可表示的范围时为 None
+fn zip_modified_time(path: &Path) -> Option<DateTime> {
+    let modified: chrono::DateTime<Local> = fs::metadata(path).ok()?.modified().ok()?.into();
+    DateTime::from_date_and_time(
+        u16::try_from(modified.year()).ok()?,
+        modified.month() as u8,
+        modified.day() as u8,
+        modified.hour() as u8,
+        modified.minute() as u8,
+        modified.second() as u8,
+    )
+    .ok()
+}
+
+/// 正在写入的一组压缩包，按压缩包名在第一次写入时创建；已存在的同名压缩包不会被修改
+pub struct ZipOutput {
+    target_dir: PathBuf,
+    /// 压缩包名 -> (路径, 写入器)
+    writers: HashMap<String, (PathBuf, ZipWriter<File>)>,
+    /// 压缩包的创建顺序
+    order: Vec<String>,
+}
+
+impl ZipOutput {
+    pub fn new(target_dir: &Path) -> Self {
+        Self {
+            target_dir: target_dir.to_path_buf(),
+            writers: HashMap::new(),
+            order: Vec::new(),
+        }
+    }
+
+    /// 把源文件写入 target_path 对应的压缩包，返回（压缩包路径，压缩包内的路径，写入的字节数）。
+    /// 写入失败时移除不完整的条目，压缩包中已有的条目不受影响
+    pub fn add_file(&mut self, source: &Path, target_path: &Path) -> Result<(PathBuf, String, u64), String> {
+        let (name, entry) = archive_location(&self.target_dir, target_path);
+        if !self.writers.contains_key(&name) {
+            fs::create_dir_all(&self.target_dir)
+                .map_err(|e| format!("创建目录失败 {}: {}", self.target_dir.display(), e))?;
+            let path = unique_archive_path(&self.target_dir, &name);
+            let file = File::create(&path).map_err(|e| format!("创建压缩包失败 {}: {}", path.display(), e))?;
+            self.writers.insert(name.clone(), (path, ZipWriter::new(file)));
+            self.order.push(name.clone());
+        }
+        let (archive_path, writer) = self.writers.get_mut(&name).expect("压缩包已创建");
+
+        let mut options = SimpleFileOptions::default()
+            .compression_method(CompressionMethod::Stored)
+            .large_file(true);
+        if let Some(modified) = zip_modified_time(source) {
+            options = options.last_modified_time(modified);
+        }
+        let mut file = File::open(source).map_err(|e| e.to_string())?;
+        writer.start_file(entry.as_str(), options).map_err(|e| e.to_string())?;
+        match io::copy(&mut file, writer) {
+            Ok(written) => Ok((archive_path.clone(), entry, written)),
+            Err(e) => {
+                // 只移除正在写入的条目
+                writer.abort_file().ok();
+                Err(e.to_string())
+            }
+        }
+    }
+
+    /// 写入所有压缩包的目录并关闭，返回按创建顺序排列的压缩包路径和关闭失败的错误
+    pub fn finish(mut self) -> (Vec<PathBuf>, Vec<String>) {
+        let mut archives = Vec::new();
+        let mut errors = Vec::new();
+        for name in self.order {
+            let (path, writer) = self.writers.remove(&name).expect("压缩包已创建");
+            match writer.finish() {
+                Ok(_) => archives.push(path),
+                Err(e) => errors.push(format!("写入压缩包失败 {}: {}", path.display(), e)),
+            }
+        }
+        (archives, errors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_archive_location() {
+        let target = Path::new("/dst");
+        assert_eq!(
+            archive_location(target, Path::new("/dst/2024-03/IMG_0001.JPG")),
+            ("2024-03".to_string(), "IMG_0001.JPG".to_string())
+        );
+        assert_eq!(
+            archive_location(target, Path::new("/dst/2024/03/IMG_0001.JPG")),
+            ("2024".to_string(), "03/IMG_0001.JPG".to_string())
+        );
+        assert_eq!(
+            archive_location(target, Path::new("/dst/IMG_0001.JPG")),
+            (ROOT_ARCHIVE_NAME.to_string(), "IMG_0001.JPG".to_string())
+        );
+    }
+
+    #[test]
+    fn test_zip_output_writes_entries() {
+        let dir = TempDir::new().unwrap();
+        let target = dir.path().join("target");
+        let source = dir.path().join("IMG_0001.JPG");
+        fs::write(&source, b"jpeg data").unwrap();
+        // 已有同名压缩包时不覆盖
+        fs::create_dir_all(&target).unwrap();
+        fs::write(target.join("2024.zip"), b"old").unwrap();
+
+        let mut output = ZipOutput::new(&target);
+        let (archive, entry, written) = output.add_file(&source, &target.join("2024/03/IMG_0001.JPG")).unwrap();
+        assert_eq!((archive.clone(), entry.as_str(), written), (target.join("2024_1.zip"), "03/IMG_0001.JPG", 9));
+        assert!(output.add_file(&dir.path().join("missing.jpg"), &target.join("2024/03/B.JPG")).is_err());
+        let (archives, errors) = output.finish();
+        assert_eq!(archives, vec![archive.clone()]);
+        assert!(errors.is_empty());
+        assert_eq!(fs::read(target.join("2024.zip")).unwrap(), b"old");
+
+        let mut zip = zip::ZipArchive::new(File::open(&archive).unwrap()).unwrap();
+        assert_eq!(zip.file_names().collect::<Vec<_>>(), ["03/IMG_0001.JPG"]);
+        let mut content = Vec::new();
+        zip.by_name("03/IMG_0001.JPG").unwrap().read_to_end(&mut content).unwrap();
+        assert_eq!(content, b"jpeg data");
+    }
+}
@@ -2,6 +2,7 @@ use crate::exif::PhotoMetadata;
 use chrono::NaiveDateTime;
 use serde::{Deserialize, Serialize};
 use std::path::Path;
+use unicode_normalization::UnicodeNormalization;
 
 /// 分类规则配置
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -12,10 +13,35 @@ pub struct ClassifyConfig {
     /// {day} - 日期 (2位)
     /// {camera} - 相机型号
     /// {make} - 相机品牌
+    /// {camera_full} - 品牌和型号合并后的相机全称（去掉重复的品牌）
+    /// {orientation} - 构图方向（横构图/竖构图/方图），按旋转后的尺寸判断
+    /// {rating} - 星级评分（1星-5星，未评级时为 rating_labels.unrated）
+    /// {label} - XMP 颜色标签（如 Red，没有时为"无标签"）
+    /// {keyword} - 第一个关键词（没有时为"无关键词"）
+    /// {folder} - 照片在源文件夹中的相对子目录
+    /// {volume} - 源文件夹所在存储卡/磁盘卷的名称，无法获取卷标时为源文件夹名（见 source_volume_name）
+    /// 模板为空时不分类，所有照片直接放在目标文件夹中
     pub template: String,
     
     /// 当无法获取日期时使用的备用文件夹名
     pub fallback_folder: String,
+
+    /// 无法从 EXIF 获取日期时的处理方式
+    #[serde(default)]
+    pub fallback_strategy: FallbackStrategy,
+
+    /// {orientation} 使用的标签
+    #[serde(default)]
+    pub orientation_labels: OrientationLabels,
+
+    /// {rating} 使用的标签
+    #[serde(default)]
+    pub rating_labels: RatingLabels,
+
+    /// 在分类路径下保留照片在源文件夹中的完整子目录结构，如 `2024/03/DCIM/100CANON`。
+    /// 模板中已包含 {folder} 时不再重复添加
+    #[serde(default)]
+    pub preserve_tree: bool,
 }
 
 impl Default for ClassifyConfig {
@@ -23,13 +49,140 @@ impl Default for ClassifyConfig {
         Self {
             template: "{year}/{month}".to_string(),
             fallback_folder: "未知日期".to_string(),
+            fallback_strategy: FallbackStrategy::default(),
+            orientation_labels: OrientationLabels::default(),
+            rating_labels: RatingLabels::default(),
+            preserve_tree: false,
+        }
+    }
+}
+
+/// 无法从 EXIF 获取日期时的处理方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FallbackStrategy {
+    /// 全部放入 fallback_folder
+    #[default]
+    SingleFolder,
+    /// 使用文件修改时间作为日期，按模板正常分类；读取不到修改时间时放入 fallback_folder
+    FileTime,
+    /// 按扩展名放入 `未分类/{ext}`（如截图和扫描件分开存放）
+    ByExtension,
+}
+
+/// 星级评分标签
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RatingLabels {
+    /// 未评级的照片
+    pub unrated: String,
+    /// 1-5 星依次使用的标签，如把 5 星改为"精选"；缺少的星级使用"N星"
+    pub stars: Vec<String>,
+}
+
+impl Default for RatingLabels {
+    fn default() -> Self {
+        Self {
+            unrated: "未评级".to_string(),
+            stars: (1..=5).map(|n| format!("{}星", n)).collect(),
+        }
+    }
+}
+
+impl RatingLabels {
+    /// 根据星级选择标签
+    pub fn label(&self, rating: Option<u8>) -> String {
+        match rating {
+            Some(n) if n > 0 => self
+                .stars
+                .get(usize::from(n) - 1)
+                .cloned()
+                .unwrap_or_else(|| format!("{}星", n)),
+            _ => self.unrated.clone(),
+        }
+    }
+}
+
+/// 构图方向标签
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OrientationLabels {
+    pub landscape: String,
+    pub portrait: String,
+    pub square: String,
+    /// 没有尺寸信息的照片
+    pub unknown: String,
+    /// 宽高之差不超过长边的这个比例时视为方图
+    pub square_tolerance: f64,
+}
+
+impl Default for OrientationLabels {
+    fn default() -> Self {
+        Self {
+            landscape: "横构图".to_string(),
+            portrait: "竖构图".to_string(),
+            square: "方图".to_string(),
+            unknown: "未知方向".to_string(),
+            square_tolerance: 0.05,
+        }
+    }
+}
+
+impl OrientationLabels {
+    /// 根据显示尺寸（已按方向旋转）选择标签
+    pub fn label(&self, width: Option<u32>, height: Option<u32>) -> &str {
+        let (Some(width), Some(height)) = (width, height) else {
+            return &self.unknown;
+        };
+        if width == 0 || height == 0 {
+            return &self.unknown;
+        }
+
+        let longer = width.max(height) as f64;
+        let diff = (width as f64 - height as f64).abs();
+        if diff <= longer * self.square_tolerance {
+            &self.square
+        } else if width > height {
+            &self.landscape
+        } else {
+            &self.portrait
         }
     }
 }
 
 impl ClassifyConfig {
+    /// 是否为不分类模式（模板为空）
+    pub fn is_flat(&self) -> bool {
+        self.template.trim().is_empty()
+    }
+
     /// 根据照片元数据生成分类路径
     pub fn generate_path(&self, metadata: &PhotoMetadata) -> String {
+        self.generate_path_with_folder(metadata, "")
+    }
+
+    /// 根据照片元数据和照片在源文件夹中的相对子目录（`/` 分隔，根目录为空）生成分类路径
+    pub fn generate_path_with_folder(&self, metadata: &PhotoMetadata, folder: &str) -> String {
+        self.generate_path_for_source(metadata, "", folder)
+    }
+
+    /// 根据照片元数据、照片所在的源文件夹 source_root 和相对子目录生成分类路径，
+    /// 源文件夹用于 {volume}，为空时 {volume} 为"未知来源"。
+    /// 启用 preserve_tree 时在分类路径（包括备用文件夹）下追加相对子目录
+    pub fn generate_path_for_source(&self, metadata: &PhotoMetadata, source_root: &str, folder: &str) -> String {
+        let path = self.classified_path(metadata, source_root, folder);
+        if self.preserve_tree && !self.template.contains("{folder}") {
+            return contain_relative_path(&format!("{}/{}", path, folder));
+        }
+        path
+    }
+
+    fn classified_path(&self, metadata: &PhotoMetadata, source_root: &str, folder: &str) -> String {
+        // 不分类时目标就是目标文件夹本身，也不使用备用文件夹
+        if self.is_flat() {
+            return String::new();
+        }
+
         let mut path = self.template.clone();
         
         // 尝试解析日期时间
@@ -38,14 +191,25 @@ impl ClassifyConfig {
             .or(metadata.create_date.as_ref())
             .and_then(|dt| parse_exif_datetime(dt));
 
-        if let Some(dt) = datetime {
-            path = path.replace("{year}", &format!("{:04}", dt.year()));
-            path = path.replace("{month}", &format!("{:02}", dt.month()));
-            path = path.replace("{day}", &format!("{:02}", dt.day()));
-        } else {
-            // 无法解析日期，使用备用文件夹
-            return self.fallback_folder.clone();
-        }
+        // 无法解析日期时按 fallback_strategy 处理
+        let datetime = match (datetime, self.fallback_strategy) {
+            (Some(dt), _) => dt,
+            (None, FallbackStrategy::SingleFolder) => return contain_relative_path(&self.fallback_folder),
+            (None, FallbackStrategy::ByExtension) => {
+                let ext = Path::new(&metadata.file_name)
+                    .extension()
+                    .map(|ext| ext.to_string_lossy().to_lowercase())
+                    .unwrap_or_default();
+                return contain_relative_path(&format!("未分类/{}", ext));
+            }
+            (None, FallbackStrategy::FileTime) => match file_modified_time(&metadata.file_path) {
+                Some(dt) => dt,
+                None => return contain_relative_path(&self.fallback_folder),
+            },
+        };
+        path = path.replace("{year}", &format!("{:04}", datetime.year()));
+        path = path.replace("{month}", &format!("{:02}", datetime.month()));
+        path = path.replace("{day}", &format!("{:02}", datetime.day()));
 
         // 替换相机信息
         let camera = metadata.model.as_deref().unwrap_or("未知相机");
@@ -53,9 +217,111 @@ impl ClassifyConfig {
         
         path = path.replace("{camera}", &sanitize_folder_name(camera));
         path = path.replace("{make}", &sanitize_folder_name(make));
+        let camera_full = metadata.camera_full().unwrap_or_else(|| "未知相机".to_string());
+        path = path.replace("{camera_full}", &sanitize_folder_name(&camera_full));
+        let (width, height) = metadata.display_dimensions();
+        let orientation = self.orientation_labels.label(width, height);
+        path = path.replace("{orientation}", &sanitize_folder_name(orientation));
+        let rating = self.rating_labels.label(metadata.rating);
+        path = path.replace("{rating}", &sanitize_folder_name(&rating));
+        let label = metadata.label.as_deref().unwrap_or("无标签");
+        path = path.replace("{label}", &sanitize_folder_name(label));
+        let keyword = metadata.keywords.first().map(String::as_str).unwrap_or("无关键词");
+        path = path.replace("{keyword}", &sanitize_folder_name(keyword));
 
-        path
+        if path.contains("{volume}") {
+            let volume = source_volume_name(source_root);
+            let volume = if volume.is_empty() { "未知来源".to_string() } else { volume };
+            path = path.replace("{volume}", &sanitize_folder_name(&volume));
+        }
+
+        // 子目录保留层级，各段在 contain_relative_path 中清理
+        path = path.replace("{folder}", folder);
+
+        contain_relative_path(&path)
+    }
+}
+
+/// 源文件夹所在存储卡/磁盘卷的名称：
+/// 位于 /Volumes/<卷标>（macOS）、/media/<用户>/<卷标>、/run/media/<用户>/<卷标> 下时取卷标，
+/// Windows 上查询盘符的卷标；都无法获取时使用源文件夹的最后一级目录名
+pub fn source_volume_name(source_dir: &str) -> String {
+    if let Some(label) = mount_point_label(source_dir) {
+        return label;
+    }
+    #[cfg(windows)]
+    if let Some(label) = windows_volume_label(source_dir) {
+        return label;
     }
+    Path::new(source_dir)
+        .components()
+        .filter_map(|component| match component {
+            std::path::Component::Normal(name) => Some(name.to_string_lossy().to_string()),
+            _ => None,
+        })
+        .next_back()
+        .unwrap_or_default()
+}
+
+/// 从挂载点路径中取卷标
+fn mount_point_label(source_dir: &str) -> Option<String> {
+    let segments: Vec<&str> = source_dir.split(['/', '\\']).filter(|s| !s.is_empty()).collect();
+    let label = match segments.as_slice() {
+        ["Volumes", label, ..] => label,
+        ["media", _user, label, ..] | ["run", "media", _user, label, ..] => label,
+        _ => return None,
+    };
+    Some(label.to_string())
+}
+
+/// 查询 Windows 盘符的卷标（结果按盘符缓存，没有卷标时返回 None）
+#[cfg(windows)]
+fn windows_volume_label(source_dir: &str) -> Option<String> {
+    use std::collections::HashMap;
+    use std::process::Command;
+    use std::sync::{Mutex, OnceLock};
+
+    static LABELS: OnceLock<Mutex<HashMap<char, Option<String>>>> = OnceLock::new();
+
+    let drive = source_dir.split(['/', '\\']).next().filter(|s| is_drive_prefix(s))?;
+    let letter = drive.chars().next()?.to_ascii_uppercase();
+    let mut labels = LABELS.get_or_init(Default::default).lock().unwrap_or_else(|e| e.into_inner());
+    labels
+        .entry(letter)
+        .or_insert_with(|| {
+            let output = Command::new("powershell")
+                .args(["-NoProfile", "-Command", &format!("(Get-Volume -DriveLetter {}).FileSystemLabel", letter)])
+                .output()
+                .ok()
+                .filter(|output| output.status.success())?;
+            let label = String::from_utf8_lossy(&output.stdout).trim().to_string();
+            (!label.is_empty()).then_some(label)
+        })
+        .clone()
+}
+
+/// 读取文件修改时间（本地时间）
+fn file_modified_time(file_path: &str) -> Option<NaiveDateTime> {
+    let modified = std::fs::metadata(file_path).and_then(|m| m.modified()).ok()?;
+    Some(chrono::DateTime::<chrono::Local>::from(modified).naive_local())
+}
+
+/// 确保路径是相对路径且不会跳出目标目录：
+/// 去掉开头的分隔符和盘符，逐段清理非法字符，丢弃空片段以及 `.` 和 `..` 片段
+fn contain_relative_path(path: &str) -> String {
+    path.split(['/', '\\'])
+        .enumerate()
+        .filter(|(index, segment)| !(*index == 0 && is_drive_prefix(segment)))
+        .map(|(_, segment)| sanitize_folder_name(segment))
+        .filter(|segment| !segment.is_empty() && segment != "." && segment != "..")
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Windows 盘符，如 `C:`
+fn is_drive_prefix(segment: &str) -> bool {
+    let bytes = segment.as_bytes();
+    bytes.len() == 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':'
 }
 
 /// 解析 EXIF 日期时间格式 (YYYY:MM:DD HH:MM:SS)
@@ -80,14 +346,43 @@ fn parse_exif_datetime(datetime_str: &str) -> Option<NaiveDateTime> {
 
 /// 清理文件夹名称中的非法字符
 fn sanitize_folder_name(name: &str) -> String {
-    name.chars()
+    let cleaned = name.chars()
         .map(|c| match c {
             '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
             _ => c,
         })
-        .collect::<String>()
-        .trim()
-        .to_string()
+        .collect::<String>();
+    make_portable_name(cleaned.trim())
+}
+
+/// Windows 保留的设备名，无论扩展名如何都不能用作文件或文件夹名
+const RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL",
+    "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9",
+    "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// 让名称在各平台上都可用：去掉结尾的点和空格（Windows 会静默删除），
+/// 并在保留设备名后追加下划线，如 `AUX` -> `AUX_`、`CON.txt` -> `CON_.txt`。
+/// 所有平台都执行，保证照片库在不同系统间移动时仍然可用
+pub(crate) fn make_portable_name(name: &str) -> String {
+    let name = name.trim_end_matches(['.', ' ']);
+    let (stem, rest) = match name.find('.') {
+        Some(index) => name.split_at(index),
+        None => (name, ""),
+    };
+
+    if RESERVED_NAMES.iter().any(|reserved| stem.eq_ignore_ascii_case(reserved)) {
+        format!("{}_{}", stem, rest)
+    } else {
+        name.to_string()
+    }
+}
+
+/// 将名称规范化为 NFC 形式。macOS 的文件名通常为 NFD（`é` 存为 `e` + 组合重音符），
+/// Windows/Linux 通常为 NFC，统一后同一个名称在不同系统间导入时不会被当作两个文件
+pub fn to_nfc(name: &str) -> String {
+    name.nfc().collect()
 }
 
 /// 支持的照片文件扩展名
@@ -116,6 +411,38 @@ pub const SUPPORTED_EXTENSIONS: &[&str] = &[
     "gif",
 ];
 
+/// 文件类型分类：类型名称 -> 扩展名
+pub const FILE_TYPE_CATEGORIES: &[(&str, &[&str])] = &[
+    ("RAW", &[
+        "cr3", "cr2", "crw", "nef", "nrw", "arw", "srf", "sr2", "orf", "raf", "rw2",
+        "pef", "dng", "raw", "rwl", "3fr", "erf", "kdc", "dcr", "x3f",
+    ]),
+    ("JPEG", &["jpg", "jpeg"]),
+    ("HEIF", &["heic", "heif"]),
+    ("PNG", &["png"]),
+    ("TIFF", &["tiff", "tif"]),
+    ("WEBP", &["webp"]),
+    ("BMP", &["bmp"]),
+    ("GIF", &["gif"]),
+];
+
+/// 按扩展名获取文件类型名称（如 RAW、JPEG），不支持的格式返回 None
+pub fn file_type(file_path: &str) -> Option<&'static str> {
+    let ext = Path::new(file_path)
+        .extension()
+        .and_then(|ext| ext.to_str())?
+        .to_lowercase();
+    FILE_TYPE_CATEGORIES
+        .iter()
+        .find(|(_, extensions)| extensions.contains(&ext.as_str()))
+        .map(|(name, _)| *name)
+}
+
+/// 检查文件是否为 RAW 格式
+pub fn is_raw_photo(file_path: &str) -> bool {
+    file_type(file_path) == Some("RAW")
+}
+
 /// 检查文件是否为支持的照片格式
 pub fn is_supported_photo(file_path: &str) -> bool {
     Path::new(file_path)
@@ -125,6 +452,79 @@ pub fn is_supported_photo(file_path: &str) -> bool {
         .unwrap_or(false)
 }
 
+/// 分类模板支持的变量
+pub const TEMPLATE_VARIABLES: &[&str] = &[
+    "{year}", "{month}", "{day}", "{camera}", "{make}", "{camera_full}", "{orientation}", "{rating}", "{label}",
+    "{keyword}", "{folder}", "{volume}",
+];
+
+/// 模板验证结果
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TemplateValidation {
+    pub valid: bool,
+    pub example: String,
+    pub warnings: Vec<String>,
+    pub supported_vars: Vec<String>,
+}
+
+/// 验证分类模板：检查是否包含有效变量、是否有未知变量，并生成示例路径
+pub fn validate_template(template: &str) -> TemplateValidation {
+    let mut warnings = Vec::new();
+
+    // 空模板表示不分类
+    if template.trim().is_empty() {
+        return TemplateValidation {
+            valid: true,
+            example: String::new(),
+            warnings,
+            supported_vars: TEMPLATE_VARIABLES.iter().map(|s| s.to_string()).collect(),
+        };
+    }
+
+    // 检查是否包含有效变量
+    let has_valid_var = TEMPLATE_VARIABLES.iter().any(|v| template.contains(v));
+    if !has_valid_var {
+        warnings.push("模板中没有包含任何有效变量".to_string());
+    }
+
+    // 生成示例
+    let example = template
+        .replace("{year}", "2024")
+        .replace("{month}", "03")
+        .replace("{day}", "15")
+        .replace("{camera}", "Canon EOS R5")
+        .replace("{make}", "Canon")
+        .replace("{camera_full}", "Canon EOS R5")
+        .replace("{orientation}", "横构图")
+        .replace("{rating}", "5星")
+        .replace("{label}", "Red")
+        .replace("{keyword}", "旅行")
+        .replace("{folder}", "100CANON")
+        .replace("{volume}", "EOS_DIGITAL");
+
+    // 检查未知变量
+    let re = regex::Regex::new(r"\{[^}]+\}").unwrap();
+    for cap in re.find_iter(&example) {
+        warnings.push(format!("未知变量: {}", cap.as_str()));
+    }
+
+    // 检查是否会跳出目标目录
+    let trimmed = template.trim_start();
+    if trimmed.starts_with(['/', '\\']) || trimmed.split(['/', '\\']).next().is_some_and(is_drive_prefix) {
+        warnings.push("模板不能是绝对路径".to_string());
+    }
+    if template.split(['/', '\\']).any(|segment| segment.trim() == "..") {
+        warnings.push("模板不能包含 \"..\"".to_string());
+    }
+
+    TemplateValidation {
+        valid: warnings.is_empty(),
+        example,
+        warnings,
+        supported_vars: TEMPLATE_VARIABLES.iter().map(|s| s.to_string()).collect(),
+    }
+}
+
 /// 预设的分类模板
 pub fn get_preset_templates() -> Vec<(&'static str, &'static str)> {
     vec![
@@ -134,6 +534,11 @@ pub fn get_preset_templates() -> Vec<(&'static str, &'static str)> {
         ("按品牌/年/月", "{make}/{year}/{month}"),
         ("按相机/年/月", "{camera}/{year}/{month}"),
         ("按年/相机/月", "{year}/{camera}/{month}"),
+        ("按文件夹/年/月", "{folder}/{year}/{month}"),
+        ("按存储卡/年/月", "{volume}/{year}/{month}"),
+        ("按横竖构图/年/月", "{orientation}/{year}/{month}"),
+        ("按星级/年/月", "{rating}/{year}/{month}"),
+        ("不分类（全部放在一起）", ""),
     ]
 }
 
@@ -236,6 +641,40 @@ mod tests {
         assert_eq!(sanitize_folder_name("A:*?B"), "A___B");
     }
 
+    #[test]
+    fn test_sanitize_folder_name_reserved_names() {
+        assert_eq!(sanitize_folder_name("AUX"), "AUX_");
+        assert_eq!(sanitize_folder_name("COM1"), "COM1_");
+        assert_eq!(sanitize_folder_name("lpt9"), "lpt9_");
+        assert_eq!(sanitize_folder_name("NUL.backup"), "NUL_.backup");
+        // 只是包含保留名的名称不受影响
+        assert_eq!(sanitize_folder_name("CONCERT"), "CONCERT");
+        assert_eq!(sanitize_folder_name("COM10"), "COM10");
+    }
+
+    #[test]
+    fn test_sanitize_folder_name_trailing_dots() {
+        assert_eq!(sanitize_folder_name("Trip..."), "Trip");
+        assert_eq!(sanitize_folder_name("Trip. . "), "Trip");
+        assert_eq!(sanitize_folder_name("CON. "), "CON_");
+        assert_eq!(sanitize_folder_name(".."), "");
+    }
+
+    #[test]
+    fn test_generate_path_reserved_segment() {
+        let config = ClassifyConfig {
+            template: "{camera}/{year}".to_string(),
+            fallback_folder: "未知日期".to_string(),
+            ..Default::default()
+        };
+        let metadata = PhotoMetadata {
+            date_time_original: Some("2024:03:15 14:30:00".to_string()),
+            model: Some("AUX".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(config.generate_path(&metadata), "AUX_/2024");
+    }
+
     // ==================== 分类配置测试 ====================
 
     #[test]
@@ -261,6 +700,7 @@ mod tests {
         let config = ClassifyConfig {
             template: "{year}/{month}/{day}".to_string(),
             fallback_folder: "未知日期".to_string(),
+            ..Default::default()
         };
         let metadata = PhotoMetadata {
             date_time_original: Some("2024:03:15 14:30:00".to_string()),
@@ -275,6 +715,7 @@ mod tests {
         let config = ClassifyConfig {
             template: "{year}/{camera}".to_string(),
             fallback_folder: "未知日期".to_string(),
+            ..Default::default()
         };
         let metadata = PhotoMetadata {
             date_time_original: Some("2024:03:15 14:30:00".to_string()),
@@ -290,6 +731,7 @@ mod tests {
         let config = ClassifyConfig {
             template: "{make}/{year}/{month}".to_string(),
             fallback_folder: "未知日期".to_string(),
+            ..Default::default()
         };
         let metadata = PhotoMetadata {
             date_time_original: Some("2024:03:15 14:30:00".to_string()),
@@ -305,6 +747,7 @@ mod tests {
         let config = ClassifyConfig {
             template: "{year}/{camera}".to_string(),
             fallback_folder: "未知日期".to_string(),
+            ..Default::default()
         };
         let metadata = PhotoMetadata {
             date_time_original: Some("2024:03:15 14:30:00".to_string()),
@@ -320,6 +763,7 @@ mod tests {
         let config = ClassifyConfig {
             template: "{year}/{month}".to_string(),
             fallback_folder: "无日期照片".to_string(),
+            ..Default::default()
         };
         let metadata = PhotoMetadata {
             date_time_original: None,
@@ -330,6 +774,65 @@ mod tests {
         assert_eq!(path, "无日期照片");
     }
 
+    #[test]
+    fn test_generate_path_fallback_file_time() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let file_path = dir.path().join("IMG_0001.JPG");
+        std::fs::write(&file_path, b"photo").unwrap();
+        let file_path = file_path.to_string_lossy().to_string();
+        let config = ClassifyConfig {
+            template: "{year}/{month}".to_string(),
+            fallback_strategy: FallbackStrategy::FileTime,
+            ..Default::default()
+        };
+
+        let metadata = PhotoMetadata {
+            file_path: file_path.clone(),
+            file_name: "IMG_0001.JPG".to_string(),
+            ..Default::default()
+        };
+        let expected = file_modified_time(&file_path).unwrap().format("%Y/%m").to_string();
+        assert_eq!(config.generate_path(&metadata), expected);
+
+        // EXIF 日期优先于修改时间
+        let dated = PhotoMetadata {
+            date_time_original: Some("2020:01:02 03:04:05".to_string()),
+            ..metadata
+        };
+        assert_eq!(config.generate_path(&dated), "2020/01");
+
+        // 读取不到修改时间时使用备用文件夹
+        let missing = PhotoMetadata {
+            file_path: "/nonexistent/IMG_0002.JPG".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(config.generate_path(&missing), "未知日期");
+    }
+
+    #[test]
+    fn test_generate_path_fallback_by_extension() {
+        let config = ClassifyConfig {
+            fallback_strategy: FallbackStrategy::ByExtension,
+            ..Default::default()
+        };
+        let undated = |name: &str| PhotoMetadata {
+            file_name: name.to_string(),
+            ..Default::default()
+        };
+        assert_eq!(config.generate_path(&undated("Screenshot.PNG")), "未分类/png");
+        assert_eq!(config.generate_path(&undated("scan.tif")), "未分类/tif");
+        assert_eq!(config.generate_path(&undated("noext")), "未分类");
+    }
+
+    #[test]
+    fn test_fallback_strategy_default_and_serde() {
+        assert_eq!(ClassifyConfig::default().fallback_strategy, FallbackStrategy::SingleFolder);
+        let config: ClassifyConfig =
+            serde_json::from_str(r#"{"template": "{year}", "fallback_folder": "未知日期"}"#).unwrap();
+        assert_eq!(config.fallback_strategy, FallbackStrategy::SingleFolder);
+        assert_eq!(serde_json::to_string(&FallbackStrategy::ByExtension).unwrap(), "\"by_extension\"");
+    }
+
     #[test]
     fn test_generate_path_use_create_date() {
         // 当 DateTimeOriginal 不存在时，使用 CreateDate
@@ -349,6 +852,7 @@ mod tests {
         let config = ClassifyConfig {
             template: "{camera}/{year}".to_string(),
             fallback_folder: "未知日期".to_string(),
+            ..Default::default()
         };
         let metadata = PhotoMetadata {
             date_time_original: Some("2024:03:15 14:30:00".to_string()),
@@ -444,6 +948,26 @@ mod tests {
         assert!(is_supported_photo("file.with.dots.jpg"));
     }
 
+    #[test]
+    fn test_file_type() {
+        assert_eq!(file_type("IMG_0001.CR3"), Some("RAW"));
+        assert_eq!(file_type("DSC_0001.nef"), Some("RAW"));
+        assert_eq!(file_type("IMG_0001.JPG"), Some("JPEG"));
+        assert_eq!(file_type("IMG_0001.heic"), Some("HEIF"));
+        assert_eq!(file_type("video.mp4"), None);
+        assert_eq!(file_type("noextension"), None);
+
+        assert!(is_raw_photo("IMG_0001.ARW"));
+        assert!(!is_raw_photo("IMG_0001.JPG"));
+    }
+
+    #[test]
+    fn test_file_type_categories_cover_supported_extensions() {
+        for ext in SUPPORTED_EXTENSIONS {
+            assert!(file_type(&format!("photo.{}", ext)).is_some(), "{} 没有分类", ext);
+        }
+    }
+
     // ==================== 预设模板测试 ====================
 
     #[test]
@@ -466,6 +990,348 @@ mod tests {
         assert!(template_values.contains(&"{year}/{month}/{day}"));
     }
 
+    // ==================== 模板验证测试 ====================
+
+    #[test]
+    fn test_validate_template_valid() {
+        let result = validate_template("{year}/{month}/{day}");
+        assert!(result.valid);
+        assert!(result.warnings.is_empty());
+        assert_eq!(result.example, "2024/03/15");
+    }
+
+    #[test]
+    fn test_validate_template_presets_are_valid() {
+        for (name, template) in get_preset_templates() {
+            assert!(validate_template(template).valid, "预设模板 {} 应该有效", name);
+        }
+    }
+
+    #[test]
+    fn test_validate_template_unknown_variable() {
+        let result = validate_template("{yaer}/{month}");
+        assert!(!result.valid);
+        assert_eq!(result.warnings, vec!["未知变量: {yaer}"]);
+        assert_eq!(result.example, "{yaer}/03");
+    }
+
+    #[test]
+    fn test_validate_template_empty_is_flat() {
+        let result = validate_template("");
+        assert!(result.valid);
+        assert_eq!(result.example, "");
+        assert!(validate_template("  ").valid);
+    }
+
+    #[test]
+    fn test_validate_template_no_variables() {
+        let result = validate_template("照片");
+        assert!(!result.valid);
+        assert_eq!(result.warnings.len(), 1);
+        assert!(result.warnings[0].contains("没有包含任何有效变量"));
+    }
+
+    #[test]
+    fn test_validate_template_rejects_escape() {
+        let result = validate_template("../../{year}");
+        assert!(!result.valid);
+        assert!(result.warnings.iter().any(|w| w.contains("..")));
+
+        assert!(!validate_template("/etc/{month}").valid);
+        assert!(!validate_template("\\{year}").valid);
+        assert!(!validate_template("C:\\{year}").valid);
+        assert!(!validate_template("{year}\\..\\{month}").valid);
+    }
+
+    // ==================== 路径限制测试 ====================
+
+    fn dated_metadata() -> PhotoMetadata {
+        PhotoMetadata {
+            date_time_original: Some("2024:03:15 14:30:00".to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn path_for(template: &str) -> String {
+        let config = ClassifyConfig {
+            template: template.to_string(),
+            fallback_folder: "未知日期".to_string(),
+            ..Default::default()
+        };
+        config.generate_path(&dated_metadata())
+    }
+
+    #[test]
+    fn test_generate_path_strips_parent_segments() {
+        assert_eq!(path_for("../../{year}"), "2024");
+        assert_eq!(path_for("{year}/../{month}"), "2024/03");
+        assert_eq!(path_for("./{year}/./{month}"), "2024/03");
+    }
+
+    #[test]
+    fn test_generate_path_strips_absolute_prefix() {
+        assert_eq!(path_for("/etc/{month}"), "etc/03");
+        assert_eq!(path_for("C:\\{year}\\{month}"), "2024/03");
+        assert_eq!(path_for("\\\\server\\{year}"), "server/2024");
+    }
+
+    #[test]
+    fn test_generate_path_mixed_separators() {
+        assert_eq!(path_for("{year}\\..\\{month}/{day}"), "2024/03/15");
+        assert_eq!(path_for("..\\{year}/../../{month}"), "2024/03");
+    }
+
+    #[test]
+    fn test_generate_path_collapses_separators() {
+        // 开头、结尾以及连续的分隔符都会被合并
+        assert_eq!(path_for("/{year}/{month}"), "2024/03");
+        assert_eq!(path_for("{year}/{month}/"), "2024/03");
+        assert_eq!(path_for("{year}//{month}"), "2024/03");
+        assert_eq!(path_for("//{year}///{month}//"), "2024/03");
+        assert_eq!(path_for("{year}/ /{month}"), "2024/03");
+    }
+
+    #[test]
+    fn test_generate_path_sanitizes_segments() {
+        assert_eq!(path_for(" {year} / {month}"), "2024/03");
+        assert_eq!(path_for("{year}/旅行*精选?"), "2024/旅行_精选_");
+    }
+
+    #[test]
+    fn test_generate_path_fallback_collapses_separators() {
+        let config = ClassifyConfig {
+            template: "{year}/{month}".to_string(),
+            fallback_folder: "未分类//无日期/".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(config.generate_path(&PhotoMetadata::default()), "未分类/无日期");
+    }
+
+    #[test]
+    fn test_generate_path_with_folder() {
+        let config = ClassifyConfig {
+            template: "{folder}/{year}/{month}".to_string(),
+            fallback_folder: "未知日期".to_string(),
+            ..Default::default()
+        };
+        let metadata = dated_metadata();
+        assert_eq!(config.generate_path_with_folder(&metadata, "婚礼/仪式"), "婚礼/仪式/2024/03");
+        // 源文件夹根目录下的照片没有子目录
+        assert_eq!(config.generate_path_with_folder(&metadata, ""), "2024/03");
+        assert_eq!(config.generate_path(&metadata), "2024/03");
+        // 各段清理非法字符
+        assert_eq!(config.generate_path_with_folder(&metadata, "Trip: Day*1/AUX"), "Trip_ Day_1/AUX_/2024/03");
+    }
+
+    #[test]
+    fn test_generate_path_with_volume() {
+        let config = ClassifyConfig {
+            template: "{volume}/{year}/{month}".to_string(),
+            fallback_folder: "未知日期".to_string(),
+            ..Default::default()
+        };
+        let metadata = dated_metadata();
+        assert_eq!(
+            config.generate_path_for_source(&metadata, "/Volumes/EOS_DIGITAL/DCIM", "100CANON"),
+            "EOS_DIGITAL/2024/03"
+        );
+        assert_eq!(
+            config.generate_path_for_source(&metadata, "/run/media/me/CARD1", ""),
+            "CARD1/2024/03"
+        );
+        // 不在挂载点下时使用源文件夹名，并清理非法字符
+        assert_eq!(
+            config.generate_path_for_source(&metadata, "/home/me/imports/Card: 2/", ""),
+            "Card_ 2/2024/03"
+        );
+        assert_eq!(config.generate_path(&metadata), "未知来源/2024/03");
+    }
+
+    #[test]
+    fn test_source_volume_name() {
+        assert_eq!(source_volume_name("/Volumes/EOS_DIGITAL"), "EOS_DIGITAL");
+        assert_eq!(source_volume_name("/media/me/NIKON D850/DCIM/100NIKON"), "NIKON D850");
+        assert_eq!(source_volume_name("/mnt/cards/CARD2"), "CARD2");
+        assert_eq!(source_volume_name(""), "");
+    }
+
+    #[test]
+    fn test_generate_path_preserve_tree() {
+        let mut config = ClassifyConfig {
+            template: "{year}/{month}".to_string(),
+            fallback_folder: "未知日期".to_string(),
+            preserve_tree: true,
+            ..Default::default()
+        };
+        let metadata = dated_metadata();
+        assert_eq!(
+            config.generate_path_with_folder(&metadata, "DCIM/100CANON/Trip: 1"),
+            "2024/03/DCIM/100CANON/Trip_ 1"
+        );
+        assert_eq!(config.generate_path_with_folder(&metadata, ""), "2024/03");
+        // 没有日期的照片在备用文件夹下同样保留子目录
+        assert_eq!(config.generate_path_with_folder(&PhotoMetadata::default(), "a/b"), "未知日期/a/b");
+        // 模板已包含 {folder} 时不重复添加
+        config.template = "{folder}/{year}".to_string();
+        assert_eq!(config.generate_path_with_folder(&metadata, "a/b"), "a/b/2024");
+        // 不分类时完整复制源文件夹结构
+        config.template = String::new();
+        assert_eq!(config.generate_path_with_folder(&metadata, "a/b"), "a/b");
+    }
+
+    #[test]
+    fn test_generate_path_flat() {
+        let config = ClassifyConfig {
+            template: String::new(),
+            fallback_folder: "未知日期".to_string(),
+            ..Default::default()
+        };
+        assert!(config.is_flat());
+        assert_eq!(config.generate_path_with_folder(&dated_metadata(), "100CANON"), "");
+        // 没有日期时也不使用备用文件夹
+        assert_eq!(config.generate_path(&PhotoMetadata::default()), "");
+        assert!(!ClassifyConfig::default().is_flat());
+    }
+
+    #[test]
+    fn test_validate_template_folder_variable() {
+        let result = validate_template("{folder}/{year}");
+        assert!(result.valid);
+        assert_eq!(result.example, "100CANON/2024");
+    }
+
+    #[test]
+    fn test_generate_path_orientation() {
+        let config = ClassifyConfig {
+            template: "{orientation}/{year}".to_string(),
+            ..Default::default()
+        };
+        let metadata = |width, height, orientation| PhotoMetadata {
+            date_time_original: Some("2024:03:15 14:30:00".to_string()),
+            width,
+            height,
+            orientation,
+            ..Default::default()
+        };
+
+        assert_eq!(config.generate_path(&metadata(Some(6000), Some(4000), None)), "横构图/2024");
+        assert_eq!(config.generate_path(&metadata(Some(4000), Some(6000), None)), "竖构图/2024");
+        // 按方向旋转后判断
+        assert_eq!(config.generate_path(&metadata(Some(6000), Some(4000), Some(6))), "竖构图/2024");
+        // 接近正方形时视为方图
+        assert_eq!(config.generate_path(&metadata(Some(4000), Some(4000), None)), "方图/2024");
+        assert_eq!(config.generate_path(&metadata(Some(4000), Some(3900), None)), "方图/2024");
+        assert_eq!(config.generate_path(&metadata(None, None, None)), "未知方向/2024");
+
+        let config = ClassifyConfig {
+            orientation_labels: OrientationLabels {
+                landscape: "Landscape".to_string(),
+                unknown: "Other".to_string(),
+                square_tolerance: 0.0,
+                ..Default::default()
+            },
+            ..config
+        };
+        assert_eq!(config.generate_path(&metadata(Some(4000), Some(3900), None)), "Landscape/2024");
+        assert_eq!(config.generate_path(&metadata(Some(0), Some(0), None)), "Other/2024");
+
+        let result = validate_template("{orientation}/{year}/{month}");
+        assert!(result.valid);
+        assert_eq!(result.example, "横构图/2024/03");
+    }
+
+    #[test]
+    fn test_generate_path_rating_and_label() {
+        let config = ClassifyConfig {
+            template: "{rating}/{label}".to_string(),
+            ..Default::default()
+        };
+        let metadata = |rating, label: Option<&str>| PhotoMetadata {
+            date_time_original: Some("2024:03:15 14:30:00".to_string()),
+            rating,
+            label: label.map(|s| s.to_string()),
+            ..Default::default()
+        };
+
+        assert_eq!(config.generate_path(&metadata(Some(3), Some("Red"))), "3星/Red");
+        assert_eq!(config.generate_path(&metadata(None, None)), "未评级/无标签");
+
+        // 5 星单独放到"精选"，未评级使用自定义名称
+        let config = ClassifyConfig {
+            rating_labels: RatingLabels {
+                unrated: "待筛选".to_string(),
+                stars: vec!["1星".into(), "2星".into(), "3星".into(), "4星".into(), "精选".into()],
+            },
+            ..config
+        };
+        assert_eq!(config.generate_path(&metadata(Some(5), Some("Green"))), "精选/Green");
+        assert_eq!(config.generate_path(&metadata(None, Some("Green"))), "待筛选/Green");
+
+        // 缺少的星级使用默认名称
+        let config = ClassifyConfig {
+            rating_labels: RatingLabels {
+                stars: vec![],
+                ..Default::default()
+            },
+            ..config
+        };
+        assert_eq!(config.generate_path(&metadata(Some(4), None)), "4星/无标签");
+
+        let result = validate_template("{rating}/{label}/{year}");
+        assert!(result.valid);
+        assert_eq!(result.example, "5星/Red/2024");
+    }
+
+    #[test]
+    fn test_generate_path_keyword() {
+        let config = ClassifyConfig {
+            template: "{keyword}/{year}".to_string(),
+            ..Default::default()
+        };
+        let metadata = |keywords: &[&str]| PhotoMetadata {
+            date_time_original: Some("2024:03:15 14:30:00".to_string()),
+            keywords: keywords.iter().map(|k| k.to_string()).collect(),
+            ..Default::default()
+        };
+
+        assert_eq!(config.generate_path(&metadata(&[])), "无关键词/2024");
+        assert_eq!(config.generate_path(&metadata(&["旅行"])), "旅行/2024");
+        // 多个关键词时使用第一个
+        assert_eq!(config.generate_path(&metadata(&["家人", "旅行"])), "家人/2024");
+        // 关键词中的路径分隔符等非法字符被替换，不会产生额外的目录层级
+        assert_eq!(config.generate_path(&metadata(&["A/B: C"])), "A_B_ C/2024");
+
+        let result = validate_template("{keyword}/{year}");
+        assert!(result.valid);
+        assert_eq!(result.example, "旅行/2024");
+    }
+
+    #[test]
+    fn test_generate_path_camera_full_strips_redundant_make() {
+        let config = ClassifyConfig {
+            template: "{camera_full}/{year}".to_string(),
+            fallback_folder: "未知日期".to_string(),
+            ..Default::default()
+        };
+        let metadata = PhotoMetadata {
+            date_time_original: Some("2024:03:15 14:30:00".to_string()),
+            make: Some("NIKON CORPORATION".to_string()),
+            model: Some("NIKON Z 6".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(config.generate_path(&metadata), "Nikon Z 6/2024");
+
+        let metadata = PhotoMetadata {
+            date_time_original: Some("2024:03:15 14:30:00".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(config.generate_path(&metadata), "未知相机/2024");
+
+        let result = validate_template("{camera_full}/{year}");
+        assert!(result.valid);
+        assert_eq!(result.example, "Canon EOS R5/2024");
+    }
+
     // ==================== 综合测试 ====================
 
     #[test]
@@ -474,6 +1340,7 @@ mod tests {
         let config = ClassifyConfig {
             template: "{make}/{year}/{month}/{day}".to_string(),
             fallback_folder: "未分类".to_string(),
+            ..Default::default()
         };
         
         // 有完整信息的照片
@@ -486,6 +1353,12 @@ mod tests {
             model: Some("Canon EOS R5".to_string()),
             make: Some("Canon".to_string()),
             mime_type: Some("image/x-canon-cr3".to_string()),
+            width: None,
+            height: None,
+            orientation: None,
+            rating: None,
+            label: None,
+            keywords: Vec::new(),
         };
         assert_eq!(config.generate_path(&metadata1), "Canon/2024/12/25");
         
@@ -55,6 +55,7 @@ mod tests {
         let config = ClassifyConfig {
             template: "{year}/{month}/{day}".to_string(),
             fallback_folder: "未知日期".to_string(),
+            ..Default::default()
         };
         let metadata = PhotoMetadata {
             date_time_original: Some("2024:03:15 14:30:00".to_string()),
@@ -69,6 +70,7 @@ mod tests {
         let config = ClassifyConfig {
             template: "{year}/{camera}".to_string(),
             fallback_folder: "未知日期".to_string(),
+            ..Default::default()
         };
         let metadata = PhotoMetadata {
             date_time_original: Some("2024:03:15 14:30:00".to_string()),
@@ -84,6 +86,7 @@ mod tests {
         let config = ClassifyConfig {
             template: "{year}/{month}".to_string(),
             fallback_folder: "无日期".to_string(),
+            ..Default::default()
         };
         let metadata = PhotoMetadata {
             date_time_original: None,
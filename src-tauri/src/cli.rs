@@ -1,10 +1,29 @@
 // 命令行模式支持
+use std::collections::HashSet;
 use std::env;
-use std::path::Path;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, SystemTime};
 
-use crate::classify::ClassifyConfig;
+use log::{error, info, warn, LevelFilter};
+use notify::{RecursiveMode, Watcher};
+
+use serde::{Deserialize, Serialize};
+
+use crate::classify::{ClassifyConfig, FallbackStrategy};
 use crate::exif::check_exiftool;
-use crate::transfer::{scan_photos, format_size};
+use crate::history::TransferFileStatus;
+use crate::progress::CliProgress;
+use crate::rename::RenameConfig;
+use crate::transfer::{
+    check_dirs_overlap, enrich_photos, enumerate_photos, format_size, CameraRoute, PhotoInfo, PlanAction, ScanOptions, ScanResult,
+    TransferPlan,
+};
+use crate::transfer_v2::{plan_transfer, transfer_photos_v2, TransferContext};
 
 /// 命令行参数
 pub struct CliArgs {
@@ -13,10 +32,327 @@ pub struct CliArgs {
     pub template: String,
     pub skip_duplicates: bool,
     pub dry_run: bool,
+    /// 并行复制的任务数，1 表示串行
+    pub jobs: usize,
+    /// 扫描时并行读取 EXIF 的任务数，1 表示串行
+    pub scan_jobs: usize,
+    /// 重命名配置（来自配置文件）
+    pub rename: RenameConfig,
+    /// 扫描排除规则
+    pub exclude: Vec<String>,
+    /// RAW+JPEG 同名文件只导入 RAW
+    pub prefer_raw: bool,
+    /// 只导入星级不低于此值的照片
+    pub min_rating: Option<u8>,
+    /// 跳过小于此字节数的文件
+    pub min_size: Option<u64>,
+    /// 跳过大于此字节数的文件
+    pub max_size: Option<u64>,
+    /// 跳过空文件和无法读取的文件
+    pub skip_empty: bool,
+    /// 扫描时跟随符号链接
+    pub follow_links: bool,
+    /// 一并复制照片的同名附属文件（.xmp/.aae/.thm）
+    pub move_sidecars: bool,
+    /// 去重时同时比较 JPEG/PNG 解码后的像素数据
+    pub pixel_dedup: bool,
+    /// 无法从 EXIF 获取日期时的处理方式
+    pub fallback_strategy: FallbackStrategy,
+    /// 在分类路径下保留源文件夹的子目录结构
+    pub preserve_tree: bool,
+    /// 去重时额外参考的目录（如主归档），只读取不写入
+    pub reference_dirs: Vec<String>,
+    /// 去重扫描目标目录和参考目录时排除的文件/文件夹（glob）
+    pub dedup_exclude: Vec<String>,
+    /// 已归档文件的哈希清单（sha256sum 格式），其中的内容不再导入
+    pub known_hashes: Option<String>,
+    /// 允许目标文件夹与源文件夹相同或互相包含
+    pub allow_nested_target: bool,
+    /// 按相机分配目标文件夹的规则，没有匹配的规则的照片传输到 target_dir
+    pub routes: Vec<CameraRoute>,
+    /// 保存传输历史（history.json）和去重索引缓存（dedup-index）的目录，
+    /// None 时为配置目录下的 photo-truck，与图形界面共用
+    pub data_dir: Option<PathBuf>,
+    /// 加载配置文件时的错误
+    pub config_error: Option<String>,
+    /// 生成默认配置文件的路径
+    pub init_config: Option<String>,
+    /// 监视模式：源文件夹出现新文件时自动导入
+    pub watch: bool,
+    /// 监视模式下首次成功导入后退出
+    pub watch_once: bool,
+    /// 日志级别（-v 显示调试信息，-q 只显示错误）
+    pub log_level: LevelFilter,
+    /// 解析参数时产生的警告，日志初始化后输出
+    pub warnings: Vec<String>,
     pub help: bool,
     pub version: bool,
 }
 
+/// 配置文件内容（TOML 或 JSON），所有字段可选
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FileConfig {
+    pub source_dir: Option<String>,
+    pub target_dir: Option<String>,
+    pub template: Option<String>,
+    pub skip_duplicates: Option<bool>,
+    pub jobs: Option<usize>,
+    pub scan_jobs: Option<usize>,
+    pub rename: Option<RenameConfig>,
+    pub exclude: Vec<String>,
+    pub prefer_raw: Option<bool>,
+    pub min_rating: Option<u8>,
+    /// 文件大小，可带单位，如 "100K"、"2M"
+    pub min_size: Option<String>,
+    pub max_size: Option<String>,
+    pub skip_empty: Option<bool>,
+    pub follow_links: Option<bool>,
+    pub move_sidecars: Option<bool>,
+    pub pixel_dedup: Option<bool>,
+    pub fallback_strategy: Option<FallbackStrategy>,
+    pub preserve_tree: Option<bool>,
+    pub reference_dirs: Vec<String>,
+    pub dedup_exclude: Vec<String>,
+    pub known_hashes: Option<String>,
+    /// 按相机分配目标文件夹，每条为 "相机=目标文件夹"
+    pub routes: Vec<String>,
+}
+
+impl FileConfig {
+    /// 从文件加载配置，扩展名为 .json 时按 JSON 解析，否则按 TOML 解析
+    pub fn load(path: &str) -> Result<Self, String> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| format!("读取配置文件失败 {}: {}", path, e))?;
+        let is_json = Path::new(path)
+            .extension()
+            .map(|ext| ext.eq_ignore_ascii_case("json"))
+            .unwrap_or(false);
+
+        if is_json {
+            serde_json::from_str(&content).map_err(|e| format!("解析配置文件失败 {}: {}", path, e))
+        } else {
+            toml::from_str(&content).map_err(|e| format!("解析配置文件失败 {}: {}", path, e))
+        }
+    }
+
+    /// 将配置文件的值应用到命令行参数上（之后再由命令行选项覆盖）
+    fn apply_to(self, args: &mut CliArgs) {
+        if let Some(source_dir) = self.source_dir {
+            args.source_dir = source_dir;
+        }
+        if let Some(target_dir) = self.target_dir {
+            args.target_dir = target_dir;
+        }
+        if let Some(template) = self.template {
+            args.template = template;
+        }
+        if let Some(skip_duplicates) = self.skip_duplicates {
+            args.skip_duplicates = skip_duplicates;
+        }
+        if let Some(jobs) = self.jobs.filter(|&n| n > 0) {
+            args.jobs = jobs;
+        }
+        if let Some(scan_jobs) = self.scan_jobs.filter(|&n| n > 0) {
+            args.scan_jobs = scan_jobs;
+        }
+        if let Some(rename) = self.rename {
+            args.rename = rename;
+        }
+        args.exclude.extend(self.exclude);
+        if let Some(prefer_raw) = self.prefer_raw {
+            args.prefer_raw = prefer_raw;
+        }
+        match self.min_rating {
+            Some(n) if (1..=5).contains(&n) => args.min_rating = Some(n),
+            Some(n) => args.warnings.push(format!("配置文件中无效的最低星级: {}，应为 1-5", n)),
+            None => {}
+        }
+        if let Some(min_size) = &self.min_size {
+            match parse_human_size(min_size) {
+                Ok(size) => args.min_size = Some(size),
+                Err(e) => args.warnings.push(format!("配置文件 min_size {}", e)),
+            }
+        }
+        if let Some(max_size) = &self.max_size {
+            match parse_human_size(max_size) {
+                Ok(size) => args.max_size = Some(size),
+                Err(e) => args.warnings.push(format!("配置文件 max_size {}", e)),
+            }
+        }
+        if let Some(skip_empty) = self.skip_empty {
+            args.skip_empty = skip_empty;
+        }
+        if let Some(follow_links) = self.follow_links {
+            args.follow_links = follow_links;
+        }
+        if let Some(move_sidecars) = self.move_sidecars {
+            args.move_sidecars = move_sidecars;
+        }
+        if let Some(pixel_dedup) = self.pixel_dedup {
+            args.pixel_dedup = pixel_dedup;
+        }
+        if let Some(strategy) = self.fallback_strategy {
+            args.fallback_strategy = strategy;
+        }
+        if let Some(preserve_tree) = self.preserve_tree {
+            args.preserve_tree = preserve_tree;
+        }
+        args.reference_dirs.extend(self.reference_dirs);
+        args.dedup_exclude.extend(self.dedup_exclude);
+        if self.known_hashes.is_some() {
+            args.known_hashes = self.known_hashes;
+        }
+        for route in &self.routes {
+            match CameraRoute::parse(route) {
+                Ok(route) => args.routes.push(route),
+                Err(e) => args.warnings.push(e),
+            }
+        }
+    }
+}
+
+/// 默认配置文件模板（TOML）
+pub const DEFAULT_CONFIG_TEMPLATE: &str = r#"# Photo Truck 配置文件
+# 使用: photo-truck --config <本文件路径>
+# 命令行选项会覆盖这里的值
+
+# 源文件夹路径（照片所在位置）
+# source_dir = "/Volumes/SD/DCIM"
+
+# 目标文件夹路径（NAS或存储位置）
+# target_dir = "/Volumes/NAS/Photos"
+
+# 分类模板，可用变量: {year} {month} {day} {camera} {make} {camera_full}
+#                     {orientation} {rating} {label} {keyword} {folder} {volume}
+template = "{year}/{month}"
+
+# 无法从 EXIF 获取日期时: "single_folder"（全部放入"未知日期"）、
+# "file_time"（使用文件修改时间分类）、"by_extension"（按扩展名放入"未分类/扩展名"）
+fallback_strategy = "single_folder"
+
+# 在分类文件夹下保留源文件夹的子目录结构，如 2024/03/DCIM/100CANON/IMG_0001.JPG
+preserve_tree = false
+
+# 是否跳过重复文件
+skip_duplicates = true
+
+# 去重时额外参考的目录（如主归档），其中已有的照片同样跳过，不会向其中写入
+reference_dirs = []
+
+# 按相机分配目标文件夹（相机为型号、品牌或品牌加型号），其余照片传输到 target_dir。
+# 每个目标文件夹分别去重
+# routes = ["Canon EOS R5=/Volumes/NAS-A/Photos", "SONY=/Volumes/NAS-B/Photos"]
+routes = []
+
+# 去重扫描目标目录和参考目录时排除的文件/文件夹（glob），如不会重复导入的大型归档子文件夹。
+# 其中的照片不参与去重，可能被再次导入
+dedup_exclude = []
+
+# 已归档照片的哈希清单（sha256sum 格式，每行 "<哈希>  <路径>"），其中的照片不再导入
+# known_hashes = "/Volumes/NAS/known.sha256"
+
+# 并行复制任务数，1 为串行
+# jobs = 2
+
+# 扫描时并行读取 EXIF 的任务数，读卡器较慢时可设为 1 串行读取
+# scan_jobs = 2
+
+# 扫描时排除的文件/文件夹（glob）
+exclude = []
+
+# RAW+JPEG 同名文件只导入 RAW
+prefer_raw = false
+
+# 一并复制照片的同名附属文件（.xmp/.aae/.thm），重命名时跟随照片的新文件名
+move_sidecars = false
+
+# 去重时同时比较 JPEG/PNG 解码后的像素数据，可发现只修改了元数据的重复照片（较慢）
+pixel_dedup = false
+
+# 只导入星级不低于此值的照片（1-5），未评级的照片被跳过
+# min_rating = 3
+
+# 只导入大小在此范围内的文件，可使用单位 K/M/G（1024 进制），如跳过 100K 以下的缩略图
+# min_size = "100K"
+# max_size = "2G"
+
+# 跳过空文件（0 字节）和无法读取的文件，扫描完成时列出这些文件
+skip_empty = false
+
+# 扫描时跟随符号链接（链接形成的循环会被自动跳过）
+follow_links = false
+
+# 重命名设置
+[rename]
+enabled = false
+# 可用变量: {original} {date} {time} {datetime} {year} {month} {day}
+#           {hour} {minute} {second} {camera} {make} {camera_full} {orientation} {counter}
+#           {ext}（包含时不再自动在末尾添加扩展名）
+template = "{original}"
+counter_start = 1
+# 计数器起始值: "fixed"（每次从 counter_start 开始）、"existing_count"（接着目标文件夹中已有的照片数）、
+#               "max_suffix"（接着目标文件夹中文件名末尾的最大编号）
+counter_mode = "fixed"
+counter_digits = 4
+# 扩展名大小写: "preserve"（保持原样）、"lower"（小写）、"upper"（大写），未启用重命名时同样生效
+extension_case = "preserve"
+# 新文件名（不含扩展名）大小写: "preserve"、"lower"、"upper"、"title"（每个单词首字母大写）
+case = "preserve"
+# 同一目录下文件名相同的文件（如 RAW+JPEG）使用相同的新文件名，只占用一个计数
+pair_by_basename = false
+"#;
+
+/// 写入默认配置文件，不覆盖已存在的文件
+pub fn write_default_config(path: &str) -> Result<(), String> {
+    if Path::new(path).exists() {
+        return Err(format!("文件已存在: {}", path));
+    }
+    if let Some(parent) = Path::new(path).parent() {
+        if !parent.as_os_str().is_empty() {
+            fs::create_dir_all(parent).map_err(|e| format!("创建目录失败: {}", e))?;
+        }
+    }
+    fs::write(path, DEFAULT_CONFIG_TEMPLATE).map_err(|e| format!("写入配置文件失败: {}", e))
+}
+
+/// 解析文件大小：整数字节数，或数字加 K/M/G 单位（与 format_size 相同的 1024 进制），
+/// 如 `100K`、`2.5M`、`1G`。单位不区分大小写，可带 B 或 iB 后缀，数字和单位之间可以有空格，
+/// 因此 format_size 的输出（如 `1.50 GB`）也能解析回来
+pub fn parse_human_size(value: &str) -> Result<u64, String> {
+    let invalid = || format!("无效的文件大小: {}（应为数字加可选单位 K/M/G，如 100K、2.5M、1G）", value);
+
+    let upper = value.trim().to_ascii_uppercase();
+    let upper = upper
+        .strip_suffix("IB")
+        .or_else(|| upper.strip_suffix('B'))
+        .unwrap_or(&upper);
+    let (number, multiplier) = match upper.chars().last().ok_or_else(invalid)? {
+        'K' => (&upper[..upper.len() - 1], 1u64 << 10),
+        'M' => (&upper[..upper.len() - 1], 1 << 20),
+        'G' => (&upper[..upper.len() - 1], 1 << 30),
+        _ => (upper, 1),
+    };
+    let number = number.trim();
+    if multiplier == 1 {
+        // 没有单位时只接受整数字节数
+        return number.parse::<u64>().map_err(|_| invalid());
+    }
+    let number: f64 = number.parse().map_err(|_| invalid())?;
+    let bytes = (number * multiplier as f64).round();
+    if !bytes.is_finite() || number < 0.0 || bytes > u64::MAX as f64 {
+        return Err(invalid());
+    }
+    Ok(bytes as u64)
+}
+
+/// 默认并行任务数：按 CPU 核数，最多 4 个（复制主要受磁盘/网络限制）
+pub fn default_jobs() -> usize {
+    thread::available_parallelism()
+        .map(|n| n.get().min(4))
+        .unwrap_or(1)
+}
+
 impl Default for CliArgs {
     fn default() -> Self {
         Self {
@@ -25,6 +361,32 @@ impl Default for CliArgs {
             template: "{year}/{month}".to_string(),
             skip_duplicates: true,
             dry_run: false,
+            jobs: default_jobs(),
+            scan_jobs: default_jobs(),
+            rename: RenameConfig::default(),
+            exclude: Vec::new(),
+            prefer_raw: false,
+            min_rating: None,
+            min_size: None,
+            max_size: None,
+            skip_empty: false,
+            follow_links: false,
+            move_sidecars: false,
+            pixel_dedup: false,
+            fallback_strategy: FallbackStrategy::default(),
+            preserve_tree: false,
+            reference_dirs: Vec::new(),
+            dedup_exclude: Vec::new(),
+            known_hashes: None,
+            allow_nested_target: false,
+            routes: Vec::new(),
+            data_dir: None,
+            config_error: None,
+            init_config: None,
+            watch: false,
+            watch_once: false,
+            log_level: LevelFilter::Info,
+            warnings: Vec::new(),
             help: false,
             version: false,
         }
@@ -33,14 +395,29 @@ impl Default for CliArgs {
 
 /// 解析命令行参数
 pub fn parse_args() -> Option<CliArgs> {
-    let args: Vec<String> = env::args().collect();
-    
+    parse_args_from(env::args().collect())
+}
+
+/// 从给定的参数列表解析（第一个元素为程序名）
+pub fn parse_args_from(args: Vec<String>) -> Option<CliArgs> {
     // 如果没有参数，返回 None 表示使用 GUI 模式
     if args.len() <= 1 {
         return None;
     }
 
     let mut cli_args = CliArgs::default();
+
+    // 先加载配置文件，再由命令行选项覆盖
+    if let Some(pos) = args.iter().position(|a| a == "-c" || a == "--config") {
+        match args.get(pos + 1) {
+            Some(path) => match FileConfig::load(path) {
+                Ok(file_config) => file_config.apply_to(&mut cli_args),
+                Err(e) => cli_args.config_error = Some(e),
+            },
+            None => cli_args.config_error = Some("--config 需要指定配置文件路径".to_string()),
+        }
+    }
+
     let mut i = 1;
 
     while i < args.len() {
@@ -49,7 +426,7 @@ pub fn parse_args() -> Option<CliArgs> {
                 cli_args.help = true;
                 return Some(cli_args);
             }
-            "-v" | "--version" => {
+            "-V" | "--version" => {
                 cli_args.version = true;
                 return Some(cli_args);
             }
@@ -77,6 +454,114 @@ pub fn parse_args() -> Option<CliArgs> {
             "-n" | "--dry-run" => {
                 cli_args.dry_run = true;
             }
+            "-j" | "--jobs" => {
+                if i + 1 < args.len() {
+                    match args[i + 1].parse::<usize>() {
+                        Ok(n) if n > 0 => cli_args.jobs = n,
+                        _ => cli_args.warnings.push(format!(
+                            "无效的任务数: {}，使用默认值 {}",
+                            args[i + 1],
+                            cli_args.jobs
+                        )),
+                    }
+                    i += 1;
+                }
+            }
+            "-c" | "--config" => {
+                // 已在解析前加载
+                i += 1;
+            }
+            "--init-config" => {
+                if i + 1 < args.len() {
+                    cli_args.init_config = Some(args[i + 1].clone());
+                    i += 1;
+                }
+            }
+            "--watch" => {
+                cli_args.watch = true;
+            }
+            "--watch-once" => {
+                cli_args.watch = true;
+                cli_args.watch_once = true;
+            }
+            "--exclude" => {
+                if i + 1 < args.len() {
+                    cli_args.exclude.push(args[i + 1].clone());
+                    i += 1;
+                }
+            }
+            "--prefer-raw" => {
+                cli_args.prefer_raw = true;
+            }
+            "--preserve-tree" => {
+                cli_args.preserve_tree = true;
+            }
+            "--scan-jobs" if i + 1 < args.len() => {
+                match args[i + 1].parse::<usize>() {
+                    Ok(n) if n > 0 => cli_args.scan_jobs = n,
+                    _ => cli_args.warnings.push(format!(
+                        "无效的扫描任务数: {}，使用默认值 {}",
+                        args[i + 1],
+                        cli_args.scan_jobs
+                    )),
+                }
+                i += 1;
+            }
+            "--move-sidecars" => {
+                cli_args.move_sidecars = true;
+            }
+            "--pixel-dedup" => {
+                cli_args.pixel_dedup = true;
+            }
+            "--skip-empty" => {
+                cli_args.skip_empty = true;
+            }
+            "--follow-links" => {
+                cli_args.follow_links = true;
+            }
+            "--allow-nested-target" => {
+                cli_args.allow_nested_target = true;
+            }
+            "--reference" if i + 1 < args.len() => {
+                cli_args.reference_dirs.push(args[i + 1].clone());
+                i += 1;
+            }
+            "--route" if i + 1 < args.len() => {
+                match CameraRoute::parse(&args[i + 1]) {
+                    Ok(route) => cli_args.routes.push(route),
+                    Err(e) => cli_args.warnings.push(e),
+                }
+                i += 1;
+            }
+            "--dedup-exclude" if i + 1 < args.len() => {
+                cli_args.dedup_exclude.push(args[i + 1].clone());
+                i += 1;
+            }
+            "--known-hashes" if i + 1 < args.len() => {
+                cli_args.known_hashes = Some(args[i + 1].clone());
+                i += 1;
+            }
+            "--min-rating" if i + 1 < args.len() => {
+                match args[i + 1].parse::<u8>() {
+                    Ok(n) if (1..=5).contains(&n) => cli_args.min_rating = Some(n),
+                    _ => cli_args.warnings.push(format!("无效的最低星级: {}，应为 1-5", args[i + 1])),
+                }
+                i += 1;
+            }
+            "--min-size" | "--max-size" if i + 1 < args.len() => {
+                match parse_human_size(&args[i + 1]) {
+                    Ok(size) if args[i] == "--min-size" => cli_args.min_size = Some(size),
+                    Ok(size) => cli_args.max_size = Some(size),
+                    Err(e) => cli_args.warnings.push(e),
+                }
+                i += 1;
+            }
+            "-v" | "--verbose" => {
+                cli_args.log_level = LevelFilter::Debug;
+            }
+            "-q" | "--quiet" => {
+                cli_args.log_level = LevelFilter::Error;
+            }
             _ => {
                 // 忽略未知参数
             }
@@ -84,6 +569,19 @@ pub fn parse_args() -> Option<CliArgs> {
         i += 1;
     }
 
+    // 配置文件和命令行选项都处理完后再检查大小范围
+    if let (Some(min_size), Some(max_size)) = (cli_args.min_size, cli_args.max_size) {
+        if min_size > max_size {
+            cli_args.warnings.push(format!(
+                "最小文件大小 {} 大于最大文件大小 {}，忽略文件大小范围",
+                format_size(min_size),
+                format_size(max_size)
+            ));
+            cli_args.min_size = None;
+            cli_args.max_size = None;
+        }
+    }
+
     Some(cli_args)
 }
 
@@ -101,8 +599,31 @@ Photo Truck - 照片传输归类工具
     -p, --template <模板>     分类模板（默认: {{year}}/{{month}}）
     --no-skip-duplicates      不跳过重复文件
     -n, --dry-run             预览模式，不实际传输文件
+    -j, --jobs <数量>         并行复制任务数（默认按 CPU 核数，最多 4；1 为串行）
+    --scan-jobs <数量>        扫描时并行读取 EXIF 的任务数（默认按 CPU 核数，最多 4；1 为串行）
+    --exclude <规则>          排除匹配的文件/文件夹（glob，可重复）
+    --prefer-raw              RAW+JPEG 同名文件只导入 RAW
+    --preserve-tree           在分类文件夹下保留源文件夹的子目录结构
+    --min-rating <星级>       只导入星级不低于此值的照片（1-5）
+    --min-size <大小>         跳过小于此大小的文件（如 100K，单位 K/M/G）
+    --max-size <大小>         跳过大于此大小的文件（如 2G）
+    --skip-empty              跳过空文件（0 字节）和无法读取的文件，并列出这些文件
+    --follow-links            扫描时跟随符号链接（默认不跟随）
+    --move-sidecars           一并复制同名 .xmp/.aae/.thm 附属文件
+    --pixel-dedup             按像素数据去重 JPEG/PNG，忽略元数据差异（较慢）
+    --reference <路径>        去重时参考的目录（如主归档），其中已有的照片不再导入（可重复）
+    --route <相机=路径>       相机型号或品牌匹配的照片传输到指定文件夹（可重复），如 "Canon EOS R5=/Volumes/A"
+    --dedup-exclude <规则>    去重扫描目标/参考目录时排除的文件夹（glob，可重复），如 归档
+    --known-hashes <文件>     已归档照片的哈希清单（sha256sum 格式），其中的照片不再导入
+    --allow-nested-target     允许目标文件夹位于源文件夹内（或反之）
+    -c, --config <路径>       从配置文件读取选项（TOML 或 JSON）
+    --init-config <路径>      生成带注释的默认配置文件
+    --watch                   监视模式：插卡或出现新照片时自动导入
+    --watch-once              监视模式，首次成功导入后退出
+    -v, --verbose             显示调试日志
+    -q, --quiet               只显示错误日志
     -h, --help                显示帮助信息
-    -v, --version             显示版本信息
+    -V, --version             显示版本信息
 
 模板变量:
     {{year}}   - 4位年份 (如: 2024)
@@ -110,6 +631,13 @@ Photo Truck - 照片传输归类工具
     {{day}}    - 2位日期 (如: 15)
     {{camera}} - 相机型号 (如: Canon EOS R5)
     {{make}}   - 相机品牌 (如: Canon)
+    {{camera_full}} - 品牌和型号合并的相机全称 (如: Nikon Z 6)
+    {{orientation}} - 构图方向 (横构图/竖构图/方图)
+    {{rating}} - 星级评分 (如: 5星，未评级为 未评级)
+    {{label}}  - XMP 颜色标签 (如: Red)
+    {{keyword}} - 第一个关键词 (如: 旅行)
+    {{folder}} - 源文件夹中的相对子目录 (如: DCIM/100CANON)
+    {{volume}} - 源存储卡的卷标，获取不到时为源文件夹名 (如: EOS_DIGITAL)
 
 示例:
     # 基本用法
@@ -123,6 +651,16 @@ Photo Truck - 照片传输归类工具
 
     # 不跳过重复文件
     photo-truck -s ~/Pictures -t ~/Backup --no-skip-duplicates
+
+    # 机械硬盘 NAS 上串行复制
+    photo-truck -s ~/Pictures -t ~/Backup -j 1
+
+    # 使用配置文件（命令行选项优先）
+    photo-truck --init-config ~/.photo-truck.toml
+    photo-truck --config ~/.photo-truck.toml -t /Volumes/Backup
+
+    # 插入存储卡后自动导入
+    photo-truck --watch -s /Volumes/SD/DCIM -t /Volumes/NAS/Photos
 "#);
 }
 
@@ -134,6 +672,11 @@ pub fn print_version() {
 
 /// 运行命令行模式
 pub fn run_cli(args: CliArgs) -> i32 {
+    crate::logging::init(args.log_level);
+    for warning in &args.warnings {
+        warn!("{}", warning);
+    }
+
     if args.help {
         print_help();
         return 0;
@@ -144,60 +687,180 @@ pub fn run_cli(args: CliArgs) -> i32 {
         return 0;
     }
 
+    if let Some(path) = &args.init_config {
+        return match write_default_config(path) {
+            Ok(()) => {
+                println!("✓ 已生成配置文件: {}", path);
+                0
+            }
+            Err(e) => {
+                error!("{}", e);
+                1
+            }
+        };
+    }
+
+    if let Some(e) = &args.config_error {
+        error!("{}", e);
+        return 1;
+    }
+
     // 检查必要参数
     if args.source_dir.is_empty() {
-        eprintln!("错误: 请指定源文件夹 (-s 或 --source)");
-        eprintln!("使用 --help 查看帮助");
+        error!("请指定源文件夹 (-s 或 --source)，使用 --help 查看帮助");
         return 1;
     }
 
     if args.target_dir.is_empty() && !args.dry_run {
-        eprintln!("错误: 请指定目标文件夹 (-t 或 --target)");
-        eprintln!("使用 --help 查看帮助");
+        error!("请指定目标文件夹 (-t 或 --target)，使用 --help 查看帮助");
         return 1;
     }
 
-    // 检查路径是否存在
-    if !Path::new(&args.source_dir).exists() {
-        eprintln!("错误: 源文件夹不存在: {}", args.source_dir);
+    // 检查路径是否存在（监视模式下会等待源文件夹出现）
+    if !args.watch && !Path::new(&args.source_dir).exists() {
+        error!("源文件夹不存在: {}", args.source_dir);
         return 1;
     }
 
     // 检查 ExifTool
-    println!("检查环境...");
+    info!("检查环境...");
     match check_exiftool() {
-        Ok(version) => println!("✓ ExifTool {} 已就绪", version),
-        Err(_) => {
-            eprintln!("⚠ ExifTool 未安装，可能无法读取照片日期");
-            eprintln!("  安装: brew install exiftool");
+        Ok(version) => info!("✓ ExifTool {} 已就绪", version),
+        Err(e) => {
+            warn!("ExifTool 未安装，可能无法读取照片日期（安装: brew install exiftool）");
+            log::debug!("{}", e);
         }
     }
 
+    let cancel_flag = install_cancel_handler();
+    if args.watch {
+        return run_watch(&args, &cancel_flag);
+    }
+
+    match run_import(&args, &cancel_flag, &mut HashSet::new()) {
+        Ok(outcome) => {
+            if outcome.cancelled {
+                EXIT_CANCELLED
+            } else if outcome.error_count > 0 {
+                1
+            } else {
+                0
+            }
+        }
+        Err(e) => {
+            error!("{}", e);
+            1
+        }
+    }
+}
+
+/// 已导入的源文件，连同大小和修改时间一起记录，
+/// 同一路径被替换为新内容（如存储卡重新格式化后拍摄）时会被再次导入
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct ImportedFile {
+    path: String,
+    size: u64,
+    modified: Option<SystemTime>,
+}
+
+impl ImportedFile {
+    fn of(path: &str) -> Self {
+        let metadata = fs::metadata(path).ok();
+        ImportedFile {
+            path: path.to_string(),
+            size: metadata.as_ref().map(|m| m.len()).unwrap_or(0),
+            modified: metadata.and_then(|m| m.modified().ok()),
+        }
+    }
+}
+
+/// 单次导入的结果
+struct ImportOutcome {
+    /// 本次处理的新照片数
+    photo_count: usize,
+    error_count: usize,
+    /// 被 Ctrl-C 取消
+    cancelled: bool,
+}
+
+/// 被 Ctrl-C 取消时的退出码（128 + SIGINT）
+const EXIT_CANCELLED: i32 = 130;
+
+/// 扫描源文件夹中尚未导入的照片：先只枚举文件（不读取 EXIF），去掉 imported 中未变化的文件，
+/// 再由 enrich 为剩余的照片读取 EXIF，最后排序并按星级筛选。监视模式下每批只读取新照片的 EXIF
+fn scan_new_photos(
+    source_dir: &str,
+    config: &ClassifyConfig,
+    options: &ScanOptions,
+    imported: &HashSet<ImportedFile>,
+    enrich: impl FnOnce(&mut [PhotoInfo]),
+) -> Result<ScanResult, String> {
+    let mut result = enumerate_photos(source_dir, config, options)?;
+    if !imported.is_empty() {
+        result.photos.retain(|p| !imported.contains(&ImportedFile::of(&p.path)));
+        result.total_files = result.photos.len();
+        result.total_size = result.photos.iter().map(|p| p.file_size).sum();
+    }
+    enrich(&mut result.photos);
+    result.sort_photos(options.sort_by);
+    if let Some(min_rating) = options.min_rating {
+        result.retain_min_rating(min_rating);
+    }
+    Ok(result)
+}
+
+/// 执行一次扫描和传输
+/// imported 记录已处理过的源文件，未变化的文件在后续导入中会被忽略（用于监视模式）
+fn run_import(args: &CliArgs, cancel_flag: &Arc<AtomicBool>, imported: &mut HashSet<ImportedFile>) -> Result<ImportOutcome, String> {
     // 创建配置
     let config = ClassifyConfig {
         template: args.template.clone(),
         fallback_folder: "未知日期".to_string(),
+        fallback_strategy: args.fallback_strategy,
+        preserve_tree: args.preserve_tree,
+        ..Default::default()
     };
 
+    if !args.allow_nested_target && !args.target_dir.is_empty() {
+        check_dirs_overlap(std::slice::from_ref(&args.source_dir), &args.target_dir)?;
+        for route in &args.routes {
+            check_dirs_overlap(std::slice::from_ref(&args.source_dir), &route.target_dir)?;
+        }
+    }
+
     // 扫描照片
     println!("\n扫描照片中...");
     println!("源文件夹: {}", args.source_dir);
 
-    let scan_result = match scan_photos(&args.source_dir, &config) {
-        Ok(result) => result,
-        Err(e) => {
-            eprintln!("扫描失败: {}", e);
-            return 1;
-        }
+    let options = ScanOptions {
+        exclude: args.exclude.clone(),
+        prefer_raw: args.prefer_raw,
+        min_rating: args.min_rating,
+        follow_links: args.follow_links,
+        exif_jobs: args.scan_jobs,
+        min_size: args.min_size,
+        max_size: args.max_size,
+        skip_empty: args.skip_empty,
+        ..Default::default()
     };
+    let scan_result = scan_new_photos(&args.source_dir, &config, &options, imported, |photos| {
+        enrich_photos(photos, &config, options.exif_jobs)
+    })
+    .map_err(|e| format!("扫描失败: {}", e))?;
 
     println!("\n扫描完成:");
     println!("  找到 {} 张照片", scan_result.total_files);
     println!("  总大小: {}", format_size(scan_result.total_size));
+    if !scan_result.skipped_unreadable.is_empty() {
+        println!("  跳过 {} 个空文件或无法读取的文件:", scan_result.skipped_unreadable.len());
+        for path in &scan_result.skipped_unreadable {
+            println!("    {}", path);
+        }
+    }
 
     if scan_result.total_files == 0 {
-        println!("\n没有找到照片，退出");
-        return 0;
+        println!("\n没有找到照片");
+        return Ok(ImportOutcome { photo_count: 0, error_count: 0, cancelled: false });
     }
 
     // 预览分类
@@ -209,128 +872,965 @@ pub fn run_cli(args: CliArgs) -> i32 {
     let mut folders: Vec<_> = folder_counts.into_iter().collect();
     folders.sort_by(|a, b| a.0.cmp(&b.0));
     for (folder, count) in folders.iter().take(10) {
+        let folder = if folder.is_empty() { "(目标文件夹根目录)" } else { folder.as_str() };
         println!("  📁 {} ({} 个文件)", folder, count);
     }
     if folders.len() > 10 {
         println!("  ... 还有 {} 个文件夹", folders.len() - 10);
     }
 
-    // 预览模式
+    // 与图形界面使用相同的传输流程（去重索引缓存、失败重试、附属文件、传输历史），
+    // Ctrl-C 通过 cancel_flag 在文件之间停止传输
+    let ctx = cli_context(args, cancel_flag.clone());
+
+    // 预览模式：与实际传输相同地去重（包括源文件夹中内容相同的多个文件）和解析目标路径，但不复制
     if args.dry_run {
+        let mut errors = Vec::new();
+        let plan = plan_transfer(&ctx, &CliProgress, &scan_result.photos, &args.target_dir, args.skip_duplicates, &mut errors)
+            .unwrap_or_else(|| TransferPlan { target_dir: args.target_dir.clone(), entries: Vec::new() });
+        for error in &errors {
+            warn!("{}", error);
+        }
+        let duplicates: Vec<_> = plan
+            .entries
+            .iter()
+            .filter(|e| e.action == PlanAction::SkipDuplicate)
+            .collect();
+        if !duplicates.is_empty() {
+            println!("\n将跳过 {} 个重复文件:", duplicates.len());
+            for entry in duplicates.iter().take(10) {
+                println!(
+                    "  ⊘ {} (与 {} 相同)",
+                    entry.source_path,
+                    entry.duplicate_of.as_deref().unwrap_or_default()
+                );
+            }
+            if duplicates.len() > 10 {
+                println!("  ... 还有 {} 个重复文件", duplicates.len() - 10);
+            }
+        }
         println!("\n[预览模式] 不执行实际传输");
-        return 0;
+        imported.extend(scan_result.photos.iter().map(|p| ImportedFile::of(&p.path)));
+        return Ok(ImportOutcome {
+            photo_count: scan_result.total_files,
+            error_count: 0,
+            cancelled: ctx.is_cancelled(),
+        });
     }
 
     // 确认传输
     println!("\n目标文件夹: {}", args.target_dir);
+    for route in &args.routes {
+        println!("  {} → {}", route.camera, route.target_dir);
+    }
     if args.skip_duplicates {
         println!("重复文件: 跳过");
     } else {
         println!("重复文件: 覆盖");
     }
-
-    // 创建目标目录
-    if !Path::new(&args.target_dir).exists() {
-        println!("创建目标目录...");
-        if let Err(e) = std::fs::create_dir_all(&args.target_dir) {
-            eprintln!("创建目录失败: {}", e);
-            return 1;
-        }
+    if args.jobs > 1 {
+        println!("并行任务数: {}", args.jobs);
+    } else {
+        println!("并行任务数: 1（串行）");
     }
 
-    // 执行传输
     println!("\n开始传输...");
-    
-    use crate::hash::Deduplicator;
-    use walkdir::WalkDir;
+    run_transfer(&ctx, args, &scan_result.photos, imported)
+}
 
-    let mut deduplicator = Deduplicator::new();
-    let mut success_count = 0;
-    let mut skip_count = 0;
-    let mut error_count = 0;
+/// 执行传输并输出结果，将已成功传输或跳过的源文件加入 imported。
+/// 取消时已开始复制的文件会完成，已处理的文件写入传输历史
+fn run_transfer(
+    ctx: &TransferContext,
+    args: &CliArgs,
+    photos: &[PhotoInfo],
+    imported: &mut HashSet<ImportedFile>,
+) -> Result<ImportOutcome, String> {
+    let result = transfer_photos_v2(ctx, &CliProgress, photos, &args.target_dir, args.skip_duplicates)?;
+    for error in &result.errors {
+        warn!("{}", error);
+    }
+    // 命令行上下文不限制文件明细数，结果中总是包含完整的文件列表
+    for file in result.files.iter().flatten() {
+        if matches!(file.status, TransferFileStatus::Success | TransferFileStatus::Skipped) {
+            imported.insert(ImportedFile::of(&file.source_path));
+        }
+    }
 
-    // 扫描目标目录已有文件（用于去重）
-    if args.skip_duplicates && Path::new(&args.target_dir).exists() {
-        print!("扫描目标目录...");
-        for entry in WalkDir::new(&args.target_dir)
-            .follow_links(true)
-            .into_iter()
-            .filter_map(|e| e.ok())
-        {
-            if entry.path().is_file() {
-                let _ = deduplicator.add_known_file(&entry.path().to_string_lossy());
-            }
+    let cancelled = ctx.is_cancelled();
+    if cancelled {
+        let processed = result.success_count + result.skip_count + result.error_count;
+        println!("\n传输已取消，已处理 {} / {} 个文件:", processed, photos.len());
+    } else {
+        println!("\n传输结果:");
+    }
+    println!("  ✓ 成功: {} 个", result.success_count);
+    println!("  ⊘ 跳过: {} 个", result.skip_count);
+    println!("  ✗ 失败: {} 个", result.error_count);
+    if !result.new_folders.is_empty() {
+        println!("\n新建的文件夹:");
+        for folder in &result.new_folders {
+            println!("  + {}", folder);
         }
-        println!(" 完成");
     }
+    if !result.merged_folders.is_empty() {
+        println!("\n合并到已有的文件夹:");
+        for folder in &result.merged_folders {
+            println!("  = {}", folder);
+        }
+    }
+
+    Ok(ImportOutcome {
+        photo_count: photos.len(),
+        error_count: result.error_count,
+        cancelled,
+    })
+}
 
-    let total = scan_result.photos.len();
-    for (index, photo) in scan_result.photos.iter().enumerate() {
-        // 进度显示
-        if (index + 1) % 10 == 0 || index + 1 == total {
-            print!("\r传输进度: {}/{} ({:.0}%)  ", 
-                index + 1, total, 
-                ((index + 1) as f64 / total as f64) * 100.0);
-            std::io::Write::flush(&mut std::io::stdout()).ok();
+/// 注册 Ctrl-C 处理：第一次按下时设置取消标志，传输在当前文件完成后停止；
+/// 再次按下时立即退出
+fn install_cancel_handler() -> Arc<AtomicBool> {
+    let cancel_flag = Arc::new(AtomicBool::new(false));
+    let flag = cancel_flag.clone();
+    let installed = ctrlc::set_handler(move || {
+        if flag.swap(true, Ordering::SeqCst) {
+            eprintln!("\n强制退出");
+            std::process::exit(EXIT_CANCELLED);
         }
+        eprintln!("\n正在取消，当前文件完成后停止（再次按 Ctrl-C 强制退出）");
+    });
+    if let Err(e) = installed {
+        warn!("注册 Ctrl-C 处理失败: {}", e);
+    }
+    cancel_flag
+}
 
-        // 检查重复
-        if args.skip_duplicates {
-            if let Ok(Some(_)) = deduplicator.check_duplicate(&photo.path, photo.file_size) {
-                skip_count += 1;
-                continue;
+/// 由命令行参数生成传输上下文
+fn cli_context(args: &CliArgs, cancel_flag: Arc<AtomicBool>) -> TransferContext {
+    let mut ctx = TransferContext::new(
+        cancel_flag,
+        &args.source_dir,
+        &args.target_dir,
+        &args.template,
+    );
+    ctx.rename_config = args.rename.clone();
+    ctx.move_sidecars = args.move_sidecars;
+    ctx.jobs = args.jobs;
+    ctx.pixel_dedup = args.pixel_dedup;
+    ctx.reference_dirs = args.reference_dirs.clone();
+    ctx.dedup_exclude = args.dedup_exclude.clone();
+    ctx.known_hashes = args.known_hashes.clone();
+    ctx.allow_nested_target = args.allow_nested_target;
+    ctx.camera_routes = args.routes.clone();
+    ctx.max_result_files = usize::MAX;
+    if let Some(dir) = &args.data_dir {
+        ctx.history_path = dir.join("history.json");
+        ctx.index_cache_dir = dir.join("dedup-index");
+    }
+    ctx
+}
+
+/// 监视模式下检查源文件夹状态的间隔
+const WATCH_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// 监视模式下的防抖时间：事件停止这么久后才开始导入
+const WATCH_DEBOUNCE: Duration = Duration::from_secs(3);
+
+/// 监视模式：等待源文件夹出现新文件并自动导入，按 Ctrl-C 时结束当前传输后退出
+fn run_watch(args: &CliArgs, cancel_flag: &Arc<AtomicBool>) -> i32 {
+    let mut imported = HashSet::new();
+    let cancelled = || cancel_flag.load(Ordering::SeqCst);
+    println!("\n[监视模式] 源文件夹出现新照片时自动导入，按 Ctrl-C 退出");
+
+    loop {
+        if cancelled() {
+            return EXIT_CANCELLED;
+        }
+        // 等待源文件夹可用（如存储卡插入）
+        if !Path::new(&args.source_dir).exists() {
+            println!("\n等待源文件夹可用: {}", args.source_dir);
+            while !Path::new(&args.source_dir).exists() {
+                if cancelled() {
+                    return EXIT_CANCELLED;
+                }
+                thread::sleep(WATCH_POLL_INTERVAL);
             }
+            // 刚挂载时文件可能尚未全部可读
+            thread::sleep(WATCH_DEBOUNCE);
         }
 
-        // 构建目标路径
-        let target_dir = Path::new(&args.target_dir).join(&photo.target_folder);
-        let target_path = target_dir.join(&photo.file_name);
-
-        // 创建目录
-        if let Err(_) = std::fs::create_dir_all(&target_dir) {
-            error_count += 1;
+        match run_import(args, cancel_flag, &mut imported) {
+            Ok(outcome) => {
+                if args.watch_once && outcome.photo_count > 0 && outcome.error_count == 0 && !outcome.cancelled {
+                    return 0;
+                }
+            }
+            Err(e) => error!("{}", e),
+        }
+        if cancelled() {
             continue;
         }
 
-        // 处理文件名冲突
-        let final_path = if target_path.exists() {
-            let stem = Path::new(&photo.file_name)
-                .file_stem()
-                .map(|s| s.to_string_lossy().to_string())
-                .unwrap_or_default();
-            let ext = Path::new(&photo.file_name)
-                .extension()
-                .map(|e| e.to_string_lossy().to_string())
-                .unwrap_or_default();
-            
-            let mut counter = 1;
-            loop {
-                let new_name = if ext.is_empty() {
-                    format!("{}_{}", stem, counter)
-                } else {
-                    format!("{}_{}.{}", stem, counter, ext)
-                };
-                let new_path = target_dir.join(&new_name);
-                if !new_path.exists() {
-                    break new_path;
+        println!("\n继续监视: {}", args.source_dir);
+        if let Err(e) = wait_for_changes(&args.source_dir, cancel_flag) {
+            warn!("监视失败: {}", e);
+            thread::sleep(WATCH_POLL_INTERVAL);
+        }
+    }
+}
+
+/// 阻塞直到源文件夹中有新文件（事件平静后返回），或源文件夹消失、按下 Ctrl-C
+fn wait_for_changes(source_dir: &str, cancel_flag: &AtomicBool) -> Result<(), String> {
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
+        let _ = tx.send(res);
+    })
+    .map_err(|e| format!("创建监视器失败: {}", e))?;
+    watcher
+        .watch(Path::new(source_dir), RecursiveMode::Recursive)
+        .map_err(|e| format!("监视源文件夹失败: {}", e))?;
+
+    loop {
+        match rx.recv_timeout(WATCH_POLL_INTERVAL) {
+            Ok(Ok(event)) if event.kind.is_create() || event.kind.is_modify() => break,
+            Ok(_) => {}
+            Err(RecvTimeoutError::Timeout) => {
+                if cancel_flag.load(Ordering::SeqCst) {
+                    return Ok(());
+                }
+                if !Path::new(source_dir).exists() {
+                    info!("源文件夹已断开（存储卡已弹出？）");
+                    return Ok(());
                 }
-                counter += 1;
             }
-        } else {
-            target_path
+            Err(RecvTimeoutError::Disconnected) => {
+                return Err("监视器已停止".to_string());
+            }
+        }
+    }
+
+    // 防抖：等待连续的写入事件结束
+    while rx.recv_timeout(WATCH_DEBOUNCE).is_ok() {}
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash::DedupIndex;
+    use crate::history::TransferHistory;
+    use tempfile::TempDir;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        std::iter::once("photo-truck")
+            .chain(list.iter().copied())
+            .map(String::from)
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_args_no_args_is_gui() {
+        assert!(parse_args_from(args(&[])).is_none());
+    }
+
+    #[test]
+    fn test_parse_jobs() {
+        let cli = parse_args_from(args(&["-s", "/src", "-j", "3"])).unwrap();
+        assert_eq!(cli.jobs, 3);
+
+        let cli = parse_args_from(args(&["--jobs", "1"])).unwrap();
+        assert_eq!(cli.jobs, 1);
+    }
+
+    #[test]
+    fn test_parse_jobs_invalid_keeps_default() {
+        let cli = parse_args_from(args(&["-j", "0"])).unwrap();
+        assert_eq!(cli.jobs, default_jobs());
+
+        let cli = parse_args_from(args(&["-j", "abc"])).unwrap();
+        assert_eq!(cli.jobs, default_jobs());
+        assert_eq!(cli.warnings.len(), 1);
+        assert!(cli.warnings[0].contains("abc"));
+    }
+
+    #[test]
+    fn test_default_jobs_in_range() {
+        let jobs = default_jobs();
+        assert!((1..=4).contains(&jobs));
+    }
+
+    #[test]
+    fn test_parse_prefer_raw() {
+        assert!(!parse_args_from(args(&["-s", "/tmp/src"])).unwrap().prefer_raw);
+        assert!(parse_args_from(args(&["--prefer-raw"])).unwrap().prefer_raw);
+    }
+
+    #[test]
+    fn test_parse_min_rating() {
+        assert_eq!(parse_args_from(args(&["-s", "/tmp/src"])).unwrap().min_rating, None);
+        assert_eq!(parse_args_from(args(&["--min-rating", "3"])).unwrap().min_rating, Some(3));
+
+        for invalid in ["0", "6", "abc"] {
+            let parsed = parse_args_from(args(&["--min-rating", invalid])).unwrap();
+            assert_eq!(parsed.min_rating, None);
+            assert_eq!(parsed.warnings.len(), 1);
+        }
+
+        // 配置文件中超出范围的星级同样给出警告
+        for (value, expected) in [(3, Some(3)), (0, None), (9, None)] {
+            let config: FileConfig = toml::from_str(&format!("min_rating = {}", value)).unwrap();
+            let mut cli = CliArgs::default();
+            config.apply_to(&mut cli);
+            assert_eq!(cli.min_rating, expected);
+            assert_eq!(cli.warnings.len(), usize::from(expected.is_none()), "{}", value);
+        }
+    }
+
+    #[test]
+    fn test_parse_human_size_units() {
+        assert_eq!(parse_human_size("100"), Ok(100));
+        assert_eq!(parse_human_size(" 512B "), Ok(512));
+        assert_eq!(parse_human_size("100K"), Ok(100 * 1024));
+        assert_eq!(parse_human_size("100kb"), Ok(100 * 1024));
+        assert_eq!(parse_human_size("2.5M"), Ok(2_621_440));
+        assert_eq!(parse_human_size("2MiB"), Ok(2 * 1024 * 1024));
+        assert_eq!(parse_human_size("1G"), Ok(1 << 30));
+        assert_eq!(parse_human_size("1.5 GB"), Ok(1536 * 1024 * 1024));
+    }
+
+    #[test]
+    fn test_parse_human_size_round_trips_format_size() {
+        for bytes in [0, 512, 100 * 1024, 2_621_440, 3 << 30] {
+            assert_eq!(parse_human_size(&format_size(bytes)), Ok(bytes), "{}", format_size(bytes));
+        }
+    }
+
+    #[test]
+    fn test_parse_human_size_invalid() {
+        for invalid in ["", "B", "K", "abc", "-1M", "-5", "1X", "1.5", "1..5K", "1e400G", "K100"] {
+            let error = parse_human_size(invalid).unwrap_err();
+            assert!(error.contains("无效的文件大小"), "{}: {}", invalid, error);
+        }
+
+        let parsed = parse_args_from(args(&["--min-size", "100K", "--max-size", "2G"])).unwrap();
+        assert_eq!(parsed.min_size, Some(100 * 1024));
+        assert_eq!(parsed.max_size, Some(2 << 30));
+        let parsed = parse_args_from(args(&["--min-size", "lots"])).unwrap();
+        assert_eq!(parsed.min_size, None);
+        assert_eq!(parsed.warnings.len(), 1);
+        assert!(parsed.warnings[0].contains("lots"));
+
+        // 最小值大于最大值时忽略大小范围
+        let parsed = parse_args_from(args(&["--min-size", "2G", "--max-size", "100K"])).unwrap();
+        assert_eq!((parsed.min_size, parsed.max_size), (None, None));
+        assert_eq!(parsed.warnings.len(), 1);
+        assert!(parsed.warnings[0].contains("大于最大文件大小"));
+    }
+
+    #[test]
+    fn test_config_size_range_warnings() {
+        let config: FileConfig = toml::from_str(r#"
+min_size = "lots"
+max_size = "2G"
+"#).unwrap();
+        let mut cli = CliArgs::default();
+        config.apply_to(&mut cli);
+        assert_eq!((cli.min_size, cli.max_size), (None, Some(2 << 30)));
+        assert_eq!(cli.warnings.len(), 1);
+        assert!(cli.warnings[0].contains("min_size") && cli.warnings[0].contains("lots"));
+
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("config.toml");
+        fs::write(&path, "min_size = \"1G\"\nmax_size = \"1M\"\n").unwrap();
+        let parsed = parse_args_from(args(&["--config", &path.to_string_lossy()])).unwrap();
+        assert_eq!((parsed.min_size, parsed.max_size), (None, None));
+        assert_eq!(parsed.warnings.len(), 1);
+    }
+
+    #[test]
+    fn test_parse_scan_jobs() {
+        assert_eq!(parse_args_from(args(&["--scan-jobs", "1"])).unwrap().scan_jobs, 1);
+
+        let cli = parse_args_from(args(&["--scan-jobs", "0"])).unwrap();
+        assert_eq!(cli.scan_jobs, default_jobs());
+        assert_eq!(cli.warnings.len(), 1);
+    }
+
+    #[test]
+    fn test_parse_follow_links() {
+        assert!(!parse_args_from(args(&["-s", "/tmp/src"])).unwrap().follow_links);
+        assert!(parse_args_from(args(&["--follow-links"])).unwrap().follow_links);
+
+        let config: FileConfig = toml::from_str("follow_links = true").unwrap();
+        let mut cli = CliArgs::default();
+        config.apply_to(&mut cli);
+        assert!(cli.follow_links);
+    }
+
+    #[test]
+    fn test_config_fallback_strategy() {
+        assert_eq!(CliArgs::default().fallback_strategy, FallbackStrategy::SingleFolder);
+
+        let config: FileConfig = toml::from_str(r#"fallback_strategy = "by_extension""#).unwrap();
+        let mut cli = CliArgs::default();
+        config.apply_to(&mut cli);
+        assert_eq!(cli.fallback_strategy, FallbackStrategy::ByExtension);
+    }
+
+    #[test]
+    fn test_parse_exclude_repeatable() {
+        let cli = parse_args_from(args(&["--exclude", "*.png", "--exclude", ".thumbnails"])).unwrap();
+        assert_eq!(cli.exclude, vec!["*.png", ".thumbnails"]);
+    }
+
+    #[test]
+    fn test_config_file_toml_with_cli_override() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("import.toml");
+        fs::write(&path, r#"
+source_dir = "/Volumes/SD"
+target_dir = "/Volumes/NAS"
+template = "{year}/{month}/{day}"
+skip_duplicates = false
+exclude = ["*.png"]
+
+[rename]
+enabled = true
+template = "{date}_{counter}"
+"#).unwrap();
+        let path = path.to_string_lossy().to_string();
+
+        let cli = parse_args_from(args(&["-t", "/tmp/other", "--config", &path, "--exclude", "*.gif"])).unwrap();
+        assert!(cli.config_error.is_none());
+        assert_eq!(cli.source_dir, "/Volumes/SD");
+        assert_eq!(cli.target_dir, "/tmp/other");
+        assert_eq!(cli.template, "{year}/{month}/{day}");
+        assert!(!cli.skip_duplicates);
+        assert_eq!(cli.exclude, vec!["*.png", "*.gif"]);
+        assert!(cli.rename.enabled);
+        assert_eq!(cli.rename.template, "{date}_{counter}");
+        // 未指定的字段使用默认值
+        assert_eq!(cli.rename.counter_digits, 4);
+    }
+
+    #[test]
+    fn test_config_file_json() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("import.json");
+        fs::write(&path, r#"{"source_dir": "/src", "jobs": 2}"#).unwrap();
+
+        let cli = parse_args_from(args(&["-c", &path.to_string_lossy()])).unwrap();
+        assert!(cli.config_error.is_none());
+        assert_eq!(cli.source_dir, "/src");
+        assert_eq!(cli.jobs, 2);
+    }
+
+    #[test]
+    fn test_config_file_errors() {
+        let cli = parse_args_from(args(&["--config", "/nonexistent/config.toml"])).unwrap();
+        assert!(cli.config_error.unwrap().contains("读取配置文件失败"));
+
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("bad.toml");
+        fs::write(&path, "template = ").unwrap();
+        let cli = parse_args_from(args(&["--config", &path.to_string_lossy()])).unwrap();
+        assert!(cli.config_error.unwrap().contains("解析配置文件失败"));
+    }
+
+    #[test]
+    fn test_init_config_round_trip() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("photo-truck.toml").to_string_lossy().to_string();
+
+        write_default_config(&path).unwrap();
+        // 不覆盖已有文件
+        assert!(write_default_config(&path).is_err());
+
+        let config = FileConfig::load(&path).unwrap();
+        assert_eq!(config.template.as_deref(), Some("{year}/{month}"));
+        assert_eq!(config.skip_duplicates, Some(true));
+        assert!(config.source_dir.is_none());
+        assert!(!config.rename.unwrap().enabled);
+    }
+
+    #[test]
+    fn test_parse_log_level() {
+        let cli = parse_args_from(args(&["-s", "/src"])).unwrap();
+        assert_eq!(cli.log_level, LevelFilter::Info);
+        let cli = parse_args_from(args(&["-s", "/src", "-v"])).unwrap();
+        assert_eq!(cli.log_level, LevelFilter::Debug);
+        let cli = parse_args_from(args(&["--quiet", "-s", "/src"])).unwrap();
+        assert_eq!(cli.log_level, LevelFilter::Error);
+        assert!(parse_args_from(args(&["-V"])).unwrap().version);
+        assert!(!parse_args_from(args(&["-v"])).unwrap().version);
+    }
+
+    #[test]
+    fn test_parse_watch_flags() {
+        let cli = parse_args_from(args(&["--watch", "-s", "/src"])).unwrap();
+        assert!(cli.watch);
+        assert!(!cli.watch_once);
+
+        let cli = parse_args_from(args(&["--watch-once"])).unwrap();
+        assert!(cli.watch);
+        assert!(cli.watch_once);
+    }
+
+    #[test]
+    fn test_run_import_skips_already_imported() {
+        let src = TempDir::new().unwrap();
+        let dst = TempDir::new().unwrap();
+        fs::write(src.path().join("a.jpg"), b"photo a").unwrap();
+
+        let data = TempDir::new().unwrap();
+        let cli = CliArgs {
+            source_dir: src.path().to_string_lossy().to_string(),
+            target_dir: dst.path().to_string_lossy().to_string(),
+            jobs: 1,
+            data_dir: Some(data.path().to_path_buf()),
+            ..Default::default()
+        };
+        let mut imported = HashSet::new();
+
+        let first = run_import(&cli, &Arc::default(), &mut imported).unwrap();
+        assert_eq!(first.photo_count, 1);
+        assert_eq!(first.error_count, 0);
+
+        // 新文件出现后，只导入新文件
+        fs::write(src.path().join("b.jpg"), b"photo b").unwrap();
+        let second = run_import(&cli, &Arc::default(), &mut imported).unwrap();
+        assert_eq!(second.photo_count, 1);
+
+        let third = run_import(&cli, &Arc::default(), &mut imported).unwrap();
+        assert_eq!(third.photo_count, 0);
+        assert_eq!(imported.len(), 2);
+
+        // 已导入的文件不再读取 EXIF，只有新文件需要读取
+        fs::write(src.path().join("c.jpg"), b"photo c").unwrap();
+        let mut enriched = Vec::new();
+        let config = ClassifyConfig::default();
+        let scan = scan_new_photos(&cli.source_dir, &config, &ScanOptions::default(), &imported, |photos| {
+            enriched.extend(photos.iter().map(|p| p.file_name.clone()))
+        })
+        .unwrap();
+        assert_eq!(enriched, ["c.jpg"]);
+        assert_eq!(scan.total_files, 1);
+    }
+
+    #[test]
+    fn test_run_import_reimports_replaced_file() {
+        let src = TempDir::new().unwrap();
+        let dst = TempDir::new().unwrap();
+        let source = src.path().join("IMG_0001.JPG");
+        fs::write(&source, b"first photo").unwrap();
+
+        let data = TempDir::new().unwrap();
+        let cli = CliArgs {
+            source_dir: src.path().to_string_lossy().to_string(),
+            target_dir: dst.path().to_string_lossy().to_string(),
+            jobs: 1,
+            data_dir: Some(data.path().to_path_buf()),
+            ..Default::default()
+        };
+        let mut imported = HashSet::new();
+        assert_eq!(run_import(&cli, &Arc::default(), &mut imported).unwrap().photo_count, 1);
+
+        // 存储卡重新格式化后同名文件换成了新照片，应当再次导入
+        fs::write(&source, b"second photo, different content").unwrap();
+        let second = run_import(&cli, &Arc::default(), &mut imported).unwrap();
+        assert_eq!(second.photo_count, 1);
+        assert_eq!(second.error_count, 0);
+
+        let third = run_import(&cli, &Arc::default(), &mut imported).unwrap();
+        assert_eq!(third.photo_count, 0);
+    }
+
+    #[test]
+    fn test_run_import_flat_template() {
+        let src = TempDir::new().unwrap();
+        let dst = TempDir::new().unwrap();
+        for (folder, content) in [("100CANON", "a"), ("101CANON", "b"), ("102CANON", "c")] {
+            fs::create_dir_all(src.path().join(folder)).unwrap();
+            fs::write(src.path().join(folder).join("IMG_0001.JPG"), content).unwrap();
+        }
+
+        let list_target = |dir: &Path| {
+            let mut names: Vec<String> = fs::read_dir(dir)
+                .unwrap()
+                .map(|e| {
+                    let entry = e.unwrap();
+                    assert!(entry.path().is_file(), "不分类时不应创建子目录");
+                    entry.file_name().to_string_lossy().to_string()
+                })
+                .collect();
+            names.sort();
+            names
+        };
+
+        // 同名文件按序号区分
+        let data = TempDir::new().unwrap();
+        let cli = CliArgs {
+            source_dir: src.path().to_string_lossy().to_string(),
+            target_dir: dst.path().to_string_lossy().to_string(),
+            template: String::new(),
+            jobs: 1,
+            data_dir: Some(data.path().to_path_buf()),
+            ..Default::default()
+        };
+        let outcome = run_import(&cli, &Arc::default(), &mut HashSet::new()).unwrap();
+        assert_eq!(outcome.error_count, 0);
+        assert_eq!(list_target(dst.path()), vec!["IMG_0001.JPG", "IMG_0001_1.JPG", "IMG_0001_2.JPG"]);
+
+        // 启用重命名时计数器连续递增
+        let dst = TempDir::new().unwrap();
+        let cli = CliArgs {
+            target_dir: dst.path().to_string_lossy().to_string(),
+            rename: RenameConfig {
+                enabled: true,
+                template: "photo_{counter}".to_string(),
+                counter_start: 1,
+                counter_digits: 3,
+                ..Default::default()
+            },
+            ..cli
+        };
+        run_import(&cli, &Arc::default(), &mut HashSet::new()).unwrap();
+        assert_eq!(list_target(dst.path()), vec!["photo_001.JPG", "photo_002.JPG", "photo_003.JPG"]);
+    }
+
+    #[test]
+    fn test_run_import_moves_sidecars() {
+        let src = TempDir::new().unwrap();
+        let dst = TempDir::new().unwrap();
+        fs::write(src.path().join("IMG.CR3"), "raw").unwrap();
+        fs::write(src.path().join("IMG.xmp"), "<xmp/>").unwrap();
+
+        let data = TempDir::new().unwrap();
+        let cli = CliArgs {
+            source_dir: src.path().to_string_lossy().to_string(),
+            target_dir: dst.path().to_string_lossy().to_string(),
+            template: String::new(),
+            jobs: 1,
+            move_sidecars: true,
+            rename: RenameConfig {
+                enabled: true,
+                template: "photo_{counter}".to_string(),
+                ..Default::default()
+            },
+            data_dir: Some(data.path().to_path_buf()),
+            ..Default::default()
+        };
+        let outcome = run_import(&cli, &Arc::default(), &mut HashSet::new()).unwrap();
+        assert_eq!((outcome.photo_count, outcome.error_count), (1, 0));
+        assert_eq!(fs::read_to_string(dst.path().join("photo_0001.CR3")).unwrap(), "raw");
+        assert_eq!(fs::read_to_string(dst.path().join("photo_0001.xmp")).unwrap(), "<xmp/>");
+        assert!(parse_args_from(args(&["--move-sidecars"])).unwrap().move_sidecars);
+        assert!(parse_args_from(args(&["--pixel-dedup"])).unwrap().pixel_dedup);
+    }
+
+    #[test]
+    fn test_run_import_skips_source_duplicates() {
+        let src = TempDir::new().unwrap();
+        let dst = TempDir::new().unwrap();
+        fs::write(src.path().join("IMG_0001.JPG"), b"same").unwrap();
+        fs::write(src.path().join("IMG_0001 copy.JPG"), b"same").unwrap();
+
+        // 预览模式同样进行去重，且不写入目标目录
+        let data = TempDir::new().unwrap();
+        let cli = CliArgs {
+            source_dir: src.path().to_string_lossy().to_string(),
+            target_dir: dst.path().to_string_lossy().to_string(),
+            template: String::new(),
+            dry_run: true,
+            jobs: 1,
+            data_dir: Some(data.path().to_path_buf()),
+            ..Default::default()
+        };
+        run_import(&cli, &Arc::default(), &mut HashSet::new()).unwrap();
+        assert_eq!(fs::read_dir(dst.path()).unwrap().count(), 0);
+
+        let cli = CliArgs { dry_run: false, ..cli };
+        let outcome = run_import(&cli, &Arc::default(), &mut HashSet::new()).unwrap();
+        assert_eq!(outcome.error_count, 0);
+        assert_eq!(fs::read_dir(dst.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_run_import_refuses_target_inside_source() {
+        let src = TempDir::new().unwrap();
+        fs::write(src.path().join("IMG_0001.JPG"), b"photo").unwrap();
+        let target = src.path().join("imported");
+
+        let data = TempDir::new().unwrap();
+        let cli = CliArgs {
+            source_dir: src.path().to_string_lossy().to_string(),
+            target_dir: target.to_string_lossy().to_string(),
+            template: String::new(),
+            jobs: 1,
+            data_dir: Some(data.path().to_path_buf()),
+            ..Default::default()
+        };
+        let err = run_import(&cli, &Arc::default(), &mut HashSet::new()).err().unwrap();
+        assert!(err.contains("位于源文件夹"), "{}", err);
+        assert!(!target.exists());
+
+        let cli = CliArgs { target_dir: cli.source_dir.clone(), ..cli };
+        assert!(run_import(&cli, &Arc::default(), &mut HashSet::new()).err().unwrap().contains("相同"));
+
+        // 明确允许时照常导入，文件不会被复制到自身
+        let cli = CliArgs {
+            target_dir: target.to_string_lossy().to_string(),
+            allow_nested_target: true,
+            ..cli
+        };
+        assert_eq!(run_import(&cli, &Arc::default(), &mut HashSet::new()).unwrap().error_count, 0);
+        assert!(target.join("IMG_0001.JPG").exists());
+        assert!(parse_args_from(args(&["--allow-nested-target"])).unwrap().allow_nested_target);
+    }
+
+    #[test]
+    fn test_run_import_skips_files_in_reference_dir() {
+        let src = TempDir::new().unwrap();
+        let dst = TempDir::new().unwrap();
+        let archive = TempDir::new().unwrap();
+        fs::create_dir_all(archive.path().join("2023")).unwrap();
+        fs::write(archive.path().join("2023/OLD_0001.JPG"), b"archived").unwrap();
+        fs::write(src.path().join("IMG_0001.JPG"), b"archived").unwrap();
+        fs::write(src.path().join("IMG_0002.JPG"), b"new").unwrap();
+
+        let cli = parse_args_from(args(&[
+            "-s", &src.path().to_string_lossy(),
+            "-t", &dst.path().to_string_lossy(),
+            "-p", "",
+            "-j", "1",
+            "--reference", &archive.path().to_string_lossy(),
+        ]))
+        .unwrap();
+        assert_eq!(cli.reference_dirs, [archive.path().to_string_lossy().to_string()]);
+
+        let outcome = run_import(&cli, &Arc::default(), &mut HashSet::new()).unwrap();
+        assert_eq!(outcome.error_count, 0);
+        // 归档中已有的照片不导入，且不向归档写入
+        let imported: Vec<_> = fs::read_dir(dst.path()).unwrap().map(|e| e.unwrap().file_name()).collect();
+        assert_eq!(imported, ["IMG_0002.JPG"]);
+        assert_eq!(fs::read_dir(archive.path().join("2023")).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_run_import_dedup_exclude() {
+        let src = TempDir::new().unwrap();
+        let dst = TempDir::new().unwrap();
+        fs::create_dir_all(dst.path().join("归档/2019")).unwrap();
+        fs::write(dst.path().join("归档/2019/OLD_0001.JPG"), b"archived").unwrap();
+        fs::write(src.path().join("IMG_0001.JPG"), b"archived").unwrap();
+
+        let cli = parse_args_from(args(&[
+            "-s", &src.path().to_string_lossy(),
+            "-t", &dst.path().to_string_lossy(),
+            "-p", "",
+            "-j", "1",
+            "--dedup-exclude", "归档",
+        ]))
+        .unwrap();
+        assert_eq!(cli.dedup_exclude, ["归档"]);
+
+        // 排除的子文件夹不参与去重，其中已有的照片会再次导入
+        let outcome = run_import(&cli, &Arc::default(), &mut HashSet::new()).unwrap();
+        assert_eq!(outcome.error_count, 0);
+        assert!(dst.path().join("IMG_0001.JPG").exists());
+    }
+
+    #[test]
+    fn test_run_import_skips_files_in_known_hashes() {
+        let src = TempDir::new().unwrap();
+        let dst = TempDir::new().unwrap();
+        let manifest_dir = TempDir::new().unwrap();
+        fs::write(src.path().join("IMG_0001.JPG"), b"archived").unwrap();
+        fs::write(src.path().join("IMG_0002.JPG"), b"new").unwrap();
+        let hash = crate::hash::calculate_hash(&src.path().join("IMG_0001.JPG").to_string_lossy()).unwrap();
+        let manifest = manifest_dir.path().join("known.sha256");
+        fs::write(&manifest, format!("{}  ./2023/OLD_0001.JPG\n", hash)).unwrap();
+
+        let cli = parse_args_from(args(&[
+            "-s", &src.path().to_string_lossy(),
+            "-t", &dst.path().to_string_lossy(),
+            "-p", "",
+            "-j", "1",
+            "--known-hashes", &manifest.to_string_lossy(),
+        ]))
+        .unwrap();
+        assert_eq!(cli.known_hashes.as_deref(), Some(&*manifest.to_string_lossy()));
+
+        let outcome = run_import(&cli, &Arc::default(), &mut HashSet::new()).unwrap();
+        assert_eq!(outcome.error_count, 0);
+        let imported: Vec<_> = fs::read_dir(dst.path()).unwrap().map(|e| e.unwrap().file_name()).collect();
+        assert_eq!(imported, ["IMG_0002.JPG"]);
+    }
+
+    #[test]
+    fn test_run_import_preserve_tree() {
+        let src = TempDir::new().unwrap();
+        let dst = TempDir::new().unwrap();
+        fs::create_dir_all(src.path().join("DCIM/100CANON")).unwrap();
+        fs::create_dir_all(src.path().join("DCIM/101CANON")).unwrap();
+        fs::write(src.path().join("DCIM/100CANON/IMG_0001.JPG"), b"first").unwrap();
+        fs::write(src.path().join("DCIM/101CANON/IMG_0001.JPG"), b"second").unwrap();
+        // 目标中已有同名的不同文件
+        fs::create_dir_all(dst.path().join("未知日期/DCIM/100CANON")).unwrap();
+        fs::write(dst.path().join("未知日期/DCIM/100CANON/IMG_0001.JPG"), b"existing").unwrap();
+
+        let cli = parse_args_from(args(&[
+            "-s", &src.path().to_string_lossy(),
+            "-t", &dst.path().to_string_lossy(),
+            "-j", "1",
+            "--preserve-tree",
+        ]))
+        .unwrap();
+        assert!(cli.preserve_tree);
+
+        let outcome = run_import(&cli, &Arc::default(), &mut HashSet::new()).unwrap();
+        assert_eq!(outcome.error_count, 0);
+        let root = dst.path().join("未知日期/DCIM");
+        assert_eq!(fs::read(root.join("100CANON/IMG_0001.JPG")).unwrap(), b"existing");
+        assert_eq!(fs::read(root.join("100CANON/IMG_0001_1.JPG")).unwrap(), b"first");
+        assert_eq!(fs::read(root.join("101CANON/IMG_0001.JPG")).unwrap(), b"second");
+    }
+
+    #[test]
+    fn test_run_import_end_to_end() {
+        let src = TempDir::new().unwrap();
+        let dst = TempDir::new().unwrap();
+        let data = TempDir::new().unwrap();
+        fs::write(src.path().join("IMG_0001.JPG"), b"new photo").unwrap();
+        fs::write(src.path().join("IMG_0001_dup.JPG"), b"new photo").unwrap();
+        fs::write(src.path().join("IMG_0002.JPG"), b"archived").unwrap();
+        // 目标目录中已有同名但内容不同的文件，以及与 IMG_0002 内容相同的文件
+        fs::write(dst.path().join("IMG_0001.JPG"), b"older photo").unwrap();
+        fs::write(dst.path().join("old.jpg"), b"archived").unwrap();
+
+        let cli = CliArgs {
+            source_dir: src.path().to_string_lossy().to_string(),
+            target_dir: dst.path().to_string_lossy().to_string(),
+            template: String::new(),
+            data_dir: Some(data.path().to_path_buf()),
+            ..Default::default()
         };
+        let mut imported = HashSet::new();
+        let outcome = run_import(&cli, &Arc::default(), &mut imported).unwrap();
+        assert_eq!((outcome.photo_count, outcome.error_count), (3, 0));
+        // 源文件夹内的重复文件和目标目录已有的内容都被跳过，同名文件添加序号而不覆盖
+        assert_eq!(fs::read(dst.path().join("IMG_0001.JPG")).unwrap(), b"older photo");
+        assert_eq!(fs::read(dst.path().join("IMG_0001_1.JPG")).unwrap(), b"new photo");
+        assert_eq!(fs::read_dir(dst.path()).unwrap().count(), 3);
+        assert_eq!(imported.len(), 3);
+
+        // 写入传输历史，逐个文件记录状态；去重索引缓存保存在同一目录
+        let history = TransferHistory::load_from(&data.path().join("history.json"));
+        assert_eq!(history.records.len(), 1);
+        let record = &history.records[0];
+        assert_eq!((record.success_count, record.skip_count), (1, 2));
+        let statuses: Vec<TransferFileStatus> = record.files.iter().map(|f| f.status.clone()).collect();
+        assert_eq!(statuses.iter().filter(|s| **s == TransferFileStatus::Skipped).count(), 2);
+        assert!(DedupIndex::cache_path_in(&data.path().join("dedup-index"), &cli.target_dir).exists());
+
+        // 再次导入时所有照片都已在目标目录中
+        let outcome = run_import(&cli, &Arc::default(), &mut HashSet::new()).unwrap();
+        assert_eq!(outcome.error_count, 0);
+        assert_eq!(fs::read_dir(dst.path()).unwrap().count(), 3);
+        let history = TransferHistory::load_from(&data.path().join("history.json"));
+        assert_eq!(history.records[0].skip_count, 3);
+    }
 
-        // 复制文件
-        match std::fs::copy(&photo.path, &final_path) {
-            Ok(_) => success_count += 1,
-            Err(_) => error_count += 1,
+    #[test]
+    fn test_run_transfer_cancelled_partway() {
+        let src = TempDir::new().unwrap();
+        let dst = TempDir::new().unwrap();
+        let data = TempDir::new().unwrap();
+        for name in ["IMG_0001.JPG", "IMG_0002.JPG", "IMG_0003.JPG"] {
+            fs::write(src.path().join(name), name).unwrap();
         }
+        let cli = CliArgs {
+            source_dir: src.path().to_string_lossy().to_string(),
+            target_dir: dst.path().to_string_lossy().to_string(),
+            template: String::new(),
+            data_dir: Some(data.path().to_path_buf()),
+            ..Default::default()
+        };
+        let config = ClassifyConfig { template: String::new(), ..Default::default() };
+        let mut photos = crate::transfer::scan_photos_with_options(&cli.source_dir, &config, &ScanOptions::default()).unwrap().photos;
+        photos.sort_by(|a, b| a.path.cmp(&b.path));
+
+        // 复制第一个文件时按下 Ctrl-C：该文件照常完成，之后的文件不再复制
+        let cancel_flag = Arc::new(AtomicBool::new(false));
+        let mut ctx = cli_context(&cli, cancel_flag.clone());
+        let flag = cancel_flag.clone();
+        ctx.copy_fn = Arc::new(move |source, target| {
+            flag.store(true, Ordering::SeqCst);
+            fs::copy(source, target)
+        });
+        let mut imported = HashSet::new();
+        let outcome = run_transfer(&ctx, &cli, &photos, &mut imported).unwrap();
+        assert!(outcome.cancelled);
+        assert_eq!((outcome.photo_count, outcome.error_count), (3, 0));
+        assert_eq!(fs::read_dir(dst.path()).unwrap().count(), 1);
+        assert_eq!(fs::read_to_string(dst.path().join("IMG_0001.JPG")).unwrap(), "IMG_0001.JPG");
+        assert_eq!(imported.len(), 1);
+
+        // 已处理的文件写入传输历史
+        let history = TransferHistory::load_from(&data.path().join("history.json"));
+        assert_eq!(history.records.len(), 1);
+        assert_eq!(history.records[0].success_count, 1);
+        assert_eq!(history.records[0].files.len(), 1);
+        assert!(history.records[0].errors.iter().any(|e| e == "传输已取消"));
+
+        // 再次导入时只复制剩余的文件
+        let outcome = run_import(&cli, &Arc::default(), &mut imported).unwrap();
+        assert!(!outcome.cancelled);
+        assert_eq!((outcome.photo_count, outcome.error_count), (2, 0));
+        assert_eq!(fs::read_dir(dst.path()).unwrap().count(), 3);
+    }
+
+    #[test]
+    fn test_parse_routes() {
+        let cli = parse_args_from(args(&["--route", "Canon EOS R5=/Volumes/A", "--route", "SONY=/Volumes/B", "--route", "nope"])).unwrap();
+        assert_eq!(
+            cli.routes,
+            vec![CameraRoute::parse("Canon EOS R5=/Volumes/A").unwrap(), CameraRoute::parse("SONY=/Volumes/B").unwrap()]
+        );
+        assert_eq!(cli.warnings.len(), 1);
+        assert!(cli.warnings[0].contains("nope"));
+
+        let config: FileConfig = toml::from_str(r#"routes = ["SONY=/Volumes/B"]"#).unwrap();
+        let mut cli = CliArgs::default();
+        config.apply_to(&mut cli);
+        assert_eq!(cli.routes[0].target_dir, "/Volumes/B");
     }
 
-    println!("\n\n传输完成!");
-    println!("  ✓ 成功: {} 个", success_count);
-    println!("  ⊘ 跳过: {} 个", skip_count);
-    println!("  ✗ 失败: {} 个", error_count);
+    #[test]
+    fn test_run_import_serial_and_parallel() {
+        let src = TempDir::new().unwrap();
+        for i in 0..8 {
+            fs::write(src.path().join(format!("IMG_{:04}.JPG", i)), format!("content {}", i)).unwrap();
+        }
 
-    if error_count > 0 { 1 } else { 0 }
+        for jobs in [1, 3] {
+            let dst = TempDir::new().unwrap();
+            let data = TempDir::new().unwrap();
+            let cli = CliArgs {
+                source_dir: src.path().to_string_lossy().to_string(),
+                target_dir: dst.path().to_string_lossy().to_string(),
+                template: String::new(),
+                jobs,
+                data_dir: Some(data.path().to_path_buf()),
+                ..Default::default()
+            };
+            assert_eq!(cli_context(&cli, Arc::default()).jobs, jobs);
+            let mut imported = HashSet::new();
+            let outcome = run_import(&cli, &Arc::default(), &mut imported).unwrap();
+            assert_eq!((outcome.photo_count, outcome.error_count), (8, 0), "jobs = {}", jobs);
+            assert_eq!(imported.len(), 8);
+            assert_eq!(fs::read_dir(dst.path()).unwrap().count(), 8);
+            assert_eq!(fs::read_to_string(dst.path().join("IMG_0007.JPG")).unwrap(), "content 7");
+        }
+    }
 }
@@ -1,22 +1,72 @@
-use crate::classify::{get_preset_templates, ClassifyConfig, SUPPORTED_EXTENSIONS};
-use crate::exif::check_exiftool;
-use crate::history::{TransferHistory, TransferRecord};
-use crate::rename::{get_rename_templates as get_rename_presets, RenameConfig};
-use crate::thumbnail::{extract_thumbnails, ThumbnailInfo};
-use crate::transfer::{scan_photos, ScanResult, TransferResult};
-use crate::transfer_v2::{transfer_photos_v2, TransferContext};
+use crate::archive::OutputMode;
+use crate::classify::{
+    get_preset_templates, validate_template, ClassifyConfig, FallbackStrategy, OrientationLabels, RatingLabels,
+    TemplateValidation, SUPPORTED_EXTENSIONS,
+};
+use crate::exif::{check_exiftool, is_exiftool_missing};
+use crate::history::{HistoryQuery, HistorySettings, HistoryStats, RecordKind, TransferHistory, TransferRecord, TransferredFile};
+use crate::logging::emit_logged;
+use crate::preview::RawPreviewMode;
+use crate::rename::{get_rename_templates as get_rename_presets, validate_rename_template as validate_rename, CounterMode, ExtCase, NameCase, RenameConfig};
+use crate::report::{group_by_folder, sort_by_total_size, write_photos_ndjson, ClassificationPreview, ScanReport, ScanSummary};
+use crate::settings::AppSettings;
+use crate::thumbnail::{extract_thumbnails, ThumbnailInfo, THUMBNAIL_SIZE};
+use crate::transfer::{
+    check_dirs_overlap, enrich_photo, enumerate_sources, join_source_dirs, quick_scan_dir,
+    scan_iter, scan_sources, CameraRoute, ClassifyDebug, ConflictPolicy, DuplicateEstimate,
+    PhotoInfo, QuickScanResult, RenameValidation, ScanOptions, ScanResult, ScanSortKey,
+    TransferPlan, TransferProgress, TransferResult,
+};
+use crate::transfer_v2::{execute_plan, failed_photos, plan_transfer, transfer_photos_v2, TransferContext};
+use crate::volume::{volume_info, VolumeInfo};
 use serde::Serialize;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::{Arc, Mutex};
-use tauri::{AppHandle, State};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use tauri::{AppHandle, Manager, State};
 
 /// 应用状态
+///
+/// 依赖扫描结果的命令：start_transfer、get_transfer_plan、count_duplicates、preview_classification、reclassify、export_scan_report、get_thumbnails。
+/// 扫描结果在重新扫描或 reset_scan 之前一直保留；set_classify_config 会按新模板重新计算其中的目标文件夹，
+/// 其他配置（重命名、去重）在传输时读取，修改后无需重新扫描
 pub struct AppState {
     pub scan_result: Mutex<Option<ScanResult>>,
     pub config: Mutex<ClassifyConfig>,
     pub rename_config: Mutex<RenameConfig>,
     pub cancel_flag: Arc<AtomicBool>,
-    pub source_dir: Mutex<String>,
+    pub source_dirs: Mutex<Vec<String>>,
+    /// 扫描序号，每次扫描递增，用于停止过期的后台 EXIF 读取
+    pub scan_generation: AtomicU64,
+    /// get_transfer_plan 生成的传输计划，start_transfer 可按其执行；重新扫描或 reset_scan 时清除
+    pub transfer_plan: Mutex<Option<TransferPlan>>,
+    /// 去重时额外参考的目录（如主归档），只读取不写入
+    pub reference_dirs: Mutex<Vec<String>>,
+    /// 传输照片时一并复制同名附属文件（.xmp/.aae/.thm）
+    pub move_sidecars: AtomicBool,
+    /// 去重时同时比较 JPEG/PNG 的像素数据
+    pub pixel_dedup: AtomicBool,
+    /// 去重时参考的哈希清单文件
+    pub known_hashes: Mutex<Option<String>>,
+    /// 复制完成后校验目标文件与源文件是否一致
+    pub verify: AtomicBool,
+    /// 每个文件复制完成后刷新到磁盘
+    pub fsync: AtomicBool,
+    /// 复制为单独的文件或打包为 ZIP
+    pub output_mode: Mutex<OutputMode>,
+    /// 扫描时的文件大小范围（最小、最大字节数），范围外的文件被跳过
+    pub scan_size_range: Mutex<(Option<u64>, Option<u64>)>,
+    /// 扫描时排除空文件和无法读取的文件
+    pub skip_empty: AtomicBool,
+    /// 上次传输的目标文件夹，随配置一起保存
+    pub last_target_dir: Mutex<Option<String>>,
+    /// 是否有传输（包括重试和原地重命名）正在进行，同一时间只允许一个，见 TransferGuard
+    pub is_transferring: AtomicBool,
+    /// RAW 预览的导出方式和预览文件夹
+    pub raw_preview: Mutex<(RawPreviewMode, Option<String>)>,
+    /// 按相机分配目标文件夹的规则
+    pub camera_routes: Mutex<Vec<CameraRoute>>,
+    /// 进行中的传输最近一次发送的进度，没有传输时为 None，见 get_transfer_status
+    pub transfer_status: Arc<Mutex<Option<TransferProgress>>>,
 }
 
 impl Default for AppState {
@@ -26,21 +76,96 @@ impl Default for AppState {
             config: Mutex::new(ClassifyConfig::default()),
             rename_config: Mutex::new(RenameConfig::default()),
             cancel_flag: Arc::new(AtomicBool::new(false)),
-            source_dir: Mutex::new(String::new()),
+            source_dirs: Mutex::new(Vec::new()),
+            scan_generation: AtomicU64::new(0),
+            transfer_plan: Mutex::new(None),
+            reference_dirs: Mutex::new(Vec::new()),
+            move_sidecars: AtomicBool::new(false),
+            pixel_dedup: AtomicBool::new(false),
+            known_hashes: Mutex::new(None),
+            verify: AtomicBool::new(false),
+            fsync: AtomicBool::new(false),
+            output_mode: Mutex::new(OutputMode::default()),
+            scan_size_range: Mutex::new((None, None)),
+            skip_empty: AtomicBool::new(false),
+            last_target_dir: Mutex::new(None),
+            is_transferring: AtomicBool::new(false),
+            raw_preview: Mutex::new((RawPreviewMode::default(), None)),
+            camera_routes: Mutex::new(Vec::new()),
+            transfer_status: Arc::new(Mutex::new(None)),
         }
     }
 }
 
+impl AppState {
+    /// 使用上次保存的配置（分类、重命名、源/目标文件夹）创建状态，配置文件不存在或损坏时使用默认值
+    pub fn load() -> Self {
+        let settings = AppSettings::load();
+        Self {
+            config: Mutex::new(settings.classify_config),
+            rename_config: Mutex::new(settings.rename_config),
+            source_dirs: Mutex::new(settings.last_source_dirs),
+            last_target_dir: Mutex::new(settings.last_target_dir),
+            ..Self::default()
+        }
+    }
+
+    /// 当前配置，用于保存和返回给前端
+    fn settings(&self) -> AppSettings {
+        AppSettings {
+            classify_config: lock_or_recover(&self.config).clone(),
+            rename_config: lock_or_recover(&self.rename_config).clone(),
+            last_source_dirs: lock_or_recover(&self.source_dirs).clone(),
+            last_target_dir: lock_or_recover(&self.last_target_dir).clone(),
+        }
+    }
+
+    /// 保存当前配置，失败时只记录日志，不影响当前操作
+    fn save_settings(&self) {
+        if let Err(e) = self.settings().save() {
+            log::warn!("{}", e);
+        }
+    }
+}
+
+/// 正在传输的标志：acquire 时检查并设置，已有传输在进行时返回错误；
+/// 离开作用域时清除，传输出错、被取消或 panic 都不会让标志一直保持
+struct TransferGuard<'a>(&'a AtomicBool);
+
+impl<'a> TransferGuard<'a> {
+    fn acquire(flag: &'a AtomicBool) -> Result<Self, String> {
+        flag.compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire)
+            .map(|_| Self(flag))
+            .map_err(|_| "传输已在进行中".to_string())
+    }
+}
+
+impl Drop for TransferGuard<'_> {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::Release);
+    }
+}
+
+/// 传输前的准备工作（生成计划、估算重复数）开始时重置取消标志；
+/// 传输进行中时不重置（否则会让正在进行的传输无法取消），返回错误
+fn reset_cancel_for_preparation(state: &AppState) -> Result<(), String> {
+    if state.is_transferring.load(Ordering::Acquire) {
+        return Err("传输已在进行中".to_string());
+    }
+    state.cancel_flag.store(false, Ordering::Relaxed);
+    Ok(())
+}
+
+/// 获取状态锁；其他线程持锁时 panic 导致锁中毒也继续使用其中的数据。
+/// AppState 中都是普通数据，panic 不会破坏其一致性，不应让整个会话因此不可用
+fn lock_or_recover<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
 /// 检查系统环境
 #[tauri::command]
 pub fn check_environment() -> Result<EnvironmentInfo, String> {
-    let exiftool_version = check_exiftool().ok();
-    
-    Ok(EnvironmentInfo {
-        exiftool_installed: exiftool_version.is_some(),
-        exiftool_version,
-        supported_formats: SUPPORTED_EXTENSIONS.iter().map(|s| s.to_string()).collect(),
-    })
+    Ok(EnvironmentInfo::new(check_exiftool().ok()))
 }
 
 #[derive(Debug, Serialize)]
@@ -48,6 +173,27 @@ pub struct EnvironmentInfo {
     pub exiftool_installed: bool,
     pub exiftool_version: Option<String>,
     pub supported_formats: Vec<String>,
+    /// 读取 EXIF 的方式："exiftool"，或 ExifTool 不可用时的 "filesystem"（只使用文件名、大小和修改时间）
+    pub exif_backend: String,
+    /// 是否编译了 heif 功能，可用 libheif 解码 HEIC/HEIF 缩略图
+    pub heif_supported: bool,
+    /// 可用的 CPU 核数，界面据此给出并行任务数的默认值
+    pub cpu_count: usize,
+}
+
+impl EnvironmentInfo {
+    /// 根据 ExifTool 版本（未安装时为 None）和编译时的功能生成环境信息
+    pub fn new(exiftool_version: Option<String>) -> Self {
+        let exif_backend = if exiftool_version.is_some() { "exiftool" } else { "filesystem" };
+        Self {
+            exiftool_installed: exiftool_version.is_some(),
+            exiftool_version,
+            supported_formats: SUPPORTED_EXTENSIONS.iter().map(|s| s.to_string()).collect(),
+            exif_backend: exif_backend.to_string(),
+            heif_supported: cfg!(feature = "heif"),
+            cpu_count: std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1),
+        }
+    }
 }
 
 /// 获取预设分类模板
@@ -69,85 +215,493 @@ pub struct TemplateInfo {
 }
 
 /// 设置分类配置
+/// 模板包含未知变量或没有任何变量时拒绝保存；preserve_tree 为 None 时保持原设置
 #[tauri::command]
 pub fn set_classify_config(
     state: State<AppState>,
     template: String,
     fallback_folder: String,
+    orientation_labels: Option<OrientationLabels>,
+    rating_labels: Option<RatingLabels>,
+    fallback_strategy: Option<FallbackStrategy>,
+    preserve_tree: Option<bool>,
 ) -> Result<(), String> {
-    let mut config = state.config.lock().map_err(|e| e.to_string())?;
-    config.template = template;
-    config.fallback_folder = fallback_folder;
+    let validation = validate_template(&template);
+    if !validation.valid {
+        return Err(format!("无效的分类模板: {}", validation.warnings.join("; ")));
+    }
+
+    let config = {
+        let mut config = lock_or_recover(&state.config);
+        config.template = template;
+        config.fallback_folder = fallback_folder;
+        if let Some(labels) = orientation_labels {
+            config.orientation_labels = labels;
+        }
+        if let Some(labels) = rating_labels {
+            config.rating_labels = labels;
+        }
+        if let Some(strategy) = fallback_strategy {
+            config.fallback_strategy = strategy;
+        }
+        if let Some(preserve_tree) = preserve_tree {
+            config.preserve_tree = preserve_tree;
+        }
+        config.clone()
+    };
+
+    // 已保存的扫描结果按新模板重新分类，无需重新扫描
+    if let Some(scan) = lock_or_recover(&state.scan_result).as_mut() {
+        scan.reclassify(&config);
+    }
+    state.save_settings();
     Ok(())
 }
 
+/// 获取上次保存的配置（分类、重命名、源/目标文件夹），用于启动时恢复界面
+#[tauri::command]
+pub fn get_app_settings(state: State<AppState>) -> Result<AppSettings, String> {
+    Ok(state.settings())
+}
+
 /// 获取当前分类配置
 #[tauri::command]
 pub fn get_classify_config(state: State<AppState>) -> Result<ClassifyConfig, String> {
-    let config = state.config.lock().map_err(|e| e.to_string())?;
+    let config = lock_or_recover(&state.config);
     Ok(config.clone())
 }
 
-/// 扫描源文件夹
+/// 扫描源文件夹时共用的选项：文件大小范围和 skip_empty 取自状态，prefer_raw 和 follow_links 默认为 false
+fn scan_options(state: &AppState, prefer_raw: Option<bool>, follow_links: Option<bool>) -> ScanOptions {
+    let (min_size, max_size) = *lock_or_recover(&state.scan_size_range);
+    ScanOptions {
+        prefer_raw: prefer_raw.unwrap_or(false),
+        follow_links: follow_links.unwrap_or(false),
+        min_size,
+        max_size,
+        skip_empty: state.skip_empty.load(Ordering::Relaxed),
+        ..Default::default()
+    }
+}
+
+/// 扫描一个或多个源文件夹，结果合并为一个扫描结果
+/// 先快速返回文件列表，EXIF 信息在后台读取，通过 photo-metadata 事件逐个更新，
+/// 全部完成后发送 scan-metadata-complete 事件。
+/// 指定 min_rating 时需要先（并行）读取 EXIF 才能按星级筛选，扫描完成后才返回。
+/// follow_links 为 true 时跟随符号链接扫描，默认不跟随
 #[tauri::command]
 pub fn scan_source_folder(
+    app_handle: AppHandle,
     state: State<AppState>,
-    source_dir: String,
+    source_dirs: Vec<String>,
+    prefer_raw: Option<bool>,
+    check_integrity: Option<bool>,
+    min_rating: Option<u8>,
+    follow_links: Option<bool>,
 ) -> Result<ScanResult, String> {
-    let config = state.config.lock().map_err(|e| e.to_string())?;
-    let result = scan_photos(&source_dir, &config)?;
+    let config = lock_or_recover(&state.config).clone();
+    let options = ScanOptions {
+        check_integrity: check_integrity.unwrap_or(false),
+        min_rating,
+        exif_jobs: crate::cli::default_jobs(),
+        ..scan_options(&state, prefer_raw, follow_links)
+    };
+    let enriched = options.min_rating.is_some();
+    let result = if enriched {
+        scan_sources(&source_dirs, &config, &options)?
+    } else {
+        enumerate_sources(&source_dirs, &config, &options)?
+    };
     
     // 保存扫描结果和源目录
-    let mut scan_result = state.scan_result.lock().map_err(|e| e.to_string())?;
+    let mut scan_result = lock_or_recover(&state.scan_result);
     *scan_result = Some(result.clone());
+    let generation = state.scan_generation.fetch_add(1, Ordering::SeqCst) + 1;
+    drop(scan_result);
     
-    let mut src = state.source_dir.lock().map_err(|e| e.to_string())?;
-    *src = source_dir;
+    let mut src = lock_or_recover(&state.source_dirs);
+    *src = source_dirs;
+    drop(src);
+    *lock_or_recover(&state.transfer_plan) = None;
+    state.save_settings();
+    
+    if enriched {
+        emit_logged(&app_handle, "scan-metadata-complete", result.photos.len());
+    } else {
+        let photos = result.photos.clone();
+        std::thread::spawn(move || enrich_scan_result(app_handle, generation, photos, config));
+    }
     
     Ok(result)
 }
 
-/// 开始传输
+/// 快速统计源文件夹中的照片数量和总大小（不读取 EXIF），
+/// 用于扫描前估算规模。大文件夹统计过程中发送 quick-scan-progress 事件
 #[tauri::command]
-pub async fn start_transfer(
-    app_handle: AppHandle,
-    state: State<'_, AppState>,
-    target_dir: String,
-    skip_duplicates: bool,
-) -> Result<TransferResult, String> {
-    // 重置取消标志
-    state.cancel_flag.store(false, Ordering::Relaxed);
-    
-    let scan_result = state.scan_result.lock().map_err(|e| e.to_string())?;
-    let photos = scan_result
-        .as_ref()
-        .ok_or("请先扫描源文件夹")?
-        .photos
-        .clone();
-    drop(scan_result);
-    
-    let config = state.config.lock().map_err(|e| e.to_string())?;
-    let template = config.template.clone();
-    drop(config);
+pub fn quick_scan(app_handle: AppHandle, source_dir: String) -> Result<QuickScanResult, String> {
+    quick_scan_dir(&source_dir, |progress| emit_logged(&app_handle, "quick-scan-progress", progress))
+}
+
+/// 单张照片的 EXIF 补全事件
+#[derive(Debug, Clone, Serialize)]
+pub struct PhotoMetadataEvent {
+    pub index: usize,
+    pub photo: PhotoInfo,
+}
+
+/// 连续多少张照片因 ExifTool 不可用而读取失败时发送 exiftool-missing 事件
+const EXIFTOOL_MISSING_THRESHOLD: usize = 3;
+
+/// 统计后台 EXIF 读取中 ExifTool 不可用的次数，决定何时提示安装
+///
+/// 只在连续失败达到阈值时提示一次，避免偶发错误或逐张照片重复提示
+struct ExifToolMissingTracker {
+    threshold: usize,
+    consecutive: usize,
+    notified: bool,
+}
+
+impl ExifToolMissingTracker {
+    /// 照片少于阈值时，全部失败即提示
+    fn new(photo_count: usize) -> Self {
+        Self {
+            threshold: photo_count.clamp(1, EXIFTOOL_MISSING_THRESHOLD),
+            consecutive: 0,
+            notified: false,
+        }
+    }
+
+    /// 记录一张照片的读取结果，返回是否应发送事件
+    fn record(&mut self, exif_error: Option<&str>) -> bool {
+        if exif_error.is_some_and(is_exiftool_missing) {
+            self.consecutive += 1;
+        } else {
+            self.consecutive = 0;
+        }
+        if !self.notified && self.consecutive >= self.threshold {
+            self.notified = true;
+            return true;
+        }
+        false
+    }
+}
+
+/// 清除已保存的扫描结果、源目录和传输计划，并停止后台 EXIF 读取
+#[tauri::command]
+pub fn reset_scan(state: State<AppState>) -> Result<(), String> {
+    state.scan_generation.fetch_add(1, Ordering::SeqCst);
+    *lock_or_recover(&state.scan_result) = None;
+    lock_or_recover(&state.source_dirs).clear();
+    *lock_or_recover(&state.transfer_plan) = None;
+    Ok(())
+}
+
+/// 后台逐个读取 EXIF，更新已保存的扫描结果并发送事件；开始新的扫描或 reset_scan 后停止
+///
+/// ExifTool 不可用时照片仍按文件系统信息分类，同时发送 exiftool-missing 事件提示安装
+fn enrich_scan_result(app_handle: AppHandle, generation: u64, mut photos: Vec<PhotoInfo>, config: ClassifyConfig) {
+    let state = app_handle.state::<AppState>();
+    let is_current = || state.scan_generation.load(Ordering::SeqCst) == generation;
+    let mut missing_tracker = ExifToolMissingTracker::new(photos.len());
     
-    let rename_config = state.rename_config.lock().map_err(|e| e.to_string())?;
-    let rename = rename_config.clone();
-    drop(rename_config);
+    for (index, photo) in photos.iter_mut().enumerate() {
+        if !is_current() {
+            return;
+        }
+        let exif_error = enrich_photo(photo, &config);
+        if missing_tracker.record(exif_error.as_deref()) {
+            emit_logged(&app_handle, "exiftool-missing", exif_error.unwrap_or_default());
+        }
+        
+        let mut scan_result = lock_or_recover(&state.scan_result);
+        if !is_current() {
+            return;
+        }
+        // 读取期间分类模板可能已修改
+        photo.reclassify(&lock_or_recover(&state.config));
+        if let Some(stored) = scan_result.as_mut().and_then(|r| r.photos.get_mut(index)) {
+            if stored.path == photo.path {
+                *stored = photo.clone();
+            }
+        }
+        drop(scan_result);
+        
+        emit_logged(&app_handle, "photo-metadata", PhotoMetadataEvent {
+            index,
+            photo: photo.clone(),
+        });
+    }
     
-    let source_dir = state.source_dir.lock().map_err(|e| e.to_string())?;
-    let src = source_dir.clone();
-    drop(source_dir);
+    emit_logged(&app_handle, "scan-metadata-complete", photos.len());
+}
+
+/// 按当前配置创建传输上下文
+fn transfer_context(
+    state: &AppState,
+    target_dir: &str,
+    force_rescan: Option<bool>,
+    conflict_policy: Option<ConflictPolicy>,
+) -> TransferContext {
+    let template = lock_or_recover(&state.config).template.clone();
+    let rename = lock_or_recover(&state.rename_config).clone();
+    let src = join_source_dirs(&lock_or_recover(&state.source_dirs));
     
     let mut ctx = TransferContext::new(
-        app_handle,
         state.cancel_flag.clone(),
         &src,
-        &target_dir,
+        target_dir,
         &template,
     );
     ctx.rename_config = rename;
+    ctx.force_rescan = force_rescan.unwrap_or(false);
+    ctx.conflict_policy = conflict_policy.unwrap_or_default();
+    ctx.reference_dirs = lock_or_recover(&state.reference_dirs).clone();
+    ctx.move_sidecars = state.move_sidecars.load(Ordering::Relaxed);
+    ctx.pixel_dedup = state.pixel_dedup.load(Ordering::Relaxed);
+    ctx.known_hashes = lock_or_recover(&state.known_hashes).clone();
+    ctx.verify = state.verify.load(Ordering::Relaxed);
+    ctx.fsync = state.fsync.load(Ordering::Relaxed);
+    ctx.output_mode = *lock_or_recover(&state.output_mode);
+    (ctx.raw_preview, ctx.preview_dir) = lock_or_recover(&state.raw_preview).clone();
+    ctx.camera_routes = lock_or_recover(&state.camera_routes).clone();
+    ctx.progress = state.transfer_status.clone();
+    ctx
+}
+
+/// 获取路径所在磁盘卷的容量和剩余空间，路径不存在时使用最近的已存在上级目录
+#[tauri::command]
+pub fn get_volume_info(path: String) -> Result<VolumeInfo, String> {
+    volume_info(&path)
+}
+
+/// 设置传输照片时是否一并复制同名附属文件（.xmp/.aae/.thm）
+#[tauri::command]
+pub fn set_move_sidecars(state: State<AppState>, enabled: bool) -> Result<(), String> {
+    state.move_sidecars.store(enabled, Ordering::Relaxed);
+    Ok(())
+}
+
+/// 设置去重时是否同时比较 JPEG/PNG 解码后的像素数据，可发现只修改了元数据的重复照片，但需要解码每张照片
+#[tauri::command]
+pub fn set_pixel_dedup(state: State<AppState>, enabled: bool) -> Result<(), String> {
+    state.pixel_dedup.store(enabled, Ordering::Relaxed);
+    Ok(())
+}
+
+/// 设置扫描时的文件大小范围（字节），None 表示不限制；下次扫描时生效
+#[tauri::command]
+pub fn set_scan_size_range(state: State<AppState>, min_size: Option<u64>, max_size: Option<u64>) -> Result<(), String> {
+    if let (Some(min), Some(max)) = (min_size, max_size) {
+        if min > max {
+            return Err("最小文件大小不能大于最大文件大小".to_string());
+        }
+    }
+    *lock_or_recover(&state.scan_size_range) = (min_size, max_size);
+    Ok(())
+}
+
+/// 设置扫描时是否排除空文件（0 字节）和无法读取的文件，排除的文件列在 ScanResult::skipped_unreadable 中；下次扫描时生效
+#[tauri::command]
+pub fn set_skip_empty(state: State<AppState>, enabled: bool) -> Result<(), String> {
+    state.skip_empty.store(enabled, Ordering::Relaxed);
+    Ok(())
+}
+
+/// 设置去重时参考的哈希清单文件（每行 `<哈希>  <路径>`，与 sha256sum 输出相同），None 表示不使用。
+/// 清单中的内容视为已归档，不需要访问清单中列出的文件
+#[tauri::command]
+pub fn set_known_hashes(state: State<AppState>, path: Option<String>) -> Result<(), String> {
+    *lock_or_recover(&state.known_hashes) = path;
+    // 已有的传输计划没有参考新的清单
+    *lock_or_recover(&state.transfer_plan) = None;
+    Ok(())
+}
+
+/// 设置复制完成后是否校验目标文件（重新读取源文件和目标文件比较哈希，校验阶段发送 Verifying 进度）
+#[tauri::command]
+pub fn set_verify(state: State<AppState>, enabled: bool) -> Result<(), String> {
+    state.verify.store(enabled, Ordering::Relaxed);
+    Ok(())
+}
+
+/// 设置每个文件复制完成后是否刷新到磁盘（fsync），传输完成后可以立即拔出存储设备，但复制较慢
+#[tauri::command]
+pub fn set_fsync(state: State<AppState>, enabled: bool) -> Result<(), String> {
+    state.fsync.store(enabled, Ordering::Relaxed);
+    Ok(())
+}
+
+/// 设置传输的输出方式：复制为单独的文件，或每个第一级目标文件夹打包为一个不压缩的 ZIP
+#[tauri::command]
+pub fn set_output_mode(state: State<AppState>, mode: OutputMode) -> Result<(), String> {
+    *lock_or_recover(&state.output_mode) = mode;
+    Ok(())
+}
+
+/// 设置 RAW 预览导出：为每个 RAW 导出内嵌的全尺寸 JPEG 预览到预览文件夹（保持目标文件夹的结构），
+/// 同时复制或不再复制 RAW。preview_dir 为空时使用目标文件夹旁的 `<目标文件夹名>_JPEG`
+#[tauri::command]
+pub fn set_raw_preview(state: State<AppState>, mode: RawPreviewMode, preview_dir: Option<String>) -> Result<(), String> {
+    *lock_or_recover(&state.raw_preview) = (mode, preview_dir.filter(|dir| !dir.trim().is_empty()));
+    Ok(())
+}
+
+/// 设置按相机分配目标文件夹的规则，每条为 `相机=目标文件夹`（相机为型号、品牌或品牌加型号），
+/// 空行被忽略。匹配的照片传输到规则的目标文件夹，其余照片传输到默认的目标文件夹；有无效的规则时不修改
+#[tauri::command]
+pub fn set_camera_routes(state: State<AppState>, routes: Vec<String>) -> Result<(), String> {
+    let routes = routes
+        .iter()
+        .filter(|route| !route.trim().is_empty())
+        .map(|route| CameraRoute::parse(route))
+        .collect::<Result<Vec<_>, _>>()?;
+    *lock_or_recover(&state.camera_routes) = routes;
+    // 已有的传输计划按旧的分配规则生成
+    *lock_or_recover(&state.transfer_plan) = None;
+    Ok(())
+}
+
+/// 设置去重参考目录：其中已有的文件在传输时同样视为重复而跳过，但不会向其中写入。
+/// 与目标目录一起在 get_transfer_plan 和 start_transfer 启用去重时扫描
+#[tauri::command]
+pub fn set_reference_dirs(state: State<AppState>, reference_dirs: Vec<String>) -> Result<(), String> {
+    *lock_or_recover(&state.reference_dirs) = reference_dirs;
+    // 已有的传输计划没有参考新的目录
+    *lock_or_recover(&state.transfer_plan) = None;
+    Ok(())
+}
+
+/// 已保存的扫描结果中的照片
+fn scanned_photos(state: &AppState) -> Result<Vec<PhotoInfo>, String> {
+    let scan_result = lock_or_recover(&state.scan_result);
+    Ok(scan_result
+        .as_ref()
+        .ok_or("请先扫描源文件夹")?
+        .photos
+        .clone())
+}
+
+/// 生成传输计划：确定每个文件的最终路径（含冲突序号）和去重结果，不复制任何文件。
+/// 去重同时检查目标目录已有文件和源文件夹内部的重复，结果同步标记到扫描结果中。
+/// 计划会保存下来，start_transfer 传入 use_plan 时按计划执行
+#[tauri::command]
+pub async fn get_transfer_plan(
+    app_handle: AppHandle,
+    state: State<'_, AppState>,
+    target_dir: String,
+    skip_duplicates: bool,
+    conflict_policy: Option<ConflictPolicy>,
+) -> Result<TransferPlan, String> {
+    reset_cancel_for_preparation(&state)?;
+    let photos = scanned_photos(&state)?;
+    let ctx = transfer_context(&state, &target_dir, None, conflict_policy);
+    let source_dirs = lock_or_recover(&state.source_dirs).clone();
+    check_dirs_overlap(&source_dirs, &target_dir)?;
+    for route in &ctx.camera_routes {
+        check_dirs_overlap(&source_dirs, &route.target_dir)?;
+    }
+
+    let mut errors = Vec::new();
+    let plan = plan_transfer(&ctx, &app_handle, &photos, &target_dir, skip_duplicates, &mut errors).ok_or("已取消")?;
+    for e in errors {
+        log::warn!("{}", e);
+    }
+    if let Some(scan) = lock_or_recover(&state.scan_result).as_mut() {
+        scan.mark_duplicates(&plan);
+    }
+    *lock_or_recover(&state.transfer_plan) = Some(plan.clone());
+    Ok(plan)
+}
+
+/// 传输前估算重复照片数：用目标目录（按相机分配时为各目标根目录，及参考目录）中已有的文件检查扫描结果中的每张照片，
+/// 使用去重索引缓存，不复制文件，也不保存传输计划
+#[tauri::command]
+pub async fn count_duplicates(
+    app_handle: AppHandle,
+    state: State<'_, AppState>,
+    target_dir: String,
+) -> Result<DuplicateEstimate, String> {
+    reset_cancel_for_preparation(&state)?;
+    let photos = scanned_photos(&state)?;
+    let ctx = transfer_context(&state, &target_dir, None, None);
+
+    // 与 get_transfer_plan 相同：按相机分配目标文件夹时，每个目标根目录使用各自的去重索引
+    let mut errors = Vec::new();
+    let plan = plan_transfer(&ctx, &app_handle, &photos, &target_dir, true, &mut errors).ok_or("已取消")?;
+    for e in errors {
+        log::warn!("{}", e);
+    }
+    Ok(plan.duplicate_estimate())
+}
+
+/// 开始传输；use_plan 为 true 时按 get_transfer_plan 保存的计划执行，否则传输时再决定路径和去重
+#[tauri::command]
+pub async fn start_transfer(
+    app_handle: AppHandle,
+    state: State<'_, AppState>,
+    target_dir: String,
+    skip_duplicates: bool,
+    force_rescan: Option<bool>,
+    conflict_policy: Option<ConflictPolicy>,
+    use_plan: Option<bool>,
+) -> Result<TransferResult, String> {
+    // 先占用传输标志，再重置取消标志，不影响正在进行的传输
+    let _guard = TransferGuard::acquire(&state.is_transferring)?;
+    state.cancel_flag.store(false, Ordering::Relaxed);
+    *lock_or_recover(&state.last_target_dir) = Some(target_dir.clone());
+    state.save_settings();
+    
+    let ctx = transfer_context(&state, &target_dir, force_rescan, conflict_policy);
     
-    transfer_photos_v2(&ctx, &photos, &target_dir, skip_duplicates)
+    if use_plan.unwrap_or(false) {
+        let plan = {
+            let mut stored = lock_or_recover(&state.transfer_plan);
+            if stored.as_ref().is_some_and(|plan| plan.target_dir != target_dir) {
+                return Err("传输计划的目标文件夹与当前不一致，请重新生成传输计划".to_string());
+            }
+            stored.take().ok_or("请先生成传输计划")?
+        };
+        return execute_plan(&ctx, &app_handle, &plan);
+    }
+    
+    let photos = scanned_photos(&state)?;
+    transfer_photos_v2(&ctx, &app_handle, &photos, &target_dir, skip_duplicates)
+}
+
+/// 只重试最近一次传输中失败的文件：重新读取这些文件的元数据，按原记录的目标文件夹和分类模板传输，
+/// 生成一条关联到原记录（retry_of）的新记录。源文件已不存在的文件被跳过，说明放在 warnings 中
+#[tauri::command]
+pub async fn retry_failed(app_handle: AppHandle, state: State<'_, AppState>) -> Result<TransferResult, String> {
+    let _guard = TransferGuard::acquire(&state.is_transferring)?;
+    state.cancel_flag.store(false, Ordering::Relaxed);
+    let history = TransferHistory::load();
+    let record = history
+        .records
+        .iter()
+        .find(|record| record.kind == RecordKind::Transfer)
+        .ok_or("没有传输记录")?;
+    if record.failed_files().is_empty() {
+        return Err("最近一次传输没有失败的文件".to_string());
+    }
+
+    let mut config = lock_or_recover(&state.config).clone();
+    config.template = record.template.clone();
+    let (photos, notes) = failed_photos(record, &config);
+
+    let mut ctx = transfer_context(&state, &record.target_dir, None, None);
+    ctx.source_dir = record.source_dir.clone();
+    ctx.template = record.template.clone();
+    ctx.retry_of = Some(record.id.clone());
+    let mut result = transfer_photos_v2(&ctx, &app_handle, &photos, &record.target_dir, true)?;
+    result.warnings.splice(0..0, notes);
+    Ok(result)
+}
+
+/// 进行中的传输的最新进度（与最近一次 transfer-progress 事件相同），没有进行中的传输时为 None。
+/// 用于漏掉事件或窗口重新打开后重新同步进度
+#[tauri::command]
+pub fn get_transfer_status(state: State<AppState>) -> Result<Option<TransferProgress>, String> {
+    Ok(lock_or_recover(&state.transfer_status).clone())
 }
 
 /// 取消传输
@@ -157,44 +711,127 @@ pub fn cancel_transfer(state: State<AppState>) -> Result<(), String> {
     Ok(())
 }
 
-/// 预览分类结果（不实际传输）
+/// 按当前配置查看单个文件的元数据、目标文件夹和新文件名，用于排查分类问题
+#[tauri::command]
+pub fn debug_classify(state: State<AppState>, path: String) -> Result<ClassifyDebug, String> {
+    let config = lock_or_recover(&state.config).clone();
+    let rename_config = lock_or_recover(&state.rename_config).clone();
+    crate::transfer::debug_classify(&path, &config, &rename_config)
+}
+
+/// 预览分类结果（不实际传输），默认按文件夹名称排序，sort_by_size 为 true 时按总大小从大到小排序
 #[tauri::command]
-pub fn preview_classification(state: State<AppState>) -> Result<Vec<ClassificationPreview>, String> {
-    let scan_result = state.scan_result.lock().map_err(|e| e.to_string())?;
+pub fn preview_classification(
+    state: State<AppState>,
+    sort_by_size: Option<bool>,
+) -> Result<Vec<ClassificationPreview>, String> {
+    let scan_result = lock_or_recover(&state.scan_result);
     
     let photos = scan_result
         .as_ref()
         .ok_or("请先扫描源文件夹")?;
     
     // 按目标文件夹分组
-    let mut groups: std::collections::HashMap<String, Vec<String>> = std::collections::HashMap::new();
-    
-    for photo in &photos.photos {
-        groups
-            .entry(photo.target_folder.clone())
-            .or_insert_with(Vec::new)
-            .push(photo.file_name.clone());
+    let mut previews = group_by_folder(&photos.photos);
+    if sort_by_size.unwrap_or(false) {
+        sort_by_total_size(&mut previews);
     }
-    
-    let mut previews: Vec<ClassificationPreview> = groups
-        .into_iter()
-        .map(|(folder, files)| ClassificationPreview {
-            folder,
-            file_count: files.len(),
-            files,
-        })
-        .collect();
-    
-    previews.sort_by(|a, b| a.folder.cmp(&b.folder));
-    
     Ok(previews)
 }
 
-#[derive(Debug, Serialize)]
-pub struct ClassificationPreview {
-    pub folder: String,
-    pub file_count: usize,
-    pub files: Vec<String>,
+/// 按相机和拍摄月份统计已扫描的照片（只使用已读取的元数据），用于导入前检查存储卡内容
+#[tauri::command]
+pub fn get_scan_summary(state: State<AppState>) -> Result<ScanSummary, String> {
+    let scan_result = lock_or_recover(&state.scan_result);
+    Ok(scan_result.as_ref().ok_or("请先扫描源文件夹")?.summary())
+}
+
+/// 重新排列内存中的扫描结果并返回，不重新扫描；排序键相同的照片按路径排列，顺序稳定
+#[tauri::command]
+pub fn sort_scan(state: State<AppState>, key: ScanSortKey, ascending: Option<bool>) -> Result<ScanResult, String> {
+    let mut scan_result = lock_or_recover(&state.scan_result);
+    let scan = scan_result.as_mut().ok_or("请先扫描源文件夹")?;
+    scan.sort_photos_by(key, ascending.unwrap_or(true));
+    Ok(scan.clone())
+}
+
+/// 按当前重命名配置对全部扫描结果试运行重命名（只读），返回每个文件的新文件名、
+/// 同一文件夹中新文件名相同的文件和模板生成空文件名的文件，用于在传输前发现有问题的模板
+#[tauri::command]
+pub fn validate_rename_over_scan(state: State<AppState>) -> Result<RenameValidation, String> {
+    let rename_config = lock_or_recover(&state.rename_config).clone();
+    let scan_result = lock_or_recover(&state.scan_result);
+    let scan = scan_result.as_ref().ok_or("请先扫描源文件夹")?;
+    Ok(crate::transfer::validate_rename_over_scan(&scan.photos, &rename_config))
+}
+
+/// 在已整理好的目标文件夹中按重命名模板原地重命名照片（不移动到其他文件夹），
+/// rename_config 为空时使用当前重命名配置；结果写入一条重命名类型的历史记录
+#[tauri::command]
+pub async fn rename_in_place(
+    state: State<'_, AppState>,
+    target_dir: String,
+    rename_config: Option<RenameConfig>,
+) -> Result<TransferResult, String> {
+    let _guard = TransferGuard::acquire(&state.is_transferring)?;
+    state.cancel_flag.store(false, Ordering::Relaxed);
+    let rename_config = rename_config.unwrap_or_else(|| lock_or_recover(&state.rename_config).clone());
+    let cancel_flag = state.cancel_flag.clone();
+    crate::transfer_v2::rename_in_place(
+        std::path::Path::new(&target_dir),
+        &rename_config,
+        &TransferHistory::get_history_file_path(),
+        || cancel_flag.load(Ordering::Relaxed),
+    )
+}
+
+/// 按当前分类配置重新计算已扫描照片的目标文件夹（不重新扫描和读取 EXIF）
+#[tauri::command]
+pub fn reclassify(state: State<AppState>) -> Result<ScanResult, String> {
+    let config = lock_or_recover(&state.config).clone();
+    let mut scan_result = lock_or_recover(&state.scan_result);
+    let scan = scan_result
+        .as_mut()
+        .ok_or("请先扫描源文件夹")?;
+
+    scan.reclassify(&config);
+    Ok(scan.clone())
+}
+
+/// 导出扫描报告（JSON），用于传输前的规划和归档
+#[tauri::command]
+pub fn export_scan_report(state: State<AppState>, path: String) -> Result<(), String> {
+    let scan_result = lock_or_recover(&state.scan_result);
+    let scan = scan_result
+        .as_ref()
+        .ok_or("请先扫描源文件夹")?;
+
+    let source_dirs = join_source_dirs(&lock_or_recover(&state.source_dirs));
+    ScanReport::from_scan(&source_dirs, scan).write_to(std::path::Path::new(&path))
+}
+
+/// 重新扫描当前源文件夹，边扫描边把每张照片写入 NDJSON 文件（每行一个 JSON，最后一行为按文件夹的统计），
+/// 不在内存中保留全部照片，适合照片数量很多的图库；返回导出的照片数。
+/// 与 scan_source_folder 使用相同的文件大小范围、skip_empty、prefer_raw 和 follow_links 设置
+#[tauri::command]
+pub fn export_scan_ndjson(
+    state: State<AppState>,
+    path: String,
+    prefer_raw: Option<bool>,
+    follow_links: Option<bool>,
+) -> Result<usize, String> {
+    let source_dirs = lock_or_recover(&state.source_dirs).clone();
+    if source_dirs.is_empty() {
+        return Err("请先选择源文件夹".to_string());
+    }
+    let config = lock_or_recover(&state.config).clone();
+    let options = scan_options(&state, prefer_raw, follow_links);
+    let photos = source_dirs
+        .iter()
+        .map(|dir| scan_iter(dir, &config, &options))
+        .collect::<Result<Vec<_>, String>>()?;
+    let summary = write_photos_ndjson(photos.into_iter().flatten(), std::path::Path::new(&path))?;
+    Ok(summary.total_files)
 }
 
 // ==================== 重命名相关命令 ====================
@@ -217,7 +854,7 @@ pub struct RenameTemplateInfo {
     pub template: String,
 }
 
-/// 设置重命名配置
+/// 设置重命名配置；计数器位数超出 1-12 时按边界保存，并返回提示
 #[tauri::command]
 pub fn set_rename_config(
     state: State<AppState>,
@@ -225,12 +862,35 @@ pub fn set_rename_config(
     template: String,
     counter_start: u32,
     counter_digits: u32,
-) -> Result<(), String> {
-    let mut config = state.rename_config.lock().map_err(|e| e.to_string())?;
+    extension_case: Option<ExtCase>,
+    pair_by_basename: Option<bool>,
+) -> Result<Option<String>, String> {
+    let mut config = lock_or_recover(&state.rename_config);
     config.enabled = enabled;
     config.template = template;
     config.counter_start = counter_start;
     config.counter_digits = counter_digits;
+    let warning = config.normalize_counter_digits();
+    config.extension_case = extension_case.unwrap_or_default();
+    config.pair_by_basename = pair_by_basename.unwrap_or(false);
+    drop(config);
+    state.save_settings();
+    Ok(warning)
+}
+
+/// 设置 {counter} 起始值的确定方式，如接着目标文件夹中已有的照片继续编号
+#[tauri::command]
+pub fn set_counter_mode(state: State<AppState>, counter_mode: CounterMode) -> Result<(), String> {
+    lock_or_recover(&state.rename_config).counter_mode = counter_mode;
+    state.save_settings();
+    Ok(())
+}
+
+/// 设置新文件名主干的大小写（小写、大写、首字母大写），扩展名仍按 extension_case 处理
+#[tauri::command]
+pub fn set_name_case(state: State<AppState>, case: NameCase) -> Result<(), String> {
+    lock_or_recover(&state.rename_config).case = case;
+    state.save_settings();
     Ok(())
 }
 
@@ -243,6 +903,32 @@ pub fn get_transfer_history() -> Result<Vec<TransferRecord>, String> {
     Ok(history.records)
 }
 
+/// 获取最近一次传输的文件明细（源路径、最终目标路径、状态），
+/// 用于 start_transfer 因文件过多未直接返回明细时；跳过原地重命名记录
+#[tauri::command]
+pub fn get_last_transfer_files() -> Result<Vec<TransferredFile>, String> {
+    let history = TransferHistory::load();
+    Ok(history
+        .records
+        .into_iter()
+        .find(|record| record.kind == RecordKind::Transfer)
+        .map(|record| record.files)
+        .unwrap_or_default())
+}
+
+/// 按条件搜索传输历史记录
+#[tauri::command]
+pub fn search_history(query: HistoryQuery) -> Result<Vec<TransferRecord>, String> {
+    let history = TransferHistory::load();
+    Ok(history.search(&query).into_iter().cloned().collect())
+}
+
+/// 获取全部历史记录的汇总统计
+#[tauri::command]
+pub fn get_history_stats() -> Result<HistoryStats, String> {
+    Ok(TransferHistory::load().statistics())
+}
+
 /// 清空历史记录
 #[tauri::command]
 pub fn clear_transfer_history() -> Result<(), String> {
@@ -259,19 +945,37 @@ pub fn delete_history_record(id: String) -> Result<(), String> {
     history.save()
 }
 
+/// 获取历史记录设置
+#[tauri::command]
+pub fn get_history_settings() -> Result<HistorySettings, String> {
+    Ok(TransferHistory::load().settings())
+}
+
+/// 设置历史记录保留条数（None 或 0 表示不限制）
+#[tauri::command]
+pub fn set_history_settings(max_records: Option<usize>) -> Result<(), String> {
+    let mut history = TransferHistory::load();
+    history.set_max_records(max_records);
+    history.save()
+}
+
 // ==================== 缩略图相关命令 ====================
 
-/// 获取照片缩略图
+/// 获取照片缩略图，size 为最长边像素（默认 160）
 #[tauri::command]
-pub fn get_thumbnails(state: State<AppState>, max_count: usize) -> Result<Vec<ThumbnailInfo>, String> {
-    let scan_result = state.scan_result.lock().map_err(|e| e.to_string())?;
+pub fn get_thumbnails(
+    state: State<AppState>,
+    max_count: usize,
+    size: Option<u32>,
+) -> Result<Vec<ThumbnailInfo>, String> {
+    let scan_result = lock_or_recover(&state.scan_result);
     
     let photos = scan_result
         .as_ref()
         .ok_or("请先扫描源文件夹")?;
     
     let paths: Vec<String> = photos.photos.iter().map(|p| p.path.clone()).collect();
-    Ok(extract_thumbnails(&paths, max_count))
+    Ok(extract_thumbnails(&paths, max_count, size.unwrap_or(THUMBNAIL_SIZE)))
 }
 
 // ==================== 模板验证命令 ====================
@@ -279,41 +983,120 @@ pub fn get_thumbnails(state: State<AppState>, max_count: usize) -> Result<Vec<Th
 /// 验证自定义模板
 #[tauri::command]
 pub fn validate_custom_template(template: String) -> Result<TemplateValidation, String> {
-    let valid_vars = vec!["{year}", "{month}", "{day}", "{camera}", "{make}"];
-    let mut warnings = Vec::new();
-    let mut example = template.clone();
-    
-    // 检查是否包含有效变量
-    let has_valid_var = valid_vars.iter().any(|v| template.contains(v));
-    if !has_valid_var {
-        warnings.push("模板中没有包含任何有效变量".to_string());
+    Ok(validate_template(&template))
+}
+
+/// 验证重命名模板
+#[tauri::command]
+pub fn validate_rename_template(template: String) -> Result<TemplateValidation, String> {
+    Ok(validate_rename(&template))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lock_or_recover_poisoned() {
+        let mutex = Arc::new(Mutex::new(ClassifyConfig::default()));
+        let poisoner = mutex.clone();
+        let _ = std::thread::spawn(move || {
+            let mut config = poisoner.lock().unwrap();
+            config.template = "{year}".to_string();
+            panic!("持锁时 panic");
+        })
+        .join();
+
+        assert!(mutex.is_poisoned());
+        let config = lock_or_recover(&mutex);
+        assert_eq!(config.template, "{year}");
     }
-    
-    // 生成示例
-    example = example.replace("{year}", "2024");
-    example = example.replace("{month}", "03");
-    example = example.replace("{day}", "15");
-    example = example.replace("{camera}", "Canon EOS R5");
-    example = example.replace("{make}", "Canon");
-    
-    // 检查未知变量
-    let re = regex::Regex::new(r"\{[^}]+\}").unwrap();
-    for cap in re.find_iter(&example) {
-        warnings.push(format!("未知变量: {}", cap.as_str()));
+
+    #[test]
+    fn test_environment_info_serializes_capabilities() {
+        let info = EnvironmentInfo::new(Some("12.76".to_string()));
+        let json = serde_json::to_value(&info).unwrap();
+        assert_eq!(json["exiftool_installed"], true);
+        assert_eq!(json["exiftool_version"], "12.76");
+        assert!(json["supported_formats"].as_array().unwrap().iter().any(|f| f == "cr3"));
+        assert_eq!(json["exif_backend"], "exiftool");
+        assert_eq!(json["heif_supported"], cfg!(feature = "heif"));
+        assert!(json["cpu_count"].as_u64().unwrap() >= 1);
+
+        let json = serde_json::to_value(EnvironmentInfo::new(None)).unwrap();
+        assert_eq!(json["exiftool_installed"], false);
+        assert_eq!(json["exif_backend"], "filesystem");
     }
-    
-    Ok(TemplateValidation {
-        valid: warnings.is_empty(),
-        example,
-        warnings,
-        supported_vars: valid_vars.iter().map(|s| s.to_string()).collect(),
-    })
-}
 
-#[derive(Debug, Serialize)]
-pub struct TemplateValidation {
-    pub valid: bool,
-    pub example: String,
-    pub warnings: Vec<String>,
-    pub supported_vars: Vec<String>,
+    #[test]
+    fn test_exiftool_missing_tracker_notifies_once_after_threshold() {
+        let missing = Some(crate::exif::EXIFTOOL_MISSING_ERROR);
+        let mut tracker = ExifToolMissingTracker::new(10);
+
+        assert!(!tracker.record(missing));
+        assert!(!tracker.record(missing));
+        assert!(tracker.record(missing));
+        // 只提示一次
+        assert!(!tracker.record(missing));
+        assert!(!tracker.record(missing));
+        assert!(!tracker.record(missing));
+    }
+
+    #[test]
+    fn test_exiftool_missing_tracker_requires_consecutive_failures() {
+        let missing = Some(crate::exif::EXIFTOOL_MISSING_ERROR);
+        let mut tracker = ExifToolMissingTracker::new(10);
+
+        assert!(!tracker.record(missing));
+        assert!(!tracker.record(missing));
+        // 读取成功或其他错误会重新计数
+        assert!(!tracker.record(None));
+        assert!(!tracker.record(missing));
+        assert!(!tracker.record(Some("执行 exiftool 失败: permission denied")));
+        assert!(!tracker.record(missing));
+        assert!(!tracker.record(missing));
+        assert!(tracker.record(missing));
+    }
+
+    #[test]
+    fn test_exiftool_missing_tracker_few_photos() {
+        let mut tracker = ExifToolMissingTracker::new(1);
+        assert!(tracker.record(Some(crate::exif::EXIFTOOL_MISSING_ERROR)));
+    }
+
+    #[test]
+    fn test_transfer_guard_rejects_second_transfer() {
+        let state = AppState::default();
+        let guard = TransferGuard::acquire(&state.is_transferring).unwrap();
+        // 传输进行中再次开始传输
+        assert_eq!(TransferGuard::acquire(&state.is_transferring).err().as_deref(), Some("传输已在进行中"));
+        assert!(state.is_transferring.load(Ordering::Acquire));
+        drop(guard);
+        assert!(!state.is_transferring.load(Ordering::Acquire));
+
+        // 传输中 panic 也会清除标志
+        let flag = Arc::new(AtomicBool::new(false));
+        let panicking = flag.clone();
+        let _ = std::thread::spawn(move || {
+            let _guard = TransferGuard::acquire(&panicking).unwrap();
+            panic!("传输中 panic");
+        })
+        .join();
+        assert!(!flag.load(Ordering::Acquire));
+        assert!(TransferGuard::acquire(&flag).is_ok());
+    }
+
+    #[test]
+    fn test_reset_cancel_for_preparation_keeps_running_transfer_cancelled() {
+        let state = AppState::default();
+        state.cancel_flag.store(true, Ordering::Relaxed);
+        assert!(reset_cancel_for_preparation(&state).is_ok());
+        assert!(!state.cancel_flag.load(Ordering::Relaxed));
+
+        // 传输进行中并已请求取消时，生成计划不会清除取消请求
+        let _guard = TransferGuard::acquire(&state.is_transferring).unwrap();
+        state.cancel_flag.store(true, Ordering::Relaxed);
+        assert_eq!(reset_cancel_for_preparation(&state).err().as_deref(), Some("传输已在进行中"));
+        assert!(state.cancel_flag.load(Ordering::Relaxed));
+    }
 }
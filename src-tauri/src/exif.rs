@@ -1,5 +1,24 @@
 use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
 use std::process::Command;
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+/// ExifTool 不可用时 read_exif / check_exiftool 返回的错误信息
+pub const EXIFTOOL_MISSING_ERROR: &str = "ExifTool 未安装。请运行: brew install exiftool";
+
+/// 未找到 ExifTool 时，间隔多久重新探测一次，使运行期间安装的 ExifTool 无需重启即可使用
+const EXIFTOOL_RECHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// 最近一次探测 ExifTool 路径的结果
+struct ExifToolLookup {
+    path: Option<String>,
+    checked_at: Instant,
+}
+
+/// 缓存的探测结果，避免每读取一张照片都逐个尝试候选路径
+static EXIFTOOL_LOOKUP: Mutex<Option<ExifToolLookup>> = Mutex::new(None);
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct PhotoMetadata {
@@ -11,11 +30,167 @@ pub struct PhotoMetadata {
     pub model: Option<String>,
     pub make: Option<String>,
     pub mime_type: Option<String>,
+    /// 图像宽度（像素，未考虑旋转）
+    #[serde(default)]
+    pub width: Option<u32>,
+    /// 图像高度（像素，未考虑旋转）
+    #[serde(default)]
+    pub height: Option<u32>,
+    /// EXIF 方向 (1-8)，5-8 表示需要旋转 90°
+    #[serde(default)]
+    pub orientation: Option<u8>,
+    /// 星级评分 (1-5)，未评级、0 星或拒绝 (-1) 时为 None
+    #[serde(default)]
+    pub rating: Option<u8>,
+    /// XMP 颜色标签（如 Red）
+    #[serde(default)]
+    pub label: Option<String>,
+    /// 关键词（IPTC Keywords，没有时使用 XMP Subject），保持原顺序并去重
+    #[serde(default)]
+    pub keywords: Vec<String>,
+}
+
+impl PhotoMetadata {
+    /// 按方向旋转后的显示尺寸 (宽, 高)
+    pub fn display_dimensions(&self) -> (Option<u32>, Option<u32>) {
+        match self.orientation {
+            Some(5..=8) => (self.height, self.width),
+            _ => (self.width, self.height),
+        }
+    }
+
+    /// 品牌和型号合并后的相机全称，如 `Nikon Z 6`
+    pub fn camera_full(&self) -> Option<String> {
+        combine_make_model(self.make.as_deref(), self.model.as_deref())
+    }
+}
+
+/// 常见厂商的 EXIF 品牌（小写前缀）与简称
+const VENDOR_NAMES: &[(&str, &str)] = &[
+    ("nikon", "Nikon"),
+    ("canon", "Canon"),
+    ("sony", "Sony"),
+    ("fujifilm", "Fujifilm"),
+    ("olympus", "Olympus"),
+    ("om digital solutions", "OM System"),
+    ("panasonic", "Panasonic"),
+    ("pentax", "Pentax"),
+    ("ricoh", "Ricoh"),
+    ("leica", "Leica"),
+    ("hasselblad", "Hasselblad"),
+    ("sigma", "Sigma"),
+    ("samsung", "Samsung"),
+    ("apple", "Apple"),
+    ("google", "Google"),
+    ("dji", "DJI"),
+    ("gopro", "GoPro"),
+    ("eastman kodak", "Kodak"),
+];
+
+/// 品牌名称结尾的公司后缀
+const VENDOR_SUFFIXES: &[&str] = &[
+    "corporation", "corp", "company", "co", "ltd", "inc", "imaging", "gmbh", "ag",
+];
+
+/// 规范化品牌名称：常见厂商使用简称，其他厂商去掉结尾的公司后缀
+pub fn normalize_make(make: &str) -> String {
+    let make = make.trim();
+    let lower = make.to_lowercase();
+    if let Some((_, name)) = VENDOR_NAMES.iter().find(|(prefix, _)| lower.starts_with(prefix)) {
+        return name.to_string();
+    }
+
+    let mut words: Vec<&str> = make.split_whitespace().collect();
+    while words.len() > 1 {
+        let last = words[words.len() - 1]
+            .trim_matches(|c: char| c == '.' || c == ',')
+            .to_lowercase();
+        if !VENDOR_SUFFIXES.contains(&last.as_str()) {
+            break;
+        }
+        words.pop();
+    }
+    words.join(" ").trim_end_matches(',').to_string()
+}
+
+/// 去掉型号开头与品牌重复的部分（不区分大小写，只在单词边界处去掉）
+fn strip_make_prefix<'a>(model: &'a str, prefixes: &[&str]) -> &'a str {
+    for prefix in prefixes {
+        if prefix.is_empty() || model.len() < prefix.len() || !model.is_char_boundary(prefix.len()) {
+            continue;
+        }
+        let (head, rest) = model.split_at(prefix.len());
+        if head.eq_ignore_ascii_case(prefix) && (rest.is_empty() || rest.starts_with([' ', '-', '_'])) {
+            return rest.trim_start_matches([' ', '-', '_']);
+        }
+    }
+    model
+}
+
+/// 合并品牌和型号：品牌规范化为简称，型号中重复的品牌前缀去掉，
+/// 如 `NIKON CORPORATION` + `NIKON Z 6` -> `Nikon Z 6`
+pub fn combine_make_model(make: Option<&str>, model: Option<&str>) -> Option<String> {
+    let raw_make = make.map(str::trim).filter(|m| !m.is_empty());
+    let model = model.map(str::trim).filter(|m| !m.is_empty());
+    let Some(raw_make) = raw_make else {
+        return model.map(|m| m.to_string());
+    };
+
+    let make = normalize_make(raw_make);
+    let Some(model) = model else {
+        return Some(make);
+    };
+
+    let first_word = raw_make.split_whitespace().next().unwrap_or_default();
+    let model = strip_make_prefix(model, &[raw_make, make.as_str(), first_word]);
+    if model.is_empty() {
+        Some(make)
+    } else {
+        Some(format!("{} {}", make, model))
+    }
 }
 
 /// 获取 ExifTool 的可执行路径
-/// macOS 应用打包后无法直接访问 PATH 中的命令，需要尝试多个可能的路径
+///
+/// 找到后一直使用缓存的路径，直到 reset_exiftool_cache；
+/// 未找到时每隔 EXIFTOOL_RECHECK_INTERVAL 重新探测
 pub fn get_exiftool_path() -> Option<String> {
+    let mut cache = EXIFTOOL_LOOKUP.lock().unwrap_or_else(PoisonError::into_inner);
+    lookup_cached(&mut cache, Instant::now(), probe_exiftool_path)
+}
+
+/// 清除缓存的 ExifTool 路径，下次使用时重新探测（如用户刚安装或卸载了 ExifTool）
+pub fn reset_exiftool_cache() {
+    *EXIFTOOL_LOOKUP.lock().unwrap_or_else(PoisonError::into_inner) = None;
+}
+
+/// 判断错误是否由 ExifTool 不可用引起
+pub fn is_exiftool_missing(error: &str) -> bool {
+    error == EXIFTOOL_MISSING_ERROR
+}
+
+/// 按缓存规则决定是否重新探测：已找到的路径一直有效，未找到的结果在重新探测间隔内有效
+fn lookup_cached(
+    cache: &mut Option<ExifToolLookup>,
+    now: Instant,
+    probe: impl FnOnce() -> Option<String>,
+) -> Option<String> {
+    if let Some(lookup) = cache.as_ref() {
+        if lookup.path.is_some() || now.duration_since(lookup.checked_at) < EXIFTOOL_RECHECK_INTERVAL {
+            return lookup.path.clone();
+        }
+    }
+    let path = probe();
+    *cache = Some(ExifToolLookup {
+        path: path.clone(),
+        checked_at: now,
+    });
+    path
+}
+
+/// 逐个尝试可能的 ExifTool 路径
+/// macOS 应用打包后无法直接访问 PATH 中的命令，需要尝试多个可能的路径
+fn probe_exiftool_path() -> Option<String> {
     // 常见的 ExifTool 安装路径
     let possible_paths = [
         "exiftool",                           // 系统 PATH
@@ -38,57 +213,148 @@ pub fn get_exiftool_path() -> Option<String> {
     None
 }
 
+/// 生成 ExifTool 的参数列表：选项之后用 `--` 结束选项，文件名以 `-` 开头时也不会被当作选项。
+/// Windows 上明确指定文件名使用 UTF-8 编码，否则非 ASCII 路径会按系统代码页解释而找不到文件
+pub(crate) fn exiftool_args<'a>(options: &[&'a str], file_path: &'a str) -> Vec<&'a str> {
+    let mut args = options.to_vec();
+    if cfg!(windows) {
+        args.extend(["-charset", "filename=utf8"]);
+    }
+    args.extend(["--", file_path]);
+    args
+}
+
 /// 使用 ExifTool 读取照片元数据
+///
+/// 只有 ExifTool 不可用时才返回错误；ExifTool 没有返回可用数据时，
+/// 使用文件系统信息（文件名、大小）填充，EXIF 字段保持为 None
 pub fn read_exif(file_path: &str) -> Result<PhotoMetadata, String> {
-    let exiftool_path = get_exiftool_path()
-        .ok_or_else(|| "ExifTool 未安装。请运行: brew install exiftool".to_string())?;
+    let exiftool_path = get_exiftool_path().ok_or_else(|| EXIFTOOL_MISSING_ERROR.to_string())?;
 
     let output = Command::new(&exiftool_path)
-        .args(["-json", "-DateTimeOriginal", "-CreateDate", "-Model", "-Make", "-MIMEType", "-FileName", "-FileSize#", file_path])
+        .args(exiftool_args(
+            &[
+                "-json", "-DateTimeOriginal", "-CreateDate", "-Model", "-Make", "-MIMEType", "-FileName", "-FileSize#",
+                "-ImageWidth", "-ImageHeight", "-ExifImageWidth", "-ExifImageHeight",
+                "-PreviewImageWidth", "-PreviewImageHeight", "-Orientation#", "-Rating#", "-Label", "-Keywords", "-Subject",
+            ],
+            file_path,
+        ))
         .output()
-        .map_err(|e| format!("执行 exiftool 失败: {}", e))?;
-
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        return Err(format!("exiftool 返回错误: {}", stderr));
-    }
+        .map_err(|e| {
+            // 缓存的路径已失效（ExifTool 被卸载或移动），下次重新探测
+            if e.kind() == std::io::ErrorKind::NotFound {
+                reset_exiftool_cache();
+                EXIFTOOL_MISSING_ERROR.to_string()
+            } else {
+                format!("执行 exiftool 失败: {}", e)
+            }
+        })?;
 
+    // ExifTool 对无法识别的文件会返回非零状态，但仍可能输出部分字段
     let stdout = String::from_utf8_lossy(&output.stdout);
-    let json_array: Vec<serde_json::Value> = serde_json::from_str(&stdout)
-        .map_err(|e| format!("解析 exiftool 输出失败: {}", e))?;
-
-    if json_array.is_empty() {
-        return Err("未能读取到元数据".to_string());
-    }
+    Ok(parse_exiftool_output(file_path, &stdout))
+}
 
-    let json = &json_array[0];
+/// 解析 ExifTool 的 JSON 输出，缺失的文件名和大小从文件系统补齐
+fn parse_exiftool_output(file_path: &str, stdout: &str) -> PhotoMetadata {
+    let fallback = metadata_from_file(file_path);
+    let json = match serde_json::from_str::<Vec<serde_json::Value>>(stdout) {
+        Ok(mut json_array) if !json_array.is_empty() && json_array[0].is_object() => json_array.swap_remove(0),
+        _ => return fallback,
+    };
 
-    Ok(PhotoMetadata {
+    PhotoMetadata {
         file_path: file_path.to_string(),
-        file_name: json["FileName"].as_str().unwrap_or("").to_string(),
-        file_size: json["FileSize"].as_u64().unwrap_or(0),
+        file_name: json["FileName"].as_str().map(|s| s.to_string()).unwrap_or(fallback.file_name),
+        file_size: json["FileSize"].as_u64().unwrap_or(fallback.file_size),
         date_time_original: json["DateTimeOriginal"].as_str().map(|s| s.to_string()),
         create_date: json["CreateDate"].as_str().map(|s| s.to_string()),
         model: json["Model"].as_str().map(|s| s.to_string()),
         make: json["Make"].as_str().map(|s| s.to_string()),
         mime_type: json["MIMEType"].as_str().map(|s| s.to_string()),
+        // 部分 RAW 格式没有 ImageWidth，依次尝试 EXIF 尺寸和内嵌预览图尺寸
+        width: first_u32(&json, &["ImageWidth", "ExifImageWidth", "PreviewImageWidth"]),
+        height: first_u32(&json, &["ImageHeight", "ExifImageHeight", "PreviewImageHeight"]),
+        orientation: first_u32(&json, &["Orientation"]).and_then(|o| u8::try_from(o).ok()),
+        rating: first_u32(&json, &["Rating"])
+            .filter(|r| (1..=5).contains(r))
+            .and_then(|r| u8::try_from(r).ok()),
+        label: json["Label"]
+            .as_str()
+            .map(|s| s.trim())
+            .filter(|s| !s.is_empty())
+            .map(|s| s.to_string()),
+        keywords: parse_keywords(&json),
+    }
+}
+
+/// 读取关键词：ExifTool 对单个关键词输出字符串，多个时输出数组，数字关键词可能输出为数值
+fn parse_keywords(json: &serde_json::Value) -> Vec<String> {
+    let values = |key: &str| -> Vec<String> {
+        let items = match &json[key] {
+            serde_json::Value::Array(items) => items.clone(),
+            serde_json::Value::Null => Vec::new(),
+            other => vec![other.clone()],
+        };
+        items
+            .iter()
+            .filter_map(|item| match item {
+                serde_json::Value::String(s) => Some(s.trim().to_string()),
+                serde_json::Value::Number(n) => Some(n.to_string()),
+                _ => None,
+            })
+            .filter(|s| !s.is_empty())
+            .collect()
+    };
+
+    let mut keywords = values("Keywords");
+    if keywords.is_empty() {
+        keywords = values("Subject");
+    }
+    let mut seen = std::collections::HashSet::new();
+    keywords.retain(|k| seen.insert(k.clone()));
+    keywords
+}
+
+/// 取第一个存在的数值字段（兼容数字和数字字符串）
+fn first_u32(json: &serde_json::Value, keys: &[&str]) -> Option<u32> {
+    keys.iter().find_map(|key| match &json[*key] {
+        serde_json::Value::Number(n) => n.as_u64().and_then(|n| u32::try_from(n).ok()),
+        serde_json::Value::String(s) => s.trim().parse().ok(),
+        _ => None,
     })
 }
 
+/// 仅使用文件系统信息（文件名、大小）构造元数据，EXIF 字段为空
+pub fn metadata_from_file(file_path: &str) -> PhotoMetadata {
+    PhotoMetadata {
+        file_path: file_path.to_string(),
+        file_name: Path::new(file_path)
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default(),
+        file_size: fs::metadata(file_path).map(|m| m.len()).unwrap_or(0),
+        ..Default::default()
+    }
+}
+
 /// 批量读取多个文件的 EXIF 信息
 pub fn read_exif_batch(file_paths: &[String]) -> Vec<Result<PhotoMetadata, String>> {
     file_paths.iter().map(|path| read_exif(path)).collect()
 }
 
 /// 检查 ExifTool 是否已安装
+///
+/// 总是重新探测路径，用户安装 ExifTool 后再次检查即可生效
 pub fn check_exiftool() -> Result<String, String> {
-    let exiftool_path = get_exiftool_path()
-        .ok_or_else(|| "ExifTool 未安装。请运行: brew install exiftool".to_string())?;
+    reset_exiftool_cache();
+    let exiftool_path = get_exiftool_path().ok_or_else(|| EXIFTOOL_MISSING_ERROR.to_string())?;
 
     let output = Command::new(&exiftool_path)
         .arg("-ver")
         .output()
-        .map_err(|_| "ExifTool 未安装。请运行: brew install exiftool".to_string())?;
+        .map_err(|_| EXIFTOOL_MISSING_ERROR.to_string())?;
 
     if output.status.success() {
         let version = String::from_utf8_lossy(&output.stdout).trim().to_string();
@@ -124,6 +390,41 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_lookup_cached_keeps_found_path() {
+        let mut cache = None;
+        let start = Instant::now();
+        let found = lookup_cached(&mut cache, start, || Some("/usr/bin/exiftool".to_string()));
+        assert_eq!(found.as_deref(), Some("/usr/bin/exiftool"));
+
+        // 已找到的路径不再探测，即使超过重新探测间隔
+        let later = start + EXIFTOOL_RECHECK_INTERVAL * 2;
+        let cached = lookup_cached(&mut cache, later, || panic!("不应重新探测"));
+        assert_eq!(cached.as_deref(), Some("/usr/bin/exiftool"));
+    }
+
+    #[test]
+    fn test_lookup_cached_rechecks_missing_after_interval() {
+        let mut cache = None;
+        let start = Instant::now();
+        assert_eq!(lookup_cached(&mut cache, start, || None), None);
+
+        // 间隔内沿用未找到的结果
+        let soon = start + EXIFTOOL_RECHECK_INTERVAL / 2;
+        assert_eq!(lookup_cached(&mut cache, soon, || panic!("不应重新探测")), None);
+
+        // 超过间隔后重新探测，能发现新安装的 ExifTool
+        let later = start + EXIFTOOL_RECHECK_INTERVAL;
+        let found = lookup_cached(&mut cache, later, || Some("exiftool".to_string()));
+        assert_eq!(found.as_deref(), Some("exiftool"));
+    }
+
+    #[test]
+    fn test_is_exiftool_missing() {
+        assert!(is_exiftool_missing(EXIFTOOL_MISSING_ERROR));
+        assert!(!is_exiftool_missing("执行 exiftool 失败: permission denied"));
+    }
+
     // ==================== PhotoMetadata 结构测试 ====================
 
     #[test]
@@ -151,6 +452,12 @@ mod tests {
             model: Some("Canon EOS R5".to_string()),
             make: Some("Canon".to_string()),
             mime_type: Some("image/x-canon-cr3".to_string()),
+            width: None,
+            height: None,
+            orientation: None,
+            rating: None,
+            label: None,
+            keywords: Vec::new(),
         };
 
         assert_eq!(metadata.file_path, "/path/to/photo.cr3");
@@ -187,6 +494,12 @@ mod tests {
             model: Some("Test Camera".to_string()),
             make: None,
             mime_type: Some("image/jpeg".to_string()),
+            width: None,
+            height: None,
+            orientation: None,
+            rating: None,
+            label: None,
+            keywords: Vec::new(),
         };
 
         // 测试序列化
@@ -209,6 +522,28 @@ mod tests {
         assert!(result.is_err() || result.is_ok()); // 取决于 ExifTool 行为
     }
 
+    #[test]
+    fn test_exiftool_args_special_file_names() {
+        let dir = tempfile::TempDir::new().unwrap();
+        for name in ["-IMG_0001.JPG", "[trip] IMG_0002.JPG"] {
+            let path = dir.path().join(name);
+            fs::write(&path, b"not a real jpeg").unwrap();
+            let path = path.to_string_lossy().to_string();
+
+            let args = exiftool_args(&["-json"], &path);
+            assert_eq!(args[0], "-json");
+            assert_eq!(&args[args.len() - 2..], ["--", path.as_str()]);
+            assert_eq!(args.contains(&"filename=utf8"), cfg!(windows));
+
+            // 安装了 ExifTool 时，文件名被当作文件而不是选项
+            if get_exiftool_path().is_some() {
+                let metadata = read_exif(&path).unwrap();
+                assert_eq!(metadata.file_name, name);
+                assert_eq!(metadata.file_size, 15);
+            }
+        }
+    }
+
     #[test]
     #[ignore] // 需要真实照片文件才能运行
     fn test_read_exif_real_photo() {
@@ -228,6 +563,122 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_exiftool_output_full() {
+        let stdout = r#"[{"SourceFile": "/photos/IMG_0001.CR3", "FileName": "IMG_0001.CR3", "FileSize": 25000000,
+            "DateTimeOriginal": "2024:03:15 14:30:00", "Model": "Canon EOS R5", "Make": "Canon",
+            "MIMEType": "image/x-canon-cr3"}]"#;
+        let metadata = parse_exiftool_output("/photos/IMG_0001.CR3", stdout);
+        assert_eq!(metadata.file_name, "IMG_0001.CR3");
+        assert_eq!(metadata.file_size, 25_000_000);
+        assert_eq!(metadata.date_time_original.as_deref(), Some("2024:03:15 14:30:00"));
+        assert_eq!(metadata.model.as_deref(), Some("Canon EOS R5"));
+        assert!(metadata.create_date.is_none());
+    }
+
+    #[test]
+    fn test_parse_exiftool_output_falls_back_to_file_info() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("unknown.jpg");
+        std::fs::write(&path, b"12345").unwrap();
+        let path = path.to_string_lossy().to_string();
+
+        // 空数组、无法解析的输出都退回到文件系统信息
+        for stdout in ["[]", "", "not json", "[null]"] {
+            let metadata = parse_exiftool_output(&path, stdout);
+            assert_eq!(metadata.file_path, path);
+            assert_eq!(metadata.file_name, "unknown.jpg");
+            assert_eq!(metadata.file_size, 5);
+            assert!(metadata.date_time_original.is_none());
+            assert!(metadata.model.is_none());
+        }
+
+        // 只缺少部分字段时保留已读取到的 EXIF 信息
+        let metadata = parse_exiftool_output(&path, r#"[{"Model": "iPhone 15 Pro"}]"#);
+        assert_eq!(metadata.file_name, "unknown.jpg");
+        assert_eq!(metadata.file_size, 5);
+        assert_eq!(metadata.model.as_deref(), Some("iPhone 15 Pro"));
+    }
+
+    #[test]
+    fn test_metadata_from_missing_file() {
+        let metadata = metadata_from_file("/nonexistent/path/photo.jpg");
+        assert_eq!(metadata.file_name, "photo.jpg");
+        assert_eq!(metadata.file_size, 0);
+    }
+
+    #[test]
+    fn test_parse_exiftool_output_dimensions() {
+        let stdout = r#"[{"FileName": "IMG_0001.JPG", "ImageWidth": 6000, "ImageHeight": 4000, "Orientation": 6}]"#;
+        let metadata = parse_exiftool_output("/photos/IMG_0001.JPG", stdout);
+        assert_eq!(metadata.width, Some(6000));
+        assert_eq!(metadata.height, Some(4000));
+        assert_eq!(metadata.orientation, Some(6));
+        // 旋转 90° 后为竖图
+        assert_eq!(metadata.display_dimensions(), (Some(4000), Some(6000)));
+    }
+
+    #[test]
+    fn test_parse_exiftool_output_raw_preview_dimensions() {
+        // RAW 文件只有内嵌预览图的尺寸
+        let stdout = r#"[{"FileName": "IMG_0001.CR3", "PreviewImageWidth": "1620", "PreviewImageHeight": "1080"}]"#;
+        let metadata = parse_exiftool_output("/photos/IMG_0001.CR3", stdout);
+        assert_eq!(metadata.width, Some(1620));
+        assert_eq!(metadata.height, Some(1080));
+        assert_eq!(metadata.orientation, None);
+        assert_eq!(metadata.display_dimensions(), (Some(1620), Some(1080)));
+    }
+
+    #[test]
+    fn test_parse_exiftool_output_rating_and_label() {
+        let stdout = r#"[{"FileName": "IMG_0001.JPG", "Rating": 5, "Label": "Red"}]"#;
+        let metadata = parse_exiftool_output("/photos/IMG_0001.JPG", stdout);
+        assert_eq!(metadata.rating, Some(5));
+        assert_eq!(metadata.label.as_deref(), Some("Red"));
+
+        // 0 星、拒绝 (-1)、超出范围和空标签都视为未评级/无标签
+        for stdout in [
+            r#"[{"Rating": 0, "Label": ""}]"#,
+            r#"[{"Rating": -1, "Label": "  "}]"#,
+            r#"[{"Rating": 6}]"#,
+            r#"[{"FileName": "IMG_0002.JPG"}]"#,
+        ] {
+            let metadata = parse_exiftool_output("/photos/IMG_0002.JPG", stdout);
+            assert_eq!(metadata.rating, None, "{}", stdout);
+            assert_eq!(metadata.label, None, "{}", stdout);
+        }
+    }
+
+    #[test]
+    fn test_parse_exiftool_output_keywords() {
+        let keywords = |stdout: &str| parse_exiftool_output("/photos/IMG_0001.JPG", stdout).keywords;
+
+        // 没有关键词
+        assert!(keywords(r#"[{"FileName": "IMG_0001.JPG"}]"#).is_empty());
+        assert!(keywords(r#"[{"Keywords": [], "Subject": ""}]"#).is_empty());
+        // 单个关键词输出为字符串，数字关键词可能输出为数值
+        assert_eq!(keywords(r#"[{"Keywords": "旅行"}]"#), ["旅行"]);
+        assert_eq!(keywords(r#"[{"Keywords": 2024}]"#), ["2024"]);
+        // 多个关键词输出为数组，去掉空白和重复项
+        assert_eq!(
+            keywords(r#"[{"Keywords": ["旅行", " 家人 ", "", "旅行", 7]}]"#),
+            ["旅行", "家人", "7"]
+        );
+        // 没有 IPTC Keywords 时使用 XMP Subject
+        assert_eq!(keywords(r#"[{"Subject": ["Wildlife", "Birds"]}]"#), ["Wildlife", "Birds"]);
+        assert_eq!(keywords(r#"[{"Keywords": "Travel", "Subject": ["Other"]}]"#), ["Travel"]);
+    }
+
+    #[test]
+    fn test_photo_metadata_deserialize_without_dimensions() {
+        // 旧版本序列化的数据没有尺寸字段
+        let json = r#"{"file_path": "/test.jpg", "file_name": "test.jpg", "file_size": 1000,
+            "date_time_original": null, "create_date": null, "model": null, "make": null, "mime_type": null}"#;
+        let metadata: PhotoMetadata = serde_json::from_str(json).unwrap();
+        assert!(metadata.width.is_none());
+        assert!(metadata.orientation.is_none());
+    }
+
     // ==================== 边界情况测试 ====================
 
     #[test]
@@ -241,6 +692,12 @@ mod tests {
             model: Some("".to_string()),
             make: None,
             mime_type: None,
+            width: None,
+            height: None,
+            orientation: None,
+            rating: None,
+            label: None,
+            keywords: Vec::new(),
         };
 
         // 空字符串应该被正确处理
@@ -272,4 +729,22 @@ mod tests {
         };
         assert_eq!(metadata.file_size, 100_000_000_000);
     }
+
+    #[test]
+    fn test_combine_make_model() {
+        let combine = |make: Option<&str>, model: Option<&str>| combine_make_model(make, model);
+        assert_eq!(combine(Some("NIKON CORPORATION"), Some("NIKON Z 6")).as_deref(), Some("Nikon Z 6"));
+        assert_eq!(combine(Some("Canon"), Some("Canon EOS R5")).as_deref(), Some("Canon EOS R5"));
+        assert_eq!(combine(Some("SONY"), Some("ILCE-7M3")).as_deref(), Some("Sony ILCE-7M3"));
+        assert_eq!(combine(Some("FUJIFILM"), Some("X-T4")).as_deref(), Some("Fujifilm X-T4"));
+        assert_eq!(combine(Some("OLYMPUS IMAGING CORP."), Some("E-M1")).as_deref(), Some("Olympus E-M1"));
+        // 品牌只在单词边界处去掉
+        assert_eq!(combine(Some("Sigma"), Some("SigmaFP")).as_deref(), Some("Sigma SigmaFP"));
+        // 未知厂商去掉公司后缀
+        assert_eq!(combine(Some("Acme Camera Co., Ltd."), Some("ACME X1")).as_deref(), Some("Acme Camera X1"));
+        assert_eq!(combine(Some("Apple"), Some("Apple")).as_deref(), Some("Apple"));
+        assert_eq!(combine(None, Some("EOS R5")).as_deref(), Some("EOS R5"));
+        assert_eq!(combine(Some("Canon"), None).as_deref(), Some("Canon"));
+        assert_eq!(combine(Some(" "), None), None);
+    }
 }
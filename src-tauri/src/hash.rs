@@ -1,8 +1,11 @@
+use crate::classify::to_nfc;
+use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
-use std::collections::HashMap;
-use std::fs::File;
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
 use std::io::{BufReader, Read};
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 /// 计算文件的 SHA-256 哈希值
 pub fn calculate_hash(file_path: &str) -> Result<String, String> {
@@ -59,31 +62,208 @@ pub fn calculate_quick_hash(file_path: &str, sample_size: usize) -> Result<Strin
     Ok(hex::encode(result))
 }
 
+/// 可以解码像素数据进行像素去重的格式
+const PIXEL_HASH_EXTENSIONS: &[&str] = &["jpg", "jpeg", "png"];
+
+/// 文件是否可以计算像素哈希（RAW 等其他格式只按文件内容去重）
+pub fn is_pixel_hashable(file_path: &str) -> bool {
+    Path::new(file_path)
+        .extension()
+        .map(|ext| PIXEL_HASH_EXTENSIONS.contains(&ext.to_string_lossy().to_lowercase().as_str()))
+        .unwrap_or(false)
+}
+
+/// 像素哈希：解码图像后只对尺寸、颜色类型和像素数据计算 SHA-256，
+/// 修改 EXIF 等元数据后重新保存（像素未重新编码）的文件哈希相同。
+///
+/// 需要完整解码图像，比读取文件计算哈希慢得多（2400 万像素的 JPEG 约需 0.1-0.3 秒），
+/// 解码时占用约 宽×高×3 字节内存
+pub fn calculate_pixel_hash(file_path: &str) -> Result<String, String> {
+    let image = image::open(file_path).map_err(|e| format!("解码图像失败: {}", e))?;
+    let mut hasher = Sha256::new();
+    hasher.update(image.width().to_le_bytes());
+    hasher.update(image.height().to_le_bytes());
+    hasher.update(format!("{:?}", image.color()).as_bytes());
+    hasher.update(image.as_bytes());
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// 快速哈希默认的采样大小（头尾各 64KB）
+pub const DEFAULT_QUICK_HASH_SAMPLE: usize = 64 * 1024;
+
+/// 去重器使用的文件哈希算法
+///
+/// 快速哈希只用于预筛选，完整哈希相同才认为文件重复
+pub trait FileHasher: Send + Sync {
+    /// 计算文件的完整哈希
+    fn full_hash(&self, file_path: &str) -> Result<String, String>;
+    /// 只读取文件头尾各 sample_size 字节计算快速哈希
+    fn quick_hash(&self, file_path: &str, sample_size: usize) -> Result<String, String>;
+    /// 算法标识，用于判断缓存的哈希能否与本算法的结果比较
+    fn id(&self) -> &'static str;
+}
+
+/// 基于 SHA-256 读取文件内容的哈希算法（默认）
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Sha256Hasher;
+
+impl FileHasher for Sha256Hasher {
+    fn full_hash(&self, file_path: &str) -> Result<String, String> {
+        calculate_hash(file_path)
+    }
+
+    fn quick_hash(&self, file_path: &str, sample_size: usize) -> Result<String, String> {
+        calculate_quick_hash(file_path, sample_size)
+    }
+
+    fn id(&self) -> &'static str {
+        "sha256"
+    }
+}
+
 /// 文件去重器
+///
+/// 只有大小相同的文件才可能重复：大小唯一的文件先只记录路径，
+/// 出现同样大小的文件时才计算哈希
 pub struct Deduplicator {
+    /// 快速哈希读取文件头尾各多少字节。
+    /// 采样越大，大小相同但内容不同的文件（如同一相机的 RAW）越不容易快速哈希相同，
+    /// 可减少不必要的完整哈希；但每次快速哈希读取的数据也越多，对小文件是浪费
+    quick_hash_sample: usize,
+    /// 计算快速哈希和完整哈希的算法
+    hasher: Box<dyn FileHasher>,
     /// 已知文件的哈希 -> 文件路径
     hash_map: HashMap<String, String>,
     /// 使用快速哈希进行预筛选
     quick_hash_map: HashMap<String, Vec<String>>,
+    /// 已知文件的大小
+    known_sizes: HashSet<u64>,
+    /// 文件大小 -> 尚未计算哈希的已知文件
+    unhashed: HashMap<u64, Vec<String>>,
+    /// 同时按像素数据去重（见 calculate_pixel_hash）
+    pixel_dedup: bool,
+    /// 像素哈希 -> 文件路径
+    pixel_map: HashMap<String, String>,
+    /// 尚未计算像素哈希的已知文件，第一次按像素检查时统一计算
+    pixel_pending: Vec<String>,
+    /// 已从哈希清单加载已知哈希。清单中的文件没有大小和快速哈希，
+    /// 需要对每个待检查文件计算完整哈希
+    has_manifest: bool,
 }
 
 impl Deduplicator {
     pub fn new() -> Self {
+        Self::with_quick_hash_sample(DEFAULT_QUICK_HASH_SAMPLE)
+    }
+
+    /// 使用指定的快速哈希采样大小（字节）创建去重器
+    pub fn with_quick_hash_sample(quick_hash_sample: usize) -> Self {
         Self {
+            quick_hash_sample,
+            hasher: Box::new(Sha256Hasher),
             hash_map: HashMap::new(),
             quick_hash_map: HashMap::new(),
+            known_sizes: HashSet::new(),
+            unhashed: HashMap::new(),
+            pixel_dedup: false,
+            pixel_map: HashMap::new(),
+            pixel_pending: Vec::new(),
+            has_manifest: false,
         }
     }
 
+    /// 启用像素去重：JPEG/PNG 除了比较文件内容，还比较解码后的像素数据，
+    /// 可以发现只修改了元数据的重复照片。已知文件需要逐个解码，较慢；
+    /// 无法解码的文件和 RAW 仍按文件内容去重
+    pub fn with_pixel_dedup(mut self, enabled: bool) -> Self {
+        self.pixel_dedup = enabled;
+        self
+    }
+
+    /// 使用指定的哈希算法计算快速哈希和完整哈希（默认 SHA-256）。
+    /// 哈希清单中记录的是 SHA-256，只能与 SHA-256 哈希比较；
+    /// 去重索引缓存中的文件在其他算法下只按大小加入，需要时再重新计算
+    pub fn with_hasher(mut self, hasher: impl FileHasher + 'static) -> Self {
+        self.hasher = Box::new(hasher);
+        self
+    }
+
+    /// 是否启用像素去重
+    pub fn pixel_dedup(&self) -> bool {
+        self.pixel_dedup
+    }
+
     /// 检查文件是否重复
-    /// 返回 Some(原文件路径) 如果是重复的，None 如果是新文件
-    pub fn check_duplicate(&mut self, file_path: &str, _file_size: u64) -> Result<Option<String>, String> {
+    /// 返回 Some(原文件路径) 如果是重复的，None 如果是新文件。
+    /// file_size 为扫描时的大小，没有相同大小的已知文件时不读取文件内容
+    pub fn check_duplicate(&mut self, file_path: &str, file_size: u64) -> Result<Option<String>, String> {
+        if self.pixel_dedup && is_pixel_hashable(file_path) {
+            if let Some(original) = self.check_pixel_duplicate(file_path) {
+                return Ok(Some(original));
+            }
+        }
+        self.check_content_duplicate(file_path, file_size)
+    }
+
+    /// 按像素哈希检查重复，像素相同时返回原文件路径；无法解码时返回 None，只按文件内容比较
+    fn check_pixel_duplicate(&mut self, file_path: &str) -> Option<String> {
+        for path in std::mem::take(&mut self.pixel_pending) {
+            match calculate_pixel_hash(&path) {
+                Ok(hash) => {
+                    self.pixel_map.entry(hash).or_insert(path);
+                }
+                Err(e) => log::debug!("计算像素哈希失败 {}: {}", path, e),
+            }
+        }
+
+        match calculate_pixel_hash(file_path) {
+            Ok(hash) => match self.pixel_map.get(&hash) {
+                Some(original) => Some(original.clone()),
+                None => {
+                    self.pixel_map.insert(hash, file_path.to_string());
+                    None
+                }
+            },
+            Err(e) => {
+                log::debug!("计算像素哈希失败，只按文件内容去重 {}: {}", file_path, e);
+                None
+            }
+        }
+    }
+
+    /// 按文件内容检查重复
+    fn check_content_duplicate(&mut self, file_path: &str, file_size: u64) -> Result<Option<String>, String> {
+        // 有哈希清单时无法按大小预筛选，先用完整哈希与清单比对
+        let mut manifest_hash = None;
+        if self.has_manifest {
+            let full_hash = self.hasher.full_hash(file_path)?;
+            if let Some(original_path) = self.hash_map.get(&full_hash) {
+                return Ok(Some(original_path.clone()));
+            }
+            manifest_hash = Some(full_hash);
+        }
+
+        // 第零步：按大小预筛选
+        if self.known_sizes.insert(file_size) {
+            match manifest_hash {
+                Some(full_hash) => {
+                    self.hash_map.insert(full_hash, file_path.to_string());
+                }
+                None => self.unhashed.entry(file_size).or_default().push(file_path.to_string()),
+            }
+            return Ok(None);
+        }
+        self.hash_unhashed(file_size);
+
         // 第一步：快速哈希预筛选
-        let quick_hash = calculate_quick_hash(file_path, 64 * 1024)?; // 64KB 样本
+        let quick_hash = self.hasher.quick_hash(file_path, self.quick_hash_sample)?;
 
         if let Some(_candidates) = self.quick_hash_map.get(&quick_hash) {
             // 有潜在重复，进行完整哈希比对
-            let full_hash = calculate_hash(file_path)?;
+            let full_hash = match manifest_hash {
+                Some(full_hash) => full_hash,
+                None => self.hasher.full_hash(file_path)?,
+            };
             
             if let Some(original_path) = self.hash_map.get(&full_hash) {
                 return Ok(Some(original_path.clone()));
@@ -99,43 +279,292 @@ impl Deduplicator {
                 .push(file_path.to_string());
             
             // 计算并存储完整哈希
-            let full_hash = calculate_hash(file_path)?;
+            let full_hash = match manifest_hash {
+                Some(full_hash) => full_hash,
+                None => self.hasher.full_hash(file_path)?,
+            };
             self.hash_map.insert(full_hash, file_path.to_string());
         }
 
         Ok(None)
     }
 
+    /// 为指定大小的已知文件补算哈希；此时无法读取的文件不再参与比较
+    fn hash_unhashed(&mut self, size: u64) {
+        for path in self.unhashed.remove(&size).unwrap_or_default() {
+            let hashes = self.hasher.quick_hash(&path, self.quick_hash_sample)
+                .and_then(|quick_hash| Ok((quick_hash, self.hasher.full_hash(&path)?)));
+            match hashes {
+                Ok((quick_hash, full_hash)) => self.insert_hashes(&path, quick_hash, full_hash),
+                Err(e) => log::debug!("计算哈希失败 {}: {}", path, e),
+            }
+        }
+    }
+
     /// 添加已知文件（用于加载目标目录中已有的文件）
     pub fn add_known_file(&mut self, file_path: &str) -> Result<(), String> {
-        let quick_hash = calculate_quick_hash(file_path, 64 * 1024)?;
-        let full_hash = calculate_hash(file_path)?;
-        
+        let size = fs::metadata(file_path)
+            .map_err(|e| format!("无法读取文件元数据: {}", e))?
+            .len();
+        let quick_hash = self.hasher.quick_hash(file_path, self.quick_hash_sample)?;
+        let full_hash = self.hasher.full_hash(file_path)?;
+        self.add_known_hashes(file_path, size, quick_hash, full_hash);
+        Ok(())
+    }
+
+    /// 当前哈希算法的标识
+    pub fn hasher_id(&self) -> &'static str {
+        self.hasher.id()
+    }
+
+    /// 快速哈希的采样大小
+    pub fn quick_hash_sample(&self) -> usize {
+        self.quick_hash_sample
+    }
+
+    /// 只按大小添加已知文件，检查到同样大小的文件时再按本去重器的采样大小计算哈希
+    fn add_known_unhashed(&mut self, file_path: &str, size: u64) {
+        self.known_sizes.insert(size);
+        self.unhashed.entry(size).or_default().push(file_path.to_string());
+        self.add_pixel_pending(file_path);
+    }
+
+    /// 使用已计算好的哈希添加已知文件（用于从去重索引缓存加载）
+    pub fn add_known_hashes(&mut self, file_path: &str, size: u64, quick_hash: String, full_hash: String) {
+        self.known_sizes.insert(size);
+        self.insert_hashes(file_path, quick_hash, full_hash);
+        self.add_pixel_pending(file_path);
+    }
+
+    /// 从哈希清单加载已知哈希，不读取清单中列出的文件，返回加载的条目数。
+    /// 清单格式与 sha256sum 相同，每行 `<哈希>  <路径>`（二进制模式的 `<哈希> *<路径>` 也可）；
+    /// 空行和 # 开头的注释行被忽略，格式不正确的行记录警告后跳过
+    pub fn load_hash_manifest(&mut self, manifest_path: &Path) -> Result<usize, String> {
+        let content = fs::read_to_string(manifest_path)
+            .map_err(|e| format!("读取哈希清单失败: {}", e))?;
+
+        let mut loaded = 0;
+        for (line_no, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match parse_manifest_line(line) {
+                Some((hash, path)) => {
+                    self.hash_map.entry(hash).or_insert(path);
+                    loaded += 1;
+                }
+                None => log::warn!(
+                    "哈希清单第 {} 行格式不正确，已跳过 {}",
+                    line_no + 1,
+                    manifest_path.display()
+                ),
+            }
+        }
+        self.has_manifest |= loaded > 0;
+        Ok(loaded)
+    }
+
+    /// 启用像素去重时记录已知文件，等到需要时再解码
+    fn add_pixel_pending(&mut self, file_path: &str) {
+        if self.pixel_dedup && is_pixel_hashable(file_path) {
+            self.pixel_pending.push(file_path.to_string());
+        }
+    }
+
+    fn insert_hashes(&mut self, file_path: &str, quick_hash: String, full_hash: String) {
         self.quick_hash_map
             .entry(quick_hash)
             .or_insert_with(Vec::new)
             .push(file_path.to_string());
         self.hash_map.insert(full_hash, file_path.to_string());
-        
-        Ok(())
     }
 
     /// 获取已记录的文件数量
     pub fn len(&self) -> usize {
-        self.hash_map.len()
+        self.hash_map.len() + self.unhashed.values().map(Vec::len).sum::<usize>()
     }
 
     pub fn is_empty(&self) -> bool {
-        self.hash_map.is_empty()
+        self.len() == 0
     }
 }
 
+/// 解析哈希清单中的一行，返回（小写的 SHA-256 哈希，路径）
+fn parse_manifest_line(line: &str) -> Option<(String, String)> {
+    let (hash, rest) = line.split_once(char::is_whitespace)?;
+    if hash.len() != 64 || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let path = rest.trim_start();
+    let path = path.strip_prefix('*').unwrap_or(path);
+    if path.is_empty() {
+        return None;
+    }
+    Some((hash.to_ascii_lowercase(), path.to_string()))
+}
+
 impl Default for Deduplicator {
     fn default() -> Self {
         Self::new()
     }
 }
 
+/// 去重索引中记录的单个目标文件
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexedFile {
+    pub size: u64,
+    /// 修改时间（毫秒时间戳）
+    pub modified_millis: u64,
+    pub quick_hash: String,
+    pub full_hash: String,
+    /// 磁盘上的实际路径，与索引中的键（NFC 形式）不同时记录
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub disk_path: Option<String>,
+}
+
+/// 去重索引中记录的单个目标文件夹：修改时间未变时，其中的文件和子文件夹视为未变
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexedDir {
+    pub modified: SystemTime,
+    /// 子文件夹（磁盘上的实际路径）
+    pub subdirs: Vec<String>,
+}
+
+/// 目标目录的去重索引缓存
+///
+/// 按目标目录持久化已有文件的哈希，重复导入时只需检查文件大小和修改时间，
+/// 只有新增或变化的文件才重新计算哈希
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DedupIndex {
+    pub target_dir: String,
+    /// 文件路径（NFC 形式，同一目录在 macOS 和 Windows/Linux 上访问时得到相同的键）-> 索引信息
+    pub files: HashMap<String, IndexedFile>,
+    /// 文件夹路径（NFC 形式）-> 索引时的修改时间和子文件夹
+    #[serde(default)]
+    pub dirs: HashMap<String, IndexedDir>,
+    /// 上次完整遍历开始的时间，用于判断文件夹修改时间是否可信
+    #[serde(default)]
+    pub indexed_at: Option<SystemTime>,
+}
+
+impl DedupIndex {
+    pub fn new(target_dir: &str) -> Self {
+        Self {
+            target_dir: target_dir.to_string(),
+            files: HashMap::new(),
+            dirs: HashMap::new(),
+            indexed_at: None,
+        }
+    }
+
+    /// 默认的索引缓存目录（配置目录下的 photo-truck/dedup-index）
+    pub fn default_cache_dir() -> PathBuf {
+        dirs::config_dir()
+            .unwrap_or_else(|| PathBuf::from("."))
+            .join("photo-truck")
+            .join("dedup-index")
+    }
+
+    /// 获取目标目录对应的索引缓存文件路径
+    pub fn cache_path(target_dir: &str) -> PathBuf {
+        Self::cache_path_in(&Self::default_cache_dir(), target_dir)
+    }
+
+    /// 获取目标目录在指定缓存目录中的索引缓存文件路径
+    pub fn cache_path_in(cache_dir: &Path, target_dir: &str) -> PathBuf {
+        fs::create_dir_all(cache_dir).ok();
+        let key = hex::encode(Sha256::digest(target_dir.as_bytes()));
+        cache_dir.join(format!("{}.json", &key[..16]))
+    }
+
+    /// 加载目标目录的索引缓存，不存在或损坏时返回空索引
+    pub fn load(target_dir: &str) -> Self {
+        Self::load_from(&Self::cache_path(target_dir), target_dir)
+    }
+
+    pub fn load_from(path: &Path, target_dir: &str) -> Self {
+        fs::read_to_string(path)
+            .ok()
+            .and_then(|content| serde_json::from_str::<Self>(&content).ok())
+            .filter(|index| index.target_dir == target_dir)
+            .unwrap_or_else(|| Self::new(target_dir))
+    }
+
+    /// 保存索引缓存
+    pub fn save(&self) -> Result<(), String> {
+        self.save_to(&Self::cache_path(&self.target_dir))
+    }
+
+    pub fn save_to(&self, path: &Path) -> Result<(), String> {
+        let content = serde_json::to_string(self)
+            .map_err(|e| format!("序列化失败: {}", e))?;
+        fs::write(path, content)
+            .map_err(|e| format!("保存失败: {}", e))
+    }
+
+    /// 索引中是否已有缓存数据
+    pub fn is_warm(&self) -> bool {
+        !self.files.is_empty()
+    }
+
+    /// 更新单个文件的索引：大小和修改时间未变时沿用缓存的哈希，
+    /// 否则重新计算。返回是否重新计算了哈希
+    pub fn update_file(&mut self, file_path: &str) -> Result<bool, String> {
+        let metadata = fs::metadata(file_path)
+            .map_err(|e| format!("无法读取文件元数据: {}", e))?;
+        let size = metadata.len();
+        let modified_millis = metadata
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map(|duration| duration.as_millis() as u64)
+            .unwrap_or(0);
+
+        let key = to_nfc(file_path);
+        let disk_path = (key != file_path).then(|| file_path.to_string());
+        if let Some(cached) = self.files.get_mut(&key) {
+            if cached.size == size && cached.modified_millis == modified_millis {
+                cached.disk_path = disk_path;
+                return Ok(false);
+            }
+        }
+
+        let quick_hash = calculate_quick_hash(file_path, DEFAULT_QUICK_HASH_SAMPLE)?;
+        let full_hash = calculate_hash(file_path)?;
+        self.files.insert(key, IndexedFile {
+            size,
+            modified_millis,
+            quick_hash,
+            full_hash,
+            disk_path,
+        });
+        Ok(true)
+    }
+
+    /// 移除已不存在于目标目录的文件（existing 为磁盘上的实际路径）
+    pub fn retain_files(&mut self, existing: &HashSet<String>) {
+        let existing: HashSet<String> = existing.iter().map(|path| to_nfc(path)).collect();
+        self.files.retain(|path, _| existing.contains(path));
+    }
+
+    /// 将索引中的文件加入去重器。
+    /// 索引中的哈希由 SHA-256 按默认采样大小计算，去重器使用其他算法或采样大小时只按大小加入，
+    /// 需要时再重新计算
+    pub fn populate(&self, deduplicator: &mut Deduplicator) {
+        let reuse_hashes = deduplicator.hasher_id() == Sha256Hasher.id()
+            && deduplicator.quick_hash_sample() == DEFAULT_QUICK_HASH_SAMPLE;
+        for (key, file) in &self.files {
+            let path = file.disk_path.as_deref().unwrap_or(key);
+            if reuse_hashes {
+                deduplicator.add_known_hashes(path, file.size, file.quick_hash.clone(), file.full_hash.clone());
+            } else {
+                deduplicator.add_known_unhashed(path, file.size);
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -321,6 +750,25 @@ mod tests {
         assert_eq!(duplicate_of.unwrap(), path1);
     }
 
+    #[test]
+    fn test_deduplicator_unique_size_skips_hashing() {
+        let dir = TempDir::new().unwrap();
+        let known = create_test_file(&dir, "known.jpg", b"known content");
+        let mut dedup = Deduplicator::new();
+        dedup.add_known_file(&known).unwrap();
+
+        // 大小唯一的文件不会被读取：文件已不存在也不会计算哈希而出错
+        let missing = dir.path().join("missing.jpg").to_string_lossy().to_string();
+        assert_eq!(dedup.check_duplicate(&missing, 5).unwrap(), None);
+        assert_eq!(dedup.len(), 2);
+
+        // 之后出现同样大小的文件时才补算哈希，无法读取的文件不参与比较
+        let incoming = create_test_file(&dir, "incoming.jpg", b"12345");
+        assert_eq!(dedup.check_duplicate(&incoming, 5).unwrap(), None);
+        let copy = create_test_file(&dir, "copy.jpg", b"12345");
+        assert_eq!(dedup.check_duplicate(&copy, 5).unwrap(), Some(incoming));
+    }
+
     #[test]
     fn test_deduplicator_different_files() {
         let dir = TempDir::new().unwrap();
@@ -359,6 +807,118 @@ mod tests {
         assert_eq!(duplicate_of.unwrap(), path1);
     }
 
+    #[test]
+    fn test_deduplicator_custom_quick_hash_sample() {
+        let dir = TempDir::new().unwrap();
+        // 头尾各 4 字节相同、中间不同：快速哈希相同，需要完整哈希区分
+        let known = create_test_file(&dir, "known.cr3", b"HEAD-aaaa-TAIL");
+        let similar = create_test_file(&dir, "similar.cr3", b"HEAD-bbbb-TAIL");
+        let copy = create_test_file(&dir, "copy.cr3", b"HEAD-aaaa-TAIL");
+        assert_eq!(
+            calculate_quick_hash(&known, 4).unwrap(),
+            calculate_quick_hash(&similar, 4).unwrap()
+        );
+
+        let mut dedup = Deduplicator::with_quick_hash_sample(4);
+        assert_eq!(dedup.quick_hash_sample(), 4);
+        dedup.add_known_file(&known).unwrap();
+        assert_eq!(dedup.check_duplicate(&similar, 14).unwrap(), None);
+        assert_eq!(dedup.check_duplicate(&copy, 14).unwrap(), Some(known.clone()));
+
+        // 去重索引按默认采样大小缓存，加载到其他采样大小的去重器时仍能识别重复
+        let mut index = DedupIndex::new(&dir.path().to_string_lossy());
+        index.update_file(&known).unwrap();
+        let mut dedup = Deduplicator::with_quick_hash_sample(4);
+        index.populate(&mut dedup);
+        assert_eq!(dedup.check_duplicate(&similar, 14).unwrap(), None);
+        assert_eq!(dedup.check_duplicate(&copy, 14).unwrap(), Some(known));
+        assert_eq!(Deduplicator::new().quick_hash_sample(), DEFAULT_QUICK_HASH_SAMPLE);
+    }
+
+    // ==================== 去重索引缓存测试 ====================
+
+    #[test]
+    fn test_dedup_index_reuses_unchanged_files() {
+        let dir = TempDir::new().unwrap();
+        let path = create_test_file(&dir, "existing.jpg", b"existing photo");
+
+        let mut index = DedupIndex::new(&dir.path().to_string_lossy());
+        assert!(!index.is_warm());
+        assert!(index.update_file(&path).unwrap());
+        assert!(index.is_warm());
+
+        // 文件未变化时不重新计算哈希
+        assert!(!index.update_file(&path).unwrap());
+
+        // 文件内容变化后重新计算
+        create_test_file(&dir, "existing.jpg", b"changed photo content");
+        assert!(index.update_file(&path).unwrap());
+    }
+
+    #[test]
+    fn test_dedup_index_save_load_and_populate() {
+        let dir = TempDir::new().unwrap();
+        let target = dir.path().to_string_lossy().to_string();
+        let existing = create_test_file(&dir, "existing.jpg", b"same content");
+        let incoming = create_test_file(&dir, "incoming.jpg", b"same content");
+        let cache = dir.path().join("index.json");
+
+        let mut index = DedupIndex::new(&target);
+        index.update_file(&existing).unwrap();
+        index.save_to(&cache).unwrap();
+
+        let loaded = DedupIndex::load_from(&cache, &target);
+        assert_eq!(loaded.files, index.files);
+
+        // 其他目标目录不会使用这份缓存
+        assert!(!DedupIndex::load_from(&cache, "/other/target").is_warm());
+
+        let mut dedup = Deduplicator::new();
+        loaded.populate(&mut dedup);
+        assert_eq!(dedup.check_duplicate(&incoming, 12).unwrap(), Some(existing));
+    }
+
+    #[test]
+    fn test_dedup_index_retain_files() {
+        let dir = TempDir::new().unwrap();
+        let kept = create_test_file(&dir, "kept.jpg", b"kept");
+        let removed = create_test_file(&dir, "removed.jpg", b"removed");
+
+        let mut index = DedupIndex::new(&dir.path().to_string_lossy());
+        index.update_file(&kept).unwrap();
+        index.update_file(&removed).unwrap();
+        index.retain_files(&HashSet::from([kept.clone()]));
+
+        assert_eq!(index.files.len(), 1);
+        assert!(index.files.contains_key(&kept));
+    }
+
+    #[test]
+    fn test_dedup_index_normalizes_unicode_paths() {
+        let dir = TempDir::new().unwrap();
+        // macOS 上生成的 NFD 文件名（e + 组合重音符）
+        let nfd = create_test_file(&dir, "cafe\u{301}.jpg", b"same content");
+        let incoming = create_test_file(&dir, "incoming.jpg", b"same content");
+        let nfc = dir.path().join("caf\u{e9}.jpg").to_string_lossy().to_string();
+        assert_ne!(nfd, nfc);
+
+        let mut index = DedupIndex::new(&dir.path().to_string_lossy());
+        index.update_file(&nfd).unwrap();
+        index.update_file(&incoming).unwrap();
+        index.retain_files(&HashSet::from([nfd.clone(), incoming.clone()]));
+        assert_eq!(index.files.len(), 2);
+        let entry = &index.files[&nfc];
+        assert_eq!(entry.disk_path.as_deref(), Some(nfd.as_str()));
+        assert!(index.files[&incoming].disk_path.is_none());
+
+        // 去重器使用磁盘上的实际路径
+        let mut index = DedupIndex::new(&dir.path().to_string_lossy());
+        index.update_file(&nfd).unwrap();
+        let mut dedup = Deduplicator::new();
+        index.populate(&mut dedup);
+        assert_eq!(dedup.check_duplicate(&incoming, 12).unwrap(), Some(nfd));
+    }
+
     #[test]
     fn test_deduplicator_multiple_duplicates() {
         let dir = TempDir::new().unwrap();
@@ -398,4 +958,210 @@ mod tests {
         assert!(duplicate.is_some());
         assert_eq!(duplicate.unwrap(), path1);
     }
+
+    // 辅助函数：生成一张小 JPEG，并在 SOI 之后插入指定内容的 EXIF（APP1）段
+    fn jpeg_with_exif(exif: &[u8]) -> Vec<u8> {
+        let image = image::RgbImage::from_fn(16, 16, |x, y| image::Rgb([(x * 16) as u8, (y * 16) as u8, 128]));
+        let mut encoded = Vec::new();
+        image::DynamicImage::ImageRgb8(image)
+            .write_to(&mut std::io::Cursor::new(&mut encoded), image::ImageFormat::Jpeg)
+            .unwrap();
+
+        let mut segment = b"Exif\0\0".to_vec();
+        segment.extend_from_slice(exif);
+        let mut jpeg = encoded[..2].to_vec();
+        jpeg.extend_from_slice(&[0xFF, 0xE1]);
+        jpeg.extend_from_slice(&((segment.len() + 2) as u16).to_be_bytes());
+        jpeg.extend_from_slice(&segment);
+        jpeg.extend_from_slice(&encoded[2..]);
+        jpeg
+    }
+
+    #[test]
+    fn test_pixel_dedup_ignores_exif_difference() {
+        let dir = TempDir::new().unwrap();
+        let jpeg1 = jpeg_with_exif(b"Artist=Alice");
+        let jpeg2 = jpeg_with_exif(b"Artist=Bob, edited in Lightroom");
+        assert_ne!(jpeg1, jpeg2);
+        let path1 = create_test_file(&dir, "IMG_0001.jpg", &jpeg1);
+        let path2 = create_test_file(&dir, "IMG_0001 copy.JPG", &jpeg2);
+
+        // 按文件内容去重时两者不同
+        let mut dedup = Deduplicator::new();
+        assert!(dedup.check_duplicate(&path1, jpeg1.len() as u64).unwrap().is_none());
+        assert!(dedup.check_duplicate(&path2, jpeg2.len() as u64).unwrap().is_none());
+
+        // 按像素去重时识别为重复
+        let mut dedup = Deduplicator::new().with_pixel_dedup(true);
+        assert!(dedup.check_duplicate(&path1, jpeg1.len() as u64).unwrap().is_none());
+        assert_eq!(dedup.check_duplicate(&path2, jpeg2.len() as u64).unwrap(), Some(path1.clone()));
+
+        // 已知文件（目标文件夹中已有的照片）同样按像素比较
+        let mut dedup = Deduplicator::new().with_pixel_dedup(true);
+        dedup.add_known_file(&path1).unwrap();
+        assert_eq!(dedup.check_duplicate(&path2, jpeg2.len() as u64).unwrap(), Some(path1));
+    }
+
+    #[test]
+    fn test_pixel_dedup_falls_back_to_content() {
+        let dir = TempDir::new().unwrap();
+        // 无法解码的 JPEG 和 RAW 只按文件内容去重
+        let broken1 = create_test_file(&dir, "broken1.jpg", b"not a jpeg");
+        let broken2 = create_test_file(&dir, "broken2.jpg", b"not a jpeg");
+        let raw1 = create_test_file(&dir, "IMG_0001.CR3", b"raw data");
+        let raw2 = create_test_file(&dir, "IMG_0002.CR3", b"raw data 2");
+
+        let mut dedup = Deduplicator::new().with_pixel_dedup(true);
+        assert!(dedup.check_duplicate(&broken1, 10).unwrap().is_none());
+        assert_eq!(dedup.check_duplicate(&broken2, 10).unwrap(), Some(broken1));
+        assert!(dedup.check_duplicate(&raw1, 8).unwrap().is_none());
+        assert!(dedup.check_duplicate(&raw2, 10).unwrap().is_none());
+
+        assert!(is_pixel_hashable("a/IMG.JPEG"));
+        assert!(is_pixel_hashable("a/IMG.png"));
+        assert!(!is_pixel_hashable("a/IMG.CR3"));
+        assert!(!is_pixel_hashable("a/IMG"));
+    }
+
+    #[test]
+    fn test_hash_manifest_skips_known_source() {
+        let dir = TempDir::new().unwrap();
+        let archived = create_test_file(&dir, "IMG_0001.JPG", b"archived offsite");
+        let new_photo = create_test_file(&dir, "IMG_0002.JPG", b"brand new photo");
+        let archived_hash = calculate_hash(&archived).unwrap();
+
+        let manifest = dir.path().join("known.sha256");
+        fs::write(
+            &manifest,
+            format!(
+                "# offsite archive\n{}  2023/IMG_0001.JPG\n\nnot-a-hash  broken line\n{} *2023/IMG_9999.JPG\n",
+                archived_hash.to_uppercase(),
+                "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+            ),
+        )
+        .unwrap();
+
+        let mut dedup = Deduplicator::new();
+        assert_eq!(dedup.load_hash_manifest(&manifest).unwrap(), 2);
+        assert_eq!(dedup.len(), 2);
+
+        // 大小相同的已知文件不存在，也能按清单中的哈希识别为重复
+        let size = fs::metadata(&archived).unwrap().len();
+        assert_eq!(
+            dedup.check_duplicate(&archived, size).unwrap(),
+            Some("2023/IMG_0001.JPG".to_string())
+        );
+        let size = fs::metadata(&new_photo).unwrap().len();
+        assert_eq!(dedup.check_duplicate(&new_photo, size).unwrap(), None);
+
+        // 清单之外的文件之间仍然正常去重
+        let copy = create_test_file(&dir, "IMG_0002_copy.JPG", b"brand new photo");
+        assert_eq!(dedup.check_duplicate(&copy, size).unwrap(), Some(new_photo));
+    }
+
+    #[test]
+    fn test_hash_manifest_missing_file() {
+        let mut dedup = Deduplicator::new();
+        let result = dedup.load_hash_manifest(Path::new("/nonexistent/known.sha256"));
+        assert!(result.unwrap_err().contains("读取哈希清单失败"));
+        assert!(dedup.is_empty());
+    }
+
+    /// 测试用哈希算法：按路径返回预设的（快速哈希，完整哈希），并记录计算过完整哈希的文件
+    struct FakeHasher {
+        hashes: HashMap<String, (String, String)>,
+        full_hashed: std::sync::Arc<std::sync::Mutex<Vec<String>>>,
+    }
+
+    impl FileHasher for FakeHasher {
+        fn full_hash(&self, file_path: &str) -> Result<String, String> {
+            self.full_hashed.lock().unwrap().push(file_path.to_string());
+            self.hashes
+                .get(file_path)
+                .map(|(_, full)| full.clone())
+                .ok_or_else(|| format!("无法打开文件: {}", file_path))
+        }
+
+        fn quick_hash(&self, file_path: &str, _sample_size: usize) -> Result<String, String> {
+            self.hashes
+                .get(file_path)
+                .map(|(quick, _)| quick.clone())
+                .ok_or_else(|| format!("无法打开文件: {}", file_path))
+        }
+
+        fn id(&self) -> &'static str {
+            "fake"
+        }
+    }
+
+    #[test]
+    fn test_deduplicator_with_fake_hasher() {
+        let full_hashed = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let hashes = [
+            ("a.jpg", "q1", "f1"),
+            ("b.jpg", "q1", "f1"),
+            // 快速哈希相同但内容不同
+            ("c.jpg", "q1", "f2"),
+            ("d.jpg", "q1", "f2"),
+            ("e.jpg", "q9", "f1"),
+        ];
+        let mut dedup = Deduplicator::new().with_hasher(FakeHasher {
+            hashes: hashes
+                .iter()
+                .map(|(path, quick, full)| (path.to_string(), (quick.to_string(), full.to_string())))
+                .collect(),
+            full_hashed: full_hashed.clone(),
+        });
+
+        // 大小唯一，不计算哈希
+        assert_eq!(dedup.check_duplicate("a.jpg", 100).unwrap(), None);
+        assert_eq!(dedup.check_duplicate("e.jpg", 200).unwrap(), None);
+        assert!(full_hashed.lock().unwrap().is_empty());
+
+        // 出现同样大小的文件时才补算已知文件的哈希
+        assert_eq!(dedup.check_duplicate("b.jpg", 100).unwrap(), Some("a.jpg".to_string()));
+        assert_eq!(*full_hashed.lock().unwrap(), vec!["a.jpg", "b.jpg"]);
+
+        // 快速哈希碰撞由完整哈希区分
+        assert_eq!(dedup.check_duplicate("c.jpg", 100).unwrap(), None);
+        assert_eq!(dedup.check_duplicate("d.jpg", 100).unwrap(), Some("c.jpg".to_string()));
+        assert_eq!(dedup.len(), 3);
+
+        // 哈希失败时返回错误
+        assert!(dedup.check_duplicate("missing.jpg", 100).is_err());
+    }
+
+    #[test]
+    fn test_populate_with_fake_hasher_ignores_index_hashes() {
+        let full_hashed = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut dedup = Deduplicator::new().with_hasher(FakeHasher {
+            hashes: [("/target/a.jpg", "q1", "f1"), ("/source/b.jpg", "q1", "f1")]
+                .iter()
+                .map(|(path, quick, full)| (path.to_string(), (quick.to_string(), full.to_string())))
+                .collect(),
+            full_hashed: full_hashed.clone(),
+        });
+
+        // 索引中记录的是 SHA-256，与测试算法的哈希不可比较
+        let mut index = DedupIndex::new("/target");
+        index.files.insert(
+            "/target/a.jpg".to_string(),
+            IndexedFile {
+                size: 100,
+                modified_millis: 0,
+                quick_hash: "sha256-quick".to_string(),
+                full_hash: "sha256-full".to_string(),
+                disk_path: None,
+            },
+        );
+        index.populate(&mut dedup);
+        assert!(full_hashed.lock().unwrap().is_empty());
+
+        // 按测试算法补算索引中文件的哈希后识别出重复
+        assert_eq!(
+            dedup.check_duplicate("/source/b.jpg", 100).unwrap(),
+            Some("/target/a.jpg".to_string())
+        );
+        assert_eq!(*full_hashed.lock().unwrap(), vec!["/target/a.jpg", "/source/b.jpg"]);
+    }
 }
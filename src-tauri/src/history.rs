@@ -1,12 +1,67 @@
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fs;
-use std::path::PathBuf;
-use chrono::{DateTime, Local};
+use std::path::{Path, PathBuf};
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime};
+
+/// 默认保留的历史记录条数
+pub const DEFAULT_MAX_RECORDS: usize = 100;
+
+fn default_max_records() -> Option<usize> {
+    Some(DEFAULT_MAX_RECORDS)
+}
 
 /// 传输历史记录
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TransferHistory {
     pub records: Vec<TransferRecord>,
+    /// 最多保留的记录条数，None 或 0 表示不限制
+    #[serde(default = "default_max_records")]
+    pub max_records: Option<usize>,
+}
+
+/// 全部历史记录的汇总统计
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HistoryStats {
+    pub total_records: usize,
+    pub total_files: usize,
+    pub success_count: usize,
+    pub skip_count: usize,
+    pub error_count: usize,
+    /// 所有传输涉及的总字节数
+    pub total_bytes: u64,
+    /// 成功传输的字节数
+    pub transferred_bytes: u64,
+    pub average_duration_secs: f64,
+    /// 按使用次数降序排列的模板
+    pub templates: Vec<UsageCount>,
+    /// 按成功传输照片数降序排列的相机
+    pub cameras: Vec<UsageCount>,
+    pub most_used_template: Option<String>,
+    pub busiest_camera: Option<String>,
+}
+
+/// 名称及其计数
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct UsageCount {
+    pub name: String,
+    pub count: usize,
+}
+
+/// 计数表转为降序列表，计数相同时按名称排序
+fn sorted_counts(counts: HashMap<String, usize>) -> Vec<UsageCount> {
+    let mut list: Vec<UsageCount> = counts
+        .into_iter()
+        .map(|(name, count)| UsageCount { name, count })
+        .collect();
+    list.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.name.cmp(&b.name)));
+    list
+}
+
+/// 历史记录设置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistorySettings {
+    pub max_records: Option<usize>,
 }
 
 /// 单次传输记录
@@ -14,6 +69,10 @@ pub struct TransferHistory {
 pub struct TransferRecord {
     pub id: String,
     pub timestamp: String,
+    /// Unix 时间戳（秒），旧记录没有此字段时为 0
+    #[serde(default)]
+    pub epoch_secs: i64,
+    /// 源文件夹，多个源文件夹以 "; " 分隔
     pub source_dir: String,
     pub target_dir: String,
     pub template: String,
@@ -24,6 +83,113 @@ pub struct TransferRecord {
     pub total_size: u64,
     pub duration_secs: u64,
     pub files: Vec<TransferredFile>,
+    /// 传输过程中的错误信息
+    #[serde(default)]
+    pub errors: Vec<String>,
+    /// 重试失败文件时生成的记录，为被重试的原记录 ID
+    #[serde(default)]
+    pub retry_of: Option<String>,
+    /// 打包输出时创建的压缩包路径，各文件的 target_path 为 `<压缩包路径>/<压缩包内的路径>`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub archives: Vec<String>,
+    /// 记录类型，旧记录没有此字段时为传输
+    #[serde(default)]
+    pub kind: RecordKind,
+}
+
+/// 历史记录的类型
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RecordKind {
+    /// 从源文件夹传输到目标文件夹
+    #[default]
+    Transfer,
+    /// 在目标文件夹中原地重命名：source_path 为原路径，target_path 为新路径
+    Rename,
+}
+
+/// 历史记录查询条件，所有条件为可选，同时满足才匹配
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HistoryQuery {
+    /// 起始时间（含），格式 YYYY-MM-DD 或 YYYY-MM-DD HH:MM:SS
+    pub from: Option<String>,
+    /// 结束时间（含），格式同上；只有日期时包含当天全天
+    pub to: Option<String>,
+    /// 源文件夹包含的文本（不区分大小写）
+    pub source_contains: Option<String>,
+    /// 目标文件夹包含的文本（不区分大小写）
+    pub target_contains: Option<String>,
+    /// 最少文件数
+    pub min_files: Option<usize>,
+}
+
+/// 解析查询中的时间，只有日期时按 end_of_day 决定取当天开始或结束
+fn parse_query_time(value: &str, end_of_day: bool) -> Option<NaiveDateTime> {
+    let value = value.trim();
+    if let Ok(dt) = NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S") {
+        return Some(dt);
+    }
+    let date = NaiveDate::parse_from_str(value, "%Y-%m-%d").ok()?;
+    if end_of_day {
+        date.and_hms_opt(23, 59, 59)
+    } else {
+        date.and_hms_opt(0, 0, 0)
+    }
+}
+
+fn contains_ignore_case(haystack: &str, needle: &Option<String>) -> bool {
+    match needle {
+        Some(needle) => haystack.to_lowercase().contains(&needle.to_lowercase()),
+        None => true,
+    }
+}
+
+impl TransferRecord {
+    /// 记录的本地时间，优先使用时间戳，旧记录解析 timestamp 字符串
+    pub fn local_time(&self) -> Option<NaiveDateTime> {
+        if self.epoch_secs != 0 {
+            if let Some(dt) = DateTime::from_timestamp(self.epoch_secs, 0) {
+                return Some(dt.with_timezone(&Local).naive_local());
+            }
+        }
+        NaiveDateTime::parse_from_str(&self.timestamp, "%Y-%m-%d %H:%M:%S").ok()
+    }
+
+    /// 检查记录是否满足查询条件
+    pub fn matches(&self, query: &HistoryQuery) -> bool {
+        if let Some(min_files) = query.min_files {
+            if self.total_files < min_files {
+                return false;
+            }
+        }
+        if !contains_ignore_case(&self.source_dir, &query.source_contains)
+            || !contains_ignore_case(&self.target_dir, &query.target_contains)
+        {
+            return false;
+        }
+
+        let from = query.from.as_deref().and_then(|v| parse_query_time(v, false));
+        let to = query.to.as_deref().and_then(|v| parse_query_time(v, true));
+        if from.is_some() || to.is_some() {
+            let Some(time) = self.local_time() else {
+                return false;
+            };
+            if from.is_some_and(|from| time < from) || to.is_some_and(|to| time > to) {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    /// 获取传输失败的文件
+    pub fn failed_files(&self) -> Vec<&TransferredFile> {
+        self.files
+            .iter()
+            .filter(|f| matches!(f.status, TransferFileStatus::Error(_)))
+            .collect()
+    }
 }
 
 /// 传输的单个文件记录
@@ -33,6 +199,12 @@ pub struct TransferredFile {
     pub target_path: String,
     pub file_size: u64,
     pub status: TransferFileStatus,
+    /// 相机型号，旧记录没有此字段
+    #[serde(default)]
+    pub camera: Option<String>,
+    /// 因内容重复跳过时，与之相同的已有文件
+    #[serde(default)]
+    pub matched_original: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -44,7 +216,10 @@ pub enum TransferFileStatus {
 
 impl Default for TransferHistory {
     fn default() -> Self {
-        Self { records: Vec::new() }
+        Self {
+            records: Vec::new(),
+            max_records: default_max_records(),
+        }
     }
 }
 
@@ -60,9 +235,13 @@ impl TransferHistory {
 
     /// 加载历史记录
     pub fn load() -> Self {
-        let path = Self::get_history_file_path();
+        Self::load_from(&Self::get_history_file_path())
+    }
+
+    /// 从指定文件加载历史记录，文件不存在或无法解析时返回空记录
+    pub fn load_from(path: &Path) -> Self {
         if path.exists() {
-            fs::read_to_string(&path)
+            fs::read_to_string(path)
                 .ok()
                 .and_then(|content| serde_json::from_str(&content).ok())
                 .unwrap_or_default()
@@ -73,22 +252,43 @@ impl TransferHistory {
 
     /// 保存历史记录
     pub fn save(&self) -> Result<(), String> {
-        let path = Self::get_history_file_path();
+        self.save_to(&Self::get_history_file_path())
+    }
+
+    /// 保存历史记录到指定文件
+    pub fn save_to(&self, path: &Path) -> Result<(), String> {
         let content = serde_json::to_string_pretty(self)
             .map_err(|e| format!("序列化失败: {}", e))?;
-        fs::write(&path, content)
+        fs::write(path, content)
             .map_err(|e| format!("保存失败: {}", e))
     }
 
     /// 添加新记录
     pub fn add_record(&mut self, record: TransferRecord) {
         self.records.insert(0, record);
-        // 只保留最近 100 条记录
-        if self.records.len() > 100 {
-            self.records.truncate(100);
+        self.apply_retention();
+    }
+
+    /// 按保留上限截断旧记录
+    fn apply_retention(&mut self) {
+        if let Some(limit) = self.max_records.filter(|&n| n > 0) {
+            self.records.truncate(limit);
+        }
+    }
+
+    /// 获取历史记录设置
+    pub fn settings(&self) -> HistorySettings {
+        HistorySettings {
+            max_records: self.max_records,
         }
     }
 
+    /// 设置保留上限，超出的旧记录会被立即移除
+    pub fn set_max_records(&mut self, max_records: Option<usize>) {
+        self.max_records = max_records;
+        self.apply_retention();
+    }
+
     /// 创建新的传输记录
     pub fn create_record(
         source_dir: &str,
@@ -99,6 +299,7 @@ impl TransferHistory {
         TransferRecord {
             id: now.format("%Y%m%d%H%M%S%3f").to_string(),
             timestamp: now.format("%Y-%m-%d %H:%M:%S").to_string(),
+            epoch_secs: now.timestamp(),
             source_dir: source_dir.to_string(),
             target_dir: target_dir.to_string(),
             template: template.to_string(),
@@ -109,7 +310,54 @@ impl TransferHistory {
             total_size: 0,
             duration_secs: 0,
             files: Vec::new(),
+            errors: Vec::new(),
+            retry_of: None,
+            archives: Vec::new(),
+            kind: RecordKind::Transfer,
+        }
+    }
+
+    /// 汇总统计全部传输记录（不含原地重命名记录）
+    pub fn statistics(&self) -> HistoryStats {
+        let transfers: Vec<&TransferRecord> = self.records.iter().filter(|r| r.kind == RecordKind::Transfer).collect();
+        let mut stats = HistoryStats {
+            total_records: transfers.len(),
+            ..Default::default()
+        };
+        let mut templates: HashMap<String, usize> = HashMap::new();
+        let mut cameras: HashMap<String, usize> = HashMap::new();
+        let mut total_duration = 0u64;
+
+        for record in &transfers {
+            stats.total_files += record.total_files;
+            stats.success_count += record.success_count;
+            stats.skip_count += record.skip_count;
+            stats.error_count += record.error_count;
+            stats.total_bytes += record.total_size;
+            total_duration += record.duration_secs;
+            *templates.entry(record.template.clone()).or_default() += 1;
+
+            for file in record.files.iter().filter(|f| f.status == TransferFileStatus::Success) {
+                stats.transferred_bytes += file.file_size;
+                if let Some(camera) = &file.camera {
+                    *cameras.entry(camera.clone()).or_default() += 1;
+                }
+            }
+        }
+
+        if !transfers.is_empty() {
+            stats.average_duration_secs = total_duration as f64 / transfers.len() as f64;
         }
+        stats.templates = sorted_counts(templates);
+        stats.cameras = sorted_counts(cameras);
+        stats.most_used_template = stats.templates.first().map(|t| t.name.clone());
+        stats.busiest_camera = stats.cameras.first().map(|c| c.name.clone());
+        stats
+    }
+
+    /// 按条件搜索历史记录
+    pub fn search(&self, query: &HistoryQuery) -> Vec<&TransferRecord> {
+        self.records.iter().filter(|r| r.matches(query)).collect()
     }
 
     /// 清空历史记录
@@ -158,6 +406,229 @@ mod tests {
         assert_eq!(history.records.len(), 100);
     }
 
+    #[test]
+    fn test_failed_files() {
+        let mut record = TransferHistory::create_record("/src", "/dst", "{year}");
+        for (name, status) in [
+            ("a.jpg", TransferFileStatus::Success),
+            ("b.jpg", TransferFileStatus::Error("权限不足".to_string())),
+            ("c.jpg", TransferFileStatus::Skipped),
+        ] {
+            record.files.push(TransferredFile {
+                source_path: format!("/src/{}", name),
+                target_path: format!("/dst/{}", name),
+                file_size: 100,
+                status,
+                camera: None,
+                matched_original: None,
+            });
+        }
+
+        let failed = record.failed_files();
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].source_path, "/src/b.jpg");
+    }
+
+    #[test]
+    fn test_old_record_without_errors_field() {
+        let json = r#"{
+            "id": "1", "timestamp": "2024-03-15 10:00:00",
+            "source_dir": "/src", "target_dir": "/dst", "template": "{year}",
+            "total_files": 0, "success_count": 0, "skip_count": 0, "error_count": 0,
+            "total_size": 0, "duration_secs": 0, "files": []
+        }"#;
+        let record: TransferRecord = serde_json::from_str(json).unwrap();
+        assert!(record.errors.is_empty());
+    }
+
+    #[test]
+    fn test_old_file_without_matched_original() {
+        let json = r#"{"source_path": "/src/a.jpg", "target_path": "", "file_size": 100, "status": "Skipped"}"#;
+        let file: TransferredFile = serde_json::from_str(json).unwrap();
+        assert_eq!(file.status, TransferFileStatus::Skipped);
+        assert!(file.matched_original.is_none());
+    }
+
+    #[test]
+    fn test_unlimited_records() {
+        for limit in [None, Some(0)] {
+            let mut history = TransferHistory::default();
+            history.set_max_records(limit);
+            for i in 0..150 {
+                let mut record = TransferHistory::create_record("/src", "/dst", "{year}");
+                record.id = format!("{}", i);
+                history.add_record(record);
+            }
+            assert_eq!(history.records.len(), 150);
+        }
+    }
+
+    #[test]
+    fn test_shrink_max_records() {
+        let mut history = TransferHistory::default();
+        for i in 0..20 {
+            let mut record = TransferHistory::create_record("/src", "/dst", "{year}");
+            record.id = format!("{}", i);
+            history.add_record(record);
+        }
+
+        history.set_max_records(Some(5));
+        assert_eq!(history.records.len(), 5);
+        // 保留最新的记录
+        assert_eq!(history.records[0].id, "19");
+        assert_eq!(history.records[4].id, "15");
+        assert_eq!(history.settings().max_records, Some(5));
+    }
+
+    #[test]
+    fn test_old_history_default_max_records() {
+        let history: TransferHistory = serde_json::from_str(r#"{"records": []}"#).unwrap();
+        assert_eq!(history.max_records, Some(DEFAULT_MAX_RECORDS));
+    }
+
+    fn record_at(id: &str, timestamp: &str, source: &str, target: &str, total_files: usize) -> TransferRecord {
+        let mut record = TransferHistory::create_record(source, target, "{year}");
+        record.id = id.to_string();
+        record.timestamp = timestamp.to_string();
+        record.epoch_secs = 0;
+        record.total_files = total_files;
+        record
+    }
+
+    fn sample_history() -> TransferHistory {
+        let mut history = TransferHistory::default();
+        history.add_record(record_at("1", "2024-01-10 09:00:00", "/Volumes/SD_A/DCIM", "/nas/photos", 10));
+        history.add_record(record_at("2", "2024-03-15 18:30:00", "/Volumes/SD_B/DCIM", "/nas/photos", 200));
+        history.add_record(record_at("3", "2024-03-31 23:00:00", "/Users/me/Pictures", "/nas/archive", 50));
+        history
+    }
+
+    fn ids(records: Vec<&TransferRecord>) -> Vec<&str> {
+        records.iter().map(|r| r.id.as_str()).collect()
+    }
+
+    #[test]
+    fn test_search_substring() {
+        let history = sample_history();
+
+        let query = HistoryQuery {
+            source_contains: Some("sd_".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(ids(history.search(&query)), vec!["2", "1"]);
+
+        let query = HistoryQuery {
+            target_contains: Some("archive".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(ids(history.search(&query)), vec!["3"]);
+    }
+
+    #[test]
+    fn test_search_date_range() {
+        let history = sample_history();
+
+        // 只有日期时结束日包含全天
+        let query = HistoryQuery {
+            from: Some("2024-03-01".to_string()),
+            to: Some("2024-03-31".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(ids(history.search(&query)), vec!["3", "2"]);
+
+        let query = HistoryQuery {
+            to: Some("2024-03-15 18:00:00".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(ids(history.search(&query)), vec!["1"]);
+    }
+
+    #[test]
+    fn test_search_min_files_and_empty_query() {
+        let history = sample_history();
+
+        let query = HistoryQuery {
+            min_files: Some(50),
+            ..Default::default()
+        };
+        assert_eq!(ids(history.search(&query)), vec!["3", "2"]);
+
+        assert_eq!(history.search(&HistoryQuery::default()).len(), 3);
+    }
+
+    #[test]
+    fn test_local_time_prefers_epoch() {
+        let record = TransferHistory::create_record("/src", "/dst", "{year}");
+        let time = record.local_time().unwrap();
+        assert_eq!(time.format("%Y-%m-%d %H:%M:%S").to_string(), record.timestamp);
+    }
+
+    #[test]
+    fn test_statistics() {
+        let mut history = TransferHistory::default();
+
+        let mut first = TransferHistory::create_record("/src", "/dst", "{year}/{month}");
+        first.total_files = 3;
+        first.success_count = 2;
+        first.skip_count = 1;
+        first.total_size = 600;
+        first.duration_secs = 10;
+        for (camera, status) in [
+            (Some("Canon EOS R5"), TransferFileStatus::Success),
+            (Some("Canon EOS R5"), TransferFileStatus::Success),
+            (Some("Sony A7"), TransferFileStatus::Skipped),
+        ] {
+            first.files.push(TransferredFile {
+                source_path: "/src/a.jpg".to_string(),
+                target_path: "/dst/a.jpg".to_string(),
+                file_size: 200,
+                status,
+                camera: camera.map(String::from),
+                matched_original: None,
+            });
+        }
+        history.add_record(first);
+
+        let mut second = TransferHistory::create_record("/src", "/dst", "{year}/{month}");
+        second.total_files = 1;
+        second.error_count = 1;
+        second.duration_secs = 20;
+        history.add_record(second);
+
+        history.add_record(TransferHistory::create_record("/src", "/dst", "{make}/{year}"));
+
+        // 原地重命名记录不计入统计
+        let mut rename = TransferHistory::create_record("/dst", "/dst", "photo_{counter}");
+        rename.kind = RecordKind::Rename;
+        rename.total_files = 5;
+        rename.success_count = 5;
+        rename.duration_secs = 100;
+        history.add_record(rename);
+
+        let stats = history.statistics();
+        assert_eq!(stats.total_records, 3);
+        assert_eq!(stats.total_files, 4);
+        assert_eq!(stats.success_count, 2);
+        assert_eq!(stats.skip_count, 1);
+        assert_eq!(stats.error_count, 1);
+        assert_eq!(stats.total_bytes, 600);
+        assert_eq!(stats.transferred_bytes, 400);
+        assert_eq!(stats.average_duration_secs, 10.0);
+        assert_eq!(stats.most_used_template.as_deref(), Some("{year}/{month}"));
+        assert_eq!(stats.templates[0], UsageCount { name: "{year}/{month}".to_string(), count: 2 });
+        assert_eq!(stats.busiest_camera.as_deref(), Some("Canon EOS R5"));
+        assert_eq!(stats.cameras.len(), 1);
+    }
+
+    #[test]
+    fn test_statistics_empty() {
+        let stats = TransferHistory::default().statistics();
+        assert_eq!(stats.total_records, 0);
+        assert_eq!(stats.average_duration_secs, 0.0);
+        assert!(stats.most_used_template.is_none());
+        assert!(stats.busiest_camera.is_none());
+    }
+
     #[test]
     fn test_delete_record() {
         let mut history = TransferHistory::default();
@@ -0,0 +1,128 @@
+// 照片文件完整性检查
+// 只读取文件头尾做轻量验证，用于在导入前发现截断或损坏的文件
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// 检查文件尾部时读取的字节数（部分相机会在 JPEG 结束标记后追加数据）
+const TAIL_SIZE: u64 = 4096;
+
+/// 文件是否可疑（可能已损坏）
+/// 空文件和无法读取的文件视为可疑；JPEG、PNG、HEIC/HEIF 检查文件头尾标记；
+/// 无法低成本验证的格式（如 RAW）不标记
+pub fn is_suspect(path: &Path) -> bool {
+    let ext = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase())
+        .unwrap_or_default();
+
+    match read_head_tail(path) {
+        Ok((head, _)) if head.is_empty() => true,
+        Ok((head, tail)) => match ext.as_str() {
+            "jpg" | "jpeg" => !is_valid_jpeg(&head, &tail),
+            "png" => !is_valid_png(&head, &tail),
+            "heic" | "heif" => !is_valid_heif(&head),
+            _ => false,
+        },
+        Err(_) => true,
+    }
+}
+
+/// 读取文件开头和结尾的若干字节
+fn read_head_tail(path: &Path) -> std::io::Result<(Vec<u8>, Vec<u8>)> {
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+
+    let mut head = Vec::new();
+    (&mut file).take(16).read_to_end(&mut head)?;
+
+    let mut tail = Vec::new();
+    file.seek(SeekFrom::Start(len.saturating_sub(TAIL_SIZE)))?;
+    file.read_to_end(&mut tail)?;
+    Ok((head, tail))
+}
+
+/// JPEG 以 SOI (FF D8 FF) 开头，且结尾附近有 EOI (FF D9)
+fn is_valid_jpeg(head: &[u8], tail: &[u8]) -> bool {
+    head.starts_with(&[0xFF, 0xD8, 0xFF]) && tail.windows(2).any(|w| w == [0xFF, 0xD9])
+}
+
+/// PNG 以固定签名开头，且结尾附近有 IEND 块
+fn is_valid_png(head: &[u8], tail: &[u8]) -> bool {
+    head.starts_with(b"\x89PNG\r\n\x1a\n") && tail.windows(4).any(|w| w == b"IEND")
+}
+
+/// HEIC/HEIF 第 4-8 字节为 ftyp 盒子类型
+fn is_valid_heif(head: &[u8]) -> bool {
+    head.get(4..8) == Some(b"ftyp".as_slice())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    /// 构造一个结构完整的最小 JPEG
+    fn jpeg_bytes() -> Vec<u8> {
+        let mut data = vec![0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x04, 0x00, 0x00];
+        data.resize(data.len() + 10_000, 0x55);
+        data.extend_from_slice(&[0xFF, 0xD9]);
+        data
+    }
+
+    #[test]
+    fn test_valid_jpeg_not_suspect() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("IMG_0001.JPG");
+        fs::write(&path, jpeg_bytes()).unwrap();
+        assert!(!is_suspect(&path));
+    }
+
+    #[test]
+    fn test_truncated_jpeg_is_suspect() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("IMG_0001.JPG");
+        let data = jpeg_bytes();
+        fs::write(&path, &data[..data.len() / 2]).unwrap();
+        assert!(is_suspect(&path));
+    }
+
+    #[test]
+    fn test_jpeg_without_soi_is_suspect() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("IMG_0001.jpg");
+        fs::write(&path, b"not a jpeg at all\xFF\xD9").unwrap();
+        assert!(is_suspect(&path));
+    }
+
+    #[test]
+    fn test_empty_file_is_suspect() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("IMG_0001.CR3");
+        fs::write(&path, b"").unwrap();
+        assert!(is_suspect(&path));
+    }
+
+    #[test]
+    fn test_raw_not_validated() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("IMG_0001.CR3");
+        fs::write(&path, b"raw data").unwrap();
+        assert!(!is_suspect(&path));
+    }
+
+    #[test]
+    fn test_png_and_heif_headers() {
+        assert!(is_valid_png(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR", b"\0\0\0\0IEND\xAE\x42\x60\x82"));
+        assert!(!is_valid_png(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR", b"\0\0\0\0IDAT"));
+        assert!(is_valid_heif(b"\0\0\0\x18ftypheic"));
+        assert!(!is_valid_heif(b"\0\0\0\x18"));
+    }
+
+    #[test]
+    fn test_missing_file_is_suspect() {
+        assert!(is_suspect(Path::new("/nonexistent/IMG_0001.JPG")));
+    }
+}
@@ -1,14 +1,22 @@
 // 公开模块以支持测试
+pub mod archive;
 pub mod classify;
 pub mod cli;
 mod commands;
 pub mod exif;
 pub mod hash;
 pub mod history;
+pub mod integrity;
+pub mod logging;
+pub mod preview;
+pub mod progress;
 pub mod rename;
+pub mod report;
+pub mod settings;
 pub mod thumbnail;
 pub mod transfer;
 pub mod transfer_v2;
+pub mod volume;
 
 use commands::*;
 
@@ -20,28 +28,64 @@ pub fn run() {
     }
 
     // 无参数时启动 GUI 模式
+    logging::init(log::LevelFilter::Info);
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_fs::init())
-        .manage(AppState::default())
+        .manage(AppState::load())
         .invoke_handler(tauri::generate_handler![
             check_environment,
             get_templates,
             set_classify_config,
             get_classify_config,
+            get_app_settings,
             scan_source_folder,
+            quick_scan,
+            reset_scan,
             start_transfer,
+            retry_failed,
+            get_transfer_plan,
+            count_duplicates,
+            set_reference_dirs,
+            set_move_sidecars,
+            set_pixel_dedup,
+            set_known_hashes,
+            set_scan_size_range,
+            set_skip_empty,
+            set_verify,
+            set_fsync,
+            set_output_mode,
+            set_raw_preview,
+            set_camera_routes,
+            get_volume_info,
+            debug_classify,
             preview_classification,
+            get_scan_summary,
+            validate_rename_over_scan,
+            rename_in_place,
+            sort_scan,
+            reclassify,
+            export_scan_report,
+            export_scan_ndjson,
             // 新增命令
+            get_transfer_status,
             cancel_transfer,
             get_rename_templates,
             set_rename_config,
+            set_counter_mode,
+            set_name_case,
             get_transfer_history,
+            get_last_transfer_files,
+            search_history,
+            get_history_stats,
             clear_transfer_history,
             delete_history_record,
+            get_history_settings,
+            set_history_settings,
             get_thumbnails,
             validate_custom_template,
+            validate_rename_template,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
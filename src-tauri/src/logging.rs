@@ -0,0 +1,51 @@
+// 日志输出
+use std::io::Write;
+
+use log::{Level, LevelFilter};
+use serde::Serialize;
+use tauri::{AppHandle, Emitter};
+
+/// 初始化日志，输出到 stderr
+/// RUST_LOG 环境变量可覆盖 level；已初始化时忽略
+pub fn init(level: LevelFilter) {
+    let _ = env_logger::Builder::new()
+        .filter_level(level)
+        .parse_default_env()
+        .format(|buf, record| {
+            writeln!(buf, "{}{}", level_prefix(record.level(), record.target()), record.args())
+        })
+        .try_init();
+}
+
+/// 每行日志的前缀：错误和警告沿用命令行原有的提示格式，调试信息附带模块名
+fn level_prefix(level: Level, target: &str) -> String {
+    match level {
+        Level::Error => "错误: ".to_string(),
+        Level::Warn => "⚠ ".to_string(),
+        Level::Info => String::new(),
+        Level::Debug | Level::Trace => format!("[{}] {}: ", level, target),
+    }
+}
+
+/// 向前端发送事件，失败时记录调试日志
+pub(crate) fn emit_logged<S: Serialize + Clone>(app_handle: &AppHandle, event: &str, payload: S) {
+    if let Err(e) = app_handle.emit(event, payload) {
+        log::debug!("发送 {} 事件失败: {}", event, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_level_prefix() {
+        assert_eq!(level_prefix(Level::Error, "photo_truck_lib::cli"), "错误: ");
+        assert_eq!(level_prefix(Level::Warn, "photo_truck_lib::cli"), "⚠ ");
+        assert_eq!(level_prefix(Level::Info, "photo_truck_lib::cli"), "");
+        assert_eq!(
+            level_prefix(Level::Debug, "photo_truck_lib::transfer_v2"),
+            "[DEBUG] photo_truck_lib::transfer_v2: "
+        );
+    }
+}
@@ -0,0 +1,205 @@
+// RAW 预览导出
+// 把 RAW 内嵌的全尺寸 JPEG 预览导出到与目标文件夹结构相同的独立文件夹，用于交付样片
+use crate::classify::is_raw_photo;
+use crate::history::{TransferFileStatus, TransferredFile};
+use crate::transfer::{PlanAction, PlanEntry};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// RAW 预览的导出方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RawPreviewMode {
+    /// 不导出预览
+    #[default]
+    Off,
+    /// 复制 RAW，同时导出预览
+    Alongside,
+    /// 只导出预览，不复制 RAW
+    Only,
+}
+
+/// 未指定预览文件夹时，在目标文件夹名后添加的后缀
+pub const PREVIEW_DIR_SUFFIX: &str = "_JPEG";
+
+/// 预览文件夹：未指定时为与目标文件夹同级、名称加 PREVIEW_DIR_SUFFIX 的文件夹（如 /nas/photos_JPEG）
+pub fn preview_root(target_dir: &Path, preview_dir: Option<&str>) -> PathBuf {
+    match preview_dir.map(str::trim).filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => {
+            let name = target_dir
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_default();
+            target_dir.with_file_name(format!("{}{}", name, PREVIEW_DIR_SUFFIX))
+        }
+    }
+}
+
+/// RAW 的目标路径对应的预览路径：在预览文件夹中保持相对目标文件夹的结构，扩展名改为 .jpg
+pub fn preview_path(target_dir: &Path, preview_root: &Path, raw_target: &Path) -> PathBuf {
+    // 不在目标文件夹中时只保留文件名
+    let relative = raw_target
+        .strip_prefix(target_dir)
+        .ok()
+        .or_else(|| raw_target.file_name().map(Path::new))
+        .unwrap_or(raw_target);
+    preview_root.join(relative).with_extension("jpg")
+}
+
+/// 需要导出预览的计划条目：需要复制的 RAW
+pub fn is_preview_entry(entry: &PlanEntry) -> bool {
+    entry.action == PlanAction::Copy && is_raw_photo(&entry.source_path)
+}
+
+/// 导出预览的结果
+#[derive(Debug, Default)]
+pub struct PreviewExport {
+    /// 写入的预览文件
+    pub exported: Vec<String>,
+    /// 跳过的 RAW 及原因（没有足够大的内嵌预览、预览文件已存在）
+    pub notes: Vec<String>,
+    pub errors: Vec<String>,
+    /// 每个处理过的 RAW 一条记录，目标路径为预览路径：导出为 Success，跳过为 Skipped，失败为 Error
+    pub files: Vec<TransferredFile>,
+}
+
+/// 为计划中需要复制的 RAW 导出预览。extract 读取 RAW 中最大的内嵌 JPEG，没有合适的预览时返回 None
+/// （默认为 thumbnail::extract_largest_preview）。已存在的预览文件不会被覆盖
+pub fn export_raw_previews(
+    entries: &[PlanEntry],
+    target_dir: &Path,
+    preview_root: &Path,
+    extract: impl Fn(&str) -> Result<Option<Vec<u8>>, String>,
+    is_cancelled: impl Fn() -> bool,
+) -> PreviewExport {
+    let mut export = PreviewExport::default();
+    for entry in entries.iter().filter(|e| is_preview_entry(e)) {
+        if is_cancelled() {
+            break;
+        }
+        let path = preview_path(target_dir, preview_root, Path::new(&entry.target_path));
+        let record = |file_size: u64, status: TransferFileStatus| TransferredFile {
+            source_path: entry.source_path.clone(),
+            target_path: path.to_string_lossy().to_string(),
+            file_size,
+            status,
+            camera: entry.camera.clone(),
+            matched_original: None,
+        };
+        if path.exists() {
+            export.notes.push(format!("预览已存在，跳过: {}", path.display()));
+            export.files.push(record(entry.file_size, TransferFileStatus::Skipped));
+            continue;
+        }
+        let data = match extract(&entry.source_path) {
+            Ok(Some(data)) => data,
+            Ok(None) => {
+                export.notes.push(format!("没有可导出的内嵌预览，跳过: {}", entry.source_path));
+                export.files.push(record(entry.file_size, TransferFileStatus::Skipped));
+                continue;
+            }
+            Err(e) => {
+                let message = format!("提取预览失败 {}: {}", entry.source_path, e);
+                export.errors.push(message.clone());
+                export.files.push(record(entry.file_size, TransferFileStatus::Error(message)));
+                continue;
+            }
+        };
+        let written = match path.parent() {
+            Some(parent) => fs::create_dir_all(parent).and_then(|_| fs::write(&path, &data)),
+            None => fs::write(&path, &data),
+        };
+        match written {
+            Ok(()) => {
+                export.exported.push(path.to_string_lossy().to_string());
+                export.files.push(record(data.len() as u64, TransferFileStatus::Success));
+            }
+            Err(e) => {
+                let message = format!("写入预览失败 {}: {}", path.display(), e);
+                export.errors.push(message.clone());
+                export.files.push(record(entry.file_size, TransferFileStatus::Error(message)));
+            }
+        }
+    }
+    export
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn entry(source: &str, target: &Path, action: PlanAction) -> PlanEntry {
+        PlanEntry {
+            source_path: source.to_string(),
+            file_name: Path::new(source).file_name().unwrap().to_string_lossy().to_string(),
+            file_size: 1,
+            camera: None,
+            target_path: target.to_string_lossy().to_string(),
+            action,
+            duplicate_of: None,
+            error: None,
+        }
+    }
+
+    #[test]
+    fn test_preview_root_and_path() {
+        let target = Path::new("/nas/photos");
+        assert_eq!(preview_root(target, None), PathBuf::from("/nas/photos_JPEG"));
+        assert_eq!(preview_root(target, Some(" ")), PathBuf::from("/nas/photos_JPEG"));
+        assert_eq!(preview_root(target, Some("/proofs")), PathBuf::from("/proofs"));
+        assert_eq!(
+            preview_path(target, Path::new("/proofs"), Path::new("/nas/photos/2024/03/IMG_0001.CR3")),
+            PathBuf::from("/proofs/2024/03/IMG_0001.jpg")
+        );
+    }
+
+    #[test]
+    fn test_export_raw_previews_with_mock_extraction() {
+        let dir = TempDir::new().unwrap();
+        let target = dir.path().join("photos");
+        let root = preview_root(&target, None);
+        fs::create_dir_all(root.join("2024")).unwrap();
+        fs::write(root.join("2024/EXISTING.jpg"), b"old").unwrap();
+        let entries = vec![
+            entry("/card/IMG_0001.CR3", &target.join("2024/03/IMG_0001.CR3"), PlanAction::Copy),
+            entry("/card/IMG_0002.NEF", &target.join("2024/03/IMG_0002.NEF"), PlanAction::Copy),
+            entry("/card/IMG_0003.ARW", &target.join("2024/03/IMG_0003.ARW"), PlanAction::Copy),
+            entry("/card/EXISTING.DNG", &target.join("2024/EXISTING.DNG"), PlanAction::Copy),
+            // 非 RAW 和跳过的重复文件不导出
+            entry("/card/IMG_0004.JPG", &target.join("2024/03/IMG_0004.JPG"), PlanAction::Copy),
+            entry("/card/IMG_0005.CR3", &target.join("2024/03/IMG_0005.CR3"), PlanAction::SkipDuplicate),
+        ];
+        let extracted = std::cell::RefCell::new(Vec::new());
+        let extract = |source: &str| {
+            extracted.borrow_mut().push(source.to_string());
+            match source {
+                "/card/IMG_0001.CR3" => Ok(Some(b"full preview".to_vec())),
+                "/card/IMG_0002.NEF" => Ok(None),
+                _ => Err("ExifTool 未安装".to_string()),
+            }
+        };
+
+        let export = export_raw_previews(&entries, &target, &root, extract, || false);
+        assert_eq!(export.exported, vec![root.join("2024/03/IMG_0001.jpg").to_string_lossy().to_string()]);
+        assert_eq!(fs::read(root.join("2024/03/IMG_0001.jpg")).unwrap(), b"full preview");
+        assert_eq!(export.notes.len(), 2);
+        assert!(export.notes[0].contains("IMG_0002.NEF"));
+        assert!(export.notes[1].contains("EXISTING.jpg"));
+        assert_eq!(export.errors.len(), 1);
+        assert!(export.errors[0].contains("IMG_0003.ARW"));
+        assert_eq!(fs::read(root.join("2024/EXISTING.jpg")).unwrap(), b"old");
+        assert_eq!(extracted.borrow().len(), 3);
+
+        // 每个处理过的 RAW 一条记录
+        let statuses: Vec<_> = export.files.iter().map(|f| (f.source_path.as_str(), f.status.clone())).collect();
+        assert_eq!(statuses.len(), 4);
+        assert!(statuses.contains(&("/card/IMG_0001.CR3", TransferFileStatus::Success)));
+        assert!(statuses.contains(&("/card/IMG_0002.NEF", TransferFileStatus::Skipped)));
+        assert!(statuses.contains(&("/card/EXISTING.DNG", TransferFileStatus::Skipped)));
+        assert!(export.files.iter().any(|f| f.source_path == "/card/IMG_0003.ARW" && matches!(f.status, TransferFileStatus::Error(_))));
+        assert_eq!(export.files[0].target_path, root.join("2024/03/IMG_0001.jpg").to_string_lossy());
+    }
+}
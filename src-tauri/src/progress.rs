@@ -0,0 +1,93 @@
+// 进度报告
+// 传输核心逻辑通过 ProgressSink 报告进度：图形界面发送 Tauri 事件，命令行打印进度条
+use std::io::Write;
+
+use tauri::AppHandle;
+
+use crate::logging::emit_logged;
+use crate::transfer::{DedupScanProgress, TransferPhase, TransferProgress};
+
+/// 接收传输过程中的进度
+pub trait ProgressSink: Send + Sync {
+    /// 传输进度（准备、扫描目标目录、复制、校验和结束）
+    fn transfer_progress(&self, progress: TransferProgress);
+
+    /// 去重时扫描目标目录和参考目录已有文件的进度
+    fn dedup_scan_progress(&self, _progress: DedupScanProgress) {}
+
+    /// 源文件夹无法访问（如存储卡被拔出），传输已中止
+    fn source_unavailable(&self, _message: &str) {}
+}
+
+/// 图形界面：发送 transfer-progress、dedup-scan-progress 和 source-unavailable 事件
+impl ProgressSink for AppHandle {
+    fn transfer_progress(&self, progress: TransferProgress) {
+        emit_logged(self, "transfer-progress", progress);
+    }
+
+    fn dedup_scan_progress(&self, progress: DedupScanProgress) {
+        emit_logged(self, "dedup-scan-progress", progress);
+    }
+
+    fn source_unavailable(&self, message: &str) {
+        emit_logged(self, "source-unavailable", message);
+    }
+}
+
+/// 命令行进度条的宽度（字符数）
+const BAR_WIDTH: usize = 30;
+
+/// 命令行：在同一行刷新进度条，传输结束时换行
+pub struct CliProgress;
+
+/// 进度条文本，如 `[##########--------------------] 10/30 (33%)`
+fn progress_bar(current: usize, total: usize) -> String {
+    let ratio = if total == 0 { 1.0 } else { current.min(total) as f64 / total as f64 };
+    let filled = (ratio * BAR_WIDTH as f64).round() as usize;
+    format!(
+        "[{}{}] {}/{} ({:.0}%)",
+        "#".repeat(filled),
+        "-".repeat(BAR_WIDTH - filled),
+        current,
+        total,
+        ratio * 100.0
+    )
+}
+
+impl ProgressSink for CliProgress {
+    fn transfer_progress(&self, progress: TransferProgress) {
+        let line = match progress.phase {
+            TransferPhase::Preparing | TransferPhase::Scanning => progress.current_file.clone(),
+            TransferPhase::Transferring => format!("传输进度: {}", progress_bar(progress.current, progress.total)),
+            TransferPhase::Verifying => format!("校验进度: {}", progress_bar(progress.current, progress.total)),
+            TransferPhase::Completed | TransferPhase::Cancelled | TransferPhase::Aborted => {
+                println!("\r{:<60}", progress.current_file);
+                return;
+            }
+        };
+        print!("\r{:<60}", line);
+        std::io::stdout().flush().ok();
+    }
+
+    fn dedup_scan_progress(&self, progress: DedupScanProgress) {
+        if let Some(total) = progress.total {
+            print!("\r扫描目标目录: {:<40}", progress_bar(progress.indexed, total));
+            std::io::stdout().flush().ok();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_progress_bar() {
+        assert_eq!(progress_bar(0, 4), format!("[{}] 0/4 (0%)", "-".repeat(BAR_WIDTH)));
+        assert_eq!(
+            progress_bar(1, 2),
+            format!("[{}{}] 1/2 (50%)", "#".repeat(BAR_WIDTH / 2), "-".repeat(BAR_WIDTH / 2))
+        );
+        assert_eq!(progress_bar(0, 0), format!("[{}] 0/0 (100%)", "#".repeat(BAR_WIDTH)));
+    }
+}
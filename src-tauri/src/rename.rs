@@ -1,9 +1,11 @@
+use crate::classify::{make_portable_name, OrientationLabels, TemplateValidation};
 use crate::exif::PhotoMetadata;
 use serde::{Deserialize, Serialize};
 use std::path::Path;
 
 /// 重命名规则配置
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct RenameConfig {
     /// 是否启用重命名
     pub enabled: bool,
@@ -11,22 +13,142 @@ pub struct RenameConfig {
     pub template: String,
     /// 计数器起始值
     pub counter_start: u32,
+    /// 计数器起始值的确定方式，可以接着目标文件夹中已有的照片继续编号
+    pub counter_mode: CounterMode,
     /// 计数器位数
     pub counter_digits: u32,
+    /// 扩展名大小写，未启用重命名时同样生效
+    pub extension_case: ExtCase,
+    /// 新文件名主干（不含扩展名）的大小写，在清理文件名之后应用
+    pub case: NameCase,
+    /// 同一目录下文件名相同的文件（如 RAW+JPEG）使用相同的新文件名，只占用一个计数
+    pub pair_by_basename: bool,
 }
 
+/// {counter} 起始值的确定方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CounterMode {
+    /// 每次导入都从 counter_start 开始
+    #[default]
+    Fixed,
+    /// 接着目标文件夹中已有的照片数编号：已有 N 张照片时从 counter_start + N 开始
+    ExistingCount,
+    /// 接着目标文件夹中照片文件名末尾的最大编号继续，如已有 photo_0846.jpg 时从 847 开始；
+    /// 文件名末尾没有数字的照片被忽略，都没有时从 counter_start 开始
+    MaxSuffix,
+}
+
+/// 文件名（不含扩展名）末尾的数字编号，如 `photo_0846` -> 846；没有数字或超出范围时返回 None
+pub fn trailing_number(stem: &str) -> Option<u32> {
+    let digits = stem.len() - stem.trim_end_matches(|c: char| c.is_ascii_digit()).len();
+    stem[stem.len() - digits..].parse().ok()
+}
+
+/// 扩展名大小写处理方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExtCase {
+    /// 保持原样
+    #[default]
+    Preserve,
+    /// 转为小写，如 `.jpg`
+    Lower,
+    /// 转为大写，如 `.JPG`
+    Upper,
+}
+
+/// 文件名主干大小写处理方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NameCase {
+    /// 保持原样
+    #[default]
+    Preserve,
+    /// 转为小写，如 `img_0001`
+    Lower,
+    /// 转为大写，如 `IMG_0001`
+    Upper,
+    /// 每个单词首字母大写、其余小写，如 `Img_0001`、`Canon Eos R5`
+    Title,
+}
+
+impl NameCase {
+    /// 按设置转换文件名主干
+    pub fn apply(self, stem: &str) -> String {
+        match self {
+            NameCase::Preserve => stem.to_string(),
+            NameCase::Lower => stem.to_lowercase(),
+            NameCase::Upper => stem.to_uppercase(),
+            NameCase::Title => {
+                let mut result = String::with_capacity(stem.len());
+                let mut word_start = true;
+                for c in stem.chars() {
+                    if word_start {
+                        result.extend(c.to_uppercase());
+                    } else {
+                        result.extend(c.to_lowercase());
+                    }
+                    word_start = !c.is_alphanumeric();
+                }
+                result
+            }
+        }
+    }
+}
+
+impl ExtCase {
+    /// 按设置转换扩展名
+    pub fn apply(self, extension: &str) -> String {
+        match self {
+            ExtCase::Preserve => extension.to_string(),
+            ExtCase::Lower => extension.to_lowercase(),
+            ExtCase::Upper => extension.to_uppercase(),
+        }
+    }
+}
+
+/// {counter} 的位数范围，超出时按边界处理，避免过大的位数生成超长文件名
+pub const COUNTER_DIGITS_RANGE: std::ops::RangeInclusive<u32> = 1..=12;
+
 impl Default for RenameConfig {
     fn default() -> Self {
         Self {
             enabled: false,
             template: "{original}".to_string(),
             counter_start: 1,
+            counter_mode: CounterMode::Fixed,
             counter_digits: 4,
+            extension_case: ExtCase::Preserve,
+            case: NameCase::Preserve,
+            pair_by_basename: false,
         }
     }
 }
 
 impl RenameConfig {
+    /// 限制在 COUNTER_DIGITS_RANGE 内的计数器位数
+    pub fn counter_width(&self) -> usize {
+        self.counter_digits.clamp(*COUNTER_DIGITS_RANGE.start(), *COUNTER_DIGITS_RANGE.end()) as usize
+    }
+
+    /// 将 counter_digits 修正到 COUNTER_DIGITS_RANGE 内，修正时返回提示
+    pub fn normalize_counter_digits(&mut self) -> Option<String> {
+        let width = self.counter_width() as u32;
+        if width == self.counter_digits {
+            return None;
+        }
+        let warning = format!(
+            "计数器位数 {} 超出范围 {}-{}，已调整为 {}",
+            self.counter_digits,
+            COUNTER_DIGITS_RANGE.start(),
+            COUNTER_DIGITS_RANGE.end(),
+            width
+        );
+        self.counter_digits = width;
+        Some(warning)
+    }
+
     /// 根据照片元数据生成新文件名
     /// 
     /// 支持的变量:
@@ -34,6 +156,9 @@ impl RenameConfig {
     /// - {year}, {month}, {day} - 日期
     /// - {hour}, {minute}, {second} - 时间
     /// - {camera}, {make} - 相机信息
+    /// - {camera_full} - 品牌和型号合并后的相机全称
+    /// - {orientation} - 构图方向（横构图/竖构图/方图，使用默认标签）
+    /// - {ext} - 原扩展名（按 extension_case 转换）；模板包含 {ext} 时不再自动在末尾添加扩展名
     /// - {counter} - 自增计数器
     /// - {date} - 日期 YYYYMMDD
     /// - {time} - 时间 HHMMSS
@@ -44,9 +169,21 @@ impl RenameConfig {
         counter: u32,
     ) -> String {
         if !self.enabled {
-            return metadata.file_name.clone();
+            return self.normalize_extension(&metadata.file_name);
         }
+        self.try_generate_filename(metadata, counter).unwrap_or_else(|| {
+            // 模板没有生成可用的文件名时使用原文件名
+            let path = Path::new(&metadata.file_name);
+            let original_name = path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+            match path.extension() {
+                Some(ext) => format!("{}.{}", original_name, self.extension_case.apply(&ext.to_string_lossy())),
+                None => original_name,
+            }
+        })
+    }
 
+    /// 按模板生成新文件名（不检查 enabled）；文件名主干为空或只剩分隔符（如 "_"、"." 或 ".jpg"）时返回 None
+    pub fn try_generate_filename(&self, metadata: &PhotoMetadata, counter: u32) -> Option<String> {
         let original_name = Path::new(&metadata.file_name)
             .file_stem()
             .map(|s| s.to_string_lossy().to_string())
@@ -54,16 +191,20 @@ impl RenameConfig {
         
         let extension = Path::new(&metadata.file_name)
             .extension()
-            .map(|s| s.to_string_lossy().to_string())
+            .map(|s| self.extension_case.apply(&s.to_string_lossy()))
             .unwrap_or_default();
 
         let mut name = self.template.clone();
 
+        // 模板中指定了扩展名的位置时，由模板决定扩展名
+        let has_ext = name.contains("{ext}");
+        name = name.replace("{ext}", &extension);
+
         // 替换原文件名
         name = name.replace("{original}", &original_name);
 
         // 替换计数器
-        let counter_str = format!("{:0width$}", counter, width = self.counter_digits as usize);
+        let counter_str = format!("{:0width$}", counter, width = self.counter_width());
         name = name.replace("{counter}", &counter_str);
 
         // 解析日期时间
@@ -101,6 +242,11 @@ impl RenameConfig {
         let make = sanitize_filename(metadata.make.as_deref().unwrap_or(""));
         name = name.replace("{camera}", &camera);
         name = name.replace("{make}", &make);
+        let camera_full = sanitize_filename(&metadata.camera_full().unwrap_or_default());
+        name = name.replace("{camera_full}", &camera_full);
+        let (width, height) = metadata.display_dimensions();
+        let orientation = OrientationLabels::default();
+        name = name.replace("{orientation}", &sanitize_filename(orientation.label(width, height)));
 
         // 清理文件名
         name = sanitize_filename(&name);
@@ -109,18 +255,38 @@ impl RenameConfig {
         while name.contains("__") {
             name = name.replace("__", "_");
         }
-        name = name.trim_matches('_').to_string();
+        name = make_portable_name(name.trim_matches('_'));
 
-        // 如果文件名为空，使用原文件名
-        if name.is_empty() {
-            name = original_name;
+        // 模板以 {ext} 结尾时扩展名不参与大小写转换
+        let (stem, suffix) = if has_ext {
+            name.strip_suffix(extension.as_str())
+                .map_or((name.as_str(), ""), |stem| (stem, extension.as_str()))
+        } else {
+            (name.as_str(), "")
+        };
+        if is_blank_stem(stem) {
+            return None;
         }
+        let stem = self.case.apply(stem);
 
         // 添加扩展名
-        if !extension.is_empty() {
-            format!("{}.{}", name, extension)
+        if !has_ext && !extension.is_empty() {
+            Some(format!("{}.{}", stem, extension))
         } else {
-            name
+            Some(format!("{}{}", stem, suffix))
+        }
+    }
+
+    /// 按 extension_case 转换文件名的扩展名，文件名其余部分不变
+    pub fn normalize_extension(&self, file_name: &str) -> String {
+        let path = Path::new(file_name);
+        match (path.file_stem(), path.extension()) {
+            (Some(stem), Some(ext)) if self.extension_case != ExtCase::Preserve => format!(
+                "{}.{}",
+                stem.to_string_lossy(),
+                self.extension_case.apply(&ext.to_string_lossy())
+            ),
+            _ => file_name.to_string(),
         }
     }
 }
@@ -154,16 +320,76 @@ fn parse_datetime(datetime_str: &str) -> Option<(u32, u32, u32, u32, u32, u32)>
     None
 }
 
+/// 文件名主干是否为空，或只包含空白、点、下划线和连字符
+fn is_blank_stem(stem: &str) -> bool {
+    stem.chars().all(|c| c.is_whitespace() || matches!(c, '.' | '_' | '-'))
+}
+
 /// 清理文件名中的非法字符
 fn sanitize_filename(name: &str) -> String {
-    name.chars()
+    let cleaned = name.chars()
         .map(|c| match c {
             '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
             _ => c,
         })
-        .collect::<String>()
-        .trim()
-        .to_string()
+        .collect::<String>();
+    make_portable_name(cleaned.trim())
+}
+
+/// 重命名模板支持的变量
+pub const RENAME_VARIABLES: &[&str] = &[
+    "{original}", "{year}", "{month}", "{day}", "{hour}", "{minute}", "{second}", "{date}", "{time}",
+    "{datetime}", "{camera}", "{make}", "{camera_full}", "{orientation}", "{ext}", "{counter}",
+];
+
+/// 验证重命名模板：检查是否包含有效变量、是否有未知变量，并用示例照片生成文件名
+pub fn validate_rename_template(template: &str) -> TemplateValidation {
+    let mut warnings = Vec::new();
+
+    // 空模板时保持原文件名
+    let has_valid_var = RENAME_VARIABLES.iter().any(|v| template.contains(v));
+    if !template.trim().is_empty() && !has_valid_var {
+        warnings.push("模板中没有包含任何有效变量，所有照片会使用相同的文件名".to_string());
+    }
+
+    // 检查未知变量
+    let re = regex::Regex::new(r"\{[^}]+\}").unwrap();
+    for cap in re.find_iter(template) {
+        if !RENAME_VARIABLES.contains(&cap.as_str()) {
+            warnings.push(format!("未知变量: {}", cap.as_str()));
+        }
+    }
+
+    // 生成示例
+    let config = RenameConfig {
+        enabled: true,
+        template: template.to_string(),
+        ..Default::default()
+    };
+    let sample = PhotoMetadata {
+        file_path: "/DCIM/100CANON/IMG_0001.CR3".to_string(),
+        file_name: "IMG_0001.CR3".to_string(),
+        file_size: 0,
+        date_time_original: Some("2024:03:15 10:30:45".to_string()),
+        create_date: None,
+        make: Some("Canon".to_string()),
+        model: Some("EOS R5".to_string()),
+        mime_type: None,
+        width: Some(6000),
+        height: Some(4000),
+        orientation: None,
+        rating: None,
+        label: None,
+        keywords: Vec::new(),
+    };
+    let example = config.generate_filename(&sample, config.counter_start);
+
+    TemplateValidation {
+        valid: warnings.is_empty(),
+        example,
+        warnings,
+        supported_vars: RENAME_VARIABLES.iter().map(|s| s.to_string()).collect(),
+    }
 }
 
 /// 预设的重命名模板
@@ -192,6 +418,12 @@ mod tests {
             make: Some("Canon".to_string()),
             model: Some("EOS R5".to_string()),
             mime_type: None,
+            width: None,
+            height: None,
+            orientation: None,
+            rating: None,
+            label: None,
+            keywords: Vec::new(),
         }
     }
 
@@ -209,7 +441,11 @@ mod tests {
             enabled: true,
             template: "{date}_{original}".to_string(),
             counter_start: 1,
+            counter_mode: CounterMode::Fixed,
             counter_digits: 4,
+            extension_case: ExtCase::Preserve,
+            case: NameCase::Preserve,
+            pair_by_basename: false,
         };
         let metadata = create_test_metadata("IMG_0001.CR3", Some("2024:03:15 10:30:45"));
         let result = config.generate_filename(&metadata, 1);
@@ -222,7 +458,11 @@ mod tests {
             enabled: true,
             template: "{date}_{counter}".to_string(),
             counter_start: 1,
+            counter_mode: CounterMode::Fixed,
             counter_digits: 4,
+            extension_case: ExtCase::Preserve,
+            case: NameCase::Preserve,
+            pair_by_basename: false,
         };
         let metadata = create_test_metadata("IMG_0001.CR3", Some("2024:03:15 10:30:45"));
         let result = config.generate_filename(&metadata, 42);
@@ -235,20 +475,51 @@ mod tests {
             enabled: true,
             template: "{camera}_{counter}".to_string(),
             counter_start: 1,
+            counter_mode: CounterMode::Fixed,
             counter_digits: 3,
+            extension_case: ExtCase::Preserve,
+            case: NameCase::Preserve,
+            pair_by_basename: false,
         };
         let metadata = create_test_metadata("IMG_0001.JPG", Some("2024:03:15 10:30:45"));
         let result = config.generate_filename(&metadata, 1);
         assert_eq!(result, "EOS R5_001.JPG");
     }
 
+    #[test]
+    fn test_rename_with_camera_full() {
+        let config = RenameConfig {
+            enabled: true,
+            template: "{camera_full}_{original}".to_string(),
+            counter_start: 1,
+            counter_mode: CounterMode::Fixed,
+            counter_digits: 3,
+            extension_case: ExtCase::Preserve,
+            case: NameCase::Preserve,
+            pair_by_basename: false,
+        };
+        let mut metadata = create_test_metadata("DSC_0001.NEF", None);
+        metadata.make = Some("NIKON CORPORATION".to_string());
+        metadata.model = Some("NIKON Z 6".to_string());
+        assert_eq!(config.generate_filename(&metadata, 1), "Nikon Z 6_DSC_0001.NEF");
+
+        // 没有相机信息时去掉占位符
+        metadata.make = None;
+        metadata.model = None;
+        assert_eq!(config.generate_filename(&metadata, 1), "DSC_0001.NEF");
+    }
+
     #[test]
     fn test_rename_with_datetime() {
         let config = RenameConfig {
             enabled: true,
             template: "{datetime}".to_string(),
             counter_start: 1,
+            counter_mode: CounterMode::Fixed,
             counter_digits: 4,
+            extension_case: ExtCase::Preserve,
+            case: NameCase::Preserve,
+            pair_by_basename: false,
         };
         let metadata = create_test_metadata("IMG_0001.CR3", Some("2024:03:15 10:30:45"));
         let result = config.generate_filename(&metadata, 1);
@@ -267,9 +538,230 @@ mod tests {
         assert_eq!(result, Some((2024, 3, 15, 10, 30, 45)));
     }
 
+    #[test]
+    fn test_sanitize_filename_reserved_and_trailing_dots() {
+        assert_eq!(sanitize_filename("AUX"), "AUX_");
+        assert_eq!(sanitize_filename("com1"), "com1_");
+        assert_eq!(sanitize_filename("photo. ."), "photo");
+        assert_eq!(sanitize_filename("PRN.final"), "PRN_.final");
+        assert_eq!(sanitize_filename("Canon"), "Canon");
+    }
+
+    #[test]
+    fn test_rename_reserved_name() {
+        let config = RenameConfig {
+            enabled: true,
+            template: "{original}".to_string(),
+            counter_start: 1,
+            counter_mode: CounterMode::Fixed,
+            counter_digits: 4,
+            extension_case: ExtCase::Preserve,
+            case: NameCase::Preserve,
+            pair_by_basename: false,
+        };
+        let metadata = create_test_metadata("CON.JPG", Some("2024:03:15 10:30:45"));
+        assert_eq!(config.generate_filename(&metadata, 1), "CON_.JPG");
+
+        let metadata = create_test_metadata("photo..JPG", Some("2024:03:15 10:30:45"));
+        assert_eq!(config.generate_filename(&metadata, 1), "photo.JPG");
+    }
+
     #[test]
     fn test_get_rename_templates() {
         let templates = get_rename_templates();
         assert!(templates.len() >= 5);
     }
+
+    #[test]
+    fn test_rename_extension_case() {
+        let metadata = create_test_metadata("IMG_0001.CR3", Some("2024:03:15 10:30:45"));
+        let config = |extension_case| RenameConfig {
+            enabled: true,
+            template: "{date}_{original}".to_string(),
+            counter_start: 1,
+            counter_mode: CounterMode::Fixed,
+            counter_digits: 4,
+            extension_case,
+            case: NameCase::Preserve,
+            pair_by_basename: false,
+        };
+
+        assert_eq!(config(ExtCase::Preserve).generate_filename(&metadata, 1), "20240315_IMG_0001.CR3");
+        assert_eq!(config(ExtCase::Lower).generate_filename(&metadata, 1), "20240315_IMG_0001.cr3");
+        assert_eq!(config(ExtCase::Upper).generate_filename(&metadata, 1), "20240315_IMG_0001.CR3");
+
+        // 未启用重命名时只转换扩展名
+        let disabled = RenameConfig {
+            extension_case: ExtCase::Lower,
+            ..Default::default()
+        };
+        assert_eq!(disabled.generate_filename(&metadata, 1), "IMG_0001.cr3");
+        assert_eq!(disabled.normalize_extension("IMG_0001.CR3"), "IMG_0001.cr3");
+        assert_eq!(disabled.normalize_extension("README"), "README");
+        let upper = RenameConfig {
+            extension_case: ExtCase::Upper,
+            ..Default::default()
+        };
+        assert_eq!(upper.normalize_extension("img_0001.cr3"), "img_0001.CR3");
+    }
+
+    #[test]
+    fn test_rename_with_ext_token() {
+        let metadata = create_test_metadata("IMG_0001.CR3", Some("2024:03:15 10:30:45"));
+        let config = |template: &str, extension_case| RenameConfig {
+            enabled: true,
+            template: template.to_string(),
+            counter_start: 1,
+            counter_mode: CounterMode::Fixed,
+            counter_digits: 4,
+            extension_case,
+            case: NameCase::Preserve,
+            pair_by_basename: false,
+        };
+
+        // 模板包含 {ext} 时不再自动添加扩展名
+        assert_eq!(config("{date}.{ext}", ExtCase::Preserve).generate_filename(&metadata, 1), "20240315.CR3");
+        assert_eq!(config("{date}.{ext}", ExtCase::Lower).generate_filename(&metadata, 1), "20240315.cr3");
+        assert_eq!(config("{ext}_{original}", ExtCase::Lower).generate_filename(&metadata, 1), "cr3_IMG_0001");
+
+        // 不含 {ext} 时保持自动添加
+        assert_eq!(config("{date}", ExtCase::Lower).generate_filename(&metadata, 1), "20240315.cr3");
+
+        // 原文件没有扩展名时不留下结尾的点
+        let no_ext = create_test_metadata("IMG_0001", Some("2024:03:15 10:30:45"));
+        assert_eq!(config("{date}.{ext}", ExtCase::Preserve).generate_filename(&no_ext, 1), "20240315");
+    }
+
+    #[test]
+    fn test_rename_with_orientation() {
+        let config = RenameConfig {
+            enabled: true,
+            template: "{orientation}_{original}".to_string(),
+            counter_start: 1,
+            counter_mode: CounterMode::Fixed,
+            counter_digits: 4,
+            extension_case: ExtCase::Preserve,
+            case: NameCase::Preserve,
+            pair_by_basename: false,
+        };
+        let mut metadata = create_test_metadata("IMG_0001.JPG", None);
+        metadata.width = Some(3000);
+        metadata.height = Some(4000);
+        assert_eq!(config.generate_filename(&metadata, 1), "竖构图_IMG_0001.JPG");
+
+        metadata.orientation = Some(8);
+        assert_eq!(config.generate_filename(&metadata, 1), "横构图_IMG_0001.JPG");
+    }
+
+    #[test]
+    fn test_validate_rename_template_valid() {
+        let result = validate_rename_template("{datetime}_{camera}_{counter}");
+        assert!(result.valid);
+        assert!(result.warnings.is_empty());
+        assert_eq!(result.example, "20240315_103045_EOS R5_0001.CR3");
+        assert!(result.supported_vars.contains(&"{counter}".to_string()));
+
+        for (name, template) in get_rename_templates() {
+            assert!(validate_rename_template(template).valid, "预设模板 {} 应该有效", name);
+        }
+    }
+
+    #[test]
+    fn test_validate_rename_template_unknown_variable() {
+        let result = validate_rename_template("{date}_{ms}");
+        assert!(!result.valid);
+        assert_eq!(result.warnings, vec!["未知变量: {ms}"]);
+        assert!(result.example.starts_with("20240315_"));
+    }
+
+    #[test]
+    fn test_validate_rename_template_no_variables() {
+        let result = validate_rename_template("photo");
+        assert!(!result.valid);
+        assert_eq!(result.warnings.len(), 1);
+        assert!(result.warnings[0].contains("没有包含任何有效变量"));
+        assert_eq!(result.example, "photo.CR3");
+
+        // 空模板保持原文件名
+        let result = validate_rename_template("");
+        assert!(result.valid);
+        assert_eq!(result.example, "IMG_0001.CR3");
+    }
+
+    #[test]
+    fn test_rename_blank_stem_falls_back_to_original() {
+        let config = |template: &str| RenameConfig {
+            enabled: true,
+            template: template.to_string(),
+            ..Default::default()
+        };
+        let mut no_make = create_test_metadata("IMG_0001.jpg", None);
+        no_make.make = None;
+        let undated = create_test_metadata("IMG_0002.jpg", None);
+
+        assert_eq!(config("{make}").generate_filename(&no_make, 1), "IMG_0001.jpg");
+        assert_eq!(config("{make}.{ext}").generate_filename(&no_make, 1), "IMG_0001.jpg");
+        assert_eq!(config("{make} - {date}").generate_filename(&no_make, 1), "IMG_0001.jpg");
+        assert_eq!(config("{year}").generate_filename(&undated, 1), "IMG_0002.jpg");
+        assert_eq!(config("{year}.{month}").generate_filename(&undated, 1), "IMG_0002.jpg");
+        assert_eq!(config("{year}-{month}.{ext}").generate_filename(&undated, 1), "IMG_0002.jpg");
+    }
+
+    #[test]
+    fn test_trailing_number() {
+        assert_eq!(trailing_number("photo_0846"), Some(846));
+        assert_eq!(trailing_number("IMG_1234"), Some(1234));
+        assert_eq!(trailing_number("2024"), Some(2024));
+        assert_eq!(trailing_number("IMG_abc"), None);
+        assert_eq!(trailing_number("旅行照片"), None);
+        assert_eq!(trailing_number(""), None);
+        // 超出 u32 范围的编号被忽略
+        assert_eq!(trailing_number("photo_99999999999"), None);
+    }
+
+    #[test]
+    fn test_counter_digits_clamped() {
+        let metadata = create_test_metadata("IMG_0001.jpg", None);
+        let mut config = RenameConfig {
+            enabled: true,
+            template: "photo_{counter}".to_string(),
+            counter_digits: 2_000_000_000,
+            ..Default::default()
+        };
+        assert_eq!(config.generate_filename(&metadata, 7), "photo_000000000007.jpg");
+        assert_eq!(config.generate_filename(&metadata, u32::MAX), "photo_004294967295.jpg");
+
+        let warning = config.normalize_counter_digits().unwrap();
+        assert!(warning.contains("2000000000"), "{}", warning);
+        assert_eq!(config.counter_digits, 12);
+        assert_eq!(config.normalize_counter_digits(), None);
+
+        config.counter_digits = 0;
+        assert_eq!(config.generate_filename(&metadata, 7), "photo_7.jpg");
+        assert!(config.normalize_counter_digits().is_some());
+        assert_eq!(config.counter_digits, 1);
+    }
+
+    #[test]
+    fn test_name_case() {
+        let metadata = create_test_metadata("IMG_0001.JPG", Some("2024:03:15 10:30:00"));
+        let config = |template: &str, case| RenameConfig {
+            enabled: true,
+            template: template.to_string(),
+            case,
+            ..Default::default()
+        };
+
+        assert_eq!(config("{original}", NameCase::Preserve).generate_filename(&metadata, 1), "IMG_0001.JPG");
+        assert_eq!(config("{original}", NameCase::Lower).generate_filename(&metadata, 1), "img_0001.JPG");
+        assert_eq!(config("{original}", NameCase::Upper).generate_filename(&metadata, 1), "IMG_0001.JPG");
+        assert_eq!(config("{original}", NameCase::Title).generate_filename(&metadata, 1), "Img_0001.JPG");
+        assert_eq!(config("{camera} {original}", NameCase::Title).generate_filename(&metadata, 1), "Eos R5 Img_0001.JPG");
+        assert_eq!(config("Trip_{original}", NameCase::Upper).generate_filename(&metadata, 1), "TRIP_IMG_0001.JPG");
+        // 扩展名由 extension_case 决定
+        let mut lower = config("{original}.{ext}", NameCase::Lower);
+        assert_eq!(lower.generate_filename(&metadata, 1), "img_0001.JPG");
+        lower.extension_case = ExtCase::Lower;
+        assert_eq!(lower.generate_filename(&metadata, 1), "img_0001.jpg");
+    }
 }
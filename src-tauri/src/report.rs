@@ -0,0 +1,343 @@
+use crate::exif::combine_make_model;
+use crate::transfer::{PhotoInfo, ScanResult};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// 单个目标文件夹的分类预览
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClassificationPreview {
+    pub folder: String,
+    pub file_count: usize,
+    /// 文件夹中照片的总字节数
+    #[serde(default)]
+    pub total_size: u64,
+    pub files: Vec<String>,
+}
+
+/// 按目标文件夹分组，结果按文件夹名称排序
+pub fn group_by_folder(photos: &[PhotoInfo]) -> Vec<ClassificationPreview> {
+    let mut groups: HashMap<&str, ClassificationPreview> = HashMap::new();
+
+    for photo in photos {
+        let group = groups
+            .entry(&photo.target_folder)
+            .or_insert_with(|| ClassificationPreview {
+                folder: photo.target_folder.clone(),
+                file_count: 0,
+                total_size: 0,
+                files: Vec::new(),
+            });
+        group.file_count += 1;
+        group.total_size += photo.file_size;
+        group.files.push(photo.file_name.clone());
+    }
+
+    let mut previews: Vec<ClassificationPreview> = groups.into_values().collect();
+    previews.sort_by(|a, b| a.folder.cmp(&b.folder));
+    previews
+}
+
+/// 按总大小从大到小排序，大小相同时按文件夹名称排序
+pub fn sort_by_total_size(previews: &mut [ClassificationPreview]) {
+    previews.sort_by(|a, b| b.total_size.cmp(&a.total_size).then_with(|| a.folder.cmp(&b.folder)));
+}
+
+/// 扫描结果中一组照片（同一相机或同一拍摄月份）的数量和总大小
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SummaryBucket {
+    /// 相机全称或 `YYYY-MM` 月份，缺少信息时为"未知"
+    pub key: String,
+    pub count: usize,
+    pub total_size: u64,
+}
+
+/// 扫描结果统计：按相机和按拍摄月份分组，只使用已读取的元数据，不读取文件
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ScanSummary {
+    pub total_files: usize,
+    pub total_size: u64,
+    /// 按照片数量从多到少排序
+    pub by_camera: Vec<SummaryBucket>,
+    /// 按月份排序，"未知"在最后
+    pub by_month: Vec<SummaryBucket>,
+}
+
+/// 缺少相机或日期信息时的分组名
+pub const UNKNOWN_BUCKET: &str = "未知";
+
+impl ScanSummary {
+    pub fn from_photos(photos: &[PhotoInfo]) -> Self {
+        let mut by_camera = summarize(photos, |photo| {
+            combine_make_model(photo.make.as_deref(), photo.camera.as_deref())
+        });
+        by_camera.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.key.cmp(&b.key)));
+
+        let mut by_month = summarize(photos, |photo| photo.date_time.as_deref().and_then(capture_month));
+        by_month.sort_by(|a, b| {
+            (a.key == UNKNOWN_BUCKET)
+                .cmp(&(b.key == UNKNOWN_BUCKET))
+                .then_with(|| a.key.cmp(&b.key))
+        });
+
+        Self {
+            total_files: photos.len(),
+            total_size: photos.iter().map(|p| p.file_size).sum(),
+            by_camera,
+            by_month,
+        }
+    }
+}
+
+/// 按 key_of 分组统计，key_of 返回 None 的照片归入"未知"
+fn summarize(photos: &[PhotoInfo], key_of: impl Fn(&PhotoInfo) -> Option<String>) -> Vec<SummaryBucket> {
+    let mut buckets: HashMap<String, SummaryBucket> = HashMap::new();
+    for photo in photos {
+        let key = key_of(photo).unwrap_or_else(|| UNKNOWN_BUCKET.to_string());
+        let bucket = buckets.entry(key.clone()).or_insert_with(|| SummaryBucket {
+            key,
+            count: 0,
+            total_size: 0,
+        });
+        bucket.count += 1;
+        bucket.total_size += photo.file_size;
+    }
+    buckets.into_values().collect()
+}
+
+/// 从 EXIF 日期（`2024:03:15 10:00:00` 或 `2024-03-15`）中取出 `2024-03`
+fn capture_month(date_time: &str) -> Option<String> {
+    let year = date_time.get(0..4)?;
+    let month = date_time.get(5..7)?;
+    let valid = year.bytes().all(|b| b.is_ascii_digit())
+        && month.bytes().all(|b| b.is_ascii_digit())
+        && matches!(date_time.as_bytes()[4], b':' | b'-')
+        && year != "0000"
+        && month != "00";
+    valid.then(|| format!("{}-{}", year, month))
+}
+
+/// 扫描报告：传输前的规划清单，包含每个文件的信息和按文件夹的统计
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScanReport {
+    pub generated_at: String,
+    /// 源文件夹，多个源文件夹以 "; " 分隔
+    pub source_dir: String,
+    pub total_files: usize,
+    pub total_size: u64,
+    pub duplicate_count: usize,
+    pub folders: Vec<ClassificationPreview>,
+    pub photos: Vec<PhotoInfo>,
+}
+
+impl ScanReport {
+    /// 根据扫描结果生成报告
+    pub fn from_scan(source_dir: &str, scan: &ScanResult) -> Self {
+        Self {
+            generated_at: chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+            source_dir: source_dir.to_string(),
+            total_files: scan.total_files,
+            total_size: scan.total_size,
+            duplicate_count: scan.photos.iter().filter(|p| p.is_duplicate).count(),
+            folders: group_by_folder(&scan.photos),
+            photos: scan.photos.clone(),
+        }
+    }
+
+    /// 以 JSON 格式写入文件
+    pub fn write_to(&self, path: &Path) -> Result<(), String> {
+        if let Some(parent) = path.parent() {
+            if !parent.as_os_str().is_empty() {
+                fs::create_dir_all(parent).map_err(|e| format!("创建目录失败: {}", e))?;
+            }
+        }
+        let content = serde_json::to_string_pretty(self)
+            .map_err(|e| format!("序列化失败: {}", e))?;
+        fs::write(path, content).map_err(|e| format!("写入报告失败: {}", e))
+    }
+
+    /// 从 JSON 文件读取报告
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let content = fs::read_to_string(path).map_err(|e| format!("读取报告失败: {}", e))?;
+        serde_json::from_str(&content).map_err(|e| format!("解析报告失败: {}", e))
+    }
+}
+
+/// NDJSON 扫描导出的最后一行（`{"summary": {...}}`）：总数和按目标文件夹的文件数
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NdjsonSummary {
+    pub total_files: usize,
+    pub total_size: u64,
+    /// 目标文件夹 -> 文件数
+    pub folders: BTreeMap<String, usize>,
+}
+
+/// 以 NDJSON 格式逐行写入照片：每张照片一行 JSON，最后一行为 `{"summary": NdjsonSummary}`。
+/// 照片取出后立即写入，不在内存中保留全部照片，适合配合 scan_iter 导出大型图库
+pub fn write_photos_ndjson(photos: impl IntoIterator<Item = PhotoInfo>, path: &Path) -> Result<NdjsonSummary, String> {
+    if let Some(parent) = path.parent() {
+        if !parent.as_os_str().is_empty() {
+            fs::create_dir_all(parent).map_err(|e| format!("创建目录失败: {}", e))?;
+        }
+    }
+    let file = fs::File::create(path).map_err(|e| format!("创建导出文件失败: {}", e))?;
+    let mut writer = BufWriter::new(file);
+
+    let mut summary = NdjsonSummary::default();
+    for photo in photos {
+        write_ndjson_line(&mut writer, &photo)?;
+        summary.total_files += 1;
+        summary.total_size += photo.file_size;
+        *summary.folders.entry(photo.target_folder).or_default() += 1;
+    }
+    write_ndjson_line(&mut writer, &serde_json::json!({ "summary": &summary }))?;
+    writer.flush().map_err(|e| format!("写入导出文件失败: {}", e))?;
+    Ok(summary)
+}
+
+/// 写入一行 JSON
+fn write_ndjson_line(writer: &mut impl Write, value: &impl Serialize) -> Result<(), String> {
+    serde_json::to_writer(&mut *writer, value).map_err(|e| format!("序列化失败: {}", e))?;
+    writer.write_all(b"\n").map_err(|e| format!("写入导出文件失败: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn photo(file_name: &str, folder: &str, size: u64, is_duplicate: bool) -> PhotoInfo {
+        PhotoInfo {
+            path: format!("/DCIM/{}", file_name),
+            file_name: file_name.to_string(),
+            file_size: size,
+            date_time: Some("2024:03:15 10:00:00".to_string()),
+            date_time_original: None,
+            create_date: None,
+            camera: Some("Canon EOS R5".to_string()),
+            make: None,
+            target_folder: folder.to_string(),
+            is_duplicate,
+            duplicate_of: None,
+            width: None,
+            height: None,
+            relative_dir: String::new(),
+            suspect: false,
+            source_root: String::new(),
+            rating: None,
+            label: None,
+            keywords: Vec::new(),
+        }
+    }
+
+    fn sample_scan() -> ScanResult {
+        let photos = vec![
+            photo("IMG_0002.CR3", "2024/03", 300, false),
+            photo("IMG_0001.CR3", "2024/01", 100, false),
+            photo("IMG_0003.CR3", "2024/03", 200, true),
+        ];
+        ScanResult {
+            total_files: photos.len(),
+            total_size: photos.iter().map(|p| p.file_size).sum(),
+            photos,
+            skipped_unreadable: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_group_by_folder() {
+        let groups = group_by_folder(&sample_scan().photos);
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].folder, "2024/01");
+        assert_eq!(groups[1].folder, "2024/03");
+        assert_eq!(groups[1].file_count, 2);
+        assert_eq!(groups[1].files, vec!["IMG_0002.CR3", "IMG_0003.CR3"]);
+    }
+
+    #[test]
+    fn test_group_by_folder_total_size() {
+        let mut groups = group_by_folder(&sample_scan().photos);
+        assert_eq!(groups[0].total_size, 100);
+        assert_eq!(groups[1].total_size, 500);
+
+        sort_by_total_size(&mut groups);
+        assert_eq!(groups[0].folder, "2024/03");
+        assert_eq!(groups[1].folder, "2024/01");
+    }
+
+    #[test]
+    fn test_scan_report_round_trip() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("reports").join("scan.json");
+
+        let report = ScanReport::from_scan("/DCIM", &sample_scan());
+        assert_eq!(report.total_files, 3);
+        assert_eq!(report.total_size, 600);
+        assert_eq!(report.duplicate_count, 1);
+        report.write_to(&path).unwrap();
+
+        let loaded = ScanReport::load(&path).unwrap();
+        assert_eq!(loaded.source_dir, "/DCIM");
+        assert_eq!(loaded.total_files, 3);
+        assert_eq!(loaded.total_size, 600);
+        assert_eq!(loaded.duplicate_count, 1);
+        assert_eq!(loaded.folders.len(), 2);
+        assert_eq!(loaded.photos.len(), 3);
+        assert_eq!(loaded.photos[2].target_folder, "2024/03");
+        assert!(loaded.photos[2].is_duplicate);
+    }
+
+    #[test]
+    fn test_scan_summary() {
+        let mut photos = sample_scan().photos;
+        photos[1].date_time = Some("2019:07:01 08:00:00".to_string());
+        let mut sony = photo("DSC00001.ARW", "2024/03", 50, false);
+        sony.make = Some("SONY".to_string());
+        sony.camera = Some("ILCE-7M3".to_string());
+        photos.push(sony);
+        let mut unknown = photo("IMG_0004.JPG", "未知日期", 10, false);
+        unknown.camera = None;
+        unknown.date_time = Some("0000:00:00 00:00:00".to_string());
+        photos.push(unknown);
+        let scan = ScanResult {
+            total_files: photos.len(),
+            total_size: photos.iter().map(|p| p.file_size).sum(),
+            photos,
+            skipped_unreadable: Vec::new(),
+        };
+
+        let summary = scan.summary();
+        assert_eq!(summary.total_files, 5);
+        assert_eq!(summary.total_size, 660);
+        let cameras: Vec<(&str, usize, u64)> =
+            summary.by_camera.iter().map(|b| (b.key.as_str(), b.count, b.total_size)).collect();
+        assert_eq!(cameras, [("Canon EOS R5", 3, 600), ("Sony ILCE-7M3", 1, 50), ("未知", 1, 10)]);
+        let months: Vec<(&str, usize)> = summary.by_month.iter().map(|b| (b.key.as_str(), b.count)).collect();
+        assert_eq!(months, [("2019-07", 1), ("2024-03", 3), ("未知", 1)]);
+    }
+
+    #[test]
+    fn test_write_photos_ndjson() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("export").join("scan.ndjson");
+        let photos = sample_scan().photos;
+
+        let summary = write_photos_ndjson(photos.clone(), &path).unwrap();
+        assert_eq!((summary.total_files, summary.total_size), (3, 600));
+        assert_eq!(summary.folders, BTreeMap::from([("2024/01".to_string(), 1), ("2024/03".to_string(), 2)]));
+
+        let content = fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = content.lines().collect();
+        // 每张照片一行，最后一行为统计
+        assert_eq!(lines.len() - 1, photos.len());
+        for (line, photo) in lines.iter().zip(&photos) {
+            let parsed: PhotoInfo = serde_json::from_str(line).unwrap();
+            assert_eq!(parsed.path, photo.path);
+        }
+        let trailer: serde_json::Value = serde_json::from_str(lines[3]).unwrap();
+        let parsed: NdjsonSummary = serde_json::from_value(trailer["summary"].clone()).unwrap();
+        assert_eq!(parsed, summary);
+    }
+}
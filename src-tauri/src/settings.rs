@@ -0,0 +1,108 @@
+// 应用设置持久化
+// 保存上次使用的分类、重命名配置和源/目标文件夹，下次启动时恢复
+use crate::classify::ClassifyConfig;
+use crate::history::TransferHistory;
+use crate::rename::RenameConfig;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// 上次使用的配置，保存在历史记录同一目录下的 config.json
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AppSettings {
+    pub classify_config: ClassifyConfig,
+    pub rename_config: RenameConfig,
+    /// 上次扫描的源文件夹
+    pub last_source_dirs: Vec<String>,
+    /// 上次传输的目标文件夹
+    pub last_target_dir: Option<String>,
+}
+
+impl AppSettings {
+    /// 获取配置文件路径
+    pub fn get_settings_file_path() -> PathBuf {
+        TransferHistory::get_history_file_path().with_file_name("config.json")
+    }
+
+    /// 加载配置
+    pub fn load() -> Self {
+        Self::load_from(&Self::get_settings_file_path())
+    }
+
+    /// 从指定文件加载配置，文件不存在或无法解析时返回默认配置
+    pub fn load_from(path: &Path) -> Self {
+        match fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                log::warn!("配置文件无法解析，使用默认配置 {}: {}", path.display(), e);
+                Self::default()
+            }),
+            Err(_) => Self::default(),
+        }
+    }
+
+    /// 保存配置
+    pub fn save(&self) -> Result<(), String> {
+        self.save_to(&Self::get_settings_file_path())
+    }
+
+    /// 保存配置到指定文件
+    pub fn save_to(&self, path: &Path) -> Result<(), String> {
+        let content = serde_json::to_string_pretty(self)
+            .map_err(|e| format!("序列化失败: {}", e))?;
+        fs::write(path, content)
+            .map_err(|e| format!("保存配置失败: {}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_settings_round_trip() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("config.json");
+
+        let mut settings = AppSettings::default();
+        settings.classify_config.template = "{year}/{camera}".to_string();
+        settings.classify_config.fallback_folder = "无日期".to_string();
+        settings.rename_config.enabled = true;
+        settings.rename_config.template = "{date}_{counter}".to_string();
+        settings.last_source_dirs = vec!["/Volumes/EOS_DIGITAL/DCIM".to_string()];
+        settings.last_target_dir = Some("/Volumes/NAS/Photos".to_string());
+        settings.save_to(&path).unwrap();
+
+        let loaded = AppSettings::load_from(&path);
+        assert_eq!(loaded.classify_config.template, "{year}/{camera}");
+        assert_eq!(loaded.classify_config.fallback_folder, "无日期");
+        assert!(loaded.rename_config.enabled);
+        assert_eq!(loaded.rename_config.template, "{date}_{counter}");
+        assert_eq!(loaded.last_source_dirs, settings.last_source_dirs);
+        assert_eq!(loaded.last_target_dir, settings.last_target_dir);
+    }
+
+    #[test]
+    fn test_missing_or_corrupt_settings_use_defaults() {
+        let dir = TempDir::new().unwrap();
+        let default_template = ClassifyConfig::default().template;
+
+        let missing = AppSettings::load_from(&dir.path().join("config.json"));
+        assert_eq!(missing.classify_config.template, default_template);
+        assert!(missing.last_target_dir.is_none());
+
+        let corrupt = dir.path().join("corrupt.json");
+        fs::write(&corrupt, "{ not json").unwrap();
+        let loaded = AppSettings::load_from(&corrupt);
+        assert_eq!(loaded.classify_config.template, default_template);
+        assert!(!loaded.rename_config.enabled);
+
+        // 缺少的字段使用默认值
+        let partial = dir.path().join("partial.json");
+        fs::write(&partial, r#"{"last_source_dirs": ["/a"]}"#).unwrap();
+        let loaded = AppSettings::load_from(&partial);
+        assert_eq!(loaded.last_source_dirs, vec!["/a".to_string()]);
+        assert_eq!(loaded.classify_config.template, default_template);
+    }
+}
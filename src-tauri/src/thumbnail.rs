@@ -13,68 +13,206 @@ pub struct ThumbnailInfo {
     pub format: String,
 }
 
-/// 缩略图大小
+/// 默认缩略图大小（最长边像素）
 pub const THUMBNAIL_SIZE: u32 = 160;
 
-/// 使用 ExifTool 提取内嵌缩略图
-pub fn extract_thumbnail(file_path: &str) -> Result<ThumbnailInfo, String> {
+/// 提取缩略图：优先使用 ExifTool 提取内嵌缩略图，
+/// HEIC/HEIF 文件提取失败时再尝试直接解码。
+/// size 为期望的最长边像素：内嵌缩略图小于该尺寸时改用更大的内嵌预览图，
+/// 解码路径按该尺寸缩放
+pub fn extract_thumbnail(file_path: &str, size: u32) -> Result<ThumbnailInfo, String> {
     let path = Path::new(file_path);
     if !path.exists() {
         return Err(format!("文件不存在: {}", file_path));
     }
 
-    // 尝试使用 ExifTool 提取缩略图
-    let exiftool_path = crate::exif::get_exiftool_path()
-        .ok_or("ExifTool 未安装")?;
+    match extract_embedded_thumbnail(file_path, size) {
+        Err(_) if is_heif(file_path) => decode_heif_thumbnail(file_path, size),
+        result => result,
+    }
+}
 
-    // 提取缩略图数据
-    let output = Command::new(&exiftool_path)
-        .args(["-b", "-ThumbnailImage", file_path])
+/// 使用 ExifTool 以二进制方式读取指定的内嵌图片标签
+fn read_embedded_image(exiftool_path: &str, tag: &str, file_path: &str) -> Result<Vec<u8>, String> {
+    let output = Command::new(exiftool_path)
+        .args(crate::exif::exiftool_args(&["-b", tag], file_path))
         .stdout(Stdio::piped())
         .stderr(Stdio::null())
         .output()
         .map_err(|e| format!("执行 ExifTool 失败: {}", e))?;
+    Ok(output.stdout)
+}
+
+/// 使用 ExifTool 提取内嵌缩略图，返回图片的真实尺寸
+fn extract_embedded_thumbnail(file_path: &str, size: u32) -> Result<ThumbnailInfo, String> {
+    let exiftool_path = crate::exif::get_exiftool_path()
+        .ok_or("ExifTool 未安装")?;
 
-    if output.stdout.is_empty() {
-        // 尝试提取预览图
-        let output = Command::new(&exiftool_path)
-            .args(["-b", "-PreviewImage", file_path])
-            .stdout(Stdio::piped())
-            .stderr(Stdio::null())
-            .output()
-            .map_err(|e| format!("执行 ExifTool 失败: {}", e))?;
-
-        if output.stdout.is_empty() {
-            return Err("无法提取缩略图".to_string());
+    let thumbnail = read_embedded_image(&exiftool_path, "-ThumbnailImage", file_path)?;
+
+    // 没有缩略图或缩略图不够清晰时，尝试提取更大的预览图
+    let too_small = jpeg_dimensions(&thumbnail)
+        .map(|(width, height)| width.max(height) < size)
+        .unwrap_or(true);
+    let data = if too_small {
+        let preview = read_embedded_image(&exiftool_path, "-PreviewImage", file_path)?;
+        if preview.is_empty() { thumbnail } else { preview }
+    } else {
+        thumbnail
+    };
+
+    if data.is_empty() {
+        return Err("无法提取缩略图".to_string());
+    }
+
+    // 无法解析尺寸时为 0
+    let (width, height) = jpeg_dimensions(&data).unwrap_or((0, 0));
+    Ok(ThumbnailInfo {
+        file_path: file_path.to_string(),
+        data: BASE64.encode(&data),
+        width,
+        height,
+        format: "image/jpeg".to_string(),
+    })
+}
+
+/// 作为 RAW 全尺寸预览导出的内嵌 JPEG 的最小尺寸（最长边像素），更小的只是缩略图
+pub const MIN_PREVIEW_EDGE: u32 = 1024;
+
+/// 使用 ExifTool 提取 RAW 中最大的内嵌 JPEG（JpgFromRaw 和 PreviewImage 中较大的一个），
+/// 都没有或最长边小于 MIN_PREVIEW_EDGE 时返回 None
+pub fn extract_largest_preview(file_path: &str) -> Result<Option<Vec<u8>>, String> {
+    let exiftool_path = crate::exif::get_exiftool_path()
+        .ok_or("ExifTool 未安装")?;
+
+    let mut candidates = Vec::new();
+    for tag in ["-JpgFromRaw", "-PreviewImage"] {
+        candidates.push(read_embedded_image(&exiftool_path, tag, file_path)?);
+    }
+    Ok(largest_preview(candidates))
+}
+
+/// 候选 JPEG 中像素最多、且最长边不小于 MIN_PREVIEW_EDGE 的一个
+fn largest_preview(candidates: Vec<Vec<u8>>) -> Option<Vec<u8>> {
+    candidates
+        .into_iter()
+        .filter_map(|data| jpeg_dimensions(&data).map(|(width, height)| (width, height, data)))
+        .filter(|(width, height, _)| (*width).max(*height) >= MIN_PREVIEW_EDGE)
+        .max_by_key(|(width, height, _)| u64::from(*width) * u64::from(*height))
+        .map(|(_, _, data)| data)
+}
+
+/// 从 JPEG 数据的 SOF 段读取图片尺寸 (宽, 高)
+fn jpeg_dimensions(data: &[u8]) -> Option<(u32, u32)> {
+    if data.len() < 4 || data[0] != 0xFF || data[1] != 0xD8 {
+        return None;
+    }
+
+    let mut pos = 2;
+    while pos + 4 <= data.len() {
+        if data[pos] != 0xFF {
+            return None;
+        }
+        let marker = data[pos + 1];
+        // 填充字节和无长度的独立标记
+        if marker == 0xFF {
+            pos += 1;
+            continue;
+        }
+        if marker == 0x01 || (0xD0..=0xD9).contains(&marker) {
+            pos += 2;
+            continue;
         }
 
-        let base64_data = BASE64.encode(&output.stdout);
-        return Ok(ThumbnailInfo {
-            file_path: file_path.to_string(),
-            data: base64_data,
-            width: THUMBNAIL_SIZE,
-            height: THUMBNAIL_SIZE,
-            format: "image/jpeg".to_string(),
-        });
+        let length = u16::from_be_bytes([data[pos + 2], data[pos + 3]]) as usize;
+        // SOF0-SOF15，排除 DHT(C4)、JPG(C8)、DAC(CC)
+        if (0xC0..=0xCF).contains(&marker) && !matches!(marker, 0xC4 | 0xC8 | 0xCC) {
+            let segment = data.get(pos + 5..pos + 9)?;
+            let height = u16::from_be_bytes([segment[0], segment[1]]) as u32;
+            let width = u16::from_be_bytes([segment[2], segment[3]]) as u32;
+            return Some((width, height));
+        }
+        pos += 2 + length;
     }
+    None
+}
+
+/// 是否为 HEIC/HEIF 文件
+fn is_heif(file_path: &str) -> bool {
+    Path::new(file_path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| matches!(ext.to_lowercase().as_str(), "heic" | "heif"))
+        .unwrap_or(false)
+}
+
+/// 按比例缩放到最长边不超过 size
+#[cfg_attr(not(feature = "heif"), allow(dead_code))]
+fn scaled_dimensions(width: u32, height: u32, size: u32) -> (u32, u32) {
+    if width <= size && height <= size {
+        return (width.max(1), height.max(1));
+    }
+    let scale = size as f64 / width.max(height) as f64;
+    (
+        ((width as f64 * scale).round() as u32).max(1),
+        ((height as f64 * scale).round() as u32).max(1),
+    )
+}
+
+/// 使用 libheif 解码 HEIC/HEIF 并缩放为 JPEG 缩略图
+#[cfg(feature = "heif")]
+fn decode_heif_thumbnail(file_path: &str, size: u32) -> Result<ThumbnailInfo, String> {
+    use image::codecs::jpeg::JpegEncoder;
+    use libheif_rs::{ColorSpace, HeifContext, LibHeif, RgbChroma};
+
+    let lib_heif = LibHeif::new();
+    let ctx = HeifContext::read_from_file(file_path)
+        .map_err(|e| format!("读取 HEIF 文件失败: {}", e))?;
+    let handle = ctx.primary_image_handle()
+        .map_err(|e| format!("读取 HEIF 图像失败: {}", e))?;
+    let decoded = lib_heif.decode(&handle, ColorSpace::Rgb(RgbChroma::Rgb), None)
+        .map_err(|e| format!("解码 HEIF 失败: {}", e))?;
+
+    let (width, height) = scaled_dimensions(decoded.width(), decoded.height(), size);
+    let scaled = decoded.scale(width, height, None)
+        .map_err(|e| format!("缩放 HEIF 失败: {}", e))?;
+    let planes = scaled.planes();
+    let plane = planes.interleaved.ok_or("无法提取缩略图")?;
+
+    // 逐行复制像素，去掉每行末尾的对齐填充
+    let row_len = plane.width as usize * 3;
+    let mut rgb = Vec::with_capacity(row_len * plane.height as usize);
+    for row in plane.data.chunks(plane.stride).take(plane.height as usize) {
+        rgb.extend_from_slice(&row[..row_len]);
+    }
+
+    let mut jpeg = Vec::new();
+    JpegEncoder::new_with_quality(&mut jpeg, 85)
+        .encode(&rgb, plane.width, plane.height, image::ExtendedColorType::Rgb8)
+        .map_err(|e| format!("编码缩略图失败: {}", e))?;
 
-    let base64_data = BASE64.encode(&output.stdout);
     Ok(ThumbnailInfo {
         file_path: file_path.to_string(),
-        data: base64_data,
-        width: THUMBNAIL_SIZE,
-        height: THUMBNAIL_SIZE,
+        data: BASE64.encode(&jpeg),
+        width: plane.width,
+        height: plane.height,
         format: "image/jpeg".to_string(),
     })
 }
 
+/// 未启用 heif 功能时无法解码 HEIC/HEIF
+#[cfg(not(feature = "heif"))]
+fn decode_heif_thumbnail(_file_path: &str, _size: u32) -> Result<ThumbnailInfo, String> {
+    Err("无法提取缩略图".to_string())
+}
+
 /// 批量提取缩略图
-pub fn extract_thumbnails(file_paths: &[String], max_count: usize) -> Vec<ThumbnailInfo> {
+pub fn extract_thumbnails(file_paths: &[String], max_count: usize, size: u32) -> Vec<ThumbnailInfo> {
     let mut thumbnails = Vec::new();
     let count = std::cmp::min(file_paths.len(), max_count);
 
     for path in file_paths.iter().take(count) {
-        if let Ok(thumb) = extract_thumbnail(path) {
+        if let Ok(thumb) = extract_thumbnail(path, size) {
             thumbnails.push(thumb);
         }
     }
@@ -90,7 +228,7 @@ pub fn has_embedded_thumbnail(file_path: &str) -> bool {
     };
 
     let output = Command::new(&exiftool_path)
-        .args(["-ThumbnailImage", "-PreviewImage", file_path])
+        .args(crate::exif::exiftool_args(&["-ThumbnailImage", "-PreviewImage"], file_path))
         .stdout(Stdio::piped())
         .stderr(Stdio::null())
         .output();
@@ -112,17 +250,85 @@ mod tests {
 
     #[test]
     fn test_extract_thumbnail_nonexistent() {
-        let result = extract_thumbnail("/nonexistent/file.jpg");
+        let result = extract_thumbnail("/nonexistent/file.jpg", THUMBNAIL_SIZE);
         assert!(result.is_err());
     }
 
     #[test]
     fn test_extract_thumbnails_empty() {
         let paths: Vec<String> = vec![];
-        let result = extract_thumbnails(&paths, 10);
+        let result = extract_thumbnails(&paths, 10, THUMBNAIL_SIZE);
         assert!(result.is_empty());
     }
 
+    /// 构造只包含 SOF0 段的最小 JPEG 数据
+    fn jpeg_with_size(width: u16, height: u16) -> Vec<u8> {
+        let mut data = vec![0xFF, 0xD8];
+        // APP0 段
+        data.extend_from_slice(&[0xFF, 0xE0, 0x00, 0x04, 0x00, 0x00]);
+        // SOF0 段: 长度、精度、高、宽、分量数
+        data.extend_from_slice(&[0xFF, 0xC0, 0x00, 0x0B, 0x08]);
+        data.extend_from_slice(&height.to_be_bytes());
+        data.extend_from_slice(&width.to_be_bytes());
+        data.extend_from_slice(&[0x01, 0x01, 0x11, 0x00]);
+        data.extend_from_slice(&[0xFF, 0xD9]);
+        data
+    }
+
+    #[test]
+    fn test_jpeg_dimensions() {
+        assert_eq!(jpeg_dimensions(&jpeg_with_size(160, 120)), Some((160, 120)));
+        assert_eq!(jpeg_dimensions(&jpeg_with_size(1620, 1080)), Some((1620, 1080)));
+    }
+
+    #[test]
+    fn test_largest_preview() {
+        let small = jpeg_with_size(640, 480);
+        let medium = jpeg_with_size(1620, 1080);
+        let full = jpeg_with_size(6000, 4000);
+        assert_eq!(largest_preview(vec![medium.clone(), full.clone()]), Some(full));
+        assert_eq!(largest_preview(vec![Vec::new(), medium.clone()]), Some(medium));
+        // 只有缩略图大小的预览或没有内嵌 JPEG
+        assert_eq!(largest_preview(vec![small, Vec::new()]), None);
+        assert_eq!(largest_preview(Vec::new()), None);
+    }
+
+    #[test]
+    fn test_jpeg_dimensions_invalid() {
+        assert_eq!(jpeg_dimensions(&[]), None);
+        assert_eq!(jpeg_dimensions(b"not a jpeg"), None);
+        // 截断的数据
+        let data = jpeg_with_size(160, 120);
+        assert_eq!(jpeg_dimensions(&data[..10]), None);
+    }
+
+    #[test]
+    fn test_is_heif() {
+        assert!(is_heif("IMG_0001.HEIC"));
+        assert!(is_heif("/photos/IMG_0001.heif"));
+        assert!(!is_heif("IMG_0001.JPG"));
+        assert!(!is_heif("heic"));
+    }
+
+    #[test]
+    fn test_scaled_dimensions() {
+        assert_eq!(scaled_dimensions(4032, 3024, 160), (160, 120));
+        assert_eq!(scaled_dimensions(3024, 4032, 160), (120, 160));
+        assert_eq!(scaled_dimensions(100, 80, 160), (100, 80));
+        assert_eq!(scaled_dimensions(10000, 10, 160), (160, 1));
+    }
+
+    #[cfg(not(feature = "heif"))]
+    #[test]
+    fn test_heif_without_feature_reports_error() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("IMG_0001.HEIC");
+        std::fs::write(&path, b"not a real heic").unwrap();
+
+        let result = extract_thumbnail(&path.to_string_lossy(), THUMBNAIL_SIZE);
+        assert_eq!(result.unwrap_err(), "无法提取缩略图");
+    }
+
     #[test]
     fn test_has_embedded_thumbnail_nonexistent() {
         let result = has_embedded_thumbnail("/nonexistent/file.jpg");
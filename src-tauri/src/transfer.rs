@@ -1,10 +1,16 @@
-use crate::classify::{is_supported_photo, ClassifyConfig};
-use crate::exif::{read_exif, PhotoMetadata};
+use crate::classify::{is_raw_photo, is_supported_photo, to_nfc, ClassifyConfig};
+use crate::exif::{metadata_from_file, read_exif, PhotoMetadata};
 use crate::hash::Deduplicator;
+use crate::history::TransferredFile;
+use crate::integrity::is_suspect;
+use crate::progress::ProgressSink;
+use crate::rename::{trailing_number, CounterMode, RenameConfig};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use std::fs;
-use std::path::Path;
-use tauri::{AppHandle, Emitter};
+use std::path::{Component, Path, PathBuf};
 use walkdir::WalkDir;
 
 /// 扫描结果
@@ -13,19 +19,154 @@ pub struct ScanResult {
     pub total_files: usize,
     pub total_size: u64,
     pub photos: Vec<PhotoInfo>,
+    /// 设置 skip_empty 时被排除的空文件（0 字节）和无法读取的文件
+    #[serde(default)]
+    pub skipped_unreadable: Vec<String>,
+}
+
+impl ScanResult {
+    /// 按相机和拍摄月份统计照片数量和大小（见 ScanSummary）
+    pub fn summary(&self) -> crate::report::ScanSummary {
+        crate::report::ScanSummary::from_photos(&self.photos)
+    }
+
+    /// 按新的分类配置重新计算所有照片的目标文件夹，不读取文件
+    pub fn reclassify(&mut self, config: &ClassifyConfig) {
+        for photo in &mut self.photos {
+            photo.reclassify(config);
+        }
+    }
+
+    /// 按排序方式排列照片，相同时按路径排序，保证多次扫描顺序一致
+    pub fn sort_photos(&mut self, key: ScanSortKey) {
+        self.sort_photos_by(key, true);
+    }
+
+    /// 按排序方式升序或降序排列照片。降序只反转排序键，排序键相同的照片仍按路径升序排列，
+    /// 没有拍摄时间的照片始终排在最后
+    pub fn sort_photos_by(&mut self, key: ScanSortKey, ascending: bool) {
+        self.photos.sort_by(|a, b| {
+            let ordering = match key {
+                ScanSortKey::Path => std::cmp::Ordering::Equal,
+                ScanSortKey::Name => a.file_name.cmp(&b.file_name),
+                ScanSortKey::Size => a.file_size.cmp(&b.file_size),
+                ScanSortKey::TargetFolder => a.target_folder.cmp(&b.target_folder),
+                ScanSortKey::CaptureTime => match (&a.date_time, &b.date_time) {
+                    (Some(x), Some(y)) if ascending => x.cmp(y),
+                    (Some(x), Some(y)) => y.cmp(x),
+                    (Some(_), None) => std::cmp::Ordering::Less,
+                    (None, Some(_)) => std::cmp::Ordering::Greater,
+                    (None, None) => std::cmp::Ordering::Equal,
+                },
+            };
+            let ordering = if ascending || key == ScanSortKey::CaptureTime { ordering } else { ordering.reverse() };
+            let path_ordering = if ascending || key != ScanSortKey::Path { a.path.cmp(&b.path) } else { b.path.cmp(&a.path) };
+            ordering.then(path_ordering)
+        });
+    }
+
+    /// 只保留星级不低于 min_rating 的照片（未评级的照片被排除），并更新统计
+    pub fn retain_min_rating(&mut self, min_rating: u8) {
+        self.photos.retain(|p| p.rating.is_some_and(|r| r >= min_rating));
+        self.total_files = self.photos.len();
+        self.total_size = self.photos.iter().map(|p| p.file_size).sum();
+    }
+
+    /// 按传输计划的去重结果标记重复照片（包括源文件夹内部内容相同的文件）
+    pub fn mark_duplicates(&mut self, plan: &TransferPlan) {
+        let duplicates: HashMap<&str, &Option<String>> = plan
+            .entries
+            .iter()
+            .filter(|e| e.action == PlanAction::SkipDuplicate)
+            .map(|e| (e.source_path.as_str(), &e.duplicate_of))
+            .collect();
+        for photo in &mut self.photos {
+            let duplicate = duplicates.get(photo.path.as_str());
+            photo.is_duplicate = duplicate.is_some();
+            photo.duplicate_of = duplicate.and_then(|d| (*d).clone());
+        }
+    }
 }
 
 /// 照片信息（用于前端显示）
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PhotoInfo {
+    // target_folder 始终使用 `/` 分隔，实际创建目录时由 join_target_folder 转换为平台分隔符
     pub path: String,
     pub file_name: String,
     pub file_size: u64,
+    /// 用于分类的拍摄时间（拍摄时间，没有时为创建时间）
     pub date_time: Option<String>,
+    /// EXIF 拍摄时间 (DateTimeOriginal)
+    #[serde(default)]
+    pub date_time_original: Option<String>,
+    /// EXIF 创建时间 (CreateDate)
+    #[serde(default)]
+    pub create_date: Option<String>,
+    /// 相机型号
     pub camera: Option<String>,
+    /// 相机品牌
+    #[serde(default)]
+    pub make: Option<String>,
     pub target_folder: String,
     pub is_duplicate: bool,
     pub duplicate_of: Option<String>,
+    /// 按方向旋转后的显示宽度
+    #[serde(default)]
+    pub width: Option<u32>,
+    /// 按方向旋转后的显示高度
+    #[serde(default)]
+    pub height: Option<u32>,
+    /// 照片所在目录相对源文件夹的路径（`/` 分隔，根目录为空），用于 {folder} 占位符
+    #[serde(default)]
+    pub relative_dir: String,
+    /// 完整性检查发现文件可能已损坏（未启用检查时为 false）
+    #[serde(default)]
+    pub suspect: bool,
+    /// 照片所属的源文件夹（同时扫描多个源文件夹时用于区分来源）
+    #[serde(default)]
+    pub source_root: String,
+    /// 星级评分 (1-5)，未评级时为 None
+    #[serde(default)]
+    pub rating: Option<u8>,
+    /// XMP 颜色标签
+    #[serde(default)]
+    pub label: Option<String>,
+    /// 关键词
+    #[serde(default)]
+    pub keywords: Vec<String>,
+}
+
+/// 传输阶段
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TransferPhase {
+    /// 已开始，尚未进行任何耗时操作
+    Preparing,
+    /// 扫描目标目录已有文件（去重）
+    Scanning,
+    /// 复制文件
+    Transferring,
+    /// 校验已复制的文件
+    Verifying,
+    Completed,
+    Cancelled,
+    /// 源文件夹无法访问（如存储卡被拔出），传输中止
+    Aborted,
+}
+
+/// 传输进度事件中的状态，序列化为与旧版本相同的字符串
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TransferStatus {
+    Preparing,
+    Scanning,
+    Transferring,
+    Verifying,
+    Completed,
+    Cancelled,
+    /// 源文件夹无法访问（如存储卡被拔出），传输中止
+    Aborted,
 }
 
 /// 传输进度事件
@@ -36,10 +177,24 @@ pub struct TransferProgress {
     pub current_file: String,
     pub bytes_transferred: u64,
     pub total_bytes: u64,
-    pub status: String,
+    /// 与 phase 对应的状态，保留用于兼容
+    pub status: TransferStatus,
+    pub phase: TransferPhase,
     pub skipped_duplicates: usize,
 }
 
+/// 去重预扫描进度事件（`dedup-scan-progress`），独立于传输进度，
+/// 用于在扫描目标目录已有文件时显示第一阶段进度
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DedupScanProgress {
+    /// 已建立索引的目标文件数
+    pub indexed: usize,
+    /// 目标目录中的文件总数（统计完成前为 None）
+    pub total: Option<usize>,
+    /// counting: 正在统计文件数, indexing: 正在建立索引, completed: 完成
+    pub status: String,
+}
+
 /// 传输结果
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TransferResult {
@@ -47,685 +202,3190 @@ pub struct TransferResult {
     pub skip_count: usize,
     pub error_count: usize,
     pub errors: Vec<String>,
+    /// 不影响传输结果的问题（如历史记录保存失败）
+    #[serde(default)]
+    pub warnings: Vec<String>,
+    /// 因内容重复而跳过的文件
+    #[serde(default)]
+    pub skipped: Vec<SkippedFile>,
+    /// 每个文件的传输结果（源路径、最终目标路径、状态），用于在界面中定位文件。
+    /// 文件过多时为 None，可通过 get_last_transfer_files 从历史记录读取
+    #[serde(default)]
+    pub files: Option<Vec<TransferredFile>>,
+    /// 导出的 RAW 预览数，见 preview::RawPreviewMode
+    #[serde(default)]
+    pub previews: usize,
+    /// 传输前不存在、由本次传输新建的目标文件夹（完整路径，已排序）
+    #[serde(default)]
+    pub new_folders: Vec<String>,
+    /// 传输前已存在、本次向其中添加了文件的目标文件夹
+    #[serde(default)]
+    pub merged_folders: Vec<String>,
 }
 
-/// 扫描源文件夹中的照片
-pub fn scan_photos(source_dir: &str, config: &ClassifyConfig) -> Result<ScanResult, String> {
-    let mut photos = Vec::new();
-    let mut total_size = 0u64;
-
-    let path = Path::new(source_dir);
-    if !path.exists() {
-        return Err(format!("源文件夹不存在: {}", source_dir));
-    }
-
-    for entry in WalkDir::new(source_dir)
-        .follow_links(true)
-        .into_iter()
-        .filter_map(|e| e.ok())
-    {
-        let file_path = entry.path();
-        if !file_path.is_file() {
-            continue;
-        }
-
-        let file_path_str = file_path.to_string_lossy().to_string();
-        if !is_supported_photo(&file_path_str) {
-            continue;
-        }
-
-        // 读取文件大小
-        let file_size = fs::metadata(file_path)
-            .map(|m| m.len())
-            .unwrap_or(0);
-        total_size += file_size;
-
-        // 读取 EXIF 信息
-        let metadata = read_exif(&file_path_str).unwrap_or_else(|_| PhotoMetadata {
-            file_path: file_path_str.clone(),
-            file_name: file_path
-                .file_name()
-                .map(|n| n.to_string_lossy().to_string())
-                .unwrap_or_default(),
-            file_size,
-            ..Default::default()
-        });
+/// 因内容重复而跳过的文件
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SkippedFile {
+    pub source: String,
+    /// 内容相同的文件：目标目录中已有的文件，或本批次中较早传输的源文件
+    pub matched_original: String,
+}
 
-        // 生成目标文件夹路径
-        let target_folder = config.generate_path(&metadata);
+/// 扫描选项
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScanOptions {
+    /// 排除规则（glob），匹配相对源文件夹的路径或文件/文件夹名。
+    /// 源文件夹中各级的 .photoignore 文件中的规则同时生效，见 PhotoIgnore
+    pub exclude: Vec<String>,
+    /// RAW+JPEG 同名文件只保留 RAW（同一目录下文件名相同、扩展名不同）
+    pub prefer_raw: bool,
+    /// 检查文件头尾，标记可能已损坏的文件（较慢，默认关闭）
+    pub check_integrity: bool,
+    /// 只保留星级不低于此值的照片（需要读取 EXIF，未评级的照片被排除）
+    pub min_rating: Option<u8>,
+    /// 跟随符号链接扫描链接指向的文件和文件夹（默认关闭，避免导入源文件夹以外的文件）。
+    /// 开启时 WalkDir 会检测链接形成的循环并跳过，不会无限扫描
+    pub follow_links: bool,
+    /// 并行读取 EXIF 的线程数，0 或 1 表示串行读取。
+    /// 读卡器等慢速设备上并行过多反而更慢，可按设备调低
+    pub exif_jobs: usize,
+    /// 照片排序方式，默认按路径排序，不依赖文件系统的遍历顺序
+    pub sort_by: ScanSortKey,
+    /// 跳过小于此字节数的文件（如缩略图、损坏的小文件）
+    pub min_size: Option<u64>,
+    /// 跳过大于此字节数的文件
+    pub max_size: Option<u64>,
+    /// 排除空文件（0 字节）和无法读取的文件（如没有读取权限），记录到 ScanResult::skipped_unreadable；
+    /// 默认关闭，这些文件与其他照片一样被扫描和传输
+    pub skip_empty: bool,
+}
 
-        photos.push(PhotoInfo {
-            path: file_path_str,
-            file_name: metadata.file_name,
-            file_size,
-            date_time: metadata.date_time_original.or(metadata.create_date),
-            camera: metadata.model,
-            target_folder,
-            is_duplicate: false,
-            duplicate_of: None,
-        });
+impl ScanOptions {
+    /// 文件大小是否在 min_size 和 max_size 范围内（包含边界）
+    pub fn size_in_range(&self, size: u64) -> bool {
+        (self.min_size.unwrap_or(0)..=self.max_size.unwrap_or(u64::MAX)).contains(&size)
     }
-
-    Ok(ScanResult {
-        total_files: photos.len(),
-        total_size,
-        photos,
-    })
 }
 
-/// 执行照片传输
-pub fn transfer_photos(
-    app_handle: &AppHandle,
-    photos: &[PhotoInfo],
-    target_base_dir: &str,
-    skip_duplicates: bool,
-) -> Result<TransferResult, String> {
-    let mut success_count = 0;
-    let mut skip_count = 0;
-    let mut error_count = 0;
-    let mut errors = Vec::new();
-    let mut deduplicator = Deduplicator::new();
-    let total = photos.len();
-    let total_bytes: u64 = photos.iter().map(|p| p.file_size).sum();
-    let mut bytes_transferred = 0u64;
-
-    // 如果启用去重，先扫描目标目录中已有的文件
-    if skip_duplicates {
-        let _ = app_handle.emit("transfer-progress", TransferProgress {
-            current: 0,
-            total,
-            current_file: "正在扫描目标目录已有文件...".to_string(),
-            bytes_transferred: 0,
-            total_bytes,
-            status: "scanning".to_string(),
-            skipped_duplicates: 0,
-        });
+/// 扫描结果中照片的排序方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScanSortKey {
+    /// 按完整路径
+    #[default]
+    Path,
+    /// 按文件名
+    Name,
+    /// 按拍摄时间（需要读取 EXIF，只枚举文件时等同于按路径）
+    CaptureTime,
+    /// 按文件大小
+    Size,
+    /// 按目标文件夹
+    TargetFolder,
+}
 
-        if Path::new(target_base_dir).exists() {
-            for entry in WalkDir::new(target_base_dir)
-                .follow_links(true)
-                .into_iter()
-                .filter_map(|e| e.ok())
-            {
-                if entry.path().is_file() {
-                    let _ = deduplicator.add_known_file(&entry.path().to_string_lossy());
-                }
-            }
-        }
+/// 编译排除规则
+pub fn build_exclude_set(patterns: &[String]) -> Result<GlobSet, String> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let glob = Glob::new(pattern).map_err(|e| format!("无效的排除规则 {}: {}", pattern, e))?;
+        builder.add(glob);
     }
+    builder.build().map_err(|e| format!("排除规则编译失败: {}", e))
+}
 
-    for (index, photo) in photos.iter().enumerate() {
-        // 发送进度事件
-        let _ = app_handle.emit("transfer-progress", TransferProgress {
-            current: index + 1,
-            total,
-            current_file: photo.file_name.clone(),
-            bytes_transferred,
-            total_bytes,
-            status: "transferring".to_string(),
-            skipped_duplicates: skip_count,
-        });
+/// 判断路径是否被排除（匹配相对路径或最后一级名称）
+pub fn is_excluded(excludes: &GlobSet, root: &Path, path: &Path) -> bool {
+    if excludes.is_empty() {
+        return false;
+    }
+    let relative = path.strip_prefix(root).unwrap_or(path);
+    if relative.as_os_str().is_empty() {
+        return false;
+    }
+    excludes.is_match(relative)
+        || path.file_name().map(|name| excludes.is_match(name)).unwrap_or(false)
+}
 
-        // 检查重复
-        if skip_duplicates {
-            match deduplicator.check_duplicate(&photo.path, photo.file_size) {
-                Ok(Some(_original)) => {
-                    skip_count += 1;
-                    bytes_transferred += photo.file_size;
-                    continue;
-                }
-                Ok(None) => {}
-                Err(e) => {
-                    errors.push(format!("检查重复失败 {}: {}", photo.file_name, e));
-                }
+/// 源文件夹中的排除规则文件，每行一条 glob 规则（规则同 exclude），只作用于所在文件夹及其子文件夹
+pub const PHOTOIGNORE_FILE: &str = ".photoignore";
+
+/// 读取文件夹中的 .photoignore：忽略空行和 # 开头的注释，去掉规则末尾的 /；
+/// 无效的规则只记录日志并跳过。没有该文件或其中没有有效规则时返回 None
+fn load_photoignore(dir: &Path) -> Option<GlobSet> {
+    let file = dir.join(PHOTOIGNORE_FILE);
+    let content = fs::read_to_string(&file).ok()?;
+    let mut builder = GlobSetBuilder::new();
+    let mut count = 0;
+    for line in content.lines() {
+        let pattern = line.trim().trim_end_matches('/');
+        if pattern.is_empty() || pattern.starts_with('#') {
+            continue;
+        }
+        match Glob::new(pattern) {
+            Ok(glob) => {
+                builder.add(glob);
+                count += 1;
             }
+            Err(e) => log::warn!("忽略 {} 中无效的规则 {}: {}", file.display(), pattern, e),
         }
+    }
+    if count == 0 {
+        return None;
+    }
+    builder
+        .build()
+        .map_err(|e| log::warn!("{} 中的规则编译失败: {}", file.display(), e))
+        .ok()
+}
 
-        // 构建目标路径
-        let target_dir = Path::new(target_base_dir).join(&photo.target_folder);
-        let target_path = target_dir.join(&photo.file_name);
+/// 源文件夹各级文件夹中 .photoignore 的排除规则：每个文件的规则相对其所在文件夹匹配，
+/// 上级文件夹的规则同样作用于下级，嵌套的规则叠加生效。读取过的文件夹会缓存，每个文件只读取一次
+pub struct PhotoIgnore {
+    root: PathBuf,
+    rules: HashMap<PathBuf, Option<GlobSet>>,
+}
 
-        // 创建目标目录
-        if let Err(e) = fs::create_dir_all(&target_dir) {
-            error_count += 1;
-            errors.push(format!("创建目录失败 {}: {}", target_dir.display(), e));
-            continue;
+impl PhotoIgnore {
+    pub fn new(root: &Path) -> Self {
+        Self {
+            root: root.to_path_buf(),
+            rules: HashMap::new(),
         }
+    }
 
-        // 如果目标文件已存在，添加序号
-        let final_target_path = if target_path.exists() {
-            let stem = Path::new(&photo.file_name)
-                .file_stem()
-                .map(|s| s.to_string_lossy().to_string())
-                .unwrap_or_default();
-            let ext = Path::new(&photo.file_name)
-                .extension()
-                .map(|e| e.to_string_lossy().to_string())
-                .unwrap_or_default();
-            
-            let mut counter = 1;
-            loop {
-                let new_name = format!("{}_{}.{}", stem, counter, ext);
-                let new_path = target_dir.join(&new_name);
-                if !new_path.exists() {
-                    break new_path;
-                }
-                counter += 1;
-            }
-        } else {
-            target_path
+    /// 路径是否被 root 到其所在文件夹之间任意一级的 .photoignore 排除
+    pub fn is_ignored(&mut self, path: &Path) -> bool {
+        let Some(parent) = path.parent().filter(|parent| parent.starts_with(&self.root)) else {
+            return false;
         };
-
-        // 复制文件
-        match fs::copy(&photo.path, &final_target_path) {
-            Ok(_) => {
-                success_count += 1;
-                bytes_transferred += photo.file_size;
+        for dir in parent.ancestors() {
+            let rules = self
+                .rules
+                .entry(dir.to_path_buf())
+                .or_insert_with(|| load_photoignore(dir));
+            if rules.as_ref().is_some_and(|rules| is_excluded(rules, dir, path)) {
+                return true;
             }
-            Err(e) => {
-                error_count += 1;
-                errors.push(format!("复制失败 {}: {}", photo.file_name, e));
+            if dir == self.root {
+                break;
             }
         }
+        false
     }
-
-    // 发送完成事件
-    let _ = app_handle.emit("transfer-progress", TransferProgress {
-        current: total,
-        total,
-        current_file: "传输完成".to_string(),
-        bytes_transferred: total_bytes,
-        total_bytes,
-        status: "completed".to_string(),
-        skipped_duplicates: skip_count,
-    });
-
-    Ok(TransferResult {
-        success_count,
-        skip_count,
-        error_count,
-        errors,
-    })
 }
 
-/// 格式化文件大小
-pub fn format_size(bytes: u64) -> String {
-    const KB: u64 = 1024;
-    const MB: u64 = KB * 1024;
-    const GB: u64 = MB * 1024;
+/// 解析路径的真实位置：对已存在的最深祖先目录取规范路径，再拼接其余部分
+/// 其余部分中的 `..` 无法安全解析，返回 None
+fn resolve_path(path: &Path) -> Option<PathBuf> {
+    let mut existing = path;
+    let mut rest = Vec::new();
+    while !existing.exists() {
+        rest.push(existing.file_name()?);
+        existing = existing.parent()?;
+    }
 
-    if bytes >= GB {
-        format!("{:.2} GB", bytes as f64 / GB as f64)
-    } else if bytes >= MB {
-        format!("{:.2} MB", bytes as f64 / MB as f64)
-    } else if bytes >= KB {
-        format!("{:.2} KB", bytes as f64 / KB as f64)
+    let mut resolved = if existing.as_os_str().is_empty() {
+        std::env::current_dir().ok()?
     } else {
-        format!("{} B", bytes)
+        existing.canonicalize().ok()?
+    };
+    for part in rest.iter().rev() {
+        match Path::new(part).components().next() {
+            Some(Component::Normal(_)) => resolved.push(part),
+            _ => return None,
+        }
     }
+    Some(resolved)
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::fs::{self, File};
-    use std::io::Write;
-    use tempfile::TempDir;
-
-    // ==================== 辅助函数 ====================
+/// 将 `/` 分隔的 target_folder 逐段拼接到目标根目录，得到使用平台分隔符的路径。
+/// 文件夹名按 NFC 规范化，已有规范化后同名的文件夹时沿用其名称（见 existing_entry_name）
+pub fn join_target_folder(base: &Path, target_folder: &str) -> PathBuf {
+    target_folder
+        .split(['/', '\\'])
+        .filter(|segment| !segment.is_empty())
+        .fold(base.to_path_buf(), |path, segment| {
+            let name = existing_entry_name(&path, segment);
+            path.join(name)
+        })
+}
 
-    fn create_test_photo(dir: &TempDir, subdir: &str, name: &str, content: &[u8]) -> String {
-        let folder = dir.path().join(subdir);
-        fs::create_dir_all(&folder).unwrap();
-        let path = folder.join(name);
-        let mut file = File::create(&path).unwrap();
-        file.write_all(content).unwrap();
-        path.to_string_lossy().to_string()
+/// 目录中与 name 规范化（NFC）后相同的已有文件或文件夹的名称，没有时返回 NFC 形式的 name。
+/// 目标文件夹中已有 NFD 形式的同名文件（如在 macOS 上导入）时沿用其名称，
+/// 避免在区分规范化形式的文件系统上生成看起来同名的两个文件
+pub fn existing_entry_name(dir: &Path, name: &str) -> String {
+    let normalized = to_nfc(name);
+    if normalized.is_ascii() || dir.join(&normalized).exists() {
+        return normalized;
     }
+    fs::read_dir(dir)
+        .ok()
+        .and_then(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.file_name().to_string_lossy().to_string())
+                .find(|existing| to_nfc(existing) == normalized)
+        })
+        .unwrap_or(normalized)
+}
 
-    fn create_test_photo_root(dir: &TempDir, name: &str, content: &[u8]) -> String {
-        let path = dir.path().join(name);
-        let mut file = File::create(&path).unwrap();
-        file.write_all(content).unwrap();
-        path.to_string_lossy().to_string()
+/// 检查 target 是否位于 base 目录之内（含 base 本身），会解析符号链接
+pub fn is_within_dir(base: &Path, target: &Path) -> bool {
+    match (resolve_path(base), resolve_path(target)) {
+        (Some(base), Some(target)) => target.starts_with(base),
+        _ => false,
     }
+}
 
-    // ==================== format_size 测试 ====================
-
-    #[test]
-    fn test_format_size_bytes() {
-        assert_eq!(format_size(0), "0 B");
-        assert_eq!(format_size(1), "1 B");
-        assert_eq!(format_size(100), "100 B");
-        assert_eq!(format_size(1023), "1023 B");
+/// 检查目标文件夹是否与源文件夹相同或互相包含（按解析符号链接后的真实位置比较）。
+/// 目标在源文件夹内时，导入的照片会在下次扫描时被再次导入；
+/// 源文件夹在目标内时，照片可能被复制到自身所在的位置
+pub fn check_dirs_overlap(source_dirs: &[String], target_dir: &str) -> Result<(), String> {
+    let target = resolve_path(Path::new(target_dir))
+        .ok_or_else(|| format!("无法解析目标文件夹: {}", target_dir))?;
+    for source_dir in source_dirs.iter().filter(|dir| !dir.is_empty()) {
+        let Some(source) = resolve_path(Path::new(source_dir)) else {
+            continue;
+        };
+        if source == target {
+            return Err(format!("目标文件夹与源文件夹相同: {}", target_dir));
+        }
+        if target.starts_with(&source) {
+            return Err(format!("目标文件夹 {} 位于源文件夹 {} 内", target_dir, source_dir));
+        }
+        if source.starts_with(&target) {
+            return Err(format!("源文件夹 {} 位于目标文件夹 {} 内", source_dir, target_dir));
+        }
     }
+    Ok(())
+}
 
-    #[test]
-    fn test_format_size_kilobytes() {
-        assert_eq!(format_size(1024), "1.00 KB");
-        assert_eq!(format_size(1536), "1.50 KB");
-        assert_eq!(format_size(10240), "10.00 KB");
-        assert_eq!(format_size(1024 * 1023), "1023.00 KB");
+/// 两个路径是否指向同一个已存在的文件
+fn is_same_file(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
     }
+}
 
-    #[test]
-    fn test_format_size_megabytes() {
-        assert_eq!(format_size(1024 * 1024), "1.00 MB");
-        assert_eq!(format_size(1024 * 1024 * 5), "5.00 MB");
-        assert_eq!(format_size(1024 * 1024 * 25), "25.00 MB"); // 典型RAW大小
-        assert_eq!(format_size(1024 * 1024 * 100), "100.00 MB");
-    }
+/// 文件所在目录相对源文件夹的路径，使用 `/` 分隔
+fn relative_dir(source: &Path, file_path: &Path) -> String {
+    file_path
+        .parent()
+        .and_then(|parent| parent.strip_prefix(source).ok())
+        .map(|relative| {
+            relative
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/")
+        })
+        .unwrap_or_default()
+}
 
-    #[test]
-    fn test_format_size_gigabytes() {
-        assert_eq!(format_size(1024 * 1024 * 1024), "1.00 GB");
-        assert_eq!(format_size(1024 * 1024 * 1024 * 2), "2.00 GB");
-        assert_eq!(format_size(1024u64 * 1024 * 1024 * 100), "100.00 GB");
-    }
+/// 同一目录下文件名（不含扩展名）相同的照片的分组键
+pub(crate) fn basename_key(path: &str) -> (PathBuf, String) {
+    let path = Path::new(path);
+    (
+        path.parent().map(Path::to_path_buf).unwrap_or_default(),
+        path.file_stem()
+            .map(|s| s.to_string_lossy().to_lowercase())
+            .unwrap_or_default(),
+    )
+}
 
-    #[test]
-    fn test_format_size_decimal() {
-        // 测试小数位
-        let size = 1024 * 1024 + 512 * 1024; // 1.5 MB
-        assert_eq!(format_size(size), "1.50 MB");
-    }
+/// 同一目录下存在同名 RAW 文件时，去掉对应的 JPEG/HEIC 等非 RAW 文件
+pub fn prefer_raw_photos(photos: Vec<PhotoInfo>) -> Vec<PhotoInfo> {
+    let raw_keys: HashSet<_> = photos
+        .iter()
+        .filter(|p| is_raw_photo(&p.path))
+        .map(|p| basename_key(&p.path))
+        .collect();
+
+    photos
+        .into_iter()
+        .filter(|p| is_raw_photo(&p.path) || !raw_keys.contains(&basename_key(&p.path)))
+        .collect()
+}
+
+/// 随照片一起传输的附属文件扩展名（XMP 编辑记录、iOS 编辑记录 AAE、THM 缩略图）。
+/// 附属文件不是照片，扫描时不计入
+pub const SIDECAR_EXTENSIONS: &[&str] = &["xmp", "aae", "thm"];
+
+/// 查找照片同目录下的附属文件：文件名相同、扩展名不同（IMG_0001.xmp），
+/// 或在照片完整文件名后追加扩展名（IMG_0001.CR3.xmp），文件名不区分大小写
+pub fn find_sidecars(photo_path: &Path) -> Vec<PathBuf> {
+    let (Some(dir), Some(stem), Some(name)) = (photo_path.parent(), photo_path.file_stem(), photo_path.file_name()) else {
+        return Vec::new();
+    };
+    let stem = stem.to_string_lossy().to_lowercase();
+    let name = name.to_string_lossy().to_lowercase();
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+
+    let mut sidecars: Vec<PathBuf> = entries
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|path| path.is_file())
+        .filter(|path| {
+            let (Some(sidecar_stem), Some(ext)) = (path.file_stem(), path.extension()) else {
+                return false;
+            };
+            let sidecar_stem = sidecar_stem.to_string_lossy().to_lowercase();
+            SIDECAR_EXTENSIONS.contains(&ext.to_string_lossy().to_lowercase().as_str())
+                && (sidecar_stem == stem || sidecar_stem == name)
+        })
+        .collect();
+    sidecars.sort();
+    sidecars
+}
+
+/// 附属文件在目标中的路径：与照片目标文件同目录，跟随照片的新文件名，保留附属文件自己的扩展名
+pub fn sidecar_target_path(sidecar: &Path, photo_source: &Path, photo_target: &Path) -> PathBuf {
+    let ext = sidecar.extension().map(|e| e.to_string_lossy().to_string()).unwrap_or_default();
+    let appended = match (sidecar.file_stem(), photo_source.file_name()) {
+        (Some(stem), Some(name)) => stem.to_string_lossy().eq_ignore_ascii_case(&name.to_string_lossy()),
+        _ => false,
+    };
+    // IMG_0001.CR3.xmp 形式的附属文件保留照片的扩展名
+    let base = if appended { photo_target.file_name() } else { photo_target.file_stem() };
+    let base = base.map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+    photo_target.with_file_name(format!("{}.{}", base, ext))
+}
+
+/// 把照片的附属文件复制到照片目标文件旁边，目标已存在的附属文件不覆盖。
+/// 返回复制失败的原因，照片本身的传输结果不受影响
+pub fn copy_sidecars(
+    photo_source: &str,
+    photo_target: &Path,
+    copy_fn: impl Fn(&Path, &Path) -> std::io::Result<u64>,
+) -> Vec<String> {
+    let photo_source = Path::new(photo_source);
+    let mut errors = Vec::new();
+    for sidecar in find_sidecars(photo_source) {
+        let target = sidecar_target_path(&sidecar, photo_source, photo_target);
+        if target.exists() {
+            log::debug!("附属文件已存在，跳过: {}", target.display());
+            continue;
+        }
+        if let Err(e) = copy_fn(&sidecar, &target) {
+            errors.push(format!("复制附属文件失败 {}: {}", sidecar.display(), e));
+        }
+    }
+    errors
+}
+
+/// 扫描源文件夹中的照片
+pub fn scan_photos(source_dir: &str, config: &ClassifyConfig) -> Result<ScanResult, String> {
+    let photos: Vec<PhotoInfo> = scan_iter(source_dir, config, &ScanOptions::default())?.collect();
+    let mut result = ScanResult {
+        total_files: photos.len(),
+        total_size: photos.iter().map(|p| p.file_size).sum(),
+        photos,
+        skipped_unreadable: Vec::new(),
+    };
+    result.sort_photos(ScanSortKey::default());
+    Ok(result)
+}
+
+/// 逐个扫描源文件夹中的照片：每次取下一项时才枚举到下一个文件并读取它的 EXIF，
+/// 调用方可以边扫描边报告进度，并在任意两项之间停止。照片按文件系统遍历顺序返回。
+/// 除 enumerate_iter 使用的设置外，还按 skip_empty 排除空文件和无法读取的文件，
+/// 按 prefer_raw 排除同一目录下有同名 RAW 文件的照片（读取所在目录判断）；min_rating 和排序需要全部照片，由调用方处理
+pub fn scan_iter<'a>(
+    source_dir: &'a str,
+    config: &'a ClassifyConfig,
+    options: &ScanOptions,
+) -> Result<impl Iterator<Item = PhotoInfo> + 'a, String> {
+    let skip_empty = options.skip_empty;
+    let prefer_raw = options.prefer_raw;
+    let mut raw_stems: HashMap<PathBuf, HashSet<String>> = HashMap::new();
+    Ok(enumerate_iter(source_dir, config, options)?
+        .filter(move |photo| !(skip_empty && is_unreadable(photo)))
+        .filter(move |photo| {
+            if !prefer_raw || is_raw_photo(&photo.path) {
+                return true;
+            }
+            let (dir, stem) = basename_key(&photo.path);
+            !raw_stems.entry(dir).or_insert_with_key(|dir| raw_stems_in(dir)).contains(&stem)
+        })
+        .map(move |mut photo| {
+            enrich_photo(&mut photo, config);
+            photo
+        }))
+}
+
+/// 目录中 RAW 文件的文件名（不含扩展名，小写），与 basename_key 的第二项相同
+fn raw_stems_in(dir: &Path) -> HashSet<String> {
+    fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .filter_map(|e| e.ok())
+                .map(|e| e.path())
+                .filter(|path| path.is_file() && is_raw_photo(&path.to_string_lossy()))
+                .map(|path| basename_key(&path.to_string_lossy()).1)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// 按扫描选项扫描源文件夹中的照片（枚举文件并读取 EXIF）
+pub fn scan_photos_with_options(
+    source_dir: &str,
+    config: &ClassifyConfig,
+    options: &ScanOptions,
+) -> Result<ScanResult, String> {
+    let mut result = enumerate_photos(source_dir, config, options)?;
+    enrich_photos(&mut result.photos, config, options.exif_jobs);
+    result.sort_photos(options.sort_by);
+    if let Some(min_rating) = options.min_rating {
+        result.retain_min_rating(min_rating);
+    }
+    Ok(result)
+}
+
+/// 扫描多个源文件夹并合并结果（枚举文件并读取 EXIF）
+pub fn scan_sources(
+    source_dirs: &[String],
+    config: &ClassifyConfig,
+    options: &ScanOptions,
+) -> Result<ScanResult, String> {
+    let mut result = enumerate_sources(source_dirs, config, options)?;
+    enrich_photos(&mut result.photos, config, options.exif_jobs);
+    result.sort_photos(options.sort_by);
+    if let Some(min_rating) = options.min_rating {
+        result.retain_min_rating(min_rating);
+    }
+    Ok(result)
+}
+
+/// 快速枚举多个源文件夹中的照片并合并为一个扫描结果。
+/// 源文件夹互相包含时，同一文件只保留一次（归属先列出的源文件夹）
+pub fn enumerate_sources(
+    source_dirs: &[String],
+    config: &ClassifyConfig,
+    options: &ScanOptions,
+) -> Result<ScanResult, String> {
+    if source_dirs.is_empty() {
+        return Err("请选择源文件夹".to_string());
+    }
+
+    let mut seen = HashSet::new();
+    let mut photos = Vec::new();
+    let mut skipped_unreadable = Vec::new();
+    for source_dir in source_dirs {
+        let result = enumerate_photos(source_dir, config, options)?;
+        photos.extend(result.photos.into_iter().filter(|p| seen.insert(p.path.clone())));
+        skipped_unreadable.extend(result.skipped_unreadable.into_iter().filter(|p| seen.insert(p.clone())));
+    }
+
+    let mut result = ScanResult {
+        total_files: photos.len(),
+        total_size: photos.iter().map(|p| p.file_size).sum(),
+        photos,
+        skipped_unreadable,
+    };
+    result.sort_photos(options.sort_by);
+    Ok(result)
+}
+
+/// 多个源文件夹合并为一个字符串，用于历史记录和扫描报告
+pub fn join_source_dirs(source_dirs: &[String]) -> String {
+    source_dirs.join("; ")
+}
+
+/// 快速统计结果：照片数量、总大小和各扩展名的文件数
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct QuickScanResult {
+    pub count: usize,
+    pub total_size: u64,
+    /// 扩展名（小写，不含点）到文件数
+    pub by_extension: HashMap<String, usize>,
+}
+
+/// 快速统计时每隔多少个文件报告一次进度
+pub const QUICK_SCAN_PROGRESS_INTERVAL: usize = 1000;
+
+/// 快速统计源文件夹中的照片数量和总大小，不读取 EXIF，也不生成目标路径。
+/// 每统计 QUICK_SCAN_PROGRESS_INTERVAL 张照片调用一次 on_progress 报告中间结果
+pub fn quick_scan_dir(
+    source_dir: &str,
+    mut on_progress: impl FnMut(&QuickScanResult),
+) -> Result<QuickScanResult, String> {
+    if !Path::new(source_dir).exists() {
+        return Err(format!("源文件夹不存在: {}", source_dir));
+    }
+
+    let mut result = QuickScanResult::default();
+    for entry in WalkDir::new(source_dir)
+        .into_iter()
+        .filter_map(|e| e.map_err(|err| log::warn!("跳过无法访问的路径: {}", err)).ok())
+    {
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let file_path = entry.path();
+        if !is_supported_photo(&file_path.to_string_lossy()) {
+            continue;
+        }
+
+        let extension = file_path
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        result.count += 1;
+        result.total_size += entry.metadata().map(|m| m.len()).unwrap_or(0);
+        *result.by_extension.entry(extension).or_insert(0) += 1;
+
+        if result.count % QUICK_SCAN_PROGRESS_INTERVAL == 0 {
+            on_progress(&result);
+        }
+    }
+
+    Ok(result)
+}
+
+/// 快速枚举源文件夹中的照片，只读取文件名和大小，不读取 EXIF。
+/// 目标文件夹暂时按无 EXIF 信息生成，之后由 enrich_photo 补全
+pub fn enumerate_photos(
+    source_dir: &str,
+    config: &ClassifyConfig,
+    options: &ScanOptions,
+) -> Result<ScanResult, String> {
+    let mut photos: Vec<PhotoInfo> = enumerate_iter(source_dir, config, options)?.collect();
+    let mut skipped_unreadable = Vec::new();
+    if options.skip_empty {
+        let (unreadable, readable): (Vec<_>, Vec<_>) = photos.into_iter().partition(is_unreadable);
+        skipped_unreadable = unreadable.into_iter().map(|p| p.path).collect();
+        photos = readable;
+    }
+    if options.prefer_raw {
+        photos = prefer_raw_photos(photos);
+    }
+    let total_size = photos.iter().map(|p| p.file_size).sum();
+
+    let mut result = ScanResult {
+        total_files: photos.len(),
+        total_size,
+        photos,
+        skipped_unreadable,
+    };
+    result.sort_photos(options.sort_by);
+    Ok(result)
+}
+
+/// 照片是否为空文件（0 字节）或无法打开读取
+fn is_unreadable(photo: &PhotoInfo) -> bool {
+    photo.file_size == 0 || fs::File::open(&photo.path).is_err()
+}
+
+/// 逐个枚举源文件夹中的照片，只读取文件名和大小（见 enumerate_photos）。
+/// 使用 options 中的排除规则、跟随链接、文件大小范围和完整性检查设置；prefer_raw、min_rating 和排序需要全部照片，由调用方处理
+pub fn enumerate_iter<'a>(
+    source_dir: &'a str,
+    config: &'a ClassifyConfig,
+    options: &ScanOptions,
+) -> Result<impl Iterator<Item = PhotoInfo> + 'a, String> {
+    let path = Path::new(source_dir);
+    if !path.exists() {
+        return Err(format!("源文件夹不存在: {}", source_dir));
+    }
+
+    let excludes = build_exclude_set(&options.exclude)?;
+    let mut photoignore = PhotoIgnore::new(path);
+    let check_integrity = options.check_integrity;
+    let size_filter = ScanOptions {
+        min_size: options.min_size,
+        max_size: options.max_size,
+        ..Default::default()
+    };
+
+    Ok(WalkDir::new(source_dir)
+        .follow_links(options.follow_links)
+        .into_iter()
+        .filter_entry(move |e| !is_excluded(&excludes, path, e.path()) && !photoignore.is_ignored(e.path()))
+        .filter_map(|e| e.map_err(|err| log::warn!("跳过无法访问的路径: {}", err)).ok())
+        // 不跟随链接时，指向文件的符号链接同样跳过
+        .filter(|entry| entry.file_type().is_file() && is_supported_photo(&entry.path().to_string_lossy()))
+        .filter_map(move |entry| {
+            let mut photo = photo_info(entry.path(), source_dir, config, false);
+            if !size_filter.size_in_range(photo.file_size) {
+                return None;
+            }
+            // 只检查大小范围内的文件是否损坏
+            photo.suspect = check_integrity && is_suspect(entry.path());
+            Some(photo)
+        }))
+}
+
+/// 为源文件夹 source_dir 中的单个文件创建 PhotoInfo，只读取文件名和大小，
+/// EXIF 信息由 enrich_photo 补全
+pub fn photo_info(file_path: &Path, source_dir: &str, config: &ClassifyConfig, check_integrity: bool) -> PhotoInfo {
+    let metadata = metadata_from_file(&file_path.to_string_lossy());
+    let relative_dir = relative_dir(Path::new(source_dir), file_path);
+    PhotoInfo {
+        path: file_path.to_string_lossy().to_string(),
+        target_folder: config.generate_path_for_source(&metadata, source_dir, &relative_dir),
+        file_name: metadata.file_name,
+        file_size: metadata.file_size,
+        date_time: None,
+        date_time_original: None,
+        create_date: None,
+        camera: None,
+        make: None,
+        is_duplicate: false,
+        duplicate_of: None,
+        width: None,
+        height: None,
+        relative_dir,
+        suspect: check_integrity && is_suspect(file_path),
+        source_root: source_dir.to_string(),
+        rating: None,
+        label: None,
+        keywords: Vec::new(),
+    }
+}
+
+/// 读取照片的 EXIF 信息，补全日期、相机、尺寸并重新生成目标文件夹
+///
+/// ExifTool 读取失败时只使用文件系统信息，并返回失败原因
+pub fn enrich_photo(photo: &mut PhotoInfo, config: &ClassifyConfig) -> Option<String> {
+    let (metadata, exif_error) = match read_exif(&photo.path) {
+        Ok(metadata) => (metadata, None),
+        Err(e) => (metadata_from_file(&photo.path), Some(e)),
+    };
+    let (width, height) = metadata.display_dimensions();
+
+    photo.target_folder = config.generate_path_for_source(&metadata, &photo.source_root, &photo.relative_dir);
+    photo.date_time = metadata.date_time_original.clone().or(metadata.create_date.clone());
+    photo.date_time_original = metadata.date_time_original;
+    photo.create_date = metadata.create_date;
+    photo.camera = metadata.model;
+    photo.make = metadata.make;
+    photo.width = width;
+    photo.height = height;
+    photo.rating = metadata.rating;
+    photo.label = metadata.label;
+    photo.keywords = metadata.keywords;
+    exif_error
+}
+
+/// 批量读取照片的 EXIF 信息，jobs 大于 1 时使用 rayon 线程池并行读取。
+/// 每张照片原地更新，结果顺序与枚举顺序一致，与串行读取完全相同
+pub fn enrich_photos(photos: &mut [PhotoInfo], config: &ClassifyConfig, jobs: usize) {
+    if jobs > 1 && photos.len() > 1 {
+        match rayon::ThreadPoolBuilder::new().num_threads(jobs).build() {
+            Ok(pool) => {
+                pool.install(|| {
+                    photos.par_iter_mut().for_each(|photo| {
+                        enrich_photo(photo, config);
+                    })
+                });
+                return;
+            }
+            Err(e) => log::warn!("创建 EXIF 读取线程池失败，改为串行读取: {}", e),
+        }
+    }
+    for photo in photos {
+        enrich_photo(photo, config);
+    }
+}
+
+/// 单个文件的分类和重命名结果，用于排查照片被分到意外位置的原因
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClassifyDebug {
+    pub metadata: PhotoMetadata,
+    pub target_folder: String,
+    pub new_filename: String,
+    /// ExifTool 读取失败的原因，此时 metadata 只包含文件系统信息
+    pub exif_error: Option<String>,
+}
+
+/// 按当前分类和重命名配置处理单个文件，返回读取到的元数据、目标文件夹和新文件名。
+/// 单个文件没有源文件夹，{folder} 视为根目录；重命名计数器使用起始值
+pub fn debug_classify(
+    file_path: &str,
+    config: &ClassifyConfig,
+    rename_config: &RenameConfig,
+) -> Result<ClassifyDebug, String> {
+    let path = Path::new(file_path);
+    if !path.exists() {
+        return Err(format!("文件不存在: {}", file_path));
+    }
+    if !path.is_file() {
+        return Err(format!("不是文件: {}", file_path));
+    }
+    if !is_supported_photo(file_path) {
+        return Err(format!("不支持的文件格式: {}", file_path));
+    }
+
+    let (metadata, exif_error) = match read_exif(file_path) {
+        Ok(metadata) => (metadata, None),
+        Err(e) => (metadata_from_file(file_path), Some(e)),
+    };
+    Ok(ClassifyDebug {
+        target_folder: config.generate_path(&metadata),
+        new_filename: rename_config.generate_filename(&metadata, rename_config.counter_start),
+        metadata,
+        exif_error,
+    })
+}
+
+impl PhotoInfo {
+    /// 按新的分类配置重新计算目标文件夹，使用已读取的日期和相机信息
+    pub fn reclassify(&mut self, config: &ClassifyConfig) {
+        let metadata = self.metadata();
+        self.target_folder = config.generate_path_for_source(&metadata, &self.source_root, &self.relative_dir);
+    }
+
+    /// 用扫描时已读取的信息还原照片元数据，不读取文件
+    pub fn metadata(&self) -> PhotoMetadata {
+        // 旧版本扫描报告中只有合并后的 date_time
+        let date_time_original = match (&self.date_time_original, &self.create_date) {
+            (None, None) => self.date_time.clone(),
+            (original, _) => original.clone(),
+        };
+        PhotoMetadata {
+            file_path: self.path.clone(),
+            file_name: self.file_name.clone(),
+            file_size: self.file_size,
+            date_time_original,
+            create_date: self.create_date.clone(),
+            model: self.camera.clone(),
+            make: self.make.clone(),
+            width: self.width,
+            height: self.height,
+            rating: self.rating,
+            label: self.label.clone(),
+            keywords: self.keywords.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+/// 重命名检查中单个文件的新文件名
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RenameMapping {
+    pub source_path: String,
+    pub target_folder: String,
+    pub new_name: String,
+}
+
+/// 同一目标文件夹中得到相同新文件名的多个文件
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RenameCollision {
+    /// 相对目标文件夹的路径，如 `2024/03/20240315.jpg`
+    pub target: String,
+    pub sources: Vec<String>,
+}
+
+/// 按当前重命名配置检查全部扫描结果的结果
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RenameValidation {
+    pub mappings: Vec<RenameMapping>,
+    pub collisions: Vec<RenameCollision>,
+    /// 模板没有生成可用文件名（如只有 {date} 而照片没有日期）、改用原文件名的文件
+    pub empties: Vec<String>,
+}
+
+/// 对扫描结果中的每张照片试运行重命名，找出同一文件夹中新文件名相同的文件和模板生成空文件名的文件。
+/// 只使用扫描时读取的元数据，不读取文件也不访问目标文件夹：计数器从 counter_start 开始，
+/// 也不考虑目标文件夹中已有的文件。文件名按 NFC 且不区分大小写比较（macOS/Windows 默认的文件系统不区分大小写）
+pub fn validate_rename_over_scan(photos: &[PhotoInfo], rename_config: &RenameConfig) -> RenameValidation {
+    let mut validation = RenameValidation::default();
+    let mut counter = rename_config.counter_start;
+    let mut paired_names: HashMap<(PathBuf, String), String> = HashMap::new();
+    let mut targets: HashMap<(String, String), usize> = HashMap::new();
+    let mut collisions: Vec<RenameCollision> = Vec::new();
+
+    for photo in photos {
+        let pair_key = rename_config.pair_by_basename.then(|| basename_key(&photo.path));
+        let paired = pair_key.as_ref().and_then(|key| paired_names.get(key));
+        let new_name = if let Some(partner) = paired {
+            paired_file_name(partner, &photo.file_name, rename_config)
+        } else if rename_config.enabled {
+            let metadata = photo.metadata();
+            let name = rename_config.try_generate_filename(&metadata, counter).unwrap_or_else(|| {
+                validation.empties.push(photo.path.clone());
+                rename_config.generate_filename(&metadata, counter)
+            });
+            counter = counter.saturating_add(1);
+            if let Some(key) = pair_key {
+                paired_names.insert(key, name.clone());
+            }
+            name
+        } else {
+            rename_config.normalize_extension(&photo.file_name)
+        };
+
+        let key = (photo.target_folder.clone(), to_nfc(&new_name).to_lowercase());
+        let target = if photo.target_folder.is_empty() {
+            new_name.clone()
+        } else {
+            format!("{}/{}", photo.target_folder, new_name)
+        };
+        match targets.get(&key) {
+            Some(&index) => collisions[index].sources.push(photo.path.clone()),
+            None => {
+                targets.insert(key, collisions.len());
+                collisions.push(RenameCollision { target, sources: vec![photo.path.clone()] });
+            }
+        }
+        validation.mappings.push(RenameMapping {
+            source_path: photo.path.clone(),
+            target_folder: photo.target_folder.clone(),
+            new_name,
+        });
+    }
+
+    validation.collisions = collisions.into_iter().filter(|c| c.sources.len() > 1).collect();
+    validation
+}
+
+/// 按相机分配目标文件夹的规则：相机型号或品牌匹配时传输到 target_dir，而不是默认的目标文件夹
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CameraRoute {
+    /// 相机型号（如 Canon EOS R5）、品牌（如 Canon）或品牌加型号，不区分大小写
+    pub camera: String,
+    pub target_dir: String,
+}
+
+impl CameraRoute {
+    /// 解析 `相机=目标文件夹` 形式的规则，如 `Canon EOS R5=/Volumes/A`
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let (camera, target_dir) = value
+            .split_once('=')
+            .map(|(camera, dir)| (camera.trim(), dir.trim()))
+            .filter(|(camera, dir)| !camera.is_empty() && !dir.is_empty())
+            .ok_or_else(|| format!("无效的相机目标规则（应为 相机=目标文件夹）: {}", value))?;
+        Ok(Self {
+            camera: camera.to_string(),
+            target_dir: target_dir.to_string(),
+        })
+    }
+
+    /// 照片的相机型号、品牌或品牌加型号与规则相同
+    pub fn matches(&self, photo: &PhotoInfo) -> bool {
+        let camera = self.camera.trim();
+        let equals = |name: &str| name.trim().eq_ignore_ascii_case(camera);
+        match (photo.make.as_deref(), photo.camera.as_deref()) {
+            (Some(make), Some(model)) => {
+                equals(model) || equals(make) || equals(&format!("{} {}", make.trim(), model.trim()))
+            }
+            (Some(name), None) | (None, Some(name)) => equals(name),
+            (None, None) => false,
+        }
+    }
+}
+
+/// 照片的目标根目录：第一条匹配的规则的目标文件夹，没有匹配的规则时为 default_dir
+pub fn route_target_dir<'a>(routes: &'a [CameraRoute], photo: &PhotoInfo, default_dir: &'a str) -> &'a str {
+    routes
+        .iter()
+        .find(|route| route.matches(photo))
+        .map_or(default_dir, |route| route.target_dir.as_str())
+}
+
+/// 目标文件已存在时的处理方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConflictPolicy {
+    /// 添加 `_N` 序号，保留已有文件
+    #[default]
+    Rename,
+    /// 跳过该文件
+    Skip,
+    /// 覆盖目标目录中已有的文件（本批次内的同名文件仍添加序号）
+    Overwrite,
+}
+
+/// 传输计划中单个文件的处理方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PlanAction {
+    /// 复制到新路径
+    Copy,
+    /// 覆盖已有文件
+    Overwrite,
+    /// 内容重复，跳过
+    SkipDuplicate,
+    /// 目标路径已被占用，按冲突策略跳过
+    SkipExisting,
+    /// 无法传输（如目标路径超出目标文件夹）
+    Error,
+}
+
+/// 传输计划中的单个文件
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlanEntry {
+    pub source_path: String,
+    pub file_name: String,
+    pub file_size: u64,
+    pub camera: Option<String>,
+    /// 最终目标路径；跳过重复文件时为空
+    pub target_path: String,
+    pub action: PlanAction,
+    /// 内容相同的文件（目标目录中已有的文件或本批次中较早的文件）
+    pub duplicate_of: Option<String>,
+    /// 错误原因（Error），或去重检查失败时的提示
+    pub error: Option<String>,
+}
+
+/// 传输计划：在传输前确定每个文件的最终路径和去重结果
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransferPlan {
+    pub target_dir: String,
+    pub entries: Vec<PlanEntry>,
+}
+
+impl TransferPlan {
+    /// 指定处理方式的文件数
+    pub fn count(&self, action: PlanAction) -> usize {
+        self.entries.iter().filter(|e| e.action == action).count()
+    }
+
+    /// 计划中所有文件的总字节数
+    pub fn total_size(&self) -> u64 {
+        self.entries.iter().map(|e| e.file_size).sum()
+    }
+
+    /// 去重统计：有多少文件因内容重复而跳过
+    pub fn duplicate_estimate(&self) -> DuplicateEstimate {
+        let (duplicates, skipped_bytes) = self
+            .entries
+            .iter()
+            .filter(|e| e.action == PlanAction::SkipDuplicate)
+            .fold((0, 0), |(count, bytes), e| (count + 1, bytes + e.file_size));
+        DuplicateEstimate {
+            total: self.entries.len(),
+            duplicates,
+            new: self.entries.len() - duplicates,
+            skipped_bytes,
+        }
+    }
+}
+
+/// 传输前的去重统计（count_duplicates 返回）
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DuplicateEstimate {
+    /// 扫描到的照片数
+    pub total: usize,
+    /// 与目标目录（及参考目录）中已有文件或本批次中较早的文件内容相同的照片数
+    pub duplicates: usize,
+    /// 需要传输的照片数
+    pub new: usize,
+    /// 因重复而跳过的字节数
+    pub skipped_bytes: u64,
+}
+
+/// 为文件在目标目录中选择一个不冲突的路径（避开磁盘上已有的文件和 claimed 中已分配的路径）
+pub fn resolve_target_path(target_dir: &Path, file_name: &str, claimed: &HashSet<PathBuf>) -> PathBuf {
+    let target_path = target_dir.join(existing_entry_name(target_dir, file_name));
+    if !target_path.exists() && !claimed.contains(&target_path) {
+        return target_path;
+    }
+
+    let stem = Path::new(file_name)
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let ext = Path::new(file_name)
+        .extension()
+        .map(|e| e.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    let mut counter = 1;
+    loop {
+        let new_name = if ext.is_empty() {
+            format!("{}_{}", stem, counter)
+        } else {
+            format!("{}_{}.{}", stem, counter, ext)
+        };
+        let new_path = target_dir.join(existing_entry_name(target_dir, &new_name));
+        if !new_path.exists() && !claimed.contains(&new_path) {
+            return new_path;
+        }
+        counter += 1;
+    }
+}
+
+/// 同名文件的新文件名：沿用已生成的文件名，扩展名换成自己的（按 extension_case 转换）
+pub(crate) fn paired_file_name(partner: &str, file_name: &str, rename_config: &RenameConfig) -> String {
+    let extension = Path::new(file_name)
+        .extension()
+        .map(|ext| rename_config.extension_case.apply(&ext.to_string_lossy()))
+        .unwrap_or_default();
+    Path::new(partner).with_extension(extension).to_string_lossy().to_string()
+}
+
+/// 本次导入 {counter} 的起始值：按 counter_mode 统计目标文件夹（含子文件夹）中已有的照片，
+/// 目标文件夹不存在或没有照片时使用 counter_start
+pub fn initial_counter(target_base_dir: &str, rename_config: &RenameConfig) -> u32 {
+    let start = rename_config.counter_start;
+    if rename_config.counter_mode == CounterMode::Fixed {
+        return start;
+    }
+
+    let existing_photos = WalkDir::new(target_base_dir)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file() && is_supported_photo(&e.path().to_string_lossy()));
+    match rename_config.counter_mode {
+        CounterMode::Fixed => start,
+        CounterMode::ExistingCount => {
+            let count = existing_photos.count();
+            start.saturating_add(u32::try_from(count).unwrap_or(u32::MAX))
+        }
+        CounterMode::MaxSuffix => existing_photos
+            .filter_map(|e| trailing_number(&e.path().file_stem()?.to_string_lossy()))
+            .max()
+            .map_or(start, |max| max.saturating_add(1).max(start)),
+    }
+}
+
+/// 生成传输计划：依次确定每个文件的去重结果、重命名后的文件名和最终路径。
+/// 冲突同时考虑磁盘上已有的文件和本批次中已分配的路径；
+/// deduplicator 为 None 时不检查重复。不会创建目录或写入任何文件
+pub fn build_plan(
+    photos: &[PhotoInfo],
+    target_base_dir: &str,
+    rename_config: &RenameConfig,
+    conflict_policy: ConflictPolicy,
+    mut deduplicator: Option<&mut Deduplicator>,
+) -> TransferPlan {
+    let base = Path::new(target_base_dir);
+    let mut entries = Vec::with_capacity(photos.len());
+    let mut claimed: HashSet<PathBuf> = HashSet::new();
+    let mut counter = if rename_config.enabled {
+        initial_counter(target_base_dir, rename_config)
+    } else {
+        rename_config.counter_start
+    };
+    // pair_by_basename 时同一目录下同名文件已生成的新文件名
+    let mut paired_names: HashMap<(PathBuf, String), String> = HashMap::new();
+
+    for photo in photos {
+        let mut entry = PlanEntry {
+            source_path: photo.path.clone(),
+            file_name: photo.file_name.clone(),
+            file_size: photo.file_size,
+            camera: photo.camera.clone(),
+            target_path: String::new(),
+            action: PlanAction::Copy,
+            duplicate_of: None,
+            error: None,
+        };
+
+        // 检查重复
+        if let Some(deduplicator) = deduplicator.as_deref_mut() {
+            match deduplicator.check_duplicate(&photo.path, photo.file_size) {
+                Ok(Some(existing)) => {
+                    entry.action = PlanAction::SkipDuplicate;
+                    entry.duplicate_of = Some(existing);
+                    entries.push(entry);
+                    continue;
+                }
+                Ok(None) => {}
+                Err(e) => entry.error = Some(format!("检查重复失败: {}", e)),
+            }
+        }
+
+        // 构建目标路径（不能跳出目标根目录）
+        let target_dir = join_target_folder(base, &photo.target_folder);
+        if !is_within_dir(base, &target_dir) {
+            entry.action = PlanAction::Error;
+            entry.target_path = target_dir.to_string_lossy().to_string();
+            entry.error = Some(format!("目标路径超出目标目录: {}", target_dir.display()));
+            entries.push(entry);
+            continue;
+        }
+
+        // 生成新文件名（如果启用重命名）
+        let pair_key = rename_config.pair_by_basename.then(|| basename_key(&photo.path));
+        let paired = pair_key.as_ref().and_then(|key| paired_names.get(key));
+        // 本文件为同名文件中第一个生成新文件名的文件时，冲突处理后的最终文件名供其余同名文件沿用
+        let mut pair_origin = None;
+        let file_name = if let Some(partner) = paired {
+            // 与同名文件使用相同的新文件名，只替换扩展名，不占用计数
+            paired_file_name(partner, &photo.file_name, rename_config)
+        } else if rename_config.enabled {
+            let metadata = read_exif(&photo.path).unwrap_or_else(|_| PhotoMetadata {
+                file_name: photo.file_name.clone(),
+                ..Default::default()
+            });
+            let name = rename_config.generate_filename(&metadata, counter);
+            // 达到 u32::MAX 后不再递增，重名由冲突处理添加序号
+            counter = counter.saturating_add(1);
+            if let Some(key) = pair_key {
+                paired_names.insert(key.clone(), name.clone());
+                pair_origin = Some(key);
+            }
+            name
+        } else {
+            rename_config.normalize_extension(&photo.file_name)
+        };
+
+        // 处理文件名冲突（序号加在扩展名之前，扩展名保持转换后的大小写）。
+        // 文件名按 NFC 比较，已有文件为 NFD 形式时视为同名
+        let file_name = existing_entry_name(&target_dir, &file_name);
+        let target_path = target_dir.join(&file_name);
+        let on_disk = target_path.exists();
+        // 目标就是源文件本身（如目标文件夹位于源文件夹内），复制没有意义
+        if on_disk && is_same_file(Path::new(&photo.path), &target_path) {
+            entry.action = PlanAction::SkipExisting;
+            entry.target_path = target_path.to_string_lossy().to_string();
+            entry.error = Some("目标文件就是源文件，已跳过".to_string());
+            entries.push(entry);
+            continue;
+        }
+        let final_path = match conflict_policy {
+            ConflictPolicy::Skip if on_disk || claimed.contains(&target_path) => {
+                entry.action = PlanAction::SkipExisting;
+                target_path
+            }
+            ConflictPolicy::Overwrite if on_disk && !claimed.contains(&target_path) => {
+                entry.action = PlanAction::Overwrite;
+                target_path
+            }
+            _ => resolve_target_path(&target_dir, &file_name, &claimed),
+        };
+        if entry.action != PlanAction::SkipExisting {
+            claimed.insert(final_path.clone());
+        }
+        // 冲突时添加了序号（如 _1），同名文件使用同样的序号，保持成对
+        if let (Some(key), Some(final_name)) = (pair_origin, final_path.file_name()) {
+            paired_names.insert(key, final_name.to_string_lossy().to_string());
+        }
+        entry.target_path = final_path.to_string_lossy().to_string();
+        entries.push(entry);
+    }
+
+    TransferPlan {
+        target_dir: target_base_dir.to_string(),
+        entries,
+    }
+}
+
+/// 执行照片传输
+pub fn transfer_photos(
+    sink: &dyn ProgressSink,
+    photos: &[PhotoInfo],
+    target_base_dir: &str,
+    skip_duplicates: bool,
+) -> Result<TransferResult, String> {
+    let mut success_count = 0;
+    let mut skip_count = 0;
+    let mut error_count = 0;
+    let mut errors = Vec::new();
+    let mut skipped = Vec::new();
+    let mut deduplicator = Deduplicator::new();
+    let total = photos.len();
+    let total_bytes: u64 = photos.iter().map(|p| p.file_size).sum();
+    let mut bytes_transferred = 0u64;
+
+    // 如果启用去重，先扫描目标目录中已有的文件
+    if skip_duplicates {
+        sink.transfer_progress(TransferProgress {
+            current: 0,
+            total,
+            current_file: "正在扫描目标目录已有文件...".to_string(),
+            bytes_transferred: 0,
+            total_bytes,
+            status: TransferStatus::Scanning,
+            phase: TransferPhase::Scanning,
+            skipped_duplicates: 0,
+        });
+
+        if Path::new(target_base_dir).exists() {
+            for entry in WalkDir::new(target_base_dir)
+                .follow_links(true)
+                .into_iter()
+                .filter_map(|e| e.ok())
+            {
+                if entry.path().is_file() {
+                    if let Err(e) = deduplicator.add_known_file(&entry.path().to_string_lossy()) {
+                        log::debug!("索引已有文件失败 {}: {}", entry.path().display(), e);
+                    }
+                }
+            }
+        }
+    }
+
+    for (index, photo) in photos.iter().enumerate() {
+        // 发送进度事件
+        sink.transfer_progress(TransferProgress {
+            current: index + 1,
+            total,
+            current_file: photo.file_name.clone(),
+            bytes_transferred,
+            total_bytes,
+            status: TransferStatus::Transferring,
+            phase: TransferPhase::Transferring,
+            skipped_duplicates: skip_count,
+        });
+
+        // 检查重复
+        if skip_duplicates {
+            match deduplicator.check_duplicate(&photo.path, photo.file_size) {
+                Ok(Some(original)) => {
+                    skip_count += 1;
+                    bytes_transferred += photo.file_size;
+                    skipped.push(SkippedFile {
+                        source: photo.path.clone(),
+                        matched_original: original,
+                    });
+                    continue;
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    errors.push(format!("检查重复失败 {}: {}", photo.file_name, e));
+                }
+            }
+        }
+
+        // 构建目标路径
+        let target_dir = join_target_folder(Path::new(target_base_dir), &photo.target_folder);
+        let target_path = target_dir.join(&photo.file_name);
+
+        // 创建目标目录
+        if let Err(e) = fs::create_dir_all(&target_dir) {
+            error_count += 1;
+            errors.push(format!("创建目录失败 {}: {}", target_dir.display(), e));
+            continue;
+        }
+
+        // 如果目标文件已存在，添加序号
+        let final_target_path = if target_path.exists() {
+            let stem = Path::new(&photo.file_name)
+                .file_stem()
+                .map(|s| s.to_string_lossy().to_string())
+                .unwrap_or_default();
+            let ext = Path::new(&photo.file_name)
+                .extension()
+                .map(|e| e.to_string_lossy().to_string())
+                .unwrap_or_default();
+            
+            let mut counter = 1;
+            loop {
+                let new_name = format!("{}_{}.{}", stem, counter, ext);
+                let new_path = target_dir.join(&new_name);
+                if !new_path.exists() {
+                    break new_path;
+                }
+                counter += 1;
+            }
+        } else {
+            target_path
+        };
+
+        // 复制文件
+        match fs::copy(&photo.path, &final_target_path) {
+            Ok(_) => {
+                success_count += 1;
+                bytes_transferred += photo.file_size;
+            }
+            Err(e) => {
+                error_count += 1;
+                errors.push(format!("复制失败 {}: {}", photo.file_name, e));
+            }
+        }
+    }
+
+    // 发送完成事件
+    sink.transfer_progress(TransferProgress {
+        current: total,
+        total,
+        current_file: "传输完成".to_string(),
+        bytes_transferred: total_bytes,
+        total_bytes,
+        status: TransferStatus::Completed,
+        phase: TransferPhase::Completed,
+        skipped_duplicates: skip_count,
+    });
+
+    Ok(TransferResult {
+        success_count,
+        skip_count,
+        error_count,
+        errors,
+        warnings: Vec::new(),
+        skipped,
+        files: None,
+        previews: 0,
+        new_folders: Vec::new(),
+        merged_folders: Vec::new(),
+    })
+}
+
+/// 格式化文件大小
+pub fn format_size(bytes: u64) -> String {
+    const KB: u64 = 1024;
+    const MB: u64 = KB * 1024;
+    const GB: u64 = MB * 1024;
+
+    if bytes >= GB {
+        format!("{:.2} GB", bytes as f64 / GB as f64)
+    } else if bytes >= MB {
+        format!("{:.2} MB", bytes as f64 / MB as f64)
+    } else if bytes >= KB {
+        format!("{:.2} KB", bytes as f64 / KB as f64)
+    } else {
+        format!("{} B", bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rename::ExtCase;
+    use std::fs::{self, File};
+    use std::io::Write;
+    use tempfile::TempDir;
+
+    // ==================== 辅助函数 ====================
+
+    fn create_test_photo(dir: &TempDir, subdir: &str, name: &str, content: &[u8]) -> String {
+        let folder = dir.path().join(subdir);
+        fs::create_dir_all(&folder).unwrap();
+        let path = folder.join(name);
+        let mut file = File::create(&path).unwrap();
+        file.write_all(content).unwrap();
+        path.to_string_lossy().to_string()
+    }
+
+    fn create_test_photo_root(dir: &TempDir, name: &str, content: &[u8]) -> String {
+        let path = dir.path().join(name);
+        let mut file = File::create(&path).unwrap();
+        file.write_all(content).unwrap();
+        path.to_string_lossy().to_string()
+    }
+
+    // ==================== format_size 测试 ====================
+
+    #[test]
+    fn test_format_size_bytes() {
+        assert_eq!(format_size(0), "0 B");
+        assert_eq!(format_size(1), "1 B");
+        assert_eq!(format_size(100), "100 B");
+        assert_eq!(format_size(1023), "1023 B");
+    }
+
+    #[test]
+    fn test_format_size_kilobytes() {
+        assert_eq!(format_size(1024), "1.00 KB");
+        assert_eq!(format_size(1536), "1.50 KB");
+        assert_eq!(format_size(10240), "10.00 KB");
+        assert_eq!(format_size(1024 * 1023), "1023.00 KB");
+    }
+
+    #[test]
+    fn test_format_size_megabytes() {
+        assert_eq!(format_size(1024 * 1024), "1.00 MB");
+        assert_eq!(format_size(1024 * 1024 * 5), "5.00 MB");
+        assert_eq!(format_size(1024 * 1024 * 25), "25.00 MB"); // 典型RAW大小
+        assert_eq!(format_size(1024 * 1024 * 100), "100.00 MB");
+    }
+
+    #[test]
+    fn test_format_size_gigabytes() {
+        assert_eq!(format_size(1024 * 1024 * 1024), "1.00 GB");
+        assert_eq!(format_size(1024 * 1024 * 1024 * 2), "2.00 GB");
+        assert_eq!(format_size(1024u64 * 1024 * 1024 * 100), "100.00 GB");
+    }
+
+    #[test]
+    fn test_format_size_decimal() {
+        // 测试小数位
+        let size = 1024 * 1024 + 512 * 1024; // 1.5 MB
+        assert_eq!(format_size(size), "1.50 MB");
+    }
 
     // ==================== ScanResult 测试 ====================
 
     #[test]
-    fn test_scan_result_empty() {
-        let result = ScanResult {
-            total_files: 0,
-            total_size: 0,
-            photos: vec![],
+    fn test_scan_result_empty() {
+        let result = ScanResult {
+            total_files: 0,
+            total_size: 0,
+            photos: vec![],
+            skipped_unreadable: Vec::new(),
+        };
+        assert_eq!(result.total_files, 0);
+        assert!(result.photos.is_empty());
+    }
+
+    #[test]
+    fn test_scan_result_with_photos() {
+        let photos = vec![
+            PhotoInfo {
+                path: "/test/photo1.jpg".to_string(),
+                file_name: "photo1.jpg".to_string(),
+                file_size: 1000,
+                date_time: Some("2024:03:15 14:30:00".to_string()),
+                date_time_original: None,
+                create_date: None,
+                camera: Some("Canon".to_string()),
+                make: None,
+                target_folder: "2024/03".to_string(),
+                is_duplicate: false,
+                duplicate_of: None,
+                width: None,
+                height: None,
+                relative_dir: String::new(),
+                suspect: false,
+                source_root: String::new(),
+                rating: None,
+                label: None,
+                keywords: Vec::new(),
+            },
+            PhotoInfo {
+                path: "/test/photo2.jpg".to_string(),
+                file_name: "photo2.jpg".to_string(),
+                file_size: 2000,
+                date_time: None,
+                date_time_original: None,
+                create_date: None,
+                camera: None,
+                make: None,
+                target_folder: "未知日期".to_string(),
+                is_duplicate: false,
+                duplicate_of: None,
+                width: None,
+                height: None,
+                relative_dir: String::new(),
+                suspect: false,
+                source_root: String::new(),
+                rating: None,
+                label: None,
+                keywords: Vec::new(),
+            },
+        ];
+
+        let result = ScanResult {
+            total_files: 2,
+            total_size: 3000,
+            photos,
+            skipped_unreadable: Vec::new(),
+        };
+
+        assert_eq!(result.total_files, 2);
+        assert_eq!(result.total_size, 3000);
+        assert_eq!(result.photos.len(), 2);
+    }
+
+    #[test]
+    fn test_scan_result_reclassify_without_files() {
+        let photo = |name: &str, date: Option<&str>, folder: &str| PhotoInfo {
+            path: format!("/nonexistent/DCIM/{}/{}", folder, name),
+            file_name: name.to_string(),
+            file_size: 100,
+            date_time: date.map(String::from),
+            date_time_original: None,
+            create_date: None,
+            camera: Some("ILCE-7M4".to_string()),
+            make: Some("Sony".to_string()),
+            target_folder: String::new(),
+            is_duplicate: false,
+            duplicate_of: None,
+            width: None,
+            height: None,
+            relative_dir: folder.to_string(),
+            suspect: false,
+            source_root: String::new(),
+            rating: None,
+            label: None,
+            keywords: Vec::new(),
+        };
+        let mut result = ScanResult {
+            total_files: 2,
+            total_size: 200,
+            photos: vec![
+                photo("DSC00001.ARW", Some("2024:03:15 10:00:00"), "100MSDCF"),
+                photo("DSC00002.ARW", None, "101MSDCF"),
+            ],
+            skipped_unreadable: Vec::new(),
+        };
+
+        result.reclassify(&ClassifyConfig::default());
+        assert_eq!(result.photos[0].target_folder, "2024/03");
+        assert_eq!(result.photos[1].target_folder, "未知日期");
+
+        result.reclassify(&ClassifyConfig {
+            template: "{make}/{camera}/{year}".to_string(),
+            fallback_folder: "未分类".to_string(),
+            ..Default::default()
+        });
+        assert_eq!(result.photos[0].target_folder, "Sony/ILCE-7M4/2024");
+        assert_eq!(result.photos[1].target_folder, "未分类");
+        assert_eq!(result.total_files, 2);
+    }
+
+    #[test]
+    fn test_scan_result_rating_reclassify_and_filter() {
+        let photo = |name: &str, rating: Option<u8>| PhotoInfo {
+            path: format!("/nonexistent/{}", name),
+            file_name: name.to_string(),
+            file_size: 100,
+            date_time: Some("2024:03:15 10:00:00".to_string()),
+            date_time_original: None,
+            create_date: None,
+            camera: None,
+            make: None,
+            target_folder: String::new(),
+            is_duplicate: false,
+            duplicate_of: None,
+            width: None,
+            height: None,
+            relative_dir: String::new(),
+            suspect: false,
+            source_root: String::new(),
+            rating,
+            label: None,
+            keywords: Vec::new(),
+        };
+        let mut result = ScanResult {
+            total_files: 4,
+            total_size: 400,
+            photos: vec![
+                photo("a.jpg", Some(5)),
+                photo("b.jpg", Some(3)),
+                photo("c.jpg", Some(2)),
+                photo("d.jpg", None),
+            ],
+            skipped_unreadable: Vec::new(),
+        };
+
+        result.reclassify(&ClassifyConfig {
+            template: "{rating}".to_string(),
+            ..Default::default()
+        });
+        let folders: Vec<_> = result.photos.iter().map(|p| p.target_folder.as_str()).collect();
+        assert_eq!(folders, ["5星", "3星", "2星", "未评级"]);
+
+        // 未评级的照片不满足任何最低星级
+        result.retain_min_rating(3);
+        let names: Vec<_> = result.photos.iter().map(|p| p.file_name.as_str()).collect();
+        assert_eq!(names, ["a.jpg", "b.jpg"]);
+        assert_eq!(result.total_files, 2);
+        assert_eq!(result.total_size, 200);
+    }
+
+    // ==================== PhotoInfo 测试 ====================
+
+    #[test]
+    fn test_photo_info_default_values() {
+        let info = PhotoInfo {
+            path: String::new(),
+            file_name: String::new(),
+            file_size: 0,
+            date_time: None,
+            date_time_original: None,
+            create_date: None,
+            camera: None,
+            make: None,
+            target_folder: String::new(),
+            is_duplicate: false,
+            duplicate_of: None,
+            width: None,
+            height: None,
+            relative_dir: String::new(),
+            suspect: false,
+            source_root: String::new(),
+            rating: None,
+            label: None,
+            keywords: Vec::new(),
+        };
+        assert!(!info.is_duplicate);
+        assert!(info.duplicate_of.is_none());
+    }
+
+    #[test]
+    fn test_photo_info_duplicate() {
+        let info = PhotoInfo {
+            path: "/test/copy.jpg".to_string(),
+            file_name: "copy.jpg".to_string(),
+            file_size: 1000,
+            date_time: None,
+            date_time_original: None,
+            create_date: None,
+            camera: None,
+            make: None,
+            target_folder: "2024/03".to_string(),
+            is_duplicate: true,
+            duplicate_of: Some("/test/original.jpg".to_string()),
+            width: None,
+            height: None,
+            relative_dir: String::new(),
+            suspect: false,
+            source_root: String::new(),
+            rating: None,
+            label: None,
+            keywords: Vec::new(),
+        };
+        assert!(info.is_duplicate);
+        assert_eq!(info.duplicate_of.as_deref(), Some("/test/original.jpg"));
+    }
+
+    #[test]
+    fn test_photo_info_serialization() {
+        let info = PhotoInfo {
+            path: "/test/photo.cr3".to_string(),
+            file_name: "photo.cr3".to_string(),
+            file_size: 25000000,
+            date_time: Some("2024:12:25 10:30:00".to_string()),
+            date_time_original: None,
+            create_date: None,
+            camera: Some("Canon EOS R5".to_string()),
+            make: None,
+            target_folder: "2024/12/25".to_string(),
+            is_duplicate: false,
+            duplicate_of: None,
+            width: None,
+            height: None,
+            relative_dir: String::new(),
+            suspect: false,
+            source_root: String::new(),
+            rating: None,
+            label: None,
+            keywords: Vec::new(),
+        };
+
+        let json = serde_json::to_string(&info).unwrap();
+        assert!(json.contains("photo.cr3"));
+        assert!(json.contains("Canon EOS R5"));
+
+        let deserialized: PhotoInfo = serde_json::from_str(&json).unwrap();
+        assert_eq!(deserialized.file_name, "photo.cr3");
+    }
+
+    #[test]
+    fn test_photo_info_reclassify() {
+        let mut info = PhotoInfo {
+            path: "/DCIM/100CANON/IMG_0001.CR3".to_string(),
+            file_name: "IMG_0001.CR3".to_string(),
+            file_size: 100,
+            date_time: Some("2024:12:25 10:30:00".to_string()),
+            date_time_original: None,
+            create_date: None,
+            camera: Some("Canon EOS R5".to_string()),
+            make: Some("Canon".to_string()),
+            target_folder: "2024/12".to_string(),
+            is_duplicate: false,
+            duplicate_of: None,
+            width: None,
+            height: None,
+            relative_dir: "100CANON".to_string(),
+            suspect: false,
+            source_root: String::new(),
+            rating: None,
+            label: None,
+            keywords: Vec::new(),
+        };
+
+        info.reclassify(&ClassifyConfig {
+            template: "{make}/{folder}/{year}-{month}-{day}".to_string(),
+            fallback_folder: "未知日期".to_string(),
+            ..Default::default()
+        });
+        assert_eq!(info.target_folder, "Canon/100CANON/2024-12-25");
+
+        info.date_time = None;
+        info.reclassify(&ClassifyConfig::default());
+        assert_eq!(info.target_folder, "未知日期");
+
+        // 没有拍摄时间时使用创建时间
+        info.create_date = Some("2023:06:01 08:00:00".to_string());
+        info.reclassify(&ClassifyConfig::default());
+        assert_eq!(info.target_folder, "2023/06");
+    }
+
+    #[test]
+    fn test_photo_info_deserialize_without_new_fields() {
+        // 旧版本扫描报告中的照片信息
+        let json = r#"{
+            "path": "/DCIM/IMG_0001.CR3",
+            "file_name": "IMG_0001.CR3",
+            "file_size": 100,
+            "date_time": "2024:12:25 10:30:00",
+            "camera": "Canon EOS R5",
+            "target_folder": "2024/12",
+            "is_duplicate": false,
+            "duplicate_of": null
+        }"#;
+        let mut info: PhotoInfo = serde_json::from_str(json).unwrap();
+        assert_eq!(info.make, None);
+        assert_eq!(info.date_time_original, None);
+        assert_eq!(info.create_date, None);
+
+        info.reclassify(&ClassifyConfig::default());
+        assert_eq!(info.target_folder, "2024/12");
+    }
+
+    // ==================== TransferProgress 测试 ====================
+
+    #[test]
+    fn test_transfer_progress_initial() {
+        let progress = TransferProgress {
+            current: 0,
+            total: 100,
+            current_file: "准备中...".to_string(),
+            bytes_transferred: 0,
+            total_bytes: 1000000,
+            status: TransferStatus::Preparing,
+            phase: TransferPhase::Preparing,
+            skipped_duplicates: 0,
+        };
+
+        assert_eq!(progress.current, 0);
+        assert_eq!(progress.status, TransferStatus::Preparing);
+    }
+
+    #[test]
+    fn test_transfer_progress_in_progress() {
+        let progress = TransferProgress {
+            current: 50,
+            total: 100,
+            current_file: "IMG_0050.CR3".to_string(),
+            bytes_transferred: 500000,
+            total_bytes: 1000000,
+            status: TransferStatus::Transferring,
+            phase: TransferPhase::Transferring,
+            skipped_duplicates: 5,
+        };
+
+        assert_eq!(progress.current, 50);
+        assert_eq!(progress.skipped_duplicates, 5);
+        // 计算进度百分比
+        let percentage = (progress.current as f64 / progress.total as f64) * 100.0;
+        assert_eq!(percentage, 50.0);
+    }
+
+    #[test]
+    fn test_transfer_progress_completed() {
+        let progress = TransferProgress {
+            current: 100,
+            total: 100,
+            current_file: "传输完成".to_string(),
+            bytes_transferred: 1000000,
+            total_bytes: 1000000,
+            status: TransferStatus::Completed,
+            phase: TransferPhase::Completed,
+            skipped_duplicates: 10,
+        };
+
+        assert_eq!(progress.status, TransferStatus::Completed);
+        assert_eq!(progress.bytes_transferred, progress.total_bytes);
+    }
+
+    #[test]
+    fn test_transfer_progress_phase_serialization() {
+        let progress = TransferProgress {
+            current: 0,
+            total: 10,
+            current_file: "正在准备传输...".to_string(),
+            bytes_transferred: 0,
+            total_bytes: 100,
+            status: TransferStatus::Preparing,
+            phase: TransferPhase::Preparing,
+            skipped_duplicates: 0,
+        };
+        let json = serde_json::to_value(&progress).unwrap();
+        assert_eq!(json["phase"], "preparing");
+        assert_eq!(json["status"], "preparing");
+        assert_eq!(serde_json::to_value(TransferPhase::Verifying).unwrap(), "verifying");
+    }
+
+    // ==================== TransferResult 测试 ====================
+
+    #[test]
+    fn test_transfer_result_all_success() {
+        let result = TransferResult {
+            success_count: 100,
+            skip_count: 0,
+            error_count: 0,
+            errors: vec![],
+            warnings: vec![],
+            skipped: Vec::new(),
+            files: None,
+            previews: 0,
+            new_folders: Vec::new(),
+            merged_folders: Vec::new(),
+        };
+
+        assert_eq!(result.success_count, 100);
+        assert!(result.errors.is_empty());
+    }
+
+    #[test]
+    fn test_transfer_result_with_skips() {
+        let result = TransferResult {
+            success_count: 80,
+            skip_count: 20,
+            error_count: 0,
+            errors: vec![],
+            warnings: vec![],
+            skipped: Vec::new(),
+            files: None,
+            previews: 0,
+            new_folders: Vec::new(),
+            merged_folders: Vec::new(),
+        };
+
+        assert_eq!(result.success_count + result.skip_count, 100);
+    }
+
+    #[test]
+    fn test_transfer_result_skipped_details() {
+        let result = TransferResult {
+            success_count: 0,
+            skip_count: 1,
+            error_count: 0,
+            errors: vec![],
+            warnings: vec![],
+            skipped: vec![SkippedFile {
+                source: "/DCIM/IMG_0001.JPG".to_string(),
+                matched_original: "/NAS/2024/03/IMG_0001.JPG".to_string(),
+            }],
+            files: None,
+            previews: 0,
+            new_folders: Vec::new(),
+            merged_folders: Vec::new(),
+        };
+        let json = serde_json::to_value(&result).unwrap();
+        assert_eq!(json["skipped"][0]["matched_original"], "/NAS/2024/03/IMG_0001.JPG");
+
+        // 旧版本的结果没有 skipped 字段
+        let old: TransferResult = serde_json::from_str(
+            r#"{"success_count": 1, "skip_count": 0, "error_count": 0, "errors": []}"#,
+        ).unwrap();
+        assert!(old.skipped.is_empty());
+        assert!(old.files.is_none());
+    }
+
+    #[test]
+    fn test_transfer_result_with_errors() {
+        let result = TransferResult {
+            success_count: 95,
+            skip_count: 0,
+            error_count: 5,
+            errors: vec![
+                "复制失败 photo1.jpg: 权限不足".to_string(),
+                "复制失败 photo2.jpg: 磁盘空间不足".to_string(),
+            ],
+            warnings: vec![],
+            skipped: Vec::new(),
+            files: None,
+            previews: 0,
+            new_folders: Vec::new(),
+            merged_folders: Vec::new(),
+        };
+
+        assert_eq!(result.error_count, 5);
+        assert_eq!(result.errors.len(), 2);
+    }
+
+    // ==================== 路径限制测试 ====================
+
+    #[test]
+    fn test_is_within_dir() {
+        let dir = TempDir::new().unwrap();
+        let base = dir.path();
+        fs::create_dir_all(base.join("2024")).unwrap();
+
+        assert!(is_within_dir(base, base));
+        assert!(is_within_dir(base, &base.join("2024")));
+        // 尚未创建的子目录
+        assert!(is_within_dir(base, &base.join("2025/01")));
+        assert!(!is_within_dir(base, &base.join("../outside")));
+        assert!(!is_within_dir(base, &base.join("2024/../../outside")));
+        assert!(!is_within_dir(&base.join("2024"), base));
+    }
+
+    #[test]
+    fn test_join_target_folder_uses_platform_separator() {
+        let base = Path::new("nas");
+        let expected = base.join("2024").join("03");
+        assert_eq!(join_target_folder(base, "2024/03"), expected);
+        assert_eq!(join_target_folder(base, "2024\\03"), expected);
+        assert_eq!(join_target_folder(base, ""), base.to_path_buf());
+
+        // 拼接结果中只包含平台分隔符
+        let joined = join_target_folder(base, "2024/03").to_string_lossy().to_string();
+        let other = if std::path::MAIN_SEPARATOR == '/' { '\\' } else { '/' };
+        assert!(!joined.contains(other));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_is_within_dir_symlink_escape() {
+        let dir = TempDir::new().unwrap();
+        let outside = TempDir::new().unwrap();
+        std::os::unix::fs::symlink(outside.path(), dir.path().join("link")).unwrap();
+
+        assert!(!is_within_dir(dir.path(), &dir.path().join("link/2024")));
+    }
+
+    // ==================== scan_photos 测试 ====================
+
+    #[test]
+    fn test_scan_photos_empty_directory() {
+        let dir = TempDir::new().unwrap();
+        let config = ClassifyConfig::default();
+        
+        let result = scan_photos(&dir.path().to_string_lossy(), &config);
+        assert!(result.is_ok());
+        
+        let scan_result = result.unwrap();
+        assert_eq!(scan_result.total_files, 0);
+        assert!(scan_result.photos.is_empty());
+    }
+
+    #[test]
+    fn test_scan_iter_partial_consumption() {
+        let dir = TempDir::new().unwrap();
+        for i in 0..5 {
+            create_test_photo_root(&dir, &format!("IMG_{:04}.JPG", i), b"jpeg");
+        }
+        create_test_photo_root(&dir, "notes.txt", b"text");
+
+        let config = ClassifyConfig::default();
+        let source = dir.path().to_string_lossy().to_string();
+        let mut iter = scan_iter(&source, &config, &ScanOptions::default()).unwrap();
+        let first: Vec<PhotoInfo> = iter.by_ref().take(2).collect();
+        assert_eq!(first.len(), 2);
+        assert!(first.iter().all(|p| p.source_root == source && p.file_name.ends_with(".JPG")));
+        // 剩余的照片可以继续取出，不会重复
+        let rest: Vec<PhotoInfo> = iter.collect();
+        assert_eq!(rest.len(), 3);
+        assert!(rest.iter().all(|p| first.iter().all(|f| f.path != p.path)));
+
+        assert_eq!(scan_photos(&source, &config).unwrap().total_files, 5);
+        assert!(scan_iter("/nonexistent/directory/path", &config, &ScanOptions::default()).is_err());
+    }
+
+    #[test]
+    fn test_scan_iter_applies_scan_options() {
+        let dir = TempDir::new().unwrap();
+        create_test_photo_root(&dir, "IMG_0001.CR3", b"raw data");
+        create_test_photo_root(&dir, "IMG_0001.JPG", b"jpeg data");
+        create_test_photo_root(&dir, "IMG_0002.JPG", b"jpeg data");
+        create_test_photo_root(&dir, "EMPTY.JPG", b"");
+        create_test_photo_root(&dir, "BIG.JPG", &[0u8; 64]);
+
+        let config = ClassifyConfig::default();
+        let source = dir.path().to_string_lossy().to_string();
+        let names = |options: &ScanOptions| {
+            let mut names: Vec<String> = scan_iter(&source, &config, options).unwrap().map(|p| p.file_name).collect();
+            names.sort();
+            names
+        };
+        assert_eq!(names(&ScanOptions::default()).len(), 5);
+
+        let options = ScanOptions {
+            prefer_raw: true,
+            skip_empty: true,
+            max_size: Some(32),
+            ..Default::default()
+        };
+        assert_eq!(names(&options), ["IMG_0001.CR3", "IMG_0002.JPG"]);
+
+        // 与一次性扫描的结果相同
+        let mut scanned: Vec<String> = enumerate_photos(&source, &config, &options)
+            .unwrap()
+            .photos
+            .into_iter()
+            .map(|p| p.file_name)
+            .collect();
+        scanned.sort();
+        assert_eq!(names(&options), scanned);
+    }
+
+    #[test]
+    fn test_validate_rename_over_scan_collisions() {
+        let dated = |name: &str, folder: &str, date: Option<&str>| PhotoInfo {
+            date_time_original: date.map(str::to_string),
+            ..plan_photo(&format!("/DCIM/{}", name), folder)
+        };
+        let photos = vec![
+            dated("IMG_0001.JPG", "2024/03", Some("2024:03:15 10:00:00")),
+            dated("IMG_0002.jpg", "2024/03", Some("2024:03:15 11:00:00")),
+            dated("IMG_0003.JPG", "2024/04", Some("2024:03:15 12:00:00")),
+            dated("IMG_0004.JPG", "2024/03", None),
+        ];
+        let config = RenameConfig {
+            enabled: true,
+            template: "{date}".to_string(),
+            ..Default::default()
+        };
+
+        let result = validate_rename_over_scan(&photos, &config);
+        assert_eq!(result.mappings.len(), 4);
+        assert_eq!(result.mappings[0].new_name, "20240315.JPG");
+        // 扩展名大小写不同也视为同名；其他文件夹中的同名文件不冲突
+        assert_eq!(result.collisions.len(), 1);
+        assert_eq!(result.collisions[0].target, "2024/03/20240315.JPG");
+        assert_eq!(result.collisions[0].sources, ["/DCIM/IMG_0001.JPG", "/DCIM/IMG_0002.jpg"]);
+        // 没有日期时模板生成空文件名，改用原文件名
+        assert_eq!(result.empties, ["/DCIM/IMG_0004.JPG"]);
+        assert_eq!(result.mappings[3].new_name, "IMG_0004.JPG");
+
+        // 加上计数器后不再冲突
+        let config = RenameConfig {
+            template: "{date}_{counter}".to_string(),
+            ..config
+        };
+        let result = validate_rename_over_scan(&photos, &config);
+        assert!(result.collisions.is_empty());
+        assert_eq!(result.mappings[1].new_name, "20240315_0002.jpg");
+        assert!(result.empties.is_empty());
+    }
+
+    #[test]
+    fn test_scan_size_range() {
+        let dir = TempDir::new().unwrap();
+        create_test_photo_root(&dir, "tiny.jpg", &[0u8; 10]);
+        create_test_photo_root(&dir, "normal.jpg", &[0u8; 100]);
+        create_test_photo_root(&dir, "huge.jpg", &[0u8; 1000]);
+
+        let config = ClassifyConfig::default();
+        let source = dir.path().to_string_lossy().to_string();
+        let names = |options: &ScanOptions| -> Vec<String> {
+            let result = scan_photos_with_options(&source, &config, options).unwrap();
+            result.photos.into_iter().map(|p| p.file_name).collect()
+        };
+
+        let options = ScanOptions { min_size: Some(100), ..Default::default() };
+        assert_eq!(names(&options), ["huge.jpg", "normal.jpg"]);
+        let options = ScanOptions { max_size: Some(999), ..Default::default() };
+        assert_eq!(names(&options), ["normal.jpg", "tiny.jpg"]);
+        let options = ScanOptions { min_size: Some(11), max_size: Some(999), ..Default::default() };
+        let result = scan_photos_with_options(&source, &config, &options).unwrap();
+        assert_eq!(result.total_files, 1);
+        assert_eq!(result.total_size, 100);
+    }
+
+    #[test]
+    fn test_scan_photos_nonexistent_directory() {
+        let config = ClassifyConfig::default();
+        let result = scan_photos("/nonexistent/directory/path", &config);
+        
+        assert!(result.is_err());
+        assert!(result.unwrap_err().contains("不存在"));
+    }
+
+    #[test]
+    fn test_scan_photos_with_supported_files() {
+        let dir = TempDir::new().unwrap();
+        
+        // 创建测试照片文件
+        create_test_photo_root(&dir, "photo1.jpg", b"fake jpeg content");
+        create_test_photo_root(&dir, "photo2.png", b"fake png content");
+        create_test_photo_root(&dir, "photo3.cr3", b"fake cr3 content");
+        
+        let config = ClassifyConfig::default();
+        let result = scan_photos(&dir.path().to_string_lossy(), &config);
+        
+        assert!(result.is_ok());
+        let scan_result = result.unwrap();
+        assert_eq!(scan_result.total_files, 3);
+    }
+
+    #[test]
+    fn test_scan_photos_filters_unsupported() {
+        let dir = TempDir::new().unwrap();
+        
+        // 创建混合文件
+        create_test_photo_root(&dir, "photo.jpg", b"jpeg");
+        create_test_photo_root(&dir, "document.pdf", b"pdf");
+        create_test_photo_root(&dir, "video.mp4", b"mp4");
+        create_test_photo_root(&dir, "readme.txt", b"text");
+        
+        let config = ClassifyConfig::default();
+        let result = scan_photos(&dir.path().to_string_lossy(), &config);
+        
+        assert!(result.is_ok());
+        let scan_result = result.unwrap();
+        // 只应该找到 1 个照片文件
+        assert_eq!(scan_result.total_files, 1);
+    }
+
+    #[test]
+    fn test_scan_photos_recursive() {
+        let dir = TempDir::new().unwrap();
+        
+        // 在根目录创建照片
+        create_test_photo_root(&dir, "root.jpg", b"root jpeg");
+        
+        // 在子目录创建照片
+        create_test_photo(&dir, "subdir1", "sub1.jpg", b"sub1 jpeg");
+        create_test_photo(&dir, "subdir2/nested", "nested.png", b"nested png");
+        
+        let config = ClassifyConfig::default();
+        let result = scan_photos(&dir.path().to_string_lossy(), &config);
+        
+        assert!(result.is_ok());
+        let scan_result = result.unwrap();
+        // 应该递归找到所有照片
+        assert_eq!(scan_result.total_files, 3);
+    }
+
+    #[test]
+    fn test_scan_photos_calculates_total_size() {
+        let dir = TempDir::new().unwrap();
+        
+        let content1 = vec![0u8; 1000];
+        let content2 = vec![0u8; 2000];
+        
+        create_test_photo_root(&dir, "photo1.jpg", &content1);
+        create_test_photo_root(&dir, "photo2.jpg", &content2);
+        
+        let config = ClassifyConfig::default();
+        let result = scan_photos(&dir.path().to_string_lossy(), &config);
+        
+        assert!(result.is_ok());
+        let scan_result = result.unwrap();
+        assert_eq!(scan_result.total_size, 3000);
+    }
+
+    #[test]
+    fn test_scan_photos_generates_target_folders() {
+        let dir = TempDir::new().unwrap();
+        
+        // ExifTool 不会在假文件上工作，所以会使用 fallback
+        create_test_photo_root(&dir, "photo.jpg", b"fake content");
+        
+        let config = ClassifyConfig {
+            template: "{year}/{month}".to_string(),
+            fallback_folder: "未分类".to_string(),
+            ..Default::default()
+        };
+        
+        let result = scan_photos(&dir.path().to_string_lossy(), &config);
+        assert!(result.is_ok());
+        
+        let scan_result = result.unwrap();
+        assert_eq!(scan_result.photos.len(), 1);
+        // 没有 EXIF 数据，应该使用 fallback
+        assert_eq!(scan_result.photos[0].target_folder, "未分类");
+    }
+
+    // ==================== 文件名处理测试 ====================
+
+    #[test]
+    fn test_scan_photos_unicode_filename() {
+        let dir = TempDir::new().unwrap();
+        
+        create_test_photo_root(&dir, "照片_2024.jpg", b"chinese name");
+        
+        let config = ClassifyConfig::default();
+        let result = scan_photos(&dir.path().to_string_lossy(), &config);
+        
+        assert!(result.is_ok());
+        let scan_result = result.unwrap();
+        assert_eq!(scan_result.total_files, 1);
+        assert!(scan_result.photos[0].file_name.contains("照片"));
+    }
+
+    #[test]
+    fn test_scan_photos_special_characters_filename() {
+        let dir = TempDir::new().unwrap();
+        
+        create_test_photo_root(&dir, "photo with spaces.jpg", b"spaces");
+        create_test_photo_root(&dir, "photo-with-dashes.jpg", b"dashes");
+        create_test_photo_root(&dir, "photo_with_underscores.jpg", b"underscores");
+        
+        let config = ClassifyConfig::default();
+        let result = scan_photos(&dir.path().to_string_lossy(), &config);
+        
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap().total_files, 3);
+    }
+
+    // ==================== 边界情况测试 ====================
+
+    #[test]
+    fn test_scan_photos_empty_file() {
+        let dir = TempDir::new().unwrap();
+        
+        create_test_photo_root(&dir, "empty.jpg", b"");
+        
+        let config = ClassifyConfig::default();
+        let result = scan_photos(&dir.path().to_string_lossy(), &config);
+        
+        assert!(result.is_ok());
+        let scan_result = result.unwrap();
+        assert_eq!(scan_result.total_files, 1);
+        assert_eq!(scan_result.photos[0].file_size, 0);
+    }
+
+    #[test]
+    fn test_enumerate_photos_skip_empty() {
+        let dir = TempDir::new().unwrap();
+        let empty = create_test_photo_root(&dir, "empty.jpg", b"");
+        create_test_photo_root(&dir, "ok.jpg", b"jpeg");
+        let source = dir.path().to_string_lossy().to_string();
+        let config = ClassifyConfig::default();
+
+        // 默认仍然包含空文件
+        let result = enumerate_photos(&source, &config, &ScanOptions::default()).unwrap();
+        assert_eq!(result.total_files, 2);
+        assert!(result.skipped_unreadable.is_empty());
+
+        let options = ScanOptions {
+            skip_empty: true,
+            ..Default::default()
         };
-        assert_eq!(result.total_files, 0);
-        assert!(result.photos.is_empty());
+        let result = enumerate_photos(&source, &config, &options).unwrap();
+        assert_eq!(result.photos.len(), 1);
+        assert_eq!(result.photos[0].file_name, "ok.jpg");
+        assert_eq!(result.skipped_unreadable, [empty]);
     }
 
+    #[cfg(unix)]
     #[test]
-    fn test_scan_result_with_photos() {
-        let photos = vec![
-            PhotoInfo {
-                path: "/test/photo1.jpg".to_string(),
-                file_name: "photo1.jpg".to_string(),
-                file_size: 1000,
-                date_time: Some("2024:03:15 14:30:00".to_string()),
-                camera: Some("Canon".to_string()),
-                target_folder: "2024/03".to_string(),
-                is_duplicate: false,
-                duplicate_of: None,
-            },
-            PhotoInfo {
-                path: "/test/photo2.jpg".to_string(),
-                file_name: "photo2.jpg".to_string(),
-                file_size: 2000,
-                date_time: None,
-                camera: None,
-                target_folder: "未知日期".to_string(),
-                is_duplicate: false,
-                duplicate_of: None,
-            },
-        ];
+    fn test_enumerate_photos_skip_unreadable() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = TempDir::new().unwrap();
+        let locked = create_test_photo_root(&dir, "locked.jpg", b"jpeg");
+        create_test_photo_root(&dir, "ok.jpg", b"jpeg");
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o000)).unwrap();
+        // 以 root 运行时仍可读取，无法模拟
+        if fs::File::open(&locked).is_ok() {
+            return;
+        }
 
-        let result = ScanResult {
-            total_files: 2,
-            total_size: 3000,
-            photos,
+        let options = ScanOptions {
+            skip_empty: true,
+            ..Default::default()
         };
+        let result = enumerate_photos(&dir.path().to_string_lossy(), &ClassifyConfig::default(), &options).unwrap();
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o644)).unwrap();
+        assert_eq!(result.photos.len(), 1);
+        assert_eq!(result.skipped_unreadable, [locked]);
+    }
 
-        assert_eq!(result.total_files, 2);
-        assert_eq!(result.total_size, 3000);
-        assert_eq!(result.photos.len(), 2);
+    #[test]
+    fn test_scan_photos_hidden_files() {
+        let dir = TempDir::new().unwrap();
+        
+        create_test_photo_root(&dir, ".hidden.jpg", b"hidden");
+        create_test_photo_root(&dir, "visible.jpg", b"visible");
+        
+        let config = ClassifyConfig::default();
+        let result = scan_photos(&dir.path().to_string_lossy(), &config);
+        
+        assert!(result.is_ok());
+        // 隐藏文件也应该被扫描到
+        assert_eq!(result.unwrap().total_files, 2);
     }
 
-    // ==================== PhotoInfo 测试 ====================
+    #[test]
+    fn test_scan_photos_target_folder_forward_slashes() {
+        let dir = TempDir::new().unwrap();
+        create_test_photo_root(&dir, "photo.jpg", b"content");
+
+        let config = ClassifyConfig {
+            template: "{year}\\{month}".to_string(),
+            fallback_folder: "未分类\\无日期".to_string(),
+            ..Default::default()
+        };
+        let result = scan_photos(&dir.path().to_string_lossy(), &config).unwrap();
+        assert_eq!(result.photos[0].target_folder, "未分类/无日期");
+    }
 
     #[test]
-    fn test_photo_info_default_values() {
-        let info = PhotoInfo {
-            path: String::new(),
-            file_name: String::new(),
-            file_size: 0,
-            date_time: None,
-            camera: None,
-            target_folder: String::new(),
-            is_duplicate: false,
-            duplicate_of: None,
+    fn test_scan_photos_with_excludes() {
+        let dir = TempDir::new().unwrap();
+
+        create_test_photo_root(&dir, "keep.jpg", b"keep");
+        create_test_photo_root(&dir, "skip.png", b"skip by name");
+        create_test_photo(&dir, ".thumbnails", "thumb.jpg", b"thumb");
+        create_test_photo(&dir, "events/tmp", "draft.jpg", b"draft");
+        create_test_photo(&dir, "events", "party.jpg", b"party");
+
+        let options = ScanOptions {
+            exclude: vec!["*.png".to_string(), ".thumbnails".to_string(), "events/tmp".to_string()],
+            ..Default::default()
         };
-        assert!(!info.is_duplicate);
-        assert!(info.duplicate_of.is_none());
+        let config = ClassifyConfig::default();
+        let result = scan_photos_with_options(&dir.path().to_string_lossy(), &config, &options).unwrap();
+
+        let mut names: Vec<_> = result.photos.iter().map(|p| p.file_name.as_str()).collect();
+        names.sort();
+        assert_eq!(names, vec!["keep.jpg", "party.jpg"]);
     }
 
     #[test]
-    fn test_photo_info_duplicate() {
-        let info = PhotoInfo {
-            path: "/test/copy.jpg".to_string(),
-            file_name: "copy.jpg".to_string(),
-            file_size: 1000,
-            date_time: None,
-            camera: None,
-            target_folder: "2024/03".to_string(),
-            is_duplicate: true,
-            duplicate_of: Some("/test/original.jpg".to_string()),
+    fn test_scan_photos_with_photoignore() {
+        let dir = TempDir::new().unwrap();
+        create_test_photo_root(&dir, "keep.jpg", b"keep");
+        create_test_photo_root(&dir, "skip.png", b"skip by option");
+        create_test_photo(&dir, "drafts", "draft.jpg", b"draft");
+        create_test_photo(&dir, "trip", "beach.jpg", b"beach");
+        create_test_photo(&dir, "trip/rejects", "blurry.jpg", b"blurry");
+        create_test_photo(&dir, "trip/day2/rejects", "shaky.jpg", b"shaky");
+        // 规则只作用于所在文件夹及其子文件夹
+        create_test_photo(&dir, "rejects", "root_reject.jpg", b"kept");
+        fs::write(dir.path().join(PHOTOIGNORE_FILE), "# 草稿\ndrafts/\n\n[\n").unwrap();
+        fs::write(dir.path().join("trip").join(PHOTOIGNORE_FILE), "rejects\n").unwrap();
+
+        let options = ScanOptions {
+            exclude: vec!["*.png".to_string()],
+            ..Default::default()
         };
-        assert!(info.is_duplicate);
-        assert_eq!(info.duplicate_of.as_deref(), Some("/test/original.jpg"));
+        let config = ClassifyConfig::default();
+        let result = scan_photos_with_options(&dir.path().to_string_lossy(), &config, &options).unwrap();
+
+        let mut names: Vec<_> = result.photos.iter().map(|p| p.file_name.as_str()).collect();
+        names.sort();
+        assert_eq!(names, vec!["beach.jpg", "keep.jpg", "root_reject.jpg"]);
     }
 
     #[test]
-    fn test_photo_info_serialization() {
-        let info = PhotoInfo {
-            path: "/test/photo.cr3".to_string(),
-            file_name: "photo.cr3".to_string(),
-            file_size: 25000000,
-            date_time: Some("2024:12:25 10:30:00".to_string()),
-            camera: Some("Canon EOS R5".to_string()),
-            target_folder: "2024/12/25".to_string(),
-            is_duplicate: false,
-            duplicate_of: None,
+    fn test_enumerate_photos_then_enrich() {
+        let dir = TempDir::new().unwrap();
+        create_test_photo_root(&dir, "IMG_0001.JPG", b"first");
+        create_test_photo(&dir, "sub", "IMG_0002.CR3", b"second photo");
+
+        let config = ClassifyConfig {
+            template: "{year}/{month}".to_string(),
+            fallback_folder: "未知日期".to_string(),
+            ..Default::default()
         };
+        let source = dir.path().to_string_lossy().to_string();
+        let mut quick = enumerate_photos(&source, &config, &ScanOptions::default()).unwrap();
+        assert_eq!(quick.total_files, 2);
+        assert_eq!(quick.total_size, 17);
+        for photo in &quick.photos {
+            assert!(photo.date_time.is_none());
+            assert_eq!(photo.target_folder, "未知日期");
+        }
 
-        let json = serde_json::to_string(&info).unwrap();
-        assert!(json.contains("photo.cr3"));
-        assert!(json.contains("Canon EOS R5"));
+        // 补全后与一次性扫描的结果一致
+        for photo in &mut quick.photos {
+            enrich_photo(photo, &config);
+        }
+        let full = scan_photos(&source, &config).unwrap();
+        let mut quick_folders: Vec<_> = quick.photos.iter().map(|p| (p.path.clone(), p.target_folder.clone())).collect();
+        let mut full_folders: Vec<_> = full.photos.iter().map(|p| (p.path.clone(), p.target_folder.clone())).collect();
+        quick_folders.sort();
+        full_folders.sort();
+        assert_eq!(quick_folders, full_folders);
+    }
 
-        let deserialized: PhotoInfo = serde_json::from_str(&json).unwrap();
-        assert_eq!(deserialized.file_name, "photo.cr3");
+    #[test]
+    fn test_scan_photos_folder_placeholder() {
+        let dir = TempDir::new().unwrap();
+        create_test_photo_root(&dir, "root.jpg", b"root");
+        create_test_photo(&dir, "DCIM/婚礼", "IMG_0001.JPG", b"wedding");
+        create_test_photo(&dir, "DCIM/旅行/Day 1", "IMG_0002.JPG", b"trip");
+
+        let config = ClassifyConfig {
+            template: "{folder}/{year}".to_string(),
+            fallback_folder: "{folder}".to_string(),
+            ..Default::default()
+        };
+        let result = scan_photos(&dir.path().to_string_lossy(), &config).unwrap();
+
+        let mut folders: Vec<_> = result.photos.iter()
+            .map(|p| (p.file_name.as_str(), p.relative_dir.as_str()))
+            .collect();
+        folders.sort();
+        assert_eq!(folders, vec![
+            ("IMG_0001.JPG", "DCIM/婚礼"),
+            ("IMG_0002.JPG", "DCIM/旅行/Day 1"),
+            ("root.jpg", ""),
+        ]);
     }
 
-    // ==================== TransferProgress 测试 ====================
+    #[test]
+    fn test_scan_photos_prefer_raw() {
+        let dir = TempDir::new().unwrap();
+
+        create_test_photo_root(&dir, "IMG_1.CR3", b"raw data");
+        create_test_photo_root(&dir, "IMG_1.JPG", b"jpeg");
+        create_test_photo_root(&dir, "IMG_2.JPG", b"jpeg only");
+        // 不同目录下的同名文件互不影响
+        create_test_photo(&dir, "other", "IMG_1.HEIC", b"heic");
+
+        let config = ClassifyConfig::default();
+        let source = dir.path().to_string_lossy().to_string();
+
+        let all = scan_photos(&source, &config).unwrap();
+        assert_eq!(all.total_files, 4);
+
+        let options = ScanOptions {
+            prefer_raw: true,
+            ..Default::default()
+        };
+        let result = scan_photos_with_options(&source, &config, &options).unwrap();
+        let mut names: Vec<_> = result.photos.iter().map(|p| p.file_name.as_str()).collect();
+        names.sort();
+        assert_eq!(names, vec!["IMG_1.CR3", "IMG_1.HEIC", "IMG_2.JPG"]);
+        assert_eq!(result.total_files, 3);
+        assert_eq!(result.total_size, (b"raw data".len() + b"jpeg only".len() + b"heic".len()) as u64);
+    }
 
     #[test]
-    fn test_transfer_progress_initial() {
-        let progress = TransferProgress {
-            current: 0,
-            total: 100,
-            current_file: "准备中...".to_string(),
-            bytes_transferred: 0,
-            total_bytes: 1000000,
-            status: "preparing".to_string(),
-            skipped_duplicates: 0,
+    fn test_scan_sources_merges_directories() {
+        let card_a = TempDir::new().unwrap();
+        let card_b = TempDir::new().unwrap();
+        create_test_photo(&card_a, "DCIM/100CANON", "IMG_0001.JPG", b"card a photo 1");
+        create_test_photo(&card_a, "DCIM/100CANON", "IMG_0002.JPG", b"card a photo 2");
+        create_test_photo(&card_b, "DCIM/100CANON", "IMG_0001.JPG", b"card b");
+
+        let sources = vec![
+            card_a.path().to_string_lossy().to_string(),
+            card_b.path().to_string_lossy().to_string(),
+        ];
+        let config = ClassifyConfig {
+            template: "{folder}".to_string(),
+            fallback_folder: "未知日期".to_string(),
+            ..Default::default()
         };
+        let result = scan_sources(&sources, &config, &ScanOptions::default()).unwrap();
+
+        assert_eq!(result.total_files, 3);
+        assert_eq!(result.total_size, 14 + 14 + 6);
+        assert_eq!(result.photos.iter().filter(|p| p.source_root == sources[0]).count(), 2);
+        assert_eq!(result.photos.iter().filter(|p| p.source_root == sources[1]).count(), 1);
+        // {folder} 相对各自的源文件夹
+        assert!(result.photos.iter().all(|p| p.relative_dir == "DCIM/100CANON"));
+    }
 
-        assert_eq!(progress.current, 0);
-        assert_eq!(progress.status, "preparing");
+    #[test]
+    fn test_scan_sources_overlapping_and_empty() {
+        let dir = TempDir::new().unwrap();
+        create_test_photo(&dir, "DCIM", "IMG_0001.JPG", b"photo");
+        let root = dir.path().to_string_lossy().to_string();
+        let nested = dir.path().join("DCIM").to_string_lossy().to_string();
+
+        let config = ClassifyConfig::default();
+        let result = enumerate_sources(&[root.clone(), nested], &config, &ScanOptions::default()).unwrap();
+        assert_eq!(result.total_files, 1);
+        assert_eq!(result.photos[0].source_root, root);
+
+        assert!(enumerate_sources(&[], &config, &ScanOptions::default()).is_err());
     }
 
+    #[cfg(unix)]
     #[test]
-    fn test_transfer_progress_in_progress() {
-        let progress = TransferProgress {
-            current: 50,
-            total: 100,
-            current_file: "IMG_0050.CR3".to_string(),
-            bytes_transferred: 500000,
-            total_bytes: 1000000,
-            status: "transferring".to_string(),
-            skipped_duplicates: 5,
+    fn test_scan_photos_symlink_cycle_terminates() {
+        let dir = TempDir::new().unwrap();
+        let outside = TempDir::new().unwrap();
+        create_test_photo(&dir, "DCIM", "IMG_0001.JPG", b"photo");
+        create_test_photo_root(&outside, "OUTSIDE.JPG", b"outside");
+        // DCIM/loop 指回源文件夹根目录，形成循环
+        std::os::unix::fs::symlink(dir.path(), dir.path().join("DCIM/loop")).unwrap();
+        std::os::unix::fs::symlink(outside.path().join("OUTSIDE.JPG"), dir.path().join("LINK.JPG")).unwrap();
+        let source = dir.path().to_string_lossy().to_string();
+        let config = ClassifyConfig::default();
+        let names = |result: ScanResult| {
+            let mut names: Vec<_> = result.photos.into_iter().map(|p| p.file_name).collect();
+            names.sort();
+            names
         };
 
-        assert_eq!(progress.current, 50);
-        assert_eq!(progress.skipped_duplicates, 5);
-        // 计算进度百分比
-        let percentage = (progress.current as f64 / progress.total as f64) * 100.0;
-        assert_eq!(percentage, 50.0);
+        // 默认不跟随链接：只扫描源文件夹内的文件
+        let result = enumerate_photos(&source, &config, &ScanOptions::default()).unwrap();
+        assert_eq!(names(result), ["IMG_0001.JPG"]);
+
+        // 跟随链接时检测到循环并跳过，扫描正常结束
+        let options = ScanOptions {
+            follow_links: true,
+            ..Default::default()
+        };
+        let result = enumerate_photos(&source, &config, &options).unwrap();
+        assert_eq!(names(result), ["IMG_0001.JPG", "LINK.JPG"]);
     }
 
     #[test]
-    fn test_transfer_progress_completed() {
-        let progress = TransferProgress {
-            current: 100,
-            total: 100,
-            current_file: "传输完成".to_string(),
-            bytes_transferred: 1000000,
-            total_bytes: 1000000,
-            status: "completed".to_string(),
-            skipped_duplicates: 10,
+    fn test_scan_parallel_exif_matches_sequential() {
+        let dir = TempDir::new().unwrap();
+        for i in 0..12 {
+            let folder = format!("DCIM/{}", i % 3);
+            create_test_photo(&dir, &folder, &format!("IMG_{:04}.JPG", i), b"photo");
+        }
+        let source = dir.path().to_string_lossy().to_string();
+        let config = ClassifyConfig::default();
+        let scan = |exif_jobs| {
+            let options = ScanOptions {
+                exif_jobs,
+                ..Default::default()
+            };
+            serde_json::to_value(scan_photos_with_options(&source, &config, &options).unwrap()).unwrap()
+        };
+
+        let sequential = scan(1);
+        assert_eq!(sequential["total_files"], 12);
+        assert_eq!(scan(4), sequential);
+        assert_eq!(scan(0), sequential);
+    }
+
+    #[test]
+    fn test_quick_scan_counts_by_extension() {
+        let dir = TempDir::new().unwrap();
+        create_test_photo_root(&dir, "IMG_0001.JPG", b"12345");
+        create_test_photo_root(&dir, "IMG_0002.jpg", b"123");
+        create_test_photo(&dir, "RAW", "IMG_0001.CR3", b"1234567890");
+        create_test_photo(&dir, "RAW", "IMG_0003.dng", b"12");
+        create_test_photo_root(&dir, "notes.txt", b"not a photo");
+
+        let mut progress_calls = 0;
+        let result = quick_scan_dir(&dir.path().to_string_lossy(), |_| progress_calls += 1).unwrap();
+        assert_eq!(result.count, 4);
+        assert_eq!(result.total_size, 20);
+        assert_eq!(result.by_extension.len(), 3);
+        assert_eq!(result.by_extension["jpg"], 2);
+        assert_eq!(result.by_extension["cr3"], 1);
+        assert_eq!(result.by_extension["dng"], 1);
+        assert_eq!(progress_calls, 0);
+
+        assert!(quick_scan_dir("/nonexistent/path/12345", |_| {}).is_err());
+    }
+
+    #[test]
+    fn test_scan_sort_keys() {
+        let dir = TempDir::new().unwrap();
+        create_test_photo(&dir, "b", "A.JPG", b"123");
+        create_test_photo(&dir, "a", "C.JPG", b"1");
+        create_test_photo(&dir, "c", "B.JPG", b"12");
+        let source = dir.path().to_string_lossy().to_string();
+        let config = ClassifyConfig::default();
+        let scan = |sort_by| {
+            let options = ScanOptions {
+                sort_by,
+                ..Default::default()
+            };
+            let result = enumerate_photos(&source, &config, &options).unwrap();
+            result.photos.into_iter().map(|p| p.file_name).collect::<Vec<_>>()
+        };
+
+        assert_eq!(scan(ScanSortKey::Path), ["C.JPG", "A.JPG", "B.JPG"]);
+        assert_eq!(scan(ScanSortKey::Name), ["A.JPG", "B.JPG", "C.JPG"]);
+        assert_eq!(scan(ScanSortKey::Size), ["C.JPG", "B.JPG", "A.JPG"]);
+
+        // 没有拍摄时间的 A 排在最后
+        let mut result = enumerate_photos(&source, &config, &ScanOptions::default()).unwrap();
+        for photo in &mut result.photos {
+            photo.date_time = match photo.file_name.as_str() {
+                "B.JPG" => Some("2024:03:15 10:00:00".to_string()),
+                "C.JPG" => Some("2023:01:01 00:00:00".to_string()),
+                _ => None,
+            };
+        }
+        result.sort_photos(ScanSortKey::CaptureTime);
+        let names: Vec<_> = result.photos.iter().map(|p| p.file_name.as_str()).collect();
+        assert_eq!(names, ["C.JPG", "B.JPG", "A.JPG"]);
+    }
+
+    #[test]
+    fn test_sort_photos_by_each_key() {
+        let mut result = ScanResult {
+            total_files: 4,
+            total_size: 10,
+            photos: vec![
+                plan_photo("/src/b/A.JPG", "2024/03"),
+                plan_photo("/src/a/C.JPG", "2023/01"),
+                plan_photo("/src/c/B.JPG", "2024/03"),
+                plan_photo("/src/d/A.JPG", "2024/01"),
+            ],
+            skipped_unreadable: Vec::new(),
+        };
+        for (photo, (size, date)) in result.photos.iter_mut().zip([
+            (3, Some("2024:03:15 10:00:00")),
+            (1, Some("2023:01:01 00:00:00")),
+            (3, None),
+            (2, Some("2024:01:02 08:00:00")),
+        ]) {
+            photo.file_size = size;
+            photo.date_time = date.map(str::to_string);
+        }
+        let mut sorted = |key, ascending| {
+            result.sort_photos_by(key, ascending);
+            result.photos.iter().map(|p| p.path.as_str()).collect::<Vec<_>>().join(" ")
+        };
+
+        assert_eq!(sorted(ScanSortKey::Path, true), "/src/a/C.JPG /src/b/A.JPG /src/c/B.JPG /src/d/A.JPG");
+        assert_eq!(sorted(ScanSortKey::Path, false), "/src/d/A.JPG /src/c/B.JPG /src/b/A.JPG /src/a/C.JPG");
+        // 排序键相同时按路径升序
+        assert_eq!(sorted(ScanSortKey::Name, true), "/src/b/A.JPG /src/d/A.JPG /src/c/B.JPG /src/a/C.JPG");
+        assert_eq!(sorted(ScanSortKey::Name, false), "/src/a/C.JPG /src/c/B.JPG /src/b/A.JPG /src/d/A.JPG");
+        assert_eq!(sorted(ScanSortKey::Size, true), "/src/a/C.JPG /src/d/A.JPG /src/b/A.JPG /src/c/B.JPG");
+        assert_eq!(sorted(ScanSortKey::Size, false), "/src/b/A.JPG /src/c/B.JPG /src/d/A.JPG /src/a/C.JPG");
+        assert_eq!(sorted(ScanSortKey::TargetFolder, true), "/src/a/C.JPG /src/d/A.JPG /src/b/A.JPG /src/c/B.JPG");
+        // 没有拍摄时间的照片在降序时同样排在最后
+        assert_eq!(sorted(ScanSortKey::CaptureTime, true), "/src/a/C.JPG /src/d/A.JPG /src/b/A.JPG /src/c/B.JPG");
+        assert_eq!(sorted(ScanSortKey::CaptureTime, false), "/src/b/A.JPG /src/d/A.JPG /src/a/C.JPG /src/c/B.JPG");
+    }
+
+    #[test]
+    fn test_find_sidecars_and_target_path() {
+        let dir = TempDir::new().unwrap();
+        let photo = create_test_photo_root(&dir, "IMG_0001.CR3", b"raw");
+        create_test_photo_root(&dir, "IMG_0001.xmp", b"xmp");
+        create_test_photo_root(&dir, "img_0001.CR3.XMP", b"xmp");
+        create_test_photo_root(&dir, "IMG_0001.AAE", b"aae");
+        create_test_photo_root(&dir, "IMG_0001.JPG", b"jpg");
+        create_test_photo_root(&dir, "IMG_0002.xmp", b"other");
+
+        let photo = Path::new(&photo);
+        let names: Vec<_> = find_sidecars(photo)
+            .iter()
+            .map(|p| p.file_name().unwrap().to_string_lossy().to_string())
+            .collect();
+        assert_eq!(names, ["IMG_0001.AAE", "IMG_0001.xmp", "img_0001.CR3.XMP"]);
+
+        let target = Path::new("/target/2024/20240315_0001.CR3");
+        assert_eq!(
+            sidecar_target_path(&dir.path().join("IMG_0001.xmp"), photo, target),
+            Path::new("/target/2024/20240315_0001.xmp")
+        );
+        assert_eq!(
+            sidecar_target_path(&dir.path().join("img_0001.CR3.XMP"), photo, target),
+            Path::new("/target/2024/20240315_0001.CR3.XMP")
+        );
+
+        // 附属文件不计入扫描结果
+        let result = scan_photos(&dir.path().to_string_lossy(), &ClassifyConfig::default()).unwrap();
+        assert_eq!(result.total_files, 2);
+    }
+
+    #[test]
+    fn test_scan_photos_check_integrity() {
+        let dir = TempDir::new().unwrap();
+        let source = dir.path().to_string_lossy().to_string();
+        create_test_photo_root(&dir, "good.jpg", b"\xFF\xD8\xFF\xE0 image data \xFF\xD9");
+        create_test_photo_root(&dir, "truncated.jpg", b"\xFF\xD8\xFF\xE0 image da");
+        create_test_photo_root(&dir, "IMG_0001.CR3", b"raw data");
+
+        let config = ClassifyConfig::default();
+        // 默认不检查
+        let result = scan_photos(&source, &config).unwrap();
+        assert!(result.photos.iter().all(|p| !p.suspect));
+
+        let options = ScanOptions {
+            check_integrity: true,
+            ..Default::default()
         };
+        let result = scan_photos_with_options(&source, &config, &options).unwrap();
+        let mut suspect: Vec<_> = result.photos.iter()
+            .filter(|p| p.suspect)
+            .map(|p| p.file_name.as_str())
+            .collect();
+        suspect.sort();
+        assert_eq!(suspect, vec!["truncated.jpg"]);
+    }
+
+    #[test]
+    fn test_scan_photos_invalid_exclude() {
+        let dir = TempDir::new().unwrap();
+        let options = ScanOptions {
+            exclude: vec!["[".to_string()],
+            ..Default::default()
+        };
+        let result = scan_photos_with_options(&dir.path().to_string_lossy(), &ClassifyConfig::default(), &options);
+        assert!(result.unwrap_err().contains("无效的排除规则"));
+    }
+
+    #[test]
+    fn test_scan_photos_case_insensitive_extension() {
+        let dir = TempDir::new().unwrap();
+        
+        create_test_photo_root(&dir, "photo1.JPG", b"uppercase");
+        create_test_photo_root(&dir, "photo2.Jpg", b"mixed");
+        create_test_photo_root(&dir, "photo3.jpg", b"lowercase");
+        create_test_photo_root(&dir, "photo4.CR3", b"raw uppercase");
+        
+        let config = ClassifyConfig::default();
+        let result = scan_photos(&dir.path().to_string_lossy(), &config);
+        
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap().total_files, 4);
+    }
+
+    // ==================== 传输计划测试 ====================
+
+    fn plan_photo(path: &str, folder: &str) -> PhotoInfo {
+        let file_name = Path::new(path).file_name().unwrap().to_string_lossy().to_string();
+        PhotoInfo {
+            path: path.to_string(),
+            file_name,
+            file_size: fs::metadata(path).map(|m| m.len()).unwrap_or(0),
+            date_time: None,
+            date_time_original: None,
+            create_date: None,
+            camera: None,
+            make: None,
+            target_folder: folder.to_string(),
+            is_duplicate: false,
+            duplicate_of: None,
+            width: None,
+            height: None,
+            relative_dir: String::new(),
+            suspect: false,
+            source_root: String::new(),
+            rating: None,
+            label: None,
+            keywords: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_resolve_target_path_avoids_claimed() {
+        let dir = TempDir::new().unwrap();
+        fs::write(dir.path().join("IMG.jpg"), b"existing").unwrap();
 
-        assert_eq!(progress.status, "completed");
-        assert_eq!(progress.bytes_transferred, progress.total_bytes);
+        let mut claimed = HashSet::new();
+        let first = resolve_target_path(dir.path(), "IMG.jpg", &claimed);
+        assert_eq!(first, dir.path().join("IMG_1.jpg"));
+
+        claimed.insert(first);
+        let second = resolve_target_path(dir.path(), "IMG.jpg", &claimed);
+        assert_eq!(second, dir.path().join("IMG_2.jpg"));
     }
 
-    // ==================== TransferResult 测试 ====================
+    #[test]
+    fn test_build_plan_normalizes_unicode_names() {
+        let src = TempDir::new().unwrap();
+        let dst = TempDir::new().unwrap();
+        let nfc_name = "caf\u{e9}.jpg";
+        let nfd_name = "cafe\u{301}.jpg";
+        assert_ne!(nfc_name, nfd_name);
+        assert_eq!(to_nfc(nfd_name), nfc_name);
+
+        // 本批次中 NFC 和 NFD 拼写的同名文件视为冲突，目标文件名统一为 NFC
+        let photos = vec![
+            plan_photo(&create_test_photo(&src, "a", nfc_name, b"first"), "Cafe\u{301}"),
+            plan_photo(&create_test_photo(&src, "b", nfd_name, b"second"), "Caf\u{e9}"),
+        ];
+        let base = dst.path().to_string_lossy().to_string();
+        let plan = build_plan(&photos, &base, &RenameConfig::default(), ConflictPolicy::Rename, None);
+        let folder = dst.path().join("Caf\u{e9}");
+        assert_eq!(plan.entries[0].target_path, folder.join(nfc_name).to_string_lossy());
+        assert_eq!(plan.entries[1].target_path, folder.join("caf\u{e9}_1.jpg").to_string_lossy());
+
+        // 目标目录中已有 NFD 形式的同名文件时同样视为冲突
+        fs::write(dst.path().join(nfd_name), b"existing").unwrap();
+        let photos = vec![plan_photo(&create_test_photo(&src, "c", nfc_name, b"third"), "")];
+        let plan = build_plan(&photos, &base, &RenameConfig::default(), ConflictPolicy::Skip, None);
+        assert_eq!(plan.entries[0].action, PlanAction::SkipExisting);
+        let plan = build_plan(&photos, &base, &RenameConfig::default(), ConflictPolicy::Rename, None);
+        assert_eq!(plan.entries[0].target_path, dst.path().join("caf\u{e9}_1.jpg").to_string_lossy());
+    }
 
     #[test]
-    fn test_transfer_result_all_success() {
-        let result = TransferResult {
-            success_count: 100,
-            skip_count: 0,
-            error_count: 0,
-            errors: vec![],
-        };
+    fn test_build_plan_resolves_collisions() {
+        let src = TempDir::new().unwrap();
+        let dst = TempDir::new().unwrap();
+        fs::create_dir_all(dst.path().join("2024")).unwrap();
+        fs::write(dst.path().join("2024/IMG.jpg"), b"existing").unwrap();
 
-        assert_eq!(result.success_count, 100);
-        assert!(result.errors.is_empty());
+        // 两个源文件夹中的同名文件，且目标目录已有同名文件
+        let photos = vec![
+            plan_photo(&create_test_photo(&src, "a", "IMG.jpg", b"first"), "2024"),
+            plan_photo(&create_test_photo(&src, "b", "IMG.jpg", b"second"), "2024"),
+        ];
+        let base = dst.path().to_string_lossy().to_string();
+        let plan = build_plan(&photos, &base, &RenameConfig::default(), ConflictPolicy::Rename, None);
+
+        assert_eq!(plan.target_dir, base);
+        assert_eq!(plan.count(PlanAction::Copy), 2);
+        assert_eq!(plan.total_size(), 11);
+        let targets: Vec<PathBuf> = plan.entries.iter().map(|e| PathBuf::from(&e.target_path)).collect();
+        assert_eq!(targets, vec![dst.path().join("2024/IMG_1.jpg"), dst.path().join("2024/IMG_2.jpg")]);
+        // 生成计划不会写入任何文件
+        assert!(!dst.path().join("2024/IMG_1.jpg").exists());
     }
 
     #[test]
-    fn test_transfer_result_with_skips() {
-        let result = TransferResult {
-            success_count: 80,
-            skip_count: 20,
-            error_count: 0,
-            errors: vec![],
-        };
-
-        assert_eq!(result.success_count + result.skip_count, 100);
+    fn test_check_dirs_overlap() {
+        let dir = TempDir::new().unwrap();
+        let source = dir.path().join("card");
+        fs::create_dir_all(source.join("DCIM")).unwrap();
+        let source_str = source.to_string_lossy().to_string();
+        let sources = vec![source_str.clone()];
+        let check = |target: PathBuf| check_dirs_overlap(&sources, &target.to_string_lossy());
+
+        // 相同目录（包括写法不同的同一路径）
+        assert!(check(source.clone()).unwrap_err().contains("相同"));
+        assert!(check(source.join("DCIM/..")).is_err());
+        // 目标位于源文件夹内，即使目标尚不存在
+        assert!(check(source.join("DCIM")).unwrap_err().contains("位于源文件夹"));
+        assert!(check(source.join("imported/2024")).is_err());
+        // 源文件夹位于目标内
+        assert!(check(dir.path().to_path_buf()).unwrap_err().contains("位于目标文件夹"));
+        // 同级目录、名称前缀相同的目录不算包含
+        assert!(check(dir.path().join("card-backup")).is_ok());
+        assert!(check(dir.path().join("archive")).is_ok());
+        assert!(check_dirs_overlap(&[], &source_str).is_ok());
     }
 
     #[test]
-    fn test_transfer_result_with_errors() {
-        let result = TransferResult {
-            success_count: 95,
-            skip_count: 0,
-            error_count: 5,
-            errors: vec![
-                "复制失败 photo1.jpg: 权限不足".to_string(),
-                "复制失败 photo2.jpg: 磁盘空间不足".to_string(),
-            ],
+    fn test_camera_route() {
+        let route = CameraRoute::parse(" Canon EOS R5 = /Volumes/A ").unwrap();
+        assert_eq!(route, CameraRoute { camera: "Canon EOS R5".to_string(), target_dir: "/Volumes/A".to_string() });
+        assert!(CameraRoute::parse("Canon EOS R5").is_err());
+        assert!(CameraRoute::parse("=/Volumes/A").is_err());
+
+        let photo = |make: Option<&str>, model: Option<&str>| PhotoInfo {
+            make: make.map(String::from),
+            camera: model.map(String::from),
+            ..plan_photo("IMG.jpg", "")
         };
-
-        assert_eq!(result.error_count, 5);
-        assert_eq!(result.errors.len(), 2);
+        let r5 = photo(Some("Canon"), Some("EOS R5"));
+        let sony = photo(Some("SONY"), Some("ILCE-7M4"));
+        // 按型号、品牌或品牌加型号匹配，不区分大小写
+        assert!(route.matches(&r5));
+        assert!(CameraRoute::parse("eos r5=/a").unwrap().matches(&r5));
+        assert!(CameraRoute::parse("Sony=/b").unwrap().matches(&sony));
+        assert!(!route.matches(&sony));
+        assert!(!route.matches(&photo(None, None)));
+
+        let routes = vec![route, CameraRoute::parse("SONY=/Volumes/B").unwrap()];
+        assert_eq!(route_target_dir(&routes, &r5, "/default"), "/Volumes/A");
+        assert_eq!(route_target_dir(&routes, &sony, "/default"), "/Volumes/B");
+        assert_eq!(route_target_dir(&routes, &photo(Some("Nikon"), None), "/default"), "/default");
     }
 
-    // ==================== scan_photos 测试 ====================
-
     #[test]
-    fn test_scan_photos_empty_directory() {
+    fn test_build_plan_skips_file_targeting_itself() {
         let dir = TempDir::new().unwrap();
-        let config = ClassifyConfig::default();
-        
-        let result = scan_photos(&dir.path().to_string_lossy(), &config);
-        assert!(result.is_ok());
-        
-        let scan_result = result.unwrap();
-        assert_eq!(scan_result.total_files, 0);
-        assert!(scan_result.photos.is_empty());
+        let path = create_test_photo(&dir, "", "IMG.jpg", b"photo");
+        let photos = vec![plan_photo(&path, "")];
+        let base = dir.path().to_string_lossy().to_string();
+
+        // 不论冲突策略如何，都不会把文件复制或覆盖到自身
+        for policy in [ConflictPolicy::Rename, ConflictPolicy::Skip, ConflictPolicy::Overwrite] {
+            let plan = build_plan(&photos, &base, &RenameConfig::default(), policy, None);
+            assert_eq!(plan.entries[0].action, PlanAction::SkipExisting);
+            assert_eq!(plan.entries[0].error.as_deref(), Some("目标文件就是源文件，已跳过"));
+        }
     }
 
     #[test]
-    fn test_scan_photos_nonexistent_directory() {
-        let config = ClassifyConfig::default();
-        let result = scan_photos("/nonexistent/directory/path", &config);
-        
-        assert!(result.is_err());
-        assert!(result.unwrap_err().contains("不存在"));
+    fn test_build_plan_conflict_policies() {
+        let src = TempDir::new().unwrap();
+        let dst = TempDir::new().unwrap();
+        fs::write(dst.path().join("IMG.jpg"), b"existing").unwrap();
+
+        let photos = vec![
+            plan_photo(&create_test_photo(&src, "a", "IMG.jpg", b"first"), ""),
+            plan_photo(&create_test_photo(&src, "b", "IMG.jpg", b"second"), ""),
+        ];
+        let base = dst.path().to_string_lossy().to_string();
+
+        let plan = build_plan(&photos, &base, &RenameConfig::default(), ConflictPolicy::Skip, None);
+        assert_eq!(plan.count(PlanAction::SkipExisting), 2);
+
+        // 覆盖只针对目标目录中已有的文件，本批次内的同名文件仍添加序号
+        let plan = build_plan(&photos, &base, &RenameConfig::default(), ConflictPolicy::Overwrite, None);
+        assert_eq!(plan.entries[0].action, PlanAction::Overwrite);
+        assert_eq!(PathBuf::from(&plan.entries[0].target_path), dst.path().join("IMG.jpg"));
+        assert_eq!(plan.entries[1].action, PlanAction::Copy);
+        assert_eq!(PathBuf::from(&plan.entries[1].target_path), dst.path().join("IMG_1.jpg"));
     }
 
     #[test]
-    fn test_scan_photos_with_supported_files() {
-        let dir = TempDir::new().unwrap();
-        
-        // 创建测试照片文件
-        create_test_photo_root(&dir, "photo1.jpg", b"fake jpeg content");
-        create_test_photo_root(&dir, "photo2.png", b"fake png content");
-        create_test_photo_root(&dir, "photo3.cr3", b"fake cr3 content");
-        
-        let config = ClassifyConfig::default();
-        let result = scan_photos(&dir.path().to_string_lossy(), &config);
-        
-        assert!(result.is_ok());
-        let scan_result = result.unwrap();
-        assert_eq!(scan_result.total_files, 3);
+    fn test_build_plan_skips_duplicates() {
+        let src = TempDir::new().unwrap();
+        let dst = TempDir::new().unwrap();
+        let existing = dst.path().join("old.jpg");
+        fs::write(&existing, b"same content").unwrap();
+
+        let photos = vec![
+            plan_photo(&create_test_photo(&src, "", "IMG_0001.jpg", b"same content"), "2024"),
+            plan_photo(&create_test_photo(&src, "", "IMG_0002.jpg", b"new content"), "2024"),
+        ];
+        let mut deduplicator = Deduplicator::new();
+        deduplicator.add_known_file(&existing.to_string_lossy()).unwrap();
+
+        let base = dst.path().to_string_lossy().to_string();
+        let plan = build_plan(&photos, &base, &RenameConfig::default(), ConflictPolicy::Rename, Some(&mut deduplicator));
+
+        assert_eq!(plan.entries[0].action, PlanAction::SkipDuplicate);
+        assert_eq!(plan.entries[0].duplicate_of.as_deref(), Some(&*existing.to_string_lossy()));
+        assert!(plan.entries[0].target_path.is_empty());
+        assert_eq!(plan.entries[1].action, PlanAction::Copy);
     }
 
     #[test]
-    fn test_scan_photos_filters_unsupported() {
-        let dir = TempDir::new().unwrap();
-        
-        // 创建混合文件
-        create_test_photo_root(&dir, "photo.jpg", b"jpeg");
-        create_test_photo_root(&dir, "document.pdf", b"pdf");
-        create_test_photo_root(&dir, "video.mp4", b"mp4");
-        create_test_photo_root(&dir, "readme.txt", b"text");
-        
-        let config = ClassifyConfig::default();
-        let result = scan_photos(&dir.path().to_string_lossy(), &config);
-        
-        assert!(result.is_ok());
-        let scan_result = result.unwrap();
-        // 只应该找到 1 个照片文件
-        assert_eq!(scan_result.total_files, 1);
+    fn test_duplicate_estimate() {
+        let src = TempDir::new().unwrap();
+        let dst = TempDir::new().unwrap();
+        fs::create_dir(dst.path().join("2023")).unwrap();
+        fs::write(dst.path().join("2023").join("a.jpg"), b"photo a").unwrap();
+        fs::write(dst.path().join("2023").join("b.jpg"), b"photo bb").unwrap();
+
+        let photos = vec![
+            plan_photo(&create_test_photo(&src, "", "IMG_0001.jpg", b"photo a"), "2024"),
+            plan_photo(&create_test_photo(&src, "", "IMG_0002.jpg", b"photo bb"), "2024"),
+            plan_photo(&create_test_photo(&src, "", "IMG_0003.jpg", b"new photo"), "2024"),
+        ];
+        let mut deduplicator = Deduplicator::new();
+        for name in ["a.jpg", "b.jpg"] {
+            deduplicator.add_known_file(&dst.path().join("2023").join(name).to_string_lossy()).unwrap();
+        }
+
+        let base = dst.path().to_string_lossy().to_string();
+        let plan = build_plan(&photos, &base, &RenameConfig::default(), ConflictPolicy::Rename, Some(&mut deduplicator));
+        assert_eq!(
+            plan.duplicate_estimate(),
+            DuplicateEstimate { total: 3, duplicates: 2, new: 1, skipped_bytes: 15 }
+        );
+
+        let plan = build_plan(&photos, &base, &RenameConfig::default(), ConflictPolicy::Rename, None);
+        assert_eq!(plan.duplicate_estimate().duplicates, 0);
+        assert_eq!(plan.duplicate_estimate().new, 3);
     }
 
     #[test]
-    fn test_scan_photos_recursive() {
-        let dir = TempDir::new().unwrap();
-        
-        // 在根目录创建照片
-        create_test_photo_root(&dir, "root.jpg", b"root jpeg");
-        
-        // 在子目录创建照片
-        create_test_photo(&dir, "subdir1", "sub1.jpg", b"sub1 jpeg");
-        create_test_photo(&dir, "subdir2/nested", "nested.png", b"nested png");
-        
-        let config = ClassifyConfig::default();
-        let result = scan_photos(&dir.path().to_string_lossy(), &config);
-        
-        assert!(result.is_ok());
-        let scan_result = result.unwrap();
-        // 应该递归找到所有照片
-        assert_eq!(scan_result.total_files, 3);
+    fn test_build_plan_rejects_escaping_folder() {
+        let src = TempDir::new().unwrap();
+        let dst = TempDir::new().unwrap();
+        let photos = vec![plan_photo(&create_test_photo(&src, "", "IMG.jpg", b"x"), "../outside")];
+
+        let plan = build_plan(&photos, &dst.path().to_string_lossy(), &RenameConfig::default(), ConflictPolicy::Rename, None);
+        assert_eq!(plan.entries[0].action, PlanAction::Error);
+        assert!(plan.entries[0].error.as_deref().unwrap().contains("超出目标目录"));
     }
 
     #[test]
-    fn test_scan_photos_calculates_total_size() {
-        let dir = TempDir::new().unwrap();
-        
-        let content1 = vec![0u8; 1000];
-        let content2 = vec![0u8; 2000];
-        
-        create_test_photo_root(&dir, "photo1.jpg", &content1);
-        create_test_photo_root(&dir, "photo2.jpg", &content2);
-        
-        let config = ClassifyConfig::default();
-        let result = scan_photos(&dir.path().to_string_lossy(), &config);
-        
-        assert!(result.is_ok());
-        let scan_result = result.unwrap();
-        assert_eq!(scan_result.total_size, 3000);
+    fn test_build_plan_rename_counter() {
+        let src = TempDir::new().unwrap();
+        let dst = TempDir::new().unwrap();
+        let photos = vec![
+            plan_photo(&create_test_photo(&src, "", "a.jpg", b"a"), ""),
+            plan_photo(&create_test_photo(&src, "", "b.jpg", b"b"), ""),
+        ];
+        let rename = RenameConfig {
+            enabled: true,
+            template: "photo_{counter}".to_string(),
+            counter_start: 7,
+            counter_digits: 3,
+            ..Default::default()
+        };
+
+        let plan = build_plan(&photos, &dst.path().to_string_lossy(), &rename, ConflictPolicy::Rename, None);
+        let names: Vec<String> = plan
+            .entries
+            .iter()
+            .map(|e| Path::new(&e.target_path).file_name().unwrap().to_string_lossy().to_string())
+            .collect();
+        assert_eq!(names, vec!["photo_007.jpg", "photo_008.jpg"]);
     }
 
     #[test]
-    fn test_scan_photos_generates_target_folders() {
-        let dir = TempDir::new().unwrap();
-        
-        // ExifTool 不会在假文件上工作，所以会使用 fallback
-        create_test_photo_root(&dir, "photo.jpg", b"fake content");
-        
-        let config = ClassifyConfig {
-            template: "{year}/{month}".to_string(),
-            fallback_folder: "未分类".to_string(),
+    fn test_build_plan_counter_saturates_near_u32_max() {
+        let src = TempDir::new().unwrap();
+        let dst = TempDir::new().unwrap();
+        let photos: Vec<PhotoInfo> = ["a.jpg", "b.jpg", "c.jpg"]
+            .iter()
+            .map(|name| plan_photo(&create_test_photo(&src, "", name, name.as_bytes()), ""))
+            .collect();
+        let rename = RenameConfig {
+            enabled: true,
+            template: "photo_{counter}".to_string(),
+            counter_start: u32::MAX - 1,
+            counter_digits: 1,
+            ..Default::default()
         };
-        
-        let result = scan_photos(&dir.path().to_string_lossy(), &config);
-        assert!(result.is_ok());
-        
-        let scan_result = result.unwrap();
-        assert_eq!(scan_result.photos.len(), 1);
-        // 没有 EXIF 数据，应该使用 fallback
-        assert_eq!(scan_result.photos[0].target_folder, "未分类");
+
+        // 计数器停在 u32::MAX，重名的文件由冲突处理添加序号
+        let plan = build_plan(&photos, &dst.path().to_string_lossy(), &rename, ConflictPolicy::Rename, None);
+        let names: Vec<String> = plan
+            .entries
+            .iter()
+            .map(|e| Path::new(&e.target_path).file_name().unwrap().to_string_lossy().to_string())
+            .collect();
+        assert_eq!(names, vec!["photo_4294967294.jpg", "photo_4294967295.jpg", "photo_4294967295_1.jpg"]);
+
+        let validation = validate_rename_over_scan(&photos, &rename);
+        assert_eq!(validation.collisions.len(), 1);
     }
 
-    // ==================== 文件名处理测试 ====================
+    #[test]
+    fn test_build_plan_continues_counter_from_target() {
+        let src = TempDir::new().unwrap();
+        let dst = TempDir::new().unwrap();
+        // 目标文件夹中已有 5 张照片（含子文件夹），附属文件和其他文件不计入
+        fs::create_dir_all(dst.path().join("2023").join("旅行")).unwrap();
+        for name in ["photo_0001.jpg", "photo_0002.jpg", "2023/photo_0003.CR3", "2023/IMG_abc.jpg", "2023/旅行/photo_0012.jpg"] {
+            fs::write(dst.path().join(name), b"existing").unwrap();
+        }
+        fs::write(dst.path().join("photo_0099.xmp"), b"<xmp/>").unwrap();
+        fs::write(dst.path().join("notes.txt"), b"notes").unwrap();
+
+        let photos = vec![plan_photo(&create_test_photo(&src, "", "a.jpg", b"a"), "")];
+        let base = dst.path().to_string_lossy().to_string();
+        let rename = |counter_mode| RenameConfig {
+            enabled: true,
+            template: "photo_{counter}".to_string(),
+            counter_mode,
+            ..Default::default()
+        };
+        let first_name = |mode| {
+            let plan = build_plan(&photos, &base, &rename(mode), ConflictPolicy::Rename, None);
+            Path::new(&plan.entries[0].target_path).file_name().unwrap().to_string_lossy().to_string()
+        };
+
+        assert_eq!(first_name(CounterMode::ExistingCount), "photo_0006.jpg");
+        // 最大编号为 photo_0012，没有编号的 IMG_abc 被忽略
+        assert_eq!(first_name(CounterMode::MaxSuffix), "photo_0013.jpg");
+        assert_eq!(first_name(CounterMode::Fixed), "photo_0001_1.jpg");
+
+        // 目标文件夹不存在或为空时从 counter_start 开始
+        let empty = TempDir::new().unwrap();
+        let missing = empty.path().join("尚未创建").to_string_lossy().to_string();
+        assert_eq!(initial_counter(&missing, &rename(CounterMode::ExistingCount)), 1);
+        assert_eq!(initial_counter(&missing, &rename(CounterMode::MaxSuffix)), 1);
+    }
 
     #[test]
-    fn test_scan_photos_unicode_filename() {
-        let dir = TempDir::new().unwrap();
-        
-        create_test_photo_root(&dir, "照片_2024.jpg", b"chinese name");
-        
-        let config = ClassifyConfig::default();
-        let result = scan_photos(&dir.path().to_string_lossy(), &config);
-        
-        assert!(result.is_ok());
-        let scan_result = result.unwrap();
-        assert_eq!(scan_result.total_files, 1);
-        assert!(scan_result.photos[0].file_name.contains("照片"));
+    fn test_build_plan_pairs_raw_and_jpeg_names() {
+        let src = TempDir::new().unwrap();
+        let dst = TempDir::new().unwrap();
+        let photos = vec![
+            plan_photo(&create_test_photo(&src, "", "IMG_1.CR3", b"raw"), ""),
+            plan_photo(&create_test_photo(&src, "", "IMG_1.JPG", b"jpg"), ""),
+            plan_photo(&create_test_photo(&src, "", "IMG_2.JPG", b"jpg2"), ""),
+            plan_photo(&create_test_photo(&src, "other", "IMG_1.JPG", b"other"), ""),
+        ];
+        let rename = RenameConfig {
+            enabled: true,
+            template: "photo_{counter}".to_string(),
+            counter_digits: 3,
+            extension_case: ExtCase::Lower,
+            pair_by_basename: true,
+            ..Default::default()
+        };
+        let names = |rename: &RenameConfig| {
+            let plan = build_plan(&photos, &dst.path().to_string_lossy(), rename, ConflictPolicy::Rename, None);
+            plan.entries
+                .iter()
+                .map(|e| Path::new(&e.target_path).file_name().unwrap().to_string_lossy().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        // 同一目录下的 IMG_1.CR3 和 IMG_1.JPG 共用一个计数，其他目录的同名文件单独计数
+        assert_eq!(names(&rename), ["photo_001.cr3", "photo_001.jpg", "photo_002.jpg", "photo_003.jpg"]);
+
+        let unpaired = RenameConfig { pair_by_basename: false, ..rename };
+        assert_eq!(names(&unpaired), ["photo_001.cr3", "photo_002.jpg", "photo_003.jpg", "photo_004.jpg"]);
     }
 
     #[test]
-    fn test_scan_photos_special_characters_filename() {
-        let dir = TempDir::new().unwrap();
-        
-        create_test_photo_root(&dir, "photo with spaces.jpg", b"spaces");
-        create_test_photo_root(&dir, "photo-with-dashes.jpg", b"dashes");
-        create_test_photo_root(&dir, "photo_with_underscores.jpg", b"underscores");
-        
-        let config = ClassifyConfig::default();
-        let result = scan_photos(&dir.path().to_string_lossy(), &config);
-        
-        assert!(result.is_ok());
-        assert_eq!(result.unwrap().total_files, 3);
+    fn test_build_plan_pair_keeps_conflict_suffix() {
+        let src = TempDir::new().unwrap();
+        let dst = TempDir::new().unwrap();
+        // 目标文件夹中已有 RAW 将要使用的文件名
+        fs::write(dst.path().join("photo_001.cr3"), b"existing").unwrap();
+        let photos = vec![
+            plan_photo(&create_test_photo(&src, "", "IMG_1.CR3", b"raw"), ""),
+            plan_photo(&create_test_photo(&src, "", "IMG_1.JPG", b"jpg"), ""),
+        ];
+        let rename = RenameConfig {
+            enabled: true,
+            template: "photo_{counter}".to_string(),
+            counter_digits: 3,
+            counter_mode: CounterMode::Fixed,
+            extension_case: ExtCase::Lower,
+            pair_by_basename: true,
+            ..Default::default()
+        };
+
+        let plan = build_plan(&photos, &dst.path().to_string_lossy(), &rename, ConflictPolicy::Rename, None);
+        let names: Vec<_> = plan.entries
+            .iter()
+            .map(|e| Path::new(&e.target_path).file_name().unwrap().to_string_lossy().to_string())
+            .collect();
+        // RAW 添加了序号，JPG 使用同样的序号，两者仍然成对
+        assert_eq!(names, ["photo_001_1.cr3", "photo_001_1.jpg"]);
     }
 
-    // ==================== 边界情况测试 ====================
+    #[test]
+    fn test_build_plan_skips_source_duplicates() {
+        let src = TempDir::new().unwrap();
+        let dst = TempDir::new().unwrap();
+        let first = create_test_photo(&src, "100CANON", "IMG_0001.jpg", b"same content");
+        let second = create_test_photo(&src, "101CANON", "IMG_0001.jpg", b"same content");
+        let photos = vec![plan_photo(&first, "2024"), plan_photo(&second, "2024")];
+
+        // 目标目录为空时，源文件夹内部的重复文件同样被跳过
+        let mut deduplicator = Deduplicator::new();
+        let base = dst.path().to_string_lossy().to_string();
+        let plan = build_plan(&photos, &base, &RenameConfig::default(), ConflictPolicy::Rename, Some(&mut deduplicator));
+
+        assert_eq!(plan.entries[0].action, PlanAction::Copy);
+        assert_eq!(plan.entries[1].action, PlanAction::SkipDuplicate);
+        assert_eq!(plan.entries[1].duplicate_of.as_deref(), Some(first.as_str()));
+
+        let mut scan = ScanResult {
+            total_files: photos.len(),
+            total_size: photos.iter().map(|p| p.file_size).sum(),
+            photos,
+            skipped_unreadable: Vec::new(),
+        };
+        scan.mark_duplicates(&plan);
+        assert!(!scan.photos[0].is_duplicate);
+        assert!(scan.photos[1].is_duplicate);
+        assert_eq!(scan.photos[1].duplicate_of.as_deref(), Some(first.as_str()));
+    }
 
     #[test]
-    fn test_scan_photos_empty_file() {
-        let dir = TempDir::new().unwrap();
-        
-        create_test_photo_root(&dir, "empty.jpg", b"");
-        
-        let config = ClassifyConfig::default();
-        let result = scan_photos(&dir.path().to_string_lossy(), &config);
-        
-        assert!(result.is_ok());
-        let scan_result = result.unwrap();
-        assert_eq!(scan_result.total_files, 1);
-        assert_eq!(scan_result.photos[0].file_size, 0);
+    fn test_build_plan_extension_case_in_conflict_suffix() {
+        let src = TempDir::new().unwrap();
+        let dst = TempDir::new().unwrap();
+        fs::write(dst.path().join("IMG_0001.cr3"), b"existing").unwrap();
+
+        let photos = vec![plan_photo(&create_test_photo(&src, "", "IMG_0001.CR3", b"new"), "")];
+        let rename = RenameConfig {
+            extension_case: ExtCase::Lower,
+            ..Default::default()
+        };
+
+        let plan = build_plan(&photos, &dst.path().to_string_lossy(), &rename, ConflictPolicy::Rename, None);
+        assert_eq!(PathBuf::from(&plan.entries[0].target_path), dst.path().join("IMG_0001_1.cr3"));
     }
 
     #[test]
-    fn test_scan_photos_hidden_files() {
+    fn test_debug_classify() {
         let dir = TempDir::new().unwrap();
-        
-        create_test_photo_root(&dir, ".hidden.jpg", b"hidden");
-        create_test_photo_root(&dir, "visible.jpg", b"visible");
-        
-        let config = ClassifyConfig::default();
-        let result = scan_photos(&dir.path().to_string_lossy(), &config);
-        
-        assert!(result.is_ok());
-        // 隐藏文件也应该被扫描到
-        assert_eq!(result.unwrap().total_files, 2);
+        let path = create_test_photo_root(&dir, "IMG_0001.JPG", b"not a real jpeg");
+        let rename = RenameConfig {
+            enabled: true,
+            template: "photo_{counter}".to_string(),
+            counter_start: 5,
+            counter_digits: 2,
+            ..Default::default()
+        };
+
+        let result = debug_classify(&path, &ClassifyConfig::default(), &rename).unwrap();
+        assert_eq!(result.metadata.file_name, "IMG_0001.JPG");
+        assert_eq!(result.metadata.file_size, 15);
+        assert_eq!(result.new_filename, "photo_05.JPG");
+        // 没有日期信息时使用备用文件夹
+        if result.metadata.date_time_original.is_none() && result.metadata.create_date.is_none() {
+            assert_eq!(result.target_folder, "未知日期");
+        }
     }
 
     #[test]
-    fn test_scan_photos_case_insensitive_extension() {
+    fn test_debug_classify_rejects_missing_and_unsupported() {
         let dir = TempDir::new().unwrap();
-        
-        create_test_photo_root(&dir, "photo1.JPG", b"uppercase");
-        create_test_photo_root(&dir, "photo2.Jpg", b"mixed");
-        create_test_photo_root(&dir, "photo3.jpg", b"lowercase");
-        create_test_photo_root(&dir, "photo4.CR3", b"raw uppercase");
-        
         let config = ClassifyConfig::default();
-        let result = scan_photos(&dir.path().to_string_lossy(), &config);
-        
-        assert!(result.is_ok());
-        assert_eq!(result.unwrap().total_files, 4);
+        let rename = RenameConfig::default();
+
+        let missing = dir.path().join("missing.jpg").to_string_lossy().to_string();
+        assert!(debug_classify(&missing, &config, &rename).unwrap_err().contains("文件不存在"));
+
+        let text = create_test_photo_root(&dir, "notes.txt", b"text");
+        assert!(debug_classify(&text, &config, &rename).unwrap_err().contains("不支持的文件格式"));
+
+        let folder = dir.path().to_string_lossy().to_string();
+        assert!(debug_classify(&folder, &config, &rename).unwrap_err().contains("不是文件"));
     }
 }
@@ -1,286 +1,2784 @@
-use crate::exif::read_exif;
-use crate::hash::Deduplicator;
+use crate::archive::{OutputMode, ZipOutput};
+use crate::classify::{is_supported_photo, to_nfc, ClassifyConfig};
+use crate::exif::{metadata_from_file, read_exif};
+use crate::hash::{calculate_hash, DedupIndex, Deduplicator, IndexedDir};
 use crate::rename::RenameConfig;
-use crate::transfer::{PhotoInfo, TransferProgress, TransferResult};
-use crate::history::{TransferHistory, TransferredFile, TransferFileStatus};
+use crate::transfer::{
+    basename_key, build_exclude_set, build_plan, check_dirs_overlap, copy_sidecars, enrich_photo, find_sidecars, is_excluded, paired_file_name, photo_info, route_target_dir, sidecar_target_path, CameraRoute, ConflictPolicy, DedupScanProgress, PhotoInfo, PlanAction, PlanEntry, SkippedFile, TransferPhase,
+    TransferPlan, TransferProgress, TransferResult, TransferStatus,
+};
+use crate::history::{RecordKind, TransferHistory, TransferRecord, TransferredFile, TransferFileStatus};
+use crate::progress::ProgressSink;
+use crate::preview::{export_raw_previews, is_preview_entry, preview_root, PreviewExport, RawPreviewMode};
+use crate::thumbnail::extract_largest_preview;
+use globset::GlobSet;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs;
-use std::path::Path;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
-use std::time::Instant;
-use tauri::{AppHandle, Emitter};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant, SystemTime};
 use walkdir::WalkDir;
 
+/// 复制单个文件（源路径、目标路径），返回复制的字节数。
+/// 测试时可替换为按需失败的实现，以覆盖错误处理、重试和进度统计
+pub type CopyFn = Arc<dyn Fn(&Path, &Path) -> std::io::Result<u64> + Send + Sync>;
+
+/// 把已写入的文件从系统缓存刷新到磁盘（sync_all），
+/// Unix 上同时刷新所在目录，保证新文件的目录项也已写入
+pub fn sync_to_disk(path: &Path) -> std::io::Result<()> {
+    // Windows 上刷新文件需要写权限
+    fs::OpenOptions::new().write(true).open(path)?.sync_all()?;
+    #[cfg(unix)]
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::File::open(parent)?.sync_all()?;
+    }
+    Ok(())
+}
+
+/// 在 copy_fn 复制完成后把目标文件刷新到磁盘，刷新失败与复制失败同样处理（按重试策略重试）
+fn with_fsync(copy_fn: CopyFn) -> CopyFn {
+    Arc::new(move |source, target| {
+        let copied = copy_fn(source, target)?;
+        sync_to_disk(target)?;
+        Ok(copied)
+    })
+}
+
 /// 带取消支持的传输上下文
 pub struct TransferContext {
-    pub app_handle: AppHandle,
     pub cancel_flag: Arc<AtomicBool>,
     pub rename_config: RenameConfig,
     pub source_dir: String,
     pub target_dir: String,
     pub template: String,
+    /// 忽略去重索引缓存，重新计算目标目录中所有文件的哈希
+    pub force_rescan: bool,
+    /// 传输历史文件路径
+    pub history_path: PathBuf,
+    /// 去重索引缓存目录
+    pub index_cache_dir: PathBuf,
+    /// 目标文件已存在时的处理方式
+    pub conflict_policy: ConflictPolicy,
+    /// 去重时额外参考的目录（如主归档），其中已有的文件同样跳过，但不会向其中写入
+    pub reference_dirs: Vec<String>,
+    /// 去重扫描目标目录和参考目录时排除的文件/文件夹（glob，规则同扫描排除），
+    /// 其中的文件不参与去重，可跳过不会重复导入的大型归档子文件夹以加快扫描
+    pub dedup_exclude: Vec<String>,
+    /// 复制失败时的重试策略
+    pub retry_policy: RetryPolicy,
+    /// 并行复制的任务数，1 表示串行（只对复制为单独的文件有效）
+    pub jobs: usize,
+    /// 复制单个文件的函数，默认为 fs::copy
+    pub copy_fn: CopyFn,
+    /// 允许目标文件夹与源文件夹相同或互相包含
+    pub allow_nested_target: bool,
+    /// 传输照片时一并复制同名附属文件（.xmp/.aae/.thm），按照片的新文件名命名
+    pub move_sidecars: bool,
+    /// 去重时同时比较 JPEG/PNG 解码后的像素数据（较慢），见 Deduplicator::with_pixel_dedup
+    pub pixel_dedup: bool,
+    /// 已归档文件的哈希清单（sha256sum 格式），其中列出的内容同样视为重复，不读取清单中的文件
+    pub known_hashes: Option<String>,
+    /// 复制完成后重新读取目标文件，与源文件比较哈希（需要再读取一遍源文件和目标文件）
+    pub verify: bool,
+    /// 每个文件复制完成后刷新到磁盘（fsync），进度完成时数据已写入磁盘而不只是系统缓存，
+    /// 可以立即拔出存储设备；较慢，默认关闭
+    pub fsync: bool,
+    /// 复制为单独的文件，或按第一级目标文件夹打包为 ZIP（打包时不复制附属文件，也不校验）
+    pub output_mode: OutputMode,
+    /// 重试失败文件时为原传输记录的 ID，写入新记录的 retry_of
+    pub retry_of: Option<String>,
+    /// 是否为 RAW 导出内嵌的全尺寸 JPEG 预览，以及是否仍复制 RAW
+    pub raw_preview: RawPreviewMode,
+    /// 预览文件夹，None 时为目标文件夹旁的 `<目标文件夹名>_JPEG`
+    pub preview_dir: Option<String>,
+    /// 按相机分配目标文件夹的规则，没有匹配的规则的照片传输到默认的目标文件夹
+    pub camera_routes: Vec<CameraRoute>,
+    /// 最近一次发送的传输进度，与 AppState 共享，供 get_transfer_status 查询；传输结束后清空
+    pub progress: Arc<Mutex<Option<TransferProgress>>>,
+    /// TransferResult 中最多直接返回的文件明细数，超过时 files 为 None、只保存在历史记录中。
+    /// 默认为 MAX_RESULT_FILES；命令行需要完整列表时设为 usize::MAX
+    pub max_result_files: usize,
 }
 
 impl TransferContext {
     pub fn new(
-        app_handle: AppHandle,
         cancel_flag: Arc<AtomicBool>,
         source_dir: &str,
         target_dir: &str,
         template: &str,
     ) -> Self {
         Self {
-            app_handle,
             cancel_flag,
             rename_config: RenameConfig::default(),
             source_dir: source_dir.to_string(),
             target_dir: target_dir.to_string(),
             template: template.to_string(),
+            force_rescan: false,
+            history_path: TransferHistory::get_history_file_path(),
+            index_cache_dir: DedupIndex::default_cache_dir(),
+            conflict_policy: ConflictPolicy::default(),
+            reference_dirs: Vec::new(),
+            dedup_exclude: Vec::new(),
+            retry_policy: RetryPolicy::default(),
+            jobs: 1,
+            copy_fn: Arc::new(|source, target| fs::copy(source, target)),
+            allow_nested_target: false,
+            move_sidecars: false,
+            pixel_dedup: false,
+            known_hashes: None,
+            verify: false,
+            fsync: false,
+            output_mode: OutputMode::default(),
+            retry_of: None,
+            raw_preview: RawPreviewMode::default(),
+            preview_dir: None,
+            camera_routes: Vec::new(),
+            progress: Arc::new(Mutex::new(None)),
+            max_result_files: MAX_RESULT_FILES,
         }
     }
 
     pub fn is_cancelled(&self) -> bool {
         self.cancel_flag.load(Ordering::Relaxed)
     }
+
+    /// 记录最新进度并报告给 sink
+    fn report_progress(&self, sink: &dyn ProgressSink, progress: TransferProgress) {
+        record_progress(&self.progress, Some(progress.clone()));
+        sink.transfer_progress(progress);
+    }
+}
+
+/// 更新最新传输进度；None 表示没有进行中的传输
+fn record_progress(snapshot: &Mutex<Option<TransferProgress>>, progress: Option<TransferProgress>) {
+    *snapshot.lock().unwrap_or_else(PoisonError::into_inner) = progress;
+}
+
+/// 传输字节统计：按实际复制的字节数累计，
+/// 文件在扫描后发生变化时同步修正总字节数，保证进度和剩余时间准确
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ByteProgress {
+    transferred: u64,
+    total: u64,
+}
+
+impl ByteProgress {
+    fn new(total: u64) -> Self {
+        Self { transferred: 0, total }
+    }
+
+    /// 跳过的重复文件按扫描时的大小计入
+    fn add_skipped(&mut self, scanned_size: u64) {
+        self.transferred += scanned_size;
+    }
+
+    /// 复制的文件按实际字节数计入，并用实际大小替换扫描时的大小
+    fn add_copied(&mut self, scanned_size: u64, copied: u64) {
+        self.transferred += copied;
+        self.total = self.total.saturating_sub(scanned_size) + copied;
+    }
+}
+
+/// 复制失败时的重试策略：网络存储短暂断开时复制可能失败，稍后重试即可成功
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RetryPolicy {
+    /// 最多尝试的次数（包括第一次），1 表示不重试
+    pub max_attempts: u32,
+    /// 第一次重试前等待的毫秒数，之后每次重试等待时间翻倍
+    pub base_delay_ms: u64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            base_delay_ms: 500,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// 第 retry 次重试（从 1 开始）前的等待时间
+    fn delay(&self, retry: u32) -> Duration {
+        let factor = 1u64.checked_shl(retry.saturating_sub(1)).unwrap_or(u64::MAX);
+        Duration::from_millis(self.base_delay_ms.saturating_mul(factor))
+    }
+}
+
+/// 重试等待期间检查取消标志的间隔
+const RETRY_CANCEL_CHECK_INTERVAL: Duration = Duration::from_millis(50);
+
+/// 等待指定时间，期间被取消时立即返回 false
+fn sleep_unless_cancelled(duration: Duration, is_cancelled: impl Fn() -> bool) -> bool {
+    let deadline = Instant::now() + duration;
+    loop {
+        if is_cancelled() {
+            return false;
+        }
+        let now = Instant::now();
+        if now >= deadline {
+            return true;
+        }
+        std::thread::sleep(RETRY_CANCEL_CHECK_INTERVAL.min(deadline - now));
+    }
+}
+
+/// 按重试策略执行操作，失败后按指数退避等待再试。
+/// 全部失败或等待期间被取消时返回最后一次的错误和已尝试的次数
+fn retry_with_backoff<T>(
+    policy: &RetryPolicy,
+    is_cancelled: impl Fn() -> bool,
+    mut operation: impl FnMut() -> std::io::Result<T>,
+) -> Result<T, (std::io::Error, u32)> {
+    let mut attempts = 0;
+    loop {
+        attempts += 1;
+        match operation() {
+            Ok(value) => return Ok(value),
+            Err(e) if attempts >= policy.max_attempts.max(1) => return Err((e, attempts)),
+            Err(e) => {
+                log::debug!("第 {} 次尝试失败，稍后重试: {}", attempts, e);
+                if !sleep_unless_cancelled(policy.delay(attempts), &is_cancelled) {
+                    return Err((e, attempts));
+                }
+            }
+        }
+    }
+}
+
+/// 复制失败的原因，重试过时注明重试次数
+fn copy_error_detail(error: &std::io::Error, attempts: u32) -> String {
+    if attempts > 1 {
+        format!("重试 {} 次后仍失败: {}", attempts - 1, error)
+    } else {
+        error.to_string()
+    }
+}
+
+/// 复制单个文件，返回实际复制的字节数，并通过 add_bytes 计入字节统计
+fn copy_file(
+    copy_fn: &CopyFn,
+    source: &str,
+    target: &Path,
+    add_bytes: impl Fn(u64),
+) -> std::io::Result<u64> {
+    let copied = copy_fn(Path::new(source), target)?;
+    add_bytes(copied);
+    Ok(copied)
+}
+
+/// 去重预扫描时每索引多少个文件发送一次进度
+const DEDUP_SCAN_EMIT_INTERVAL: usize = 50;
+
+/// 文件夹修改时间的精度（FAT、SMB 等文件系统可达 2 秒）：
+/// 在上次遍历开始前这么久以内修改过的文件夹，下次仍然重新读取
+const DIR_MTIME_GRANULARITY: Duration = Duration::from_secs(2);
+
+/// 扫描目标目录已有文件并更新去重索引，期间通过 on_progress 报告进度。
+/// 没有缓存时先快速统计文件总数，有缓存时以缓存的文件数作为预计总数。
+/// 修改时间与缓存相同的文件夹不再读取，其中的文件和子文件夹沿用缓存（原地修改文件内容不会改变文件夹的修改时间，
+/// 需要时用 force_rescan 完整重建）；其余文件夹逐个更新索引，大小和修改时间未变的文件沿用已有哈希，
+/// 只有新增或变化的文件才计算哈希。匹配 excludes 的文件和文件夹不索引。被取消时返回 false
+fn index_target_files(
+    target_base_dir: &Path,
+    index: &mut DedupIndex,
+    excludes: &GlobSet,
+    is_cancelled: impl Fn() -> bool,
+    mut on_progress: impl FnMut(DedupScanProgress),
+) -> bool {
+    let walk_started = SystemTime::now();
+    let total = if index.is_warm() {
+        index.files.len()
+    } else {
+        on_progress(DedupScanProgress {
+            indexed: 0,
+            total: None,
+            status: "counting".to_string(),
+        });
+        WalkDir::new(target_base_dir)
+            .follow_links(true)
+            .into_iter()
+            .filter_entry(|e| !is_excluded(excludes, target_base_dir, e.path()))
+            .filter_map(|e| e.ok())
+            .filter(|e| e.path().is_file())
+            .count()
+    };
+
+    // 上次索引时各文件夹（NFC 路径）中的文件
+    let mut cached_files: HashMap<String, Vec<String>> = HashMap::new();
+    for key in index.files.keys() {
+        if let Some(parent) = Path::new(key).parent() {
+            cached_files.entry(parent.to_string_lossy().to_string()).or_default().push(key.clone());
+        }
+    }
+    let trusted_before = index.indexed_at.and_then(|t| t.checked_sub(DIR_MTIME_GRANULARITY));
+
+    let mut indexed = 0;
+    let mut next_emit = 0;
+    let mut existing = HashSet::new();
+    let mut dirs = HashMap::new();
+    let mut visited = HashSet::new();
+    let mut pending = vec![target_base_dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        if is_cancelled() {
+            return false;
+        }
+        // 跟随符号链接时避免循环
+        if !visited.insert(fs::canonicalize(&dir).unwrap_or_else(|_| dir.clone())) {
+            continue;
+        }
+        let Ok(modified) = fs::metadata(&dir).and_then(|m| m.modified()) else {
+            continue;
+        };
+        let dir_key = to_nfc(&dir.to_string_lossy());
+        let unchanged_subdirs = index
+            .dirs
+            .get(&dir_key)
+            .filter(|cached| cached.modified == modified && trusted_before.is_some_and(|t| modified <= t))
+            .map(|cached| cached.subdirs.clone());
+
+        let subdirs = match unchanged_subdirs {
+            Some(subdirs) => {
+                let files = cached_files.remove(&dir_key).unwrap_or_default();
+                let files: Vec<String> = files
+                    .into_iter()
+                    .filter(|f| !is_excluded(excludes, target_base_dir, Path::new(f)))
+                    .collect();
+                indexed += files.len();
+                existing.extend(files);
+                subdirs
+                    .into_iter()
+                    .filter(|d| !is_excluded(excludes, target_base_dir, Path::new(d)))
+                    .collect()
+            }
+            None => {
+                let Ok(entries) = fs::read_dir(&dir) else {
+                    continue;
+                };
+                let mut subdirs = Vec::new();
+                for entry in entries.filter_map(|e| e.ok()) {
+                    let path = entry.path();
+                    if is_excluded(excludes, target_base_dir, &path) {
+                        continue;
+                    }
+                    if path.is_dir() {
+                        subdirs.push(path.to_string_lossy().to_string());
+                        continue;
+                    }
+                    if !path.is_file() {
+                        continue;
+                    }
+                    if is_cancelled() {
+                        return false;
+                    }
+                    if indexed >= next_emit {
+                        on_progress(DedupScanProgress {
+                            indexed,
+                            total: Some(total.max(indexed)),
+                            status: "indexing".to_string(),
+                        });
+                        next_emit = indexed + DEDUP_SCAN_EMIT_INTERVAL;
+                    }
+                    let path = path.to_string_lossy().to_string();
+                    if index.update_file(&path).is_ok() {
+                        existing.insert(path);
+                    }
+                    indexed += 1;
+                }
+                subdirs
+            }
+        };
+        pending.extend(subdirs.iter().map(PathBuf::from));
+        dirs.insert(dir_key, IndexedDir { modified, subdirs });
+    }
+    index.retain_files(&existing);
+    index.dirs = dirs;
+    index.indexed_at = Some(walk_started);
+
+    on_progress(DedupScanProgress {
+        indexed,
+        total: Some(indexed),
+        status: "completed".to_string(),
+    });
+    true
+}
+
+/// 去重时需要索引的目录：目标目录和参考目录中存在的目录，
+/// 忽略重复列出的参考目录和位于目标目录内的参考目录（已随目标目录一起索引）
+fn dedup_dirs<'a>(target_base_dir: &'a str, reference_dirs: &'a [String]) -> Vec<&'a str> {
+    let mut dirs = vec![target_base_dir];
+    for dir in reference_dirs {
+        if !Path::new(dir).starts_with(target_base_dir) && !dirs.contains(&dir.as_str()) {
+            dirs.push(dir);
+        }
+    }
+    dirs.retain(|dir| Path::new(dir).exists());
+    dirs
+}
+
+/// 扫描目标目录和参考目录中已有的文件，生成用于去重的 Deduplicator（使用去重索引缓存）。
+/// 不存在的目录和位于目标目录内的参考目录会被忽略；设置了 known_hashes 时一并加载哈希清单。
+/// 索引保存或清单加载失败、dedup_exclude 规则无效（此时不排除任何文件）时记录到 errors；被取消时返回 None
+pub fn target_deduplicator(
+    ctx: &TransferContext,
+    sink: &dyn ProgressSink,
+    target_base_dir: &str,
+    errors: &mut Vec<String>,
+) -> Option<Deduplicator> {
+    let mut deduplicator = Deduplicator::new().with_pixel_dedup(ctx.pixel_dedup);
+    let excludes = build_exclude_set(&ctx.dedup_exclude).unwrap_or_else(|e| {
+        errors.push(e);
+        GlobSet::empty()
+    });
+    for dir in dedup_dirs(target_base_dir, &ctx.reference_dirs) {
+        let cache_path = DedupIndex::cache_path_in(&ctx.index_cache_dir, dir);
+        let mut index = if ctx.force_rescan {
+            DedupIndex::new(dir)
+        } else {
+            DedupIndex::load_from(&cache_path, dir)
+        };
+        let completed = index_target_files(
+            Path::new(dir),
+            &mut index,
+            &excludes,
+            || ctx.is_cancelled(),
+            |progress| sink.dedup_scan_progress(progress),
+        );
+        if !completed {
+            return None;
+        }
+
+        if let Err(e) = index.save_to(&cache_path) {
+            errors.push(format!("保存去重索引失败: {}", e));
+        }
+        index.populate(&mut deduplicator);
+    }
+
+    if let Some(manifest) = &ctx.known_hashes {
+        match deduplicator.load_hash_manifest(Path::new(manifest)) {
+            Ok(count) => log::info!("已从哈希清单加载 {} 个已知哈希: {}", count, manifest),
+            Err(e) => errors.push(format!("{} ({})", e, manifest)),
+        }
+    }
+    Some(deduplicator)
+}
+
+/// 生成传输计划：按 camera_routes 把照片分到各自的目标根目录，每个根目录分别去重（使用各自的去重索引）
+/// 和解析目标路径，再合并为一个计划（target_dir 为默认的目标文件夹）。
+/// 打包为 ZIP 时不按相机分配，说明记录到 errors；去重扫描被取消时返回 None
+pub fn plan_transfer(
+    ctx: &TransferContext,
+    sink: &dyn ProgressSink,
+    photos: &[PhotoInfo],
+    target_base_dir: &str,
+    skip_duplicates: bool,
+    errors: &mut Vec<String>,
+) -> Option<TransferPlan> {
+    let routes: &[CameraRoute] = if ctx.output_mode == OutputMode::Zip && !ctx.camera_routes.is_empty() {
+        errors.push("打包为 ZIP 时不按相机分配目标文件夹，所有照片传输到默认的目标文件夹".to_string());
+        &[]
+    } else {
+        &ctx.camera_routes
+    };
+
+    let mut groups: Vec<(&str, Vec<PhotoInfo>)> = Vec::new();
+    for photo in photos {
+        let root = route_target_dir(routes, photo, target_base_dir);
+        match groups.iter_mut().find(|(dir, _)| *dir == root) {
+            Some((_, group)) => group.push(photo.clone()),
+            None => groups.push((root, vec![photo.clone()])),
+        }
+    }
+
+    let mut plan = TransferPlan {
+        target_dir: target_base_dir.to_string(),
+        entries: Vec::with_capacity(photos.len()),
+    };
+    for (root, group) in groups {
+        let mut deduplicator = if skip_duplicates {
+            Some(target_deduplicator(ctx, sink, root, errors)?)
+        } else {
+            None
+        };
+        let routed = build_plan(&group, root, &ctx.rename_config, ctx.conflict_policy, deduplicator.as_mut());
+        plan.entries.extend(routed.entries);
+    }
+    Some(plan)
+}
+
+/// 计划条目所在的目标根目录：按相机分配的目标文件夹中包含目标路径的一个，否则为计划的目标文件夹
+fn entry_root<'a>(ctx: &'a TransferContext, plan: &'a TransferPlan, entry: &PlanEntry) -> &'a str {
+    let target = Path::new(&entry.target_path);
+    ctx.camera_routes
+        .iter()
+        .map(|route| route.target_dir.as_str())
+        .filter(|dir| !entry.target_path.is_empty() && target.starts_with(dir))
+        .max_by_key(|dir| dir.len())
+        .unwrap_or(&plan.target_dir)
 }
 
-/// 执行照片传输（支持取消、重命名和历史记录）
+/// 执行照片传输（支持取消、重命名和历史记录）：先生成传输计划，再按计划复制。
+/// 目标文件夹与照片所在的源文件夹相同或互相包含时返回错误，除非设置了 allow_nested_target
 pub fn transfer_photos_v2(
     ctx: &TransferContext,
+    sink: &dyn ProgressSink,
     photos: &[PhotoInfo],
     target_base_dir: &str,
     skip_duplicates: bool,
 ) -> Result<TransferResult, String> {
-    let start_time = Instant::now();
-    let mut success_count = 0;
-    let mut skip_count = 0;
-    let mut error_count = 0;
+    if !ctx.allow_nested_target {
+        let mut source_roots: Vec<String> = photos.iter().map(|p| p.source_root.clone()).collect();
+        source_roots.sort();
+        source_roots.dedup();
+        check_dirs_overlap(&source_roots, target_base_dir)?;
+        for route in &ctx.camera_routes {
+            check_dirs_overlap(&source_roots, &route.target_dir)?;
+        }
+    }
+
     let mut errors = Vec::new();
-    let mut transferred_files = Vec::new();
-    let mut deduplicator = Deduplicator::new();
-    let total = photos.len();
     let total_bytes: u64 = photos.iter().map(|p| p.file_size).sum();
-    let mut bytes_transferred = 0u64;
-    let mut counter = ctx.rename_config.counter_start;
+
+    // 立即发送准备事件，去重扫描或生成计划耗时较长时界面也能及时响应
+    ctx.report_progress(sink, TransferProgress {
+        current: 0,
+        total: photos.len(),
+        current_file: "正在准备传输...".to_string(),
+        bytes_transferred: 0,
+        total_bytes,
+        status: TransferStatus::Preparing,
+        phase: TransferPhase::Preparing,
+        skipped_duplicates: 0,
+    });
 
     // 如果启用去重，先扫描目标目录中已有的文件
     if skip_duplicates {
-        let _ = ctx.app_handle.emit("transfer-progress", TransferProgress {
+        ctx.report_progress(sink, TransferProgress {
             current: 0,
-            total,
+            total: photos.len(),
             current_file: "正在扫描目标目录已有文件...".to_string(),
             bytes_transferred: 0,
             total_bytes,
-            status: "scanning".to_string(),
+            status: TransferStatus::Scanning,
+            phase: TransferPhase::Scanning,
             skipped_duplicates: 0,
         });
 
-        if Path::new(target_base_dir).exists() {
-            for entry in WalkDir::new(target_base_dir)
-                .follow_links(true)
-                .into_iter()
-                .filter_map(|e| e.ok())
-            {
-                // 检查取消
-                if ctx.is_cancelled() {
-                    return Ok(TransferResult {
-                        success_count,
-                        skip_count,
-                        error_count,
-                        errors: vec!["传输已取消".to_string()],
-                    });
-                }
-                
-                if entry.path().is_file() {
-                    let _ = deduplicator.add_known_file(&entry.path().to_string_lossy());
-                }
+    }
+
+    let Some(plan) = plan_transfer(ctx, sink, photos, target_base_dir, skip_duplicates, &mut errors) else {
+        record_progress(&ctx.progress, None);
+        return Ok(TransferResult {
+            success_count: 0,
+            skip_count: 0,
+            error_count: 0,
+            errors: vec!["传输已取消".to_string()],
+            warnings: Vec::new(),
+            skipped: Vec::new(),
+            files: None,
+            previews: 0,
+            new_folders: Vec::new(),
+            merged_folders: Vec::new(),
+        });
+    };
+    let mut result = execute_plan(ctx, sink, &plan)?;
+    errors.append(&mut result.errors);
+    result.errors = errors;
+    Ok(result)
+}
+
+/// 收集传输记录中失败的文件，重新读取元数据后返回需要重新传输的照片（按 config 重新分类）。
+/// 源文件已不存在的文件被跳过，说明在第二个返回值中
+pub fn failed_photos(record: &TransferRecord, config: &ClassifyConfig) -> (Vec<PhotoInfo>, Vec<String>) {
+    let source_roots: Vec<&str> = record.source_dir.split("; ").collect();
+    let mut photos = Vec::new();
+    let mut notes = Vec::new();
+    for file in record.failed_files() {
+        let path = Path::new(&file.source_path);
+        if !path.is_file() {
+            notes.push(format!("源文件已不存在，跳过重试: {}", file.source_path));
+            continue;
+        }
+        // 多个源文件夹时按路径找到所属的源文件夹，用于 {folder} 占位符
+        let source_root = source_roots
+            .iter()
+            .find(|root| !root.is_empty() && path.starts_with(root))
+            .map(|root| root.to_string())
+            .or_else(|| path.parent().map(|dir| dir.to_string_lossy().to_string()))
+            .unwrap_or_default();
+        let mut photo = photo_info(path, &source_root, config, false);
+        enrich_photo(&mut photo, config);
+        photos.push(photo);
+    }
+    (photos, notes)
+}
+
+/// 按计划复制文件的统计结果
+struct PlanOutcome {
+    success_count: usize,
+    skip_count: usize,
+    error_count: usize,
+    errors: Vec<String>,
+    transferred_files: Vec<TransferredFile>,
+    skipped: Vec<SkippedFile>,
+    bytes: ByteProgress,
+    /// 源文件夹无法访问导致传输中止时的原因
+    source_unavailable: Option<String>,
+}
+
+/// 复制失败是否因为源文件所在的文件夹已无法访问（如存储卡被拔出、网络断开），
+/// 而不是单个文件的问题；此时后续文件也必然失败，应中止传输
+fn is_source_unavailable(error: &std::io::Error, source: &Path) -> bool {
+    if error.kind() == std::io::ErrorKind::PermissionDenied {
+        return false;
+    }
+    source.parent().is_some_and(|dir| fs::read_dir(dir).is_err())
+}
+
+/// 校验复制后的文件：目标文件与源文件的哈希一致时返回 Ok
+fn verify_copy(source: &str, target: &str) -> Result<(), String> {
+    let source_hash = calculate_hash(source).map_err(|e| format!("读取源文件失败: {}", e))?;
+    let target_hash = calculate_hash(target).map_err(|e| format!("读取目标文件失败: {}", e))?;
+    if source_hash == target_hash {
+        Ok(())
+    } else {
+        Err("目标文件与源文件内容不一致".to_string())
+    }
+}
+
+/// 复制阶段的选项
+#[derive(Debug, Clone, Copy, Default)]
+struct CopyOptions {
+    retry_policy: RetryPolicy,
+    /// 一并复制同名附属文件
+    move_sidecars: bool,
+    /// 复制完成后校验
+    verify: bool,
+    /// 并行复制的任务数，0 或 1 表示串行
+    jobs: usize,
+}
+
+impl CopyOptions {
+    fn from_context(ctx: &TransferContext) -> Self {
+        Self {
+            retry_policy: ctx.retry_policy,
+            move_sidecars: ctx.move_sidecars,
+            verify: ctx.verify,
+            jobs: ctx.jobs,
+        }
+    }
+}
+
+/// 单个计划条目的处理结果
+struct EntryOutcome {
+    file: TransferredFile,
+    errors: Vec<String>,
+    skipped: Option<SkippedFile>,
+    /// 源文件夹无法访问，应中止传输
+    source_unavailable: Option<String>,
+}
+
+/// 复制任务共享的进度状态
+struct SharedProgress<F> {
+    bytes: ByteProgress,
+    /// 已开始处理的条目数
+    started: usize,
+    skip_count: usize,
+    on_progress: F,
+}
+
+/// 处理单个计划条目：跳过、记录错误，或创建目标目录并按重试策略复制（含附属文件）
+fn process_entry<F>(
+    entry: &PlanEntry,
+    copy_fn: &CopyFn,
+    options: &CopyOptions,
+    is_cancelled: &(impl Fn() -> bool + Sync),
+    shared: &Mutex<SharedProgress<F>>,
+) -> EntryOutcome {
+    let record_file = |target_path: &str, file_size: u64, status: TransferFileStatus| TransferredFile {
+        source_path: entry.source_path.clone(),
+        target_path: target_path.to_string(),
+        file_size,
+        status,
+        camera: entry.camera.clone(),
+        matched_original: None,
+    };
+    let mut outcome = EntryOutcome {
+        file: record_file(&entry.target_path, entry.file_size, TransferFileStatus::Skipped),
+        errors: Vec::new(),
+        skipped: None,
+        source_unavailable: None,
+    };
+
+    match entry.action {
+        PlanAction::SkipDuplicate | PlanAction::SkipExisting => {
+            let mut state = shared.lock().unwrap_or_else(PoisonError::into_inner);
+            state.skip_count += 1;
+            state.bytes.add_skipped(entry.file_size);
+            if let Some(original) = &entry.duplicate_of {
+                outcome.skipped = Some(SkippedFile {
+                    source: entry.source_path.clone(),
+                    matched_original: original.clone(),
+                });
+                outcome.file.matched_original = Some(original.clone());
             }
+            return outcome;
         }
+        PlanAction::Error => {
+            let message = entry.error.clone().unwrap_or_default();
+            outcome.errors.push(format!("{} ({})", message, entry.file_name));
+            outcome.file.status = TransferFileStatus::Error(message);
+            return outcome;
+        }
+        PlanAction::Copy | PlanAction::Overwrite => {}
     }
 
-    for (index, photo) in photos.iter().enumerate() {
-        // 检查取消标志
-        if ctx.is_cancelled() {
-            let _ = ctx.app_handle.emit("transfer-progress", TransferProgress {
-                current: index,
-                total,
-                current_file: "传输已取消".to_string(),
-                bytes_transferred,
-                total_bytes,
-                status: "cancelled".to_string(),
-                skipped_duplicates: skip_count,
-            });
-            
-            errors.push("传输已取消".to_string());
-            break;
+    if let Some(e) = &entry.error {
+        outcome.errors.push(format!("{} ({})", e, entry.file_name));
+    }
+
+    // 创建目标目录
+    let target_path = Path::new(&entry.target_path);
+    if let Some(target_dir) = target_path.parent() {
+        if let Err(e) = fs::create_dir_all(target_dir) {
+            outcome.errors.push(format!("创建目录失败 {}: {}", target_dir.display(), e));
+            outcome.file.status = TransferFileStatus::Error(e.to_string());
+            return outcome;
         }
+    }
 
-        // 发送进度事件
-        let _ = ctx.app_handle.emit("transfer-progress", TransferProgress {
-            current: index + 1,
-            total,
-            current_file: photo.file_name.clone(),
-            bytes_transferred,
-            total_bytes,
-            status: "transferring".to_string(),
-            skipped_duplicates: skip_count,
-        });
+    if entry.action == PlanAction::Copy && target_path.exists() {
+        let message = "目标文件已存在，请重新生成传输计划".to_string();
+        outcome.errors.push(format!("{} ({})", message, entry.file_name));
+        outcome.file.status = TransferFileStatus::Error(message);
+        return outcome;
+    }
 
-        // 检查重复
-        if skip_duplicates {
-            match deduplicator.check_duplicate(&photo.path, photo.file_size) {
-                Ok(Some(_original)) => {
-                    skip_count += 1;
-                    bytes_transferred += photo.file_size;
-                    transferred_files.push(TransferredFile {
-                        source_path: photo.path.clone(),
-                        target_path: String::new(),
-                        file_size: photo.file_size,
-                        status: TransferFileStatus::Skipped,
-                    });
-                    continue;
-                }
-                Ok(None) => {}
-                Err(e) => {
-                    errors.push(format!("检查重复失败 {}: {}", photo.file_name, e));
-                }
+    // 复制文件，失败时按重试策略重试
+    let bytes = |copied| shared.lock().unwrap_or_else(PoisonError::into_inner).bytes.add_copied(entry.file_size, copied);
+    let copied = retry_with_backoff(&options.retry_policy, is_cancelled, || {
+        copy_file(copy_fn, &entry.source_path, target_path, bytes)
+    });
+    match copied {
+        Ok(copied) => {
+            outcome.file = record_file(&entry.target_path, copied, TransferFileStatus::Success);
+            if options.move_sidecars {
+                outcome.errors.extend(copy_sidecars(&entry.source_path, target_path, |s, t| copy_fn(s, t)));
             }
         }
+        Err((e, attempts)) => {
+            let detail = copy_error_detail(&e, attempts);
+            outcome.errors.push(format!("复制失败 {}: {}", entry.file_name, detail));
+            outcome.file.status = TransferFileStatus::Error(detail);
 
-        // 构建目标路径
-        let target_dir = Path::new(target_base_dir).join(&photo.target_folder);
-        
-        // 生成新文件名（如果启用重命名）
-        let new_filename = if ctx.rename_config.enabled {
-            let metadata = read_exif(&photo.path).unwrap_or_default();
-            let name = ctx.rename_config.generate_filename(&metadata, counter);
-            counter += 1;
-            name
-        } else {
-            photo.file_name.clone()
-        };
-        
-        let target_path = target_dir.join(&new_filename);
-
-        // 创建目标目录
-        if let Err(e) = fs::create_dir_all(&target_dir) {
-            error_count += 1;
-            errors.push(format!("创建目录失败 {}: {}", target_dir.display(), e));
-            transferred_files.push(TransferredFile {
-                source_path: photo.path.clone(),
-                target_path: target_path.to_string_lossy().to_string(),
-                file_size: photo.file_size,
-                status: TransferFileStatus::Error(e.to_string()),
-            });
-            continue;
+            if is_source_unavailable(&e, Path::new(&entry.source_path)) {
+                let message = format!("源文件夹无法访问（存储卡可能已拔出），传输已中止: {}", entry.source_path);
+                outcome.errors.push(message.clone());
+                outcome.source_unavailable = Some(message);
+            }
         }
+    }
+    outcome
+}
+
+/// 按传输计划复制文件，通过 on_progress 报告进度（不发送事件、不写入历史）。
+/// options.jobs 大于 1 时由多个工作线程同时复制，结果仍按计划顺序记录。
+/// 计划为 Copy 的文件在执行时若目标已存在（计划生成后磁盘发生变化），记为错误而不覆盖。
+/// verify 为 true 时复制完成后进入校验阶段（Verifying），逐个比较已复制文件与源文件的哈希；
+/// 进度中的字节数同时包含复制和校验两个阶段，复制完成时进度约为一半而不是 100%
+fn copy_plan_entries(
+    plan: &TransferPlan,
+    copy_fn: &CopyFn,
+    options: &CopyOptions,
+    is_cancelled: impl Fn() -> bool + Sync,
+    on_progress: impl FnMut(TransferProgress) + Send,
+) -> PlanOutcome {
+    let verify = options.verify;
+    let total = plan.entries.len();
+    // 校验阶段需要重新读取的字节数：复制阶段按计划估算，复制完成后按实际复制的文件计算
+    let mut verify_total: u64 = if verify {
+        plan.entries
+            .iter()
+            .filter(|e| matches!(e.action, PlanAction::Copy | PlanAction::Overwrite))
+            .map(|e| e.file_size)
+            .sum()
+    } else {
+        0
+    };
+
+    let shared = Mutex::new(SharedProgress {
+        bytes: ByteProgress::new(plan.total_size()),
+        started: 0,
+        skip_count: 0,
+        on_progress,
+    });
+    let next = AtomicUsize::new(0);
+    // 源文件夹无法访问时其他任务不再开始新的文件
+    let aborted = AtomicBool::new(false);
+    let cancelled = AtomicBool::new(false);
+    let results = Mutex::new(Vec::with_capacity(total));
 
-        // 如果目标文件已存在，添加序号
-        let final_target_path = if target_path.exists() {
-            let stem = Path::new(&new_filename)
-                .file_stem()
-                .map(|s| s.to_string_lossy().to_string())
-                .unwrap_or_default();
-            let ext = Path::new(&new_filename)
-                .extension()
-                .map(|e| e.to_string_lossy().to_string())
-                .unwrap_or_default();
-            
-            let mut file_counter = 1;
-            loop {
-                let new_name = if ext.is_empty() {
-                    format!("{}_{}", stem, file_counter)
-                } else {
-                    format!("{}_{}.{}", stem, file_counter, ext)
+    let work = || loop {
+        if aborted.load(Ordering::Relaxed) {
+            break;
+        }
+        // 检查取消标志
+        if is_cancelled() {
+            if !cancelled.swap(true, Ordering::Relaxed) {
+                let mut state = shared.lock().unwrap_or_else(PoisonError::into_inner);
+                let progress = TransferProgress {
+                    current: state.started,
+                    total,
+                    current_file: "传输已取消".to_string(),
+                    bytes_transferred: state.bytes.transferred,
+                    total_bytes: state.bytes.total + verify_total,
+                    status: TransferStatus::Cancelled,
+                    phase: TransferPhase::Cancelled,
+                    skipped_duplicates: state.skip_count,
                 };
-                let new_path = target_dir.join(&new_name);
-                if !new_path.exists() {
-                    break new_path;
-                }
-                file_counter += 1;
+                (state.on_progress)(progress);
             }
-        } else {
-            target_path
+            break;
+        }
+        let index = next.fetch_add(1, Ordering::Relaxed);
+        let Some(entry) = plan.entries.get(index) else {
+            break;
         };
 
-        // 复制文件
-        match fs::copy(&photo.path, &final_target_path) {
-            Ok(_) => {
+        // 发送进度事件
+        {
+            let mut state = shared.lock().unwrap_or_else(PoisonError::into_inner);
+            state.started += 1;
+            let progress = TransferProgress {
+                current: state.started,
+                total,
+                current_file: entry.file_name.clone(),
+                bytes_transferred: state.bytes.transferred,
+                total_bytes: state.bytes.total + verify_total,
+                status: TransferStatus::Transferring,
+                phase: TransferPhase::Transferring,
+                skipped_duplicates: state.skip_count,
+            };
+            (state.on_progress)(progress);
+        }
+
+        let outcome = process_entry(entry, copy_fn, options, &is_cancelled, &shared);
+        if outcome.source_unavailable.is_some() {
+            aborted.store(true, Ordering::Relaxed);
+        }
+        results.lock().unwrap_or_else(PoisonError::into_inner).push((index, outcome));
+    };
+    let jobs = options.jobs.max(1).min(total.max(1));
+    if jobs == 1 {
+        work();
+    } else {
+        std::thread::scope(|scope| {
+            for _ in 0..jobs {
+                scope.spawn(work);
+            }
+        });
+    }
+
+    // 按计划顺序汇总结果
+    let mut results = results.into_inner().unwrap_or_else(PoisonError::into_inner);
+    results.sort_by_key(|(index, _)| *index);
+    let mut success_count = 0;
+    let mut skip_count = 0;
+    let mut error_count = 0;
+    let mut errors = Vec::new();
+    let mut transferred_files = Vec::new();
+    let mut skipped = Vec::new();
+    let mut source_unavailable = None;
+    // 复制成功、需要校验的文件在 transferred_files 中的位置
+    let mut to_verify = Vec::new();
+    for (_, outcome) in results {
+        match outcome.file.status {
+            TransferFileStatus::Success => {
                 success_count += 1;
-                bytes_transferred += photo.file_size;
-                transferred_files.push(TransferredFile {
-                    source_path: photo.path.clone(),
-                    target_path: final_target_path.to_string_lossy().to_string(),
-                    file_size: photo.file_size,
-                    status: TransferFileStatus::Success,
-                });
+                to_verify.push(transferred_files.len());
             }
-            Err(e) => {
+            TransferFileStatus::Skipped => skip_count += 1,
+            TransferFileStatus::Error(_) => error_count += 1,
+        }
+        errors.extend(outcome.errors);
+        skipped.extend(outcome.skipped);
+        if source_unavailable.is_none() {
+            source_unavailable = outcome.source_unavailable;
+        }
+        transferred_files.push(outcome.file);
+    }
+    if cancelled.into_inner() {
+        errors.push("传输已取消".to_string());
+    }
+    let SharedProgress { bytes, mut on_progress, .. } = shared.into_inner().unwrap_or_else(PoisonError::into_inner);
+
+    // 校验阶段：重新读取已复制的文件
+    let mut verified: u64 = 0;
+    if verify {
+        verify_total = to_verify.iter().map(|&i| transferred_files[i].file_size).sum();
+    }
+    if verify && source_unavailable.is_none() && !is_cancelled() {
+        let verify_count = to_verify.len();
+        for (index, &file_index) in to_verify.iter().enumerate() {
+            if is_cancelled() {
+                errors.push("传输已取消".to_string());
+                break;
+            }
+            let file = &mut transferred_files[file_index];
+            on_progress(TransferProgress {
+                current: index + 1,
+                total: verify_count,
+                current_file: Path::new(&file.target_path)
+                    .file_name()
+                    .map(|name| name.to_string_lossy().to_string())
+                    .unwrap_or_default(),
+                bytes_transferred: bytes.transferred + verified,
+                total_bytes: bytes.total + verify_total,
+                status: TransferStatus::Verifying,
+                phase: TransferPhase::Verifying,
+                skipped_duplicates: skip_count,
+            });
+
+            if let Err(e) = verify_copy(&file.source_path, &file.target_path) {
+                success_count -= 1;
                 error_count += 1;
-                errors.push(format!("复制失败 {}: {}", photo.file_name, e));
-                transferred_files.push(TransferredFile {
-                    source_path: photo.path.clone(),
-                    target_path: final_target_path.to_string_lossy().to_string(),
-                    file_size: photo.file_size,
-                    status: TransferFileStatus::Error(e.to_string()),
-                });
+                errors.push(format!("校验失败 {}: {}", file.target_path, e));
+                file.status = TransferFileStatus::Error(format!("校验失败: {}", e));
             }
+            verified += file.file_size;
         }
     }
 
-    let final_status = if ctx.is_cancelled() { "cancelled" } else { "completed" };
+    let (final_status, final_phase, final_message) = if source_unavailable.is_some() {
+        (TransferStatus::Aborted, TransferPhase::Aborted, "源文件夹无法访问，传输已中止")
+    } else if is_cancelled() {
+        (TransferStatus::Cancelled, TransferPhase::Cancelled, "传输已取消")
+    } else {
+        (TransferStatus::Completed, TransferPhase::Completed, "传输完成")
+    };
     
     // 发送完成事件
-    let _ = ctx.app_handle.emit("transfer-progress", TransferProgress {
+    on_progress(TransferProgress {
         current: total,
         total,
-        current_file: if ctx.is_cancelled() { "传输已取消" } else { "传输完成" }.to_string(),
-        bytes_transferred,
-        total_bytes,
-        status: final_status.to_string(),
+        current_file: final_message.to_string(),
+        bytes_transferred: bytes.transferred + verified,
+        total_bytes: bytes.total + verify_total,
+        status: final_status,
+        phase: final_phase,
         skipped_duplicates: skip_count,
     });
 
-    // 保存历史记录
-    let duration = start_time.elapsed().as_secs();
-    let mut record = TransferHistory::create_record(
-        &ctx.source_dir,
-        &ctx.target_dir,
-        &ctx.template,
-    );
-    record.total_files = total;
-    record.success_count = success_count;
-    record.skip_count = skip_count;
-    record.error_count = error_count;
-    record.total_size = total_bytes;
-    record.duration_secs = duration;
-    record.files = transferred_files;
-
-    let mut history = TransferHistory::load();
-    history.add_record(record);
-    let _ = history.save();
-
-    Ok(TransferResult {
+    PlanOutcome {
         success_count,
         skip_count,
         error_count,
         errors,
-    })
+        transferred_files,
+        skipped,
+        bytes,
+        source_unavailable,
+    }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// 打包为 ZIP 时不起作用的传输选项，每个已设置的选项一条说明
+fn zip_ignored_options(ctx: &TransferContext) -> Vec<String> {
+    let mut warnings = Vec::new();
+    if ctx.verify {
+        warnings.push("打包为 ZIP 时不校验写入的文件".to_string());
+    }
+    if ctx.move_sidecars {
+        warnings.push("打包为 ZIP 时不复制 .xmp/.aae/.thm 附属文件".to_string());
+    }
+    if ctx.retry_policy != RetryPolicy::default() {
+        warnings.push("打包为 ZIP 时写入失败不重试，重试设置不起作用".to_string());
+    }
+    if ctx.jobs > 1 {
+        warnings.push("打包为 ZIP 时按顺序写入，并行任务数设置不起作用".to_string());
+    }
+    warnings
+}
 
-    #[test]
-    fn test_transfer_context_cancel() {
-        let cancel_flag = Arc::new(AtomicBool::new(false));
-        
-        // 初始状态未取消
-        assert!(!cancel_flag.load(Ordering::Relaxed));
-        
-        // 设置取消
-        cancel_flag.store(true, Ordering::Relaxed);
-        assert!(cancel_flag.load(Ordering::Relaxed));
+/// 按传输计划把文件写入 ZIP（见 ZipOutput），每个第一级目标文件夹一个压缩包，返回统计结果和创建的压缩包。
+/// 跳过和出错的计划条目与复制时相同；写入失败不重试（无法续写压缩包中的条目），
+/// fsync 为 true 时压缩包关闭后刷新到磁盘
+fn zip_plan_entries(
+    plan: &TransferPlan,
+    fsync: bool,
+    is_cancelled: impl Fn() -> bool,
+    mut on_progress: impl FnMut(TransferProgress),
+) -> (PlanOutcome, Vec<String>) {
+    let mut output = ZipOutput::new(Path::new(&plan.target_dir));
+    let mut outcome = PlanOutcome {
+        success_count: 0,
+        skip_count: 0,
+        error_count: 0,
+        errors: Vec::new(),
+        transferred_files: Vec::new(),
+        skipped: Vec::new(),
+        bytes: ByteProgress::new(plan.total_size()),
+        source_unavailable: None,
+    };
+    let total = plan.entries.len();
+    let mut cancelled = false;
+
+    for (index, entry) in plan.entries.iter().enumerate() {
+        if is_cancelled() {
+            outcome.errors.push("传输已取消".to_string());
+            cancelled = true;
+            break;
+        }
+        on_progress(TransferProgress {
+            current: index + 1,
+            total,
+            current_file: entry.file_name.clone(),
+            bytes_transferred: outcome.bytes.transferred,
+            total_bytes: outcome.bytes.total,
+            status: TransferStatus::Transferring,
+            phase: TransferPhase::Transferring,
+            skipped_duplicates: outcome.skip_count,
+        });
+
+        let record_file = |target_path: &str, file_size: u64, status: TransferFileStatus| TransferredFile {
+            source_path: entry.source_path.clone(),
+            target_path: target_path.to_string(),
+            file_size,
+            status,
+            camera: entry.camera.clone(),
+            matched_original: entry.duplicate_of.clone(),
+        };
+        match entry.action {
+            PlanAction::SkipDuplicate | PlanAction::SkipExisting => {
+                outcome.skip_count += 1;
+                outcome.bytes.add_skipped(entry.file_size);
+                if let Some(original) = &entry.duplicate_of {
+                    outcome.skipped.push(SkippedFile {
+                        source: entry.source_path.clone(),
+                        matched_original: original.clone(),
+                    });
+                }
+                outcome.transferred_files.push(record_file(&entry.target_path, entry.file_size, TransferFileStatus::Skipped));
+            }
+            PlanAction::Error => {
+                let message = entry.error.clone().unwrap_or_default();
+                outcome.error_count += 1;
+                outcome.errors.push(format!("{} ({})", message, entry.file_name));
+                outcome.transferred_files.push(record_file(&entry.target_path, entry.file_size, TransferFileStatus::Error(message)));
+            }
+            PlanAction::Copy | PlanAction::Overwrite => {
+                match output.add_file(Path::new(&entry.source_path), Path::new(&entry.target_path)) {
+                    Ok((archive, name, written)) => {
+                        outcome.success_count += 1;
+                        outcome.bytes.add_copied(entry.file_size, written);
+                        let target = format!("{}/{}", archive.to_string_lossy(), name);
+                        outcome.transferred_files.push(record_file(&target, written, TransferFileStatus::Success));
+                    }
+                    Err(e) => {
+                        outcome.error_count += 1;
+                        outcome.errors.push(format!("写入压缩包失败 {}: {}", entry.file_name, e));
+                        outcome.transferred_files.push(record_file(&entry.target_path, entry.file_size, TransferFileStatus::Error(e)));
+                    }
+                }
+            }
+        }
+    }
+
+    let (archives, mut finish_errors) = output.finish();
+    if fsync {
+        for archive in &archives {
+            if let Err(e) = sync_to_disk(archive) {
+                finish_errors.push(format!("写入磁盘失败 {}: {}", archive.display(), e));
+            }
+        }
+    }
+    outcome.errors.append(&mut finish_errors);
+
+    let (status, phase, message) = if cancelled {
+        (TransferStatus::Cancelled, TransferPhase::Cancelled, "传输已取消")
+    } else {
+        (TransferStatus::Completed, TransferPhase::Completed, "传输完成")
+    };
+    on_progress(TransferProgress {
+        current: total,
+        total,
+        current_file: message.to_string(),
+        bytes_transferred: outcome.bytes.transferred,
+        total_bytes: outcome.bytes.total,
+        status,
+        phase,
+        skipped_duplicates: outcome.skip_count,
+    });
+    let archives = archives.iter().map(|p| p.to_string_lossy().to_string()).collect();
+    (outcome, archives)
+}
+
+/// 按传输计划复制文件，报告进度并写入传输历史
+pub fn execute_plan(ctx: &TransferContext, sink: &dyn ProgressSink, plan: &TransferPlan) -> Result<TransferResult, String> {
+    let (mut result, record) = run_plan(ctx, sink, plan);
+    record_history(&mut result, record, &ctx.history_path);
+    Ok(result)
+}
+
+/// 按传输计划复制文件并报告进度，返回传输结果和对应的历史记录（不写入传输历史）
+pub fn run_plan(ctx: &TransferContext, sink: &dyn ProgressSink, plan: &TransferPlan) -> (TransferResult, TransferRecord) {
+    let start_time = Instant::now();
+    // 只导出预览时不复制 RAW
+    let raw_excluded;
+    let plan_to_copy = if ctx.raw_preview == RawPreviewMode::Only {
+        raw_excluded = TransferPlan {
+            target_dir: plan.target_dir.clone(),
+            entries: plan.entries.iter().filter(|e| !is_preview_entry(e)).cloned().collect(),
+        };
+        &raw_excluded
+    } else {
+        plan
+    };
+    // 在复制（创建目录）之前记录每个目标文件夹是否已存在；打包为 ZIP 时不创建目标文件夹
+    let folders_existed = if ctx.output_mode == OutputMode::Files {
+        target_folders_existed(plan_to_copy)
+    } else {
+        BTreeMap::new()
+    };
+    let on_progress = |progress| ctx.report_progress(sink, progress);
+    let (mut outcome, archives) = match ctx.output_mode {
+        OutputMode::Files => {
+            let copy_fn = if ctx.fsync { with_fsync(ctx.copy_fn.clone()) } else { ctx.copy_fn.clone() };
+            let outcome = copy_plan_entries(plan_to_copy, &copy_fn, &CopyOptions::from_context(ctx), || ctx.is_cancelled(), on_progress);
+            (outcome, Vec::new())
+        }
+        OutputMode::Zip => zip_plan_entries(plan_to_copy, ctx.fsync, || ctx.is_cancelled(), on_progress),
+    };
+    if let Some(message) = &outcome.source_unavailable {
+        sink.source_unavailable(message);
+    }
+    // 按相机分配目标文件夹时，每个目标根目录分别导出到各自的预览文件夹
+    let mut previews = PreviewExport::default();
+    if ctx.raw_preview != RawPreviewMode::Off {
+        let mut by_root: Vec<(&str, Vec<PlanEntry>)> = Vec::new();
+        for entry in &plan.entries {
+            let root = entry_root(ctx, plan, entry);
+            match by_root.iter_mut().find(|(dir, _)| *dir == root) {
+                Some((_, entries)) => entries.push(entry.clone()),
+                None => by_root.push((root, vec![entry.clone()])),
+            }
+        }
+        for (root, entries) in by_root {
+            let target_dir = Path::new(root);
+            let preview_dir = preview_root(target_dir, ctx.preview_dir.as_deref());
+            let export = export_raw_previews(&entries, target_dir, &preview_dir, extract_largest_preview, || ctx.is_cancelled());
+            previews.exported.extend(export.exported);
+            previews.notes.extend(export.notes);
+            previews.errors.extend(export.errors);
+            previews.files.extend(export.files);
+        }
+    }
+    // 只导出预览时 RAW 没有复制，以预览的导出结果作为 RAW 的记录
+    let mut total_files = plan_to_copy.entries.len();
+    if ctx.raw_preview == RawPreviewMode::Only {
+        total_files += previews.files.len();
+        for file in &previews.files {
+            match file.status {
+                TransferFileStatus::Success => outcome.success_count += 1,
+                TransferFileStatus::Skipped => outcome.skip_count += 1,
+                TransferFileStatus::Error(_) => outcome.error_count += 1,
+            }
+        }
+        outcome.transferred_files.append(&mut previews.files);
+    }
+    record_progress(&ctx.progress, None);
+
+    // 保存历史记录
+    let duration = start_time.elapsed().as_secs();
+    let mut record = TransferHistory::create_record(
+        &ctx.source_dir,
+        &ctx.target_dir,
+        &ctx.template,
+    );
+    record.total_files = total_files;
+    record.success_count = outcome.success_count;
+    record.skip_count = outcome.skip_count;
+    record.error_count = outcome.error_count;
+    record.total_size = outcome.bytes.total;
+    record.duration_secs = duration;
+    record.files = outcome.transferred_files;
+    let mut errors = outcome.errors;
+    errors.extend(previews.errors);
+    let mut warnings = if ctx.output_mode == OutputMode::Zip { zip_ignored_options(ctx) } else { Vec::new() };
+    warnings.extend(previews.notes);
+    record.errors = errors.clone();
+    record.retry_of = ctx.retry_of.clone();
+    record.archives = archives;
+    let files = result_files(&record.files, ctx.max_result_files);
+    let (new_folders, merged_folders) = split_new_folders(&folders_existed, &record.files);
+
+    let result = TransferResult {
+        success_count: outcome.success_count,
+        skip_count: outcome.skip_count,
+        error_count: outcome.error_count,
+        errors,
+        warnings,
+        skipped: outcome.skipped,
+        files,
+        previews: previews.exported.len(),
+        new_folders,
+        merged_folders,
+    };
+    (result, record)
+}
+
+/// 计划中需要写入文件的目标文件夹 -> 传输前是否已存在
+fn target_folders_existed(plan: &TransferPlan) -> BTreeMap<PathBuf, bool> {
+    plan.entries
+        .iter()
+        .filter(|e| matches!(e.action, PlanAction::Copy | PlanAction::Overwrite))
+        .filter_map(|e| Path::new(&e.target_path).parent())
+        .map(|folder| (folder.to_path_buf(), folder.is_dir()))
+        .collect()
+}
+
+/// 按传输前是否已存在，把成功写入了文件的目标文件夹分为新建的和合并的（均已排序）
+fn split_new_folders(existed: &BTreeMap<PathBuf, bool>, files: &[TransferredFile]) -> (Vec<String>, Vec<String>) {
+    let written: HashSet<&Path> = files
+        .iter()
+        .filter(|f| f.status == TransferFileStatus::Success)
+        .filter_map(|f| Path::new(&f.target_path).parent())
+        .collect();
+    let mut new_folders = Vec::new();
+    let mut merged_folders = Vec::new();
+    for (folder, &was_there) in existed {
+        if !written.contains(folder.as_path()) {
+            continue;
+        }
+        let folder = folder.to_string_lossy().to_string();
+        if was_there {
+            merged_folders.push(folder);
+        } else {
+            new_folders.push(folder);
+        }
+    }
+    (new_folders, merged_folders)
+}
+
+/// 原地重命名时第一步使用的临时文件名后缀
+const RENAME_TEMP_SUFFIX: &str = ".photo-truck-renaming";
+
+/// 原地重命名中的一个文件
+struct InPlaceRename {
+    source: PathBuf,
+    target: PathBuf,
+    file_size: u64,
+    camera: Option<String>,
+    /// 随照片一起改名的附属文件（原路径，新路径）
+    sidecars: Vec<(PathBuf, PathBuf)>,
+}
+
+/// 原地重命名第一步使用的临时路径
+fn rename_temp_path(path: &Path) -> PathBuf {
+    let mut counter: u32 = 0;
+    loop {
+        let mut temp = path.to_path_buf().into_os_string();
+        temp.push(RENAME_TEMP_SUFFIX);
+        if counter > 0 {
+            temp.push(format!("-{}", counter));
+        }
+        let temp = PathBuf::from(temp);
+        if fs::symlink_metadata(&temp).is_err() {
+            return temp;
+        }
+        counter = counter.saturating_add(1);
+    }
+}
+
+/// 改名但不覆盖已有的文件（fs::rename 在 Unix 上会直接替换目标）
+fn rename_no_clobber(from: &Path, to: &Path) -> std::io::Result<()> {
+    if fs::symlink_metadata(to).is_ok() {
+        return Err(std::io::Error::new(std::io::ErrorKind::AlreadyExists, "目标文件已存在"));
+    }
+    fs::rename(from, to)
+}
+
+/// 临时文件名对应的原路径：文件名为 `<原文件名>.photo-truck-renaming` 或再加上 `-序号` 时返回 Some
+fn rename_temp_origin(path: &Path) -> Option<PathBuf> {
+    let name = path.file_name()?.to_str()?;
+    let (original, rest) = name.rsplit_once(RENAME_TEMP_SUFFIX)?;
+    let is_counter = rest.strip_prefix('-').is_some_and(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()));
+    (!original.is_empty() && (rest.is_empty() || is_counter)).then(|| path.with_file_name(original))
+}
+
+/// 恢复上次原地重命名中断时遗留的临时文件，原文件名已被占用的保留不动；返回说明
+fn recover_rename_leftovers(target_dir: &Path) -> Vec<String> {
+    let mut notes = Vec::new();
+    for entry in WalkDir::new(target_dir).into_iter().filter_map(|e| e.ok()) {
+        let temp = entry.path();
+        let Some(original) = rename_temp_origin(temp).filter(|_| entry.file_type().is_file()) else {
+            continue;
+        };
+        match rename_no_clobber(temp, &original) {
+            Ok(()) => notes.push(format!("已恢复上次重命名中断时遗留的文件: {}", original.display())),
+            Err(e) => notes.push(format!("上次重命名中断时遗留的临时文件未恢复 {}: {}", temp.display(), e)),
+        }
+    }
+    notes
+}
+
+/// 文件夹中不冲突的文件名：is_taken 返回 true 时在扩展名之前添加 _1、_2……
+fn unclaimed_name(file_name: &str, is_taken: impl Fn(&str) -> bool) -> String {
+    if !is_taken(file_name) {
+        return file_name.to_string();
+    }
+    let path = Path::new(file_name);
+    let stem = path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+    let ext = path.extension().map(|e| e.to_string_lossy().to_string());
+    let mut counter: u32 = 1;
+    loop {
+        let name = match &ext {
+            Some(ext) => format!("{}_{}.{}", stem, counter, ext),
+            None => format!("{}_{}", stem, counter),
+        };
+        if !is_taken(&name) {
+            return name;
+        }
+        counter = counter.saturating_add(1);
+    }
+}
+
+/// 为一个文件夹中的照片生成新文件名。计数器从 counter_start 开始，照片按拍摄时间（没有拍摄时间的排在最后）、
+/// 路径排序；新文件名避开文件夹中不参与重命名的文件和已分配给其他照片的文件名。
+/// 同名的 .xmp/.aae/.thm 附属文件随照片一起改名（多张照片共用的附属文件只跟随排在最前的照片）
+fn plan_folder_renames(folder: &Path, photos: &[PathBuf], rename_config: &RenameConfig) -> Vec<InPlaceRename> {
+    let key = |name: &str| to_nfc(name).to_lowercase();
+    let sidecars: HashMap<&Path, Vec<PathBuf>> = photos.iter().map(|p| (p.as_path(), find_sidecars(p))).collect();
+    let renamed: HashSet<&Path> = photos
+        .iter()
+        .map(PathBuf::as_path)
+        .chain(sidecars.values().flatten().map(PathBuf::as_path))
+        .collect();
+    let mut taken: HashSet<String> = fs::read_dir(folder)
+        .map(|entries| {
+            entries
+                .filter_map(|e| e.ok())
+                .filter(|e| !renamed.contains(e.path().as_path()))
+                .map(|e| key(&e.file_name().to_string_lossy()))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let mut photos: Vec<_> = photos
+        .iter()
+        .map(|path| {
+            let path_str = path.to_string_lossy().to_string();
+            let metadata = read_exif(&path_str).unwrap_or_else(|_| metadata_from_file(&path_str));
+            (path, metadata)
+        })
+        .collect();
+    photos.sort_by(|(a_path, a), (b_path, b)| {
+        let a_date = a.date_time_original.as_ref().or(a.create_date.as_ref());
+        let b_date = b.date_time_original.as_ref().or(b.create_date.as_ref());
+        a_date.is_none()
+            .cmp(&b_date.is_none())
+            .then_with(|| a_date.cmp(&b_date))
+            .then_with(|| a_path.cmp(b_path))
+    });
+
+    let mut counter = rename_config.counter_start;
+    let mut paired_names: HashMap<(PathBuf, String), String> = HashMap::new();
+    let mut claimed_sidecars: HashSet<&Path> = HashSet::new();
+    let mut renames = Vec::with_capacity(photos.len());
+    for (path, metadata) in photos {
+        let photo_sidecars: Vec<&PathBuf> = sidecars[path.as_path()]
+            .iter()
+            .filter(|s| claimed_sidecars.insert(s.as_path()))
+            .collect();
+        let sidecar_targets = |name: &str| -> Vec<PathBuf> {
+            photo_sidecars.iter().map(|s| sidecar_target_path(s, path, &folder.join(name))).collect()
+        };
+        let file_key = |target: &Path| key(&target.file_name().unwrap_or_default().to_string_lossy());
+        let path_str = path.to_string_lossy().to_string();
+        let pair_key = rename_config.pair_by_basename.then(|| basename_key(&path_str));
+        let (name, pair_origin) = match pair_key.as_ref().and_then(|key| paired_names.get(key)) {
+            Some(partner) => (paired_file_name(partner, &metadata.file_name, rename_config), None),
+            None => {
+                let name = rename_config.generate_filename(&metadata, counter);
+                counter = counter.saturating_add(1);
+                (name, pair_key)
+            }
+        };
+        let name = unclaimed_name(&name, |candidate| {
+            taken.contains(&key(candidate)) || sidecar_targets(candidate).iter().any(|t| taken.contains(&file_key(t)))
+        });
+        // 同名文件沿用添加序号后的文件名，保持成对
+        if let Some(key) = pair_origin {
+            paired_names.insert(key, name.clone());
+        }
+        let sidecar_targets = sidecar_targets(&name);
+        taken.insert(key(&name));
+        taken.extend(sidecar_targets.iter().map(|t| file_key(t)));
+        renames.push(InPlaceRename {
+            source: path.clone(),
+            target: folder.join(name),
+            file_size: metadata.file_size,
+            camera: metadata.model.clone(),
+            sidecars: photo_sidecars.into_iter().cloned().zip(sidecar_targets).collect(),
+        });
+    }
+    renames
+}
+
+/// 在已整理好的目标文件夹中原地重命名照片：读取 EXIF，按 rename_config 的模板生成新文件名（不论是否启用重命名），
+/// 文件只在所在文件夹内改名，不会移动到其他文件夹，每个文件夹的计数器和冲突处理各自独立（见 plan_folder_renames）。
+/// 先把要改名的文件（含附属文件）全部改为临时文件名，再改为新文件名，文件名互换时不会互相覆盖；
+/// 改名失败的文件恢复原名。新文件名与原文件名相同的照片计为跳过。结果写入一条 Rename 类型的历史记录
+pub fn rename_in_place(
+    target_dir: &Path,
+    rename_config: &RenameConfig,
+    history_path: &Path,
+    is_cancelled: impl Fn() -> bool,
+) -> Result<TransferResult, String> {
+    if !target_dir.is_dir() {
+        return Err(format!("目标文件夹不存在: {}", target_dir.display()));
+    }
+    let start_time = Instant::now();
+    let rename_config = RenameConfig {
+        enabled: true,
+        ..rename_config.clone()
+    };
+    let warnings = recover_rename_leftovers(target_dir);
+
+    let mut folders: BTreeMap<PathBuf, Vec<PathBuf>> = BTreeMap::new();
+    for entry in WalkDir::new(target_dir).into_iter().filter_map(|e| e.ok()) {
+        let path = entry.path();
+        if entry.file_type().is_file() && is_supported_photo(&path.to_string_lossy()) {
+            let folder = path.parent().unwrap_or(target_dir).to_path_buf();
+            folders.entry(folder).or_default().push(path.to_path_buf());
+        }
+    }
+
+    let mut renames = Vec::new();
+    for (folder, photos) in &folders {
+        if is_cancelled() {
+            return Err("重命名已取消".to_string());
+        }
+        renames.extend(plan_folder_renames(folder, photos, &rename_config));
+    }
+
+    let mut files = Vec::with_capacity(renames.len());
+    let mut errors = Vec::new();
+    let record_file = |rename: &InPlaceRename, target: &Path, status| TransferredFile {
+        source_path: rename.source.to_string_lossy().to_string(),
+        target_path: target.to_string_lossy().to_string(),
+        file_size: rename.file_size,
+        status,
+        camera: rename.camera.clone(),
+        matched_original: None,
+    };
+
+    // 第一步：改为临时文件名
+    let mut pending = Vec::new();
+    for rename in &renames {
+        if rename.source == rename.target {
+            files.push(record_file(rename, &rename.target, TransferFileStatus::Skipped));
+            continue;
+        }
+        let temp = rename_temp_path(&rename.source);
+        match rename_no_clobber(&rename.source, &temp) {
+            Ok(()) => {
+                let mut sidecars = Vec::new();
+                for (source, target) in &rename.sidecars {
+                    let sidecar_temp = rename_temp_path(source);
+                    match rename_no_clobber(source, &sidecar_temp) {
+                        Ok(()) => sidecars.push((source, target, sidecar_temp)),
+                        Err(e) => errors.push(format!("重命名附属文件失败 {}: {}", source.display(), e)),
+                    }
+                }
+                pending.push((rename, temp, sidecars));
+            }
+            Err(e) => {
+                let message = format!("重命名失败 {}: {}", rename.source.display(), e);
+                files.push(record_file(rename, &rename.source, TransferFileStatus::Error(message.clone())));
+                errors.push(message);
+            }
+        }
+    }
+
+    // 第二步：改为新文件名，失败时恢复原名
+    for (rename, temp, sidecars) in pending {
+        let result = rename_no_clobber(&temp, &rename.target)
+            .map_err(|e| format!("重命名失败 {} -> {}: {}", rename.source.display(), rename.target.display(), e));
+        let restore = |temp: &Path, source: &Path, errors: &mut Vec<String>| {
+            if let Err(e) = rename_no_clobber(temp, source) {
+                errors.push(format!("恢复原文件名失败 {}: {}", temp.display(), e));
+            }
+        };
+        match result {
+            Ok(()) => {
+                for (source, target, sidecar_temp) in sidecars {
+                    let renamed = rename_no_clobber(&sidecar_temp, target)
+                        .map_err(|e| format!("重命名附属文件失败 {} -> {}: {}", source.display(), target.display(), e));
+                    if let Err(message) = renamed {
+                        restore(&sidecar_temp, source, &mut errors);
+                        errors.push(message);
+                    }
+                }
+                files.push(record_file(rename, &rename.target, TransferFileStatus::Success));
+            }
+            Err(message) => {
+                restore(&temp, &rename.source, &mut errors);
+                for (source, _, sidecar_temp) in sidecars {
+                    restore(&sidecar_temp, source, &mut errors);
+                }
+                files.push(record_file(rename, &rename.source, TransferFileStatus::Error(message.clone())));
+                errors.push(message);
+            }
+        }
+    }
+
+    let count = |wanted: fn(&TransferFileStatus) -> bool| files.iter().filter(|f| wanted(&f.status)).count();
+    let success_count = count(|s| *s == TransferFileStatus::Success);
+    let skip_count = count(|s| *s == TransferFileStatus::Skipped);
+    let error_count = count(|s| matches!(s, TransferFileStatus::Error(_)));
+
+    let target = target_dir.to_string_lossy();
+    let mut record = TransferHistory::create_record(&target, &target, &rename_config.template);
+    record.kind = RecordKind::Rename;
+    record.total_files = renames.len();
+    record.success_count = success_count;
+    record.skip_count = skip_count;
+    record.error_count = error_count;
+    record.total_size = renames.iter().map(|r| r.file_size).sum();
+    record.duration_secs = start_time.elapsed().as_secs();
+    record.errors = errors.clone();
+    record.files = files;
+
+    let mut result = TransferResult {
+        success_count,
+        skip_count,
+        error_count,
+        errors,
+        warnings,
+        skipped: Vec::new(),
+        files: result_files(&record.files, MAX_RESULT_FILES),
+        previews: 0,
+        new_folders: Vec::new(),
+        merged_folders: Vec::new(),
+    };
+    record_history(&mut result, record, history_path);
+    Ok(result)
+}
+
+/// TransferResult 中最多直接返回的文件明细数，超过时只保存在历史记录中
+pub const MAX_RESULT_FILES: usize = 5000;
+
+/// 返回给前端的文件明细，超过 limit 时为 None
+fn result_files(files: &[TransferredFile], limit: usize) -> Option<Vec<TransferredFile>> {
+    (files.len() <= limit).then(|| files.to_vec())
+}
+
+/// 写入传输历史；保存失败不影响传输结果，只作为警告返回
+fn record_history(result: &mut TransferResult, record: TransferRecord, history_path: &Path) {
+    let mut history = TransferHistory::load_from(history_path);
+    history.add_record(record);
+    if let Err(e) = history.save_to(history_path) {
+        log::debug!("保存传输历史失败 {}: {}", history_path.display(), e);
+        result.warnings.push(format!("传输历史记录未保存: {}", e));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transfer::PlanEntry;
+    use std::sync::atomic::AtomicUsize;
+
+    fn fs_copy() -> CopyFn {
+        Arc::new(|source, target| fs::copy(source, target))
+    }
+
+    fn copy_entry(source: &Path, target: &Path, size: u64) -> PlanEntry {
+        PlanEntry {
+            source_path: source.to_string_lossy().to_string(),
+            file_name: source.file_name().unwrap().to_string_lossy().to_string(),
+            file_size: size,
+            camera: None,
+            target_path: target.to_string_lossy().to_string(),
+            action: PlanAction::Copy,
+            duplicate_of: None,
+            error: None,
+        }
+    }
+
+    #[test]
+    fn test_copy_plan_entries_serial_and_parallel() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let source_dir = dir.path().join("card");
+        fs::create_dir_all(&source_dir).unwrap();
+        let policy = RetryPolicy { max_attempts: 1, base_delay_ms: 1 };
+
+        for jobs in [1, 3] {
+            let target_dir = dir.path().join(format!("target-{}", jobs));
+            let mut entries = Vec::new();
+            for i in 0..8 {
+                let source = source_dir.join(format!("{}.jpg", i));
+                fs::write(&source, format!("content {}", i)).unwrap();
+                entries.push(copy_entry(&source, &target_dir.join(format!("{}.jpg", i)), 9));
+            }
+            // 源文件所在的文件夹仍可访问，只有这一个文件失败，不中止传输
+            entries.insert(4, copy_entry(&source_dir.join("missing.jpg"), &target_dir.join("missing.jpg"), 1));
+            let plan = TransferPlan {
+                target_dir: target_dir.to_string_lossy().to_string(),
+                entries,
+            };
+
+            let mut events = Vec::new();
+            let options = CopyOptions { retry_policy: policy, jobs, ..Default::default() };
+            let outcome = copy_plan_entries(&plan, &fs_copy(), &options, || false, |p| events.push(p));
+            assert_eq!((outcome.success_count, outcome.error_count), (8, 1), "jobs = {}", jobs);
+            assert!(outcome.source_unavailable.is_none());
+            // 结果按计划顺序记录，与复制完成的先后无关
+            let sources: Vec<&str> = outcome.transferred_files.iter().map(|f| f.source_path.as_str()).collect();
+            let planned: Vec<&str> = plan.entries.iter().map(|e| e.source_path.as_str()).collect();
+            assert_eq!(sources, planned);
+            assert!(matches!(outcome.transferred_files[4].status, TransferFileStatus::Error(_)));
+            assert_eq!(outcome.bytes.transferred, 72);
+            assert_eq!(fs::read_to_string(target_dir.join("7.jpg")).unwrap(), "content 7");
+            // 每个文件开始时一个进度事件，最后一个为完成事件
+            assert_eq!(events.len(), plan.entries.len() + 1);
+            assert_eq!(events.last().unwrap().phase, TransferPhase::Completed);
+        }
+    }
+
+    #[test]
+    fn test_copy_plan_entries_with_fsync() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let target_dir = dir.path().join("target");
+        let source = dir.path().join("IMG_0001.JPG");
+        fs::write(&source, b"12345").unwrap();
+        let target = target_dir.join("2024").join("IMG_0001.JPG");
+        let plan = TransferPlan {
+            target_dir: target_dir.to_string_lossy().to_string(),
+            entries: vec![copy_entry(&source, &target, 5)],
+        };
+
+        let policy = RetryPolicy { max_attempts: 1, base_delay_ms: 1 };
+        let outcome = copy_plan_entries(&plan, &with_fsync(fs_copy()), &CopyOptions { retry_policy: policy, ..Default::default() }, || false, |_| {});
+
+        assert_eq!((outcome.success_count, outcome.error_count), (1, 0), "{:?}", outcome.errors);
+        assert_eq!(fs::read(&target).unwrap(), b"12345");
+        assert!(sync_to_disk(&dir.path().join("missing.jpg")).is_err());
+    }
+
+    #[test]
+    fn test_zip_plan_entries() {
+        use std::io::Read;
+        let dir = tempfile::TempDir::new().unwrap();
+        let target_dir = dir.path().join("target");
+        let a = dir.path().join("IMG_0001.JPG");
+        let b = dir.path().join("IMG_0002.JPG");
+        let dup = dir.path().join("IMG_0003.JPG");
+        fs::write(&a, b"aaaa").unwrap();
+        fs::write(&b, b"bb").unwrap();
+        fs::write(&dup, b"aaaa").unwrap();
+        let mut skipped = copy_entry(&dup, &target_dir.join("2024-03").join("IMG_0003.JPG"), 4);
+        skipped.action = PlanAction::SkipDuplicate;
+        skipped.duplicate_of = Some(a.to_string_lossy().to_string());
+        let plan = TransferPlan {
+            target_dir: target_dir.to_string_lossy().to_string(),
+            entries: vec![
+                copy_entry(&a, &target_dir.join("2024-03").join("IMG_0001.JPG"), 4),
+                copy_entry(&b, &target_dir.join("2024-04").join("raw").join("IMG_0002.JPG"), 2),
+                skipped,
+            ],
+        };
+
+        let mut events = Vec::new();
+        let (outcome, archives) = zip_plan_entries(&plan, false, || false, |p| events.push(p));
+
+        assert_eq!((outcome.success_count, outcome.skip_count, outcome.error_count), (2, 1, 0), "{:?}", outcome.errors);
+        let march = target_dir.join("2024-03.zip");
+        let april = target_dir.join("2024-04.zip");
+        assert_eq!(archives, [march.to_string_lossy(), april.to_string_lossy()]);
+        assert_eq!(outcome.transferred_files[0].target_path, format!("{}/IMG_0001.JPG", march.display()));
+        assert_eq!(outcome.bytes, ByteProgress { transferred: 10, total: 10 });
+        // 不创建单独的文件夹
+        assert!(!target_dir.join("2024-03").exists());
+
+        let zip = zip::ZipArchive::new(fs::File::open(&march).unwrap()).unwrap();
+        assert_eq!(zip.file_names().collect::<Vec<_>>(), ["IMG_0001.JPG"]);
+        let mut zip = zip::ZipArchive::new(fs::File::open(&april).unwrap()).unwrap();
+        let mut content = Vec::new();
+        zip.by_name("raw/IMG_0002.JPG").unwrap().read_to_end(&mut content).unwrap();
+        assert_eq!(content, b"bb");
+        assert_eq!(events.last().unwrap().status, TransferStatus::Completed);
+    }
+
+    #[test]
+    fn test_copy_plan_entries_with_failing_copier() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let target_dir = dir.path().join("target");
+        let good = dir.path().join("IMG_0001.JPG");
+        let bad = dir.path().join("IMG_0002.JPG");
+        fs::write(&good, b"12345").unwrap();
+        fs::write(&bad, b"1234567").unwrap();
+        let plan = TransferPlan {
+            target_dir: target_dir.to_string_lossy().to_string(),
+            entries: vec![
+                copy_entry(&good, &target_dir.join("IMG_0001.JPG"), 5),
+                copy_entry(&bad, &target_dir.join("IMG_0002.JPG"), 7),
+            ],
+        };
+
+        // IMG_0002 始终复制失败，其他文件正常复制
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let copier: CopyFn = Arc::new(move |source, target| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            if source.ends_with("IMG_0002.JPG") {
+                return Err(std::io::Error::new(std::io::ErrorKind::NotConnected, "磁盘已断开"));
+            }
+            fs::copy(source, target)
+        });
+        let policy = RetryPolicy { max_attempts: 2, base_delay_ms: 1 };
+        let mut events = Vec::new();
+        let outcome = copy_plan_entries(&plan, &copier, &CopyOptions { retry_policy: policy, ..Default::default() }, || false, |p| events.push(p));
+
+        assert_eq!((outcome.success_count, outcome.skip_count, outcome.error_count), (1, 0, 1));
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+        assert_eq!(outcome.errors, ["复制失败 IMG_0002.JPG: 重试 1 次后仍失败: 磁盘已断开"]);
+        assert_eq!(
+            outcome.transferred_files[1].status,
+            TransferFileStatus::Error("重试 1 次后仍失败: 磁盘已断开".to_string())
+        );
+        assert!(target_dir.join("IMG_0001.JPG").exists());
+        assert!(!target_dir.join("IMG_0002.JPG").exists());
+
+        // 失败的文件不计入已传输字节
+        assert_eq!(outcome.bytes, ByteProgress { transferred: 5, total: 12 });
+        let last = events.last().unwrap();
+        assert_eq!(last.status, TransferStatus::Completed);
+        assert_eq!((last.current, last.total, last.bytes_transferred), (2, 2, 5));
+    }
+
+    #[test]
+    fn test_copy_plan_entries_moves_sidecars() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let source_dir = dir.path().join("DCIM");
+        let target_dir = dir.path().join("target");
+        fs::create_dir_all(&source_dir).unwrap();
+        let raw = source_dir.join("IMG.CR3");
+        fs::write(&raw, b"raw").unwrap();
+        fs::write(source_dir.join("IMG.xmp"), b"<xmp/>").unwrap();
+
+        // 按重命名后的文件名复制附属文件
+        let renamed = target_dir.join("2024").join("20240315_0001.CR3");
+        let plan = TransferPlan {
+            target_dir: target_dir.to_string_lossy().to_string(),
+            entries: vec![copy_entry(&raw, &renamed, 3)],
+        };
+        let policy = RetryPolicy::default();
+        let outcome = copy_plan_entries(&plan, &fs_copy(), &CopyOptions { retry_policy: policy, move_sidecars: true, ..Default::default() }, || false, |_| {});
+
+        assert_eq!((outcome.success_count, outcome.error_count), (1, 0));
+        assert!(outcome.errors.is_empty());
+        assert!(renamed.exists());
+        let sidecar = target_dir.join("2024").join("20240315_0001.xmp");
+        assert_eq!(fs::read(sidecar).unwrap(), b"<xmp/>");
+
+        // 未开启时只复制照片
+        let other = dir.path().join("other");
+        let plan = TransferPlan {
+            target_dir: other.to_string_lossy().to_string(),
+            entries: vec![copy_entry(&raw, &other.join("IMG.CR3"), 3)],
+        };
+        copy_plan_entries(&plan, &fs_copy(), &CopyOptions { retry_policy: policy, ..Default::default() }, || false, |_| {});
+        assert!(other.join("IMG.CR3").exists());
+        assert!(!other.join("IMG.xmp").exists());
+    }
+
+    #[test]
+    fn test_copy_plan_entries_aborts_when_source_unavailable() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let card = dir.path().join("card");
+        let target_dir = dir.path().join("target");
+        fs::create_dir_all(&card).unwrap();
+        let entries = (1..=4)
+            .map(|i| {
+                let source = card.join(format!("IMG_000{}.JPG", i));
+                fs::write(&source, b"photo").unwrap();
+                copy_entry(&source, &target_dir.join(format!("IMG_000{}.JPG", i)), 5)
+            })
+            .collect();
+        let plan = TransferPlan {
+            target_dir: target_dir.to_string_lossy().to_string(),
+            entries,
+        };
+
+        // 复制第 2 个文件时存储卡被拔出
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let card_dir = card.clone();
+        let copier: CopyFn = Arc::new(move |source, target| {
+            if counter.fetch_add(1, Ordering::SeqCst) == 1 {
+                let _ = fs::remove_dir_all(&card_dir);
+            }
+            fs::copy(source, target)
+        });
+        let policy = RetryPolicy { max_attempts: 2, base_delay_ms: 1 };
+        let mut events = Vec::new();
+        let outcome = copy_plan_entries(&plan, &copier, &CopyOptions { retry_policy: policy, ..Default::default() }, || false, |p| events.push(p));
+
+        // 不再尝试后续文件
+        assert_eq!((outcome.success_count, outcome.error_count), (1, 1));
+        assert_eq!(outcome.transferred_files.len(), 2);
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+        assert!(outcome.source_unavailable.as_deref().unwrap().contains("源文件夹无法访问"));
+        assert_eq!(outcome.errors.len(), 2);
+        let last = events.last().unwrap();
+        assert_eq!((last.status, last.phase), (TransferStatus::Aborted, TransferPhase::Aborted));
+    }
+
+    #[test]
+    fn test_copy_plan_entries_continues_after_single_missing_file() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let target_dir = dir.path().join("target");
+        let missing = dir.path().join("IMG_0001.JPG");
+        let present = dir.path().join("IMG_0002.JPG");
+        fs::write(&present, b"photo").unwrap();
+        let plan = TransferPlan {
+            target_dir: target_dir.to_string_lossy().to_string(),
+            entries: vec![
+                copy_entry(&missing, &target_dir.join("IMG_0001.JPG"), 5),
+                copy_entry(&present, &target_dir.join("IMG_0002.JPG"), 5),
+            ],
+        };
+
+        // 只是单个文件被删除，文件夹仍可访问，继续传输其他文件
+        let policy = RetryPolicy { max_attempts: 1, base_delay_ms: 1 };
+        let mut last_status = None;
+        let outcome = copy_plan_entries(&plan, &fs_copy(), &CopyOptions { retry_policy: policy, ..Default::default() }, || false, |p| last_status = Some(p.status));
+        assert_eq!((outcome.success_count, outcome.error_count), (1, 1));
+        assert!(outcome.source_unavailable.is_none());
+        assert_eq!(last_status, Some(TransferStatus::Completed));
+    }
+
+    #[test]
+    fn test_copy_plan_entries_verify_phase_progress() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let target_dir = dir.path().join("target");
+        let first = dir.path().join("IMG_0001.JPG");
+        let second = dir.path().join("IMG_0002.JPG");
+        fs::write(&first, b"12345").unwrap();
+        fs::write(&second, b"1234567").unwrap();
+        let plan = TransferPlan {
+            target_dir: target_dir.to_string_lossy().to_string(),
+            entries: vec![
+                copy_entry(&first, &target_dir.join("IMG_0001.JPG"), 5),
+                copy_entry(&second, &target_dir.join("IMG_0002.JPG"), 7),
+            ],
+        };
+        let policy = RetryPolicy { max_attempts: 1, base_delay_ms: 1 };
+
+        let mut events = Vec::new();
+        let outcome = copy_plan_entries(&plan, &fs_copy(), &CopyOptions { retry_policy: policy, verify: true, ..Default::default() }, || false, |p| events.push(p));
+        assert_eq!((outcome.success_count, outcome.error_count), (2, 0));
+
+        // 复制阶段的总字节数包含校验阶段，复制完成时进度约为一半
+        let copying: Vec<_> = events.iter().filter(|p| p.phase == TransferPhase::Transferring).collect();
+        assert!(copying.iter().all(|p| p.total_bytes == 24));
+        let verifying: Vec<_> = events.iter().filter(|p| p.phase == TransferPhase::Verifying).collect();
+        assert_eq!(verifying.len(), 2);
+        assert_eq!(verifying[0].status, TransferStatus::Verifying);
+        assert_eq!((verifying[0].current, verifying[0].total, verifying[0].bytes_transferred), (1, 2, 12));
+        assert_eq!((verifying[1].current, verifying[1].bytes_transferred), (2, 17));
+        assert_eq!(verifying[1].current_file, "IMG_0002.JPG");
+        let last = events.last().unwrap();
+        assert_eq!(last.phase, TransferPhase::Completed);
+        assert_eq!((last.bytes_transferred, last.total_bytes), (24, 24));
+
+        // 未启用校验时没有校验阶段
+        let mut events = Vec::new();
+        let other = dir.path().join("other");
+        let plan = TransferPlan {
+            target_dir: other.to_string_lossy().to_string(),
+            entries: vec![copy_entry(&first, &other.join("IMG_0001.JPG"), 5)],
+        };
+        copy_plan_entries(&plan, &fs_copy(), &CopyOptions { retry_policy: policy, ..Default::default() }, || false, |p| events.push(p));
+        assert!(events.iter().all(|p| p.phase != TransferPhase::Verifying));
+        assert_eq!(events.last().unwrap().total_bytes, 5);
+    }
+
+    #[test]
+    fn test_copy_plan_entries_verify_detects_corruption() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let target_dir = dir.path().join("target");
+        let source = dir.path().join("IMG_0001.JPG");
+        fs::write(&source, b"12345").unwrap();
+        let plan = TransferPlan {
+            target_dir: target_dir.to_string_lossy().to_string(),
+            entries: vec![copy_entry(&source, &target_dir.join("IMG_0001.JPG"), 5)],
+        };
+
+        // 复制时写入了错误的内容
+        let copier: CopyFn = Arc::new(|_, target| {
+            fs::write(target, b"12399")?;
+            Ok(5)
+        });
+        let policy = RetryPolicy { max_attempts: 1, base_delay_ms: 1 };
+        let outcome = copy_plan_entries(&plan, &copier, &CopyOptions { retry_policy: policy, verify: true, ..Default::default() }, || false, |_| {});
+        assert_eq!((outcome.success_count, outcome.error_count), (0, 1));
+        assert!(outcome.errors[0].starts_with("校验失败"));
+        assert!(matches!(&outcome.transferred_files[0].status, TransferFileStatus::Error(e) if e.contains("内容不一致")));
+    }
+
+    #[test]
+    fn test_failed_photos_from_record() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let card = dir.path().join("card");
+        fs::create_dir_all(card.join("trip")).unwrap();
+        let failed = card.join("trip").join("IMG_0001.JPG");
+        fs::write(&failed, b"12345").unwrap();
+        let missing = card.join("IMG_0002.JPG");
+        let copied = card.join("IMG_0003.JPG");
+        fs::write(&copied, b"123").unwrap();
+
+        let file = |source: &Path, status| TransferredFile {
+            source_path: source.to_string_lossy().to_string(),
+            target_path: String::new(),
+            file_size: 5,
+            status,
+            camera: None,
+            matched_original: None,
+        };
+        let mut record = TransferHistory::create_record(
+            &format!("/other/card; {}", card.display()),
+            "/target",
+            "{folder}",
+        );
+        record.files = vec![
+            file(&failed, TransferFileStatus::Error("磁盘已断开".to_string())),
+            file(&missing, TransferFileStatus::Error("磁盘已断开".to_string())),
+            file(&copied, TransferFileStatus::Success),
+        ];
+
+        let (photos, notes) = failed_photos(&record, &ClassifyConfig::default());
+        assert_eq!(photos.len(), 1);
+        assert_eq!(photos[0].path, failed.to_string_lossy());
+        assert_eq!(photos[0].file_size, 5);
+        // 按所属的源文件夹计算相对目录
+        assert_eq!(photos[0].relative_dir, "trip");
+        assert_eq!(photos[0].source_root, card.to_string_lossy());
+        assert_eq!(notes, [format!("源文件已不存在，跳过重试: {}", missing.display())]);
+    }
+
+    #[test]
+    fn test_transfer_context_cancel() {
+        let cancel_flag = Arc::new(AtomicBool::new(false));
+        
+        // 初始状态未取消
+        assert!(!cancel_flag.load(Ordering::Relaxed));
+        
+        // 设置取消
+        cancel_flag.store(true, Ordering::Relaxed);
+        assert!(cancel_flag.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn test_copy_file_counts_actual_bytes() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let source = dir.path().join("IMG_0001.JPG");
+        fs::write(&source, b"0123456789").unwrap();
+
+        // 扫描时文件只有 4 字节，复制前被改写为 10 字节
+        let mut bytes = ByteProgress::new(4 + 100);
+        let counted = Mutex::new(0);
+        let copied = copy_file(&fs_copy(), &source.to_string_lossy(), &dir.path().join("copy.JPG"), |n| *counted.lock().unwrap() += n).unwrap();
+        bytes.add_copied(4, *counted.lock().unwrap());
+
+        assert_eq!(copied, 10);
+        assert_eq!(bytes, ByteProgress { transferred: 10, total: 110 });
+    }
+
+    #[test]
+    fn test_retry_with_backoff_succeeds_after_transient_failures() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let source = dir.path().join("IMG_0001.JPG");
+        fs::write(&source, b"photo").unwrap();
+        let target = dir.path().join("copy.JPG");
+        let policy = RetryPolicy { max_attempts: 3, base_delay_ms: 1 };
+
+        // 前两次复制失败（如网络存储短暂断开），第三次成功
+        let mut calls = 0;
+        let bytes = Mutex::new(ByteProgress::new(5));
+        let copied = retry_with_backoff(&policy, || false, || {
+            calls += 1;
+            if calls <= 2 {
+                return Err(std::io::Error::new(std::io::ErrorKind::TimedOut, "连接中断"));
+            }
+            copy_file(&fs_copy(), &source.to_string_lossy(), &target, |n| bytes.lock().unwrap().add_copied(5, n))
+        });
+        assert_eq!(copied.unwrap(), 5);
+        assert_eq!(calls, 3);
+        assert_eq!(*bytes.lock().unwrap(), ByteProgress { transferred: 5, total: 5 });
+        assert_eq!(fs::read(&target).unwrap(), b"photo");
+    }
+
+    #[test]
+    fn test_retry_with_backoff_reports_attempts() {
+        let policy = RetryPolicy { max_attempts: 3, base_delay_ms: 1 };
+        let mut calls = 0;
+        let result: Result<(), _> = retry_with_backoff(&policy, || false, || {
+            calls += 1;
+            Err(std::io::Error::new(std::io::ErrorKind::TimedOut, "连接中断"))
+        });
+        let (e, attempts) = result.unwrap_err();
+        assert_eq!((calls, attempts), (3, 3));
+        assert_eq!(copy_error_detail(&e, attempts), "重试 2 次后仍失败: 连接中断");
+
+        // 不重试时只报告原始错误
+        let no_retry = RetryPolicy { max_attempts: 1, ..policy };
+        let (e, attempts) = retry_with_backoff(&no_retry, || false, || -> std::io::Result<()> {
+            Err(std::io::Error::new(std::io::ErrorKind::TimedOut, "连接中断"))
+        })
+        .unwrap_err();
+        assert_eq!(copy_error_detail(&e, attempts), "连接中断");
+    }
+
+    #[test]
+    fn test_retry_backoff_interrupted_by_cancel() {
+        // 等待时间很长，取消后应立即返回而不等待
+        let policy = RetryPolicy { max_attempts: 5, base_delay_ms: 60_000 };
+        let cancel_flag = AtomicBool::new(false);
+        let started = Instant::now();
+        let mut calls = 0;
+        let result: Result<(), _> = retry_with_backoff(&policy, || cancel_flag.load(Ordering::Relaxed), || {
+            calls += 1;
+            cancel_flag.store(true, Ordering::Relaxed);
+            Err(std::io::Error::new(std::io::ErrorKind::TimedOut, "连接中断"))
+        });
+        assert_eq!(result.unwrap_err().1, 1);
+        assert_eq!(calls, 1);
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_retry_policy_delay_doubles() {
+        let policy = RetryPolicy { max_attempts: 4, base_delay_ms: 500 };
+        assert_eq!(policy.delay(1), Duration::from_millis(500));
+        assert_eq!(policy.delay(2), Duration::from_millis(1000));
+        assert_eq!(policy.delay(3), Duration::from_millis(2000));
+        // 不会溢出
+        assert_eq!(policy.delay(100), Duration::from_millis(u64::MAX));
+    }
+
+    #[test]
+    fn test_index_target_files_reports_progress() {
+        let dir = tempfile::TempDir::new().unwrap();
+        fs::create_dir_all(dir.path().join("2024/03")).unwrap();
+        for i in 0..3 {
+            fs::write(dir.path().join(format!("2024/03/IMG_{}.JPG", i)), format!("photo {}", i)).unwrap();
+        }
+
+        let mut index = DedupIndex::new(&dir.path().to_string_lossy());
+        let mut events = Vec::new();
+        let completed = index_target_files(dir.path(), &mut index, &GlobSet::empty(), || false, |p| events.push(p));
+
+        assert!(completed);
+        assert_eq!(index.files.len(), 3);
+        assert_eq!(events.first().unwrap().status, "counting");
+        assert_eq!(events[1], DedupScanProgress {
+            indexed: 0,
+            total: Some(3),
+            status: "indexing".to_string(),
+        });
+        assert_eq!(events.last().unwrap(), &DedupScanProgress {
+            indexed: 3,
+            total: Some(3),
+            status: "completed".to_string(),
+        });
+    }
+
+    #[test]
+    fn test_index_target_files_excludes_subtree() {
+        let dir = tempfile::TempDir::new().unwrap();
+        fs::create_dir_all(dir.path().join("归档/2019")).unwrap();
+        fs::create_dir_all(dir.path().join("2024/03")).unwrap();
+        fs::write(dir.path().join("归档/2019/IMG_0001.JPG"), b"old photo").unwrap();
+        fs::write(dir.path().join("2024/03/IMG_0002.JPG"), b"new photo").unwrap();
+
+        let excludes = build_exclude_set(&["归档".to_string()]).unwrap();
+        let mut index = DedupIndex::new(&dir.path().to_string_lossy());
+        let mut events = Vec::new();
+        assert!(index_target_files(dir.path(), &mut index, &excludes, || false, |p| events.push(p)));
+
+        let indexed: Vec<_> = index.files.keys().collect();
+        assert_eq!(indexed.len(), 1);
+        assert!(indexed[0].ends_with("IMG_0002.JPG"));
+        assert_eq!(events.last().unwrap().total, Some(1));
+    }
+
+    #[test]
+    fn test_index_target_files_cancelled() {
+        let dir = tempfile::TempDir::new().unwrap();
+        fs::write(dir.path().join("IMG_0001.JPG"), b"photo").unwrap();
+
+        let mut index = DedupIndex::new(&dir.path().to_string_lossy());
+        let completed = index_target_files(dir.path(), &mut index, &GlobSet::empty(), || true, |_| {});
+
+        assert!(!completed);
+        assert!(!index.is_warm());
+    }
+
+    #[test]
+    fn test_warm_index_skips_unchanged_dirs() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let target = dir.path().join("target");
+        let month = target.join("2024/03");
+        fs::create_dir_all(&month).unwrap();
+        let photo = month.join("IMG_0001.JPG");
+        fs::write(&photo, b"first photo").unwrap();
+        // 文件夹的修改时间早于索引时间，缓存可信
+        let an_hour_ago = SystemTime::now() - Duration::from_secs(3600);
+        for folder in [&month, &target.join("2024"), &target] {
+            fs::File::open(folder).unwrap().set_modified(an_hour_ago).unwrap();
+        }
+
+        let mut index = DedupIndex::new(&target.to_string_lossy());
+        assert!(index_target_files(&target, &mut index, &GlobSet::empty(), || false, |_| {}));
+        assert_eq!(index.dirs.len(), 3);
+        let cached_size = |index: &DedupIndex| index.files.values().map(|f| f.size).collect::<Vec<_>>();
+        assert_eq!(cached_size(&index), [11]);
+
+        // 原地修改文件不改变文件夹的修改时间：文件夹未变时不重新读取其中的文件
+        fs::write(&photo, b"edited in place").unwrap();
+        let mut events = Vec::new();
+        assert!(index_target_files(&target, &mut index, &GlobSet::empty(), || false, |p| events.push(p)));
+        assert_eq!(cached_size(&index), [11]);
+        assert!(events.iter().all(|e| e.status != "indexing"));
+        assert_eq!(events.last().unwrap().indexed, 1);
+
+        // 新增文件改变了文件夹的修改时间，重新读取该文件夹
+        fs::write(month.join("IMG_0002.JPG"), b"second").unwrap();
+        assert!(index_target_files(&target, &mut index, &GlobSet::empty(), || false, |_| {}));
+        let mut sizes = cached_size(&index);
+        sizes.sort();
+        assert_eq!(sizes, [6, 15]);
+    }
+
+    #[test]
+    fn test_warm_index_detects_new_destination_file() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let target = dir.path().join("target");
+        fs::create_dir_all(target.join("2024/03")).unwrap();
+        fs::write(target.join("2024/03/IMG_0001.JPG"), b"first photo").unwrap();
+
+        let mut index = DedupIndex::new(&target.to_string_lossy());
+        assert!(index_target_files(&target, &mut index, &GlobSet::empty(), || false, |_| {}));
+        assert!(index.is_warm());
+
+        // 索引建立后目标目录中新增的文件仍然能被识别为重复；有缓存时不再单独统计文件数
+        fs::write(target.join("2024/03/IMG_0002.JPG"), b"second photo").unwrap();
+        let mut events = Vec::new();
+        assert!(index_target_files(&target, &mut index, &GlobSet::empty(), || false, |p| events.push(p)));
+        assert_eq!(index.files.len(), 2);
+        assert!(events.iter().all(|e| e.status != "counting"));
+        assert_eq!(events.last().unwrap().total, Some(2));
+
+        let incoming = dir.path().join("IMG_0002_copy.JPG");
+        fs::write(&incoming, b"second photo").unwrap();
+        let mut deduplicator = Deduplicator::new();
+        index.populate(&mut deduplicator);
+        let duplicate = deduplicator.check_duplicate(&incoming.to_string_lossy(), 12).unwrap();
+        assert!(duplicate.unwrap().ends_with("IMG_0002.JPG"));
+    }
+
+    #[test]
+    fn test_dedup_dirs_includes_reference_dirs() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let target = dir.path().join("target");
+        let archive = dir.path().join("archive");
+        fs::create_dir_all(target.join("2024")).unwrap();
+        fs::create_dir_all(&archive).unwrap();
+        let target = target.to_string_lossy().to_string();
+        let archive = archive.to_string_lossy().to_string();
+
+        let references = vec![
+            archive.clone(),
+            archive.clone(),
+            format!("{}/2024", target),
+            dir.path().join("missing").to_string_lossy().to_string(),
+        ];
+        assert_eq!(dedup_dirs(&target, &references), [target.as_str(), archive.as_str()]);
+
+        // 目标目录尚不存在时仍然参考归档目录
+        let new_target = dir.path().join("new").to_string_lossy().to_string();
+        assert_eq!(dedup_dirs(&new_target, &references[..1]), [archive.as_str()]);
+    }
+
+    #[test]
+    fn test_reference_dir_match_is_skipped_without_writing() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let archive = dir.path().join("archive");
+        fs::create_dir_all(archive.join("2023")).unwrap();
+        fs::write(archive.join("2023/IMG_0001.JPG"), b"archived photo").unwrap();
+        let source = dir.path().join("card");
+        fs::create_dir_all(&source).unwrap();
+        fs::write(source.join("IMG_0001.JPG"), b"archived photo").unwrap();
+        fs::write(source.join("IMG_0002.JPG"), b"new photo").unwrap();
+        let target = dir.path().join("target").to_string_lossy().to_string();
+
+        let mut deduplicator = Deduplicator::new();
+        let references = vec![archive.to_string_lossy().to_string()];
+        for dir in dedup_dirs(&target, &references) {
+            let mut index = DedupIndex::new(dir);
+            assert!(index_target_files(Path::new(dir), &mut index, &GlobSet::empty(), || false, |_| {}));
+            index.populate(&mut deduplicator);
+        }
+
+        let photos: Vec<PhotoInfo> = ["IMG_0001.JPG", "IMG_0002.JPG"]
+            .iter()
+            .map(|name| PhotoInfo {
+                path: source.join(name).to_string_lossy().to_string(),
+                file_name: name.to_string(),
+                file_size: fs::metadata(source.join(name)).unwrap().len(),
+                date_time: None,
+                date_time_original: None,
+                create_date: None,
+                camera: None,
+                make: None,
+                target_folder: "2024".to_string(),
+                is_duplicate: false,
+                duplicate_of: None,
+                width: None,
+                height: None,
+                relative_dir: String::new(),
+                suspect: false,
+                source_root: String::new(),
+                rating: None,
+                label: None,
+                keywords: Vec::new(),
+            })
+            .collect();
+        let plan = build_plan(&photos, &target, &RenameConfig::default(), ConflictPolicy::Rename, Some(&mut deduplicator));
+
+        assert_eq!(plan.entries[0].action, PlanAction::SkipDuplicate);
+        assert!(plan.entries[0].duplicate_of.as_deref().unwrap().ends_with("IMG_0001.JPG"));
+        assert!(plan.entries[0].duplicate_of.as_deref().unwrap().contains("archive"));
+        assert_eq!(plan.entries[1].action, PlanAction::Copy);
+        assert!(plan.entries[1].target_path.starts_with(&target));
+        // 参考目录只被读取
+        assert_eq!(fs::read_dir(archive.join("2023")).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_record_history_failure_is_warning() {
+        let dir = tempfile::TempDir::new().unwrap();
+        // 父路径是普通文件，历史记录无法写入
+        let blocker = dir.path().join("photo-truck");
+        fs::write(&blocker, b"not a directory").unwrap();
+        let history_path = blocker.join("history.json");
+
+        let mut result = TransferResult {
+            success_count: 2,
+            skip_count: 1,
+            error_count: 0,
+            errors: vec![],
+            warnings: vec![],
+            skipped: Vec::new(),
+            files: None,
+            previews: 0,
+            new_folders: Vec::new(),
+            merged_folders: Vec::new(),
+        };
+        let record = TransferHistory::create_record("/src", "/target", "{year}/{month}");
+        record_history(&mut result, record, &history_path);
+
+        assert_eq!(result.success_count, 2);
+        assert_eq!(result.error_count, 0);
+        assert!(result.errors.is_empty());
+        assert_eq!(result.warnings.len(), 1);
+        assert!(result.warnings[0].starts_with("传输历史记录未保存"));
+    }
+
+    #[test]
+    fn test_result_files_lists_final_targets() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let target_dir = dir.path().join("target");
+        let source = dir.path().join("IMG_0001.JPG");
+        fs::write(&source, b"12345").unwrap();
+        let final_target = target_dir.join("2024").join("IMG_0001_1.JPG");
+        let plan = TransferPlan {
+            target_dir: target_dir.to_string_lossy().to_string(),
+            entries: vec![copy_entry(&source, &final_target, 5)],
+        };
+        let outcome = copy_plan_entries(&plan, &fs_copy(), &CopyOptions::default(), || false, |_| {});
+
+        let files = result_files(&outcome.transferred_files, MAX_RESULT_FILES).unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].source_path, source.to_string_lossy());
+        assert_eq!(files[0].target_path, final_target.to_string_lossy());
+        assert_eq!(files[0].status, TransferFileStatus::Success);
+
+        // 文件过多时不直接返回
+        assert!(result_files(&outcome.transferred_files, 0).is_none());
+    }
+
+    #[test]
+    fn test_record_history_saves_record() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let history_path = dir.path().join("history.json");
+
+        let mut result = TransferResult {
+            success_count: 1,
+            skip_count: 0,
+            error_count: 0,
+            errors: vec![],
+            warnings: vec![],
+            skipped: Vec::new(),
+            files: None,
+            previews: 0,
+            new_folders: Vec::new(),
+            merged_folders: Vec::new(),
+        };
+        let record = TransferHistory::create_record("/src", "/target", "{year}/{month}");
+        record_history(&mut result, record, &history_path);
+
+        assert!(result.warnings.is_empty());
+        assert_eq!(TransferHistory::load_from(&history_path).records.len(), 1);
+    }
+
+    #[test]
+    fn test_byte_progress_skipped_uses_scanned_size() {
+        let mut bytes = ByteProgress::new(300);
+        bytes.add_skipped(100);
+        bytes.add_copied(200, 150);
+        assert_eq!(bytes, ByteProgress { transferred: 250, total: 250 });
+    }
+
+    #[test]
+    fn test_rename_in_place() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let target = dir.path().join("target");
+        let march = target.join("2024-03");
+        let april = target.join("2024-04");
+        fs::create_dir_all(&march).unwrap();
+        fs::create_dir_all(&april).unwrap();
+        // a.jpg 改名为 photo_001.jpg 时，原有的 photo_001.jpg 需要同时改名为 photo_002.jpg
+        fs::write(march.join("a.jpg"), b"a").unwrap();
+        fs::write(march.join("photo_001.jpg"), b"b").unwrap();
+        fs::write(march.join("notes.txt"), b"notes").unwrap();
+        // 与不参与重命名的文件冲突时添加序号
+        fs::write(april.join("c.jpg"), b"c").unwrap();
+        fs::create_dir_all(april.join("photo_002.jpg")).unwrap();
+        fs::write(april.join("d.jpg"), b"d").unwrap();
+        let rename = RenameConfig {
+            template: "photo_{counter}".to_string(),
+            counter_digits: 3,
+            ..Default::default()
+        };
+        let history_path = dir.path().join("history.json");
+
+        let result = rename_in_place(&target, &rename, &history_path, || false).unwrap();
+        assert_eq!((result.success_count, result.skip_count, result.error_count), (4, 0, 0));
+        assert_eq!(fs::read(march.join("photo_001.jpg")).unwrap(), b"a");
+        assert_eq!(fs::read(march.join("photo_002.jpg")).unwrap(), b"b");
+        assert!(march.join("notes.txt").exists());
+        assert_eq!(fs::read(april.join("photo_001.jpg")).unwrap(), b"c");
+        assert_eq!(fs::read(april.join("photo_002_1.jpg")).unwrap(), b"d");
+        assert!(!march.join("a.jpg").exists());
+
+        let history = TransferHistory::load_from(&history_path);
+        let record = &history.records[0];
+        assert_eq!(record.kind, RecordKind::Rename);
+        assert_eq!(record.template, "photo_{counter}");
+        assert_eq!(record.files.len(), 4);
+        assert!(record.files.iter().any(|f| f.source_path == march.join("a.jpg").to_string_lossy()
+            && f.target_path == march.join("photo_001.jpg").to_string_lossy()));
+
+        // 再次执行时文件名不变，全部跳过
+        let again = rename_in_place(&target, &rename, &history_path, || false).unwrap();
+        assert_eq!((again.success_count, again.skip_count), (0, 4));
+        assert!(rename_in_place(&dir.path().join("missing"), &rename, &history_path, || false).is_err());
+    }
+
+    #[test]
+    fn test_rename_in_place_pair_keeps_conflict_suffix() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let folder = dir.path().join("target");
+        fs::create_dir_all(&folder).unwrap();
+        fs::write(folder.join("IMG_1.CR3"), b"raw").unwrap();
+        fs::write(folder.join("IMG_1.JPG"), b"jpg").unwrap();
+        // 不参与重命名的文件占用了 RAW 将要使用的文件名
+        fs::create_dir_all(folder.join("photo_001.CR3")).unwrap();
+        let rename = RenameConfig {
+            template: "photo_{counter}".to_string(),
+            counter_digits: 3,
+            pair_by_basename: true,
+            ..Default::default()
+        };
+
+        let result = rename_in_place(&folder, &rename, &dir.path().join("history.json"), || false).unwrap();
+        assert_eq!((result.success_count, result.error_count), (2, 0), "{:?}", result.errors);
+        assert_eq!(fs::read(folder.join("photo_001_1.CR3")).unwrap(), b"raw");
+        assert_eq!(fs::read(folder.join("photo_001_1.JPG")).unwrap(), b"jpg");
+    }
+
+    #[test]
+    fn test_rename_in_place_temp_names_and_leftovers() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let folder = dir.path().join("target");
+        fs::create_dir_all(&folder).unwrap();
+        fs::write(folder.join("a.jpg"), b"a").unwrap();
+        // 已有使用临时文件名的文件，且原文件名被占用：不能被覆盖，也不能恢复
+        fs::write(folder.join("a.jpg.photo-truck-renaming"), b"keep me").unwrap();
+        // 上次重命名中断时遗留的临时文件，恢复为原文件名后参与本次重命名
+        fs::write(folder.join("b.jpg.photo-truck-renaming-1"), b"b").unwrap();
+        let rename = RenameConfig {
+            template: "photo_{counter}".to_string(),
+            counter_digits: 3,
+            ..Default::default()
+        };
+
+        let result = rename_in_place(&folder, &rename, &dir.path().join("history.json"), || false).unwrap();
+        assert_eq!((result.success_count, result.error_count), (2, 0), "{:?}", result.errors);
+        assert_eq!(fs::read(folder.join("photo_001.jpg")).unwrap(), b"a");
+        assert_eq!(fs::read(folder.join("photo_002.jpg")).unwrap(), b"b");
+        assert_eq!(fs::read(folder.join("a.jpg.photo-truck-renaming")).unwrap(), b"keep me");
+        assert!(!folder.join("a.jpg.photo-truck-renaming-1").exists());
+        assert_eq!(result.warnings.len(), 2, "{:?}", result.warnings);
+        assert!(result.warnings.iter().any(|w| w.contains("已恢复") && w.contains("b.jpg")));
+        assert!(result.warnings.iter().any(|w| w.contains("未恢复") && w.contains("a.jpg.photo-truck-renaming")));
+
+        assert_eq!(rename_temp_origin(Path::new("/x/a.jpg.photo-truck-renaming-12")), Some(PathBuf::from("/x/a.jpg")));
+        assert_eq!(rename_temp_origin(Path::new("/x/a.jpg.photo-truck-renaming-x")), None);
+        assert_eq!(rename_temp_origin(Path::new("/x/a.jpg")), None);
+    }
+
+    #[test]
+    fn test_rename_in_place_moves_sidecars() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let folder = dir.path().join("target");
+        fs::create_dir_all(&folder).unwrap();
+        // 按路径排序：a.jpg、c.jpg、photo_001.jpg，附属文件跟随各自的照片
+        fs::write(folder.join("a.jpg"), b"a").unwrap();
+        fs::write(folder.join("a.xmp"), b"a xmp").unwrap();
+        fs::write(folder.join("a.jpg.aae"), b"a aae").unwrap();
+        fs::write(folder.join("photo_001.jpg"), b"b").unwrap();
+        fs::write(folder.join("photo_001.XMP"), b"b xmp").unwrap();
+        // 不参与重命名的同名附属文件占用时，照片改用其他文件名
+        fs::write(folder.join("c.jpg"), b"c").unwrap();
+        fs::write(folder.join("c.thm"), b"c thm").unwrap();
+        fs::write(folder.join("photo_002.thm"), b"orphan").unwrap();
+        let rename = RenameConfig {
+            template: "photo_{counter}".to_string(),
+            counter_digits: 3,
+            ..Default::default()
+        };
+
+        let result = rename_in_place(&folder, &rename, &dir.path().join("history.json"), || false).unwrap();
+        assert_eq!((result.success_count, result.error_count), (3, 0), "{:?}", result.errors);
+        assert_eq!(fs::read(folder.join("photo_001.jpg")).unwrap(), b"a");
+        assert_eq!(fs::read(folder.join("photo_001.xmp")).unwrap(), b"a xmp");
+        assert_eq!(fs::read(folder.join("photo_001.jpg.aae")).unwrap(), b"a aae");
+        assert_eq!(fs::read(folder.join("photo_002_1.jpg")).unwrap(), b"c");
+        assert_eq!(fs::read(folder.join("photo_002_1.thm")).unwrap(), b"c thm");
+        assert_eq!(fs::read(folder.join("photo_002.thm")).unwrap(), b"orphan");
+        assert_eq!(fs::read(folder.join("photo_003.jpg")).unwrap(), b"b");
+        assert_eq!(fs::read(folder.join("photo_003.XMP")).unwrap(), b"b xmp");
+        let mut names: Vec<_> = fs::read_dir(&folder).unwrap().map(|e| e.unwrap().file_name()).collect();
+        names.sort();
+        assert_eq!(names.len(), 8, "{:?}", names);
+    }
+
+    #[test]
+    fn test_record_progress_snapshot() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let target_dir = dir.path().join("target");
+        let a = dir.path().join("IMG_0001.JPG");
+        let b = dir.path().join("IMG_0002.JPG");
+        fs::write(&a, b"aaaa").unwrap();
+        fs::write(&b, b"bb").unwrap();
+        let plan = TransferPlan {
+            target_dir: target_dir.to_string_lossy().to_string(),
+            entries: vec![
+                copy_entry(&a, &target_dir.join("IMG_0001.JPG"), 4),
+                copy_entry(&b, &target_dir.join("IMG_0002.JPG"), 2),
+            ],
+        };
+
+        let snapshot = Mutex::new(None);
+        let mut seen = Vec::new();
+        let policy = RetryPolicy { max_attempts: 1, base_delay_ms: 1 };
+        copy_plan_entries(&plan, &fs_copy(), &CopyOptions { retry_policy: policy, ..Default::default() }, || false, |progress| {
+            record_progress(&snapshot, Some(progress));
+            // 每次记录后查询到的都是最新进度
+            let latest = snapshot.lock().unwrap().clone().unwrap();
+            seen.push((latest.current, latest.bytes_transferred));
+        });
+        assert!(seen.windows(2).all(|w| w[0] <= w[1]), "{:?}", seen);
+        let latest = snapshot.lock().unwrap().clone().unwrap();
+        assert_eq!((latest.current, latest.total, latest.bytes_transferred), (2, 2, 6));
+
+        // 传输结束后清空
+        record_progress(&snapshot, None);
+        assert!(snapshot.lock().unwrap().is_none());
+    }
+
+    /// 记录收到的全部进度
+    #[derive(Default)]
+    struct CapturingSink {
+        transfer: Mutex<Vec<TransferProgress>>,
+    }
+
+    impl ProgressSink for CapturingSink {
+        fn transfer_progress(&self, progress: TransferProgress) {
+            self.transfer.lock().unwrap().push(progress);
+        }
+    }
+
+    #[test]
+    fn test_transfer_photos_v2_reports_progress_to_sink() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let source = dir.path().join("card");
+        let target = dir.path().join("target");
+        fs::create_dir_all(&source).unwrap();
+        fs::write(source.join("IMG_0001.JPG"), b"aaaa").unwrap();
+        fs::write(source.join("IMG_0002.JPG"), b"bb").unwrap();
+        let config = ClassifyConfig {
+            template: "photos".to_string(),
+            ..Default::default()
+        };
+        let source_dir = source.to_string_lossy().to_string();
+        let photos: Vec<PhotoInfo> = ["IMG_0001.JPG", "IMG_0002.JPG"]
+            .iter()
+            .map(|name| photo_info(&source.join(name), &source_dir, &config, false))
+            .collect();
+        let target_dir = target.to_string_lossy().to_string();
+        let mut ctx = TransferContext::new(Arc::new(AtomicBool::new(false)), &source_dir, &target_dir, "photos");
+        ctx.history_path = dir.path().join("history.json");
+
+        let sink = CapturingSink::default();
+        let result = transfer_photos_v2(&ctx, &sink, &photos, &target_dir, false).unwrap();
+        assert_eq!(result.success_count, 2);
+
+        let events = sink.transfer.into_inner().unwrap();
+        let phases: Vec<TransferPhase> = events.iter().map(|p| p.phase).collect();
+        assert_eq!(
+            phases,
+            vec![TransferPhase::Preparing, TransferPhase::Transferring, TransferPhase::Transferring, TransferPhase::Completed]
+        );
+        assert_eq!(events.iter().map(|p| p.current).collect::<Vec<_>>(), vec![0, 1, 2, 2]);
+        let last = events.last().unwrap();
+        assert_eq!((last.bytes_transferred, last.total_bytes), (6, 6));
+        // 结束后清空进度快照，并写入指定的历史记录文件
+        assert!(ctx.progress.lock().unwrap().is_none());
+        assert_eq!(TransferHistory::load_from(&ctx.history_path).records.len(), 1);
+    }
+
+    #[test]
+    fn test_transfer_photos_v2_routes_cameras_to_roots() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let source = dir.path().join("card");
+        let nas_a = dir.path().join("nas-a");
+        let nas_b = dir.path().join("nas-b");
+        let default_target = dir.path().join("target");
+        fs::create_dir_all(&source).unwrap();
+        fs::create_dir_all(&nas_b).unwrap();
+        for (name, content) in [("R5_0001.JPG", "r5 a"), ("R5_0002.JPG", "r5 b"), ("A7_0001.JPG", "a7"), ("PHONE.JPG", "phone")] {
+            fs::write(source.join(name), content).unwrap();
+        }
+        // B 中已有与 A7_0001 内容相同的文件
+        fs::write(nas_b.join("old.jpg"), "a7").unwrap();
+
+        let config = ClassifyConfig {
+            template: String::new(),
+            ..Default::default()
+        };
+        let source_dir = source.to_string_lossy().to_string();
+        let photos: Vec<PhotoInfo> = [
+            ("R5_0001.JPG", "Canon", "Canon EOS R5"),
+            ("R5_0002.JPG", "Canon", "Canon EOS R5"),
+            ("A7_0001.JPG", "SONY", "ILCE-7M4"),
+            ("PHONE.JPG", "Apple", "iPhone 15"),
+        ]
+        .iter()
+        .map(|(name, make, model)| PhotoInfo {
+            make: Some(make.to_string()),
+            camera: Some(model.to_string()),
+            ..photo_info(&source.join(name), &source_dir, &config, false)
+        })
+        .collect();
+
+        let target_dir = default_target.to_string_lossy().to_string();
+        let mut ctx = TransferContext::new(Arc::new(AtomicBool::new(false)), &source_dir, &target_dir, "");
+        ctx.history_path = dir.path().join("history.json");
+        ctx.index_cache_dir = dir.path().join("dedup-index");
+        ctx.camera_routes = vec![
+            CameraRoute::parse(&format!("Canon EOS R5={}", nas_a.display())).unwrap(),
+            CameraRoute::parse(&format!("SONY={}", nas_b.display())).unwrap(),
+        ];
+
+        let result = transfer_photos_v2(&ctx, &CapturingSink::default(), &photos, &target_dir, true).unwrap();
+        assert_eq!((result.success_count, result.skip_count, result.error_count), (3, 1, 0));
+        assert_eq!(fs::read_to_string(nas_a.join("R5_0001.JPG")).unwrap(), "r5 a");
+        assert_eq!(fs::read_to_string(nas_a.join("R5_0002.JPG")).unwrap(), "r5 b");
+        assert!(!nas_b.join("A7_0001.JPG").exists());
+        assert_eq!(fs::read_to_string(default_target.join("PHONE.JPG")).unwrap(), "phone");
+        assert!(!default_target.join("R5_0001.JPG").exists());
+
+        // 再次传输时各个根目录中已有的照片都被跳过，每个根目录使用各自的去重索引
+        let again = transfer_photos_v2(&ctx, &CapturingSink::default(), &photos, &target_dir, true).unwrap();
+        assert_eq!((again.success_count, again.skip_count), (0, 4));
+        assert_eq!(fs::read_dir(&nas_a).unwrap().count(), 2);
+        for root in [&nas_a, &nas_b, &default_target] {
+            let cache = DedupIndex::cache_path_in(&ctx.index_cache_dir, &root.to_string_lossy());
+            assert!(cache.exists(), "{}", root.display());
+        }
+    }
+
+    #[test]
+    fn test_transfer_photos_v2_zip_warns_about_ignored_options() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let source = dir.path().join("card");
+        let target = dir.path().join("target");
+        fs::create_dir_all(&source).unwrap();
+        fs::write(source.join("A.JPG"), "a").unwrap();
+        fs::write(source.join("A.xmp"), "xmp").unwrap();
+
+        let config = ClassifyConfig {
+            template: String::new(),
+            ..Default::default()
+        };
+        let source_dir = source.to_string_lossy().to_string();
+        let photos = vec![photo_info(&source.join("A.JPG"), &source_dir, &config, false)];
+        let target_dir = target.to_string_lossy().to_string();
+        let mut ctx = TransferContext::new(Arc::new(AtomicBool::new(false)), &source_dir, &target_dir, "");
+        ctx.history_path = dir.path().join("history.json");
+        ctx.index_cache_dir = dir.path().join("dedup-index");
+        ctx.output_mode = OutputMode::Zip;
+
+        let result = transfer_photos_v2(&ctx, &CapturingSink::default(), &photos, &target_dir, false).unwrap();
+        assert_eq!(result.success_count, 1);
+        assert!(result.warnings.is_empty(), "{:?}", result.warnings);
+
+        ctx.verify = true;
+        ctx.move_sidecars = true;
+        ctx.retry_policy = RetryPolicy { max_attempts: 5, base_delay_ms: 10 };
+        ctx.jobs = 2;
+        let result = transfer_photos_v2(&ctx, &CapturingSink::default(), &photos, &target_dir, false).unwrap();
+        assert_eq!(result.success_count, 1);
+        assert_eq!(result.warnings.len(), 4, "{:?}", result.warnings);
+        assert!(result.warnings.iter().any(|w| w.contains("不校验")));
+        assert!(result.warnings.iter().any(|w| w.contains("附属文件")));
+
+        // 复制为单独的文件时这些选项都有效，没有说明
+        ctx.output_mode = OutputMode::Files;
+        let result = transfer_photos_v2(&ctx, &CapturingSink::default(), &photos, &target_dir, false).unwrap();
+        assert!(result.warnings.is_empty(), "{:?}", result.warnings);
+    }
+
+    #[test]
+    fn test_transfer_photos_v2_preview_only_records_raws() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let source = dir.path().join("card");
+        let target = dir.path().join("target");
+        fs::create_dir_all(&source).unwrap();
+        fs::write(source.join("IMG_0001.CR3"), "not a real raw").unwrap();
+        fs::write(source.join("IMG_0002.JPG"), "jpeg").unwrap();
+
+        let config = ClassifyConfig {
+            template: String::new(),
+            ..Default::default()
+        };
+        let source_dir = source.to_string_lossy().to_string();
+        let photos: Vec<PhotoInfo> = ["IMG_0001.CR3", "IMG_0002.JPG"]
+            .iter()
+            .map(|name| photo_info(&source.join(name), &source_dir, &config, false))
+            .collect();
+        let target_dir = target.to_string_lossy().to_string();
+        let mut ctx = TransferContext::new(Arc::new(AtomicBool::new(false)), &source_dir, &target_dir, "");
+        ctx.history_path = dir.path().join("history.json");
+        ctx.index_cache_dir = dir.path().join("dedup-index");
+        ctx.raw_preview = RawPreviewMode::Only;
+
+        // 测试文件没有内嵌预览：RAW 记为跳过或失败（取决于是否安装了 ExifTool），但一定出现在记录中
+        let result = transfer_photos_v2(&ctx, &CapturingSink::default(), &photos, &target_dir, false).unwrap();
+        assert!(!target.join("IMG_0001.CR3").exists());
+        assert_eq!(result.success_count + result.skip_count + result.error_count, 2);
+        let record = &TransferHistory::load_from(&ctx.history_path).records[0];
+        assert_eq!(record.total_files, 2);
+        let raw = record.files.iter().find(|f| f.source_path.ends_with("IMG_0001.CR3")).unwrap();
+        assert_ne!(raw.status, TransferFileStatus::Success);
+        assert!(raw.target_path.ends_with("IMG_0001.jpg"), "{}", raw.target_path);
+    }
+
+    #[test]
+    fn test_transfer_photos_v2_reports_new_and_merged_folders() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let source = dir.path().join("card");
+        let target = dir.path().join("target");
+        fs::create_dir_all(&source).unwrap();
+        fs::create_dir_all(target.join("2024")).unwrap();
+        fs::write(target.join("2024").join("OLD.JPG"), "old").unwrap();
+        for name in ["A.JPG", "B.JPG", "C.JPG"] {
+            fs::write(source.join(name), name).unwrap();
+        }
+
+        let config = ClassifyConfig {
+            template: String::new(),
+            ..Default::default()
+        };
+        let source_dir = source.to_string_lossy().to_string();
+        let photos: Vec<PhotoInfo> = [("A.JPG", "2024"), ("B.JPG", "2025"), ("C.JPG", "2025")]
+            .iter()
+            .map(|(name, folder)| PhotoInfo {
+                target_folder: folder.to_string(),
+                ..photo_info(&source.join(name), &source_dir, &config, false)
+            })
+            .collect();
+        let target_dir = target.to_string_lossy().to_string();
+        let mut ctx = TransferContext::new(Arc::new(AtomicBool::new(false)), &source_dir, &target_dir, "");
+        ctx.history_path = dir.path().join("history.json");
+
+        let result = transfer_photos_v2(&ctx, &CapturingSink::default(), &photos, &target_dir, false).unwrap();
+        assert_eq!(result.success_count, 3);
+        assert_eq!(result.merged_folders, vec![target.join("2024").to_string_lossy().to_string()]);
+        assert_eq!(result.new_folders, vec![target.join("2025").to_string_lossy().to_string()]);
+
+        // 再次传输时两个文件夹都已存在
+        let again = transfer_photos_v2(&ctx, &CapturingSink::default(), &photos, &target_dir, false).unwrap();
+        assert!(again.new_folders.is_empty());
+        assert_eq!(again.merged_folders.len(), 2);
+    }
+
+    #[test]
+    fn test_transfer_photos_v2_max_result_files() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let source = dir.path().join("card");
+        fs::create_dir_all(&source).unwrap();
+        fs::write(source.join("IMG_0001.JPG"), "one").unwrap();
+        fs::write(source.join("IMG_0002.JPG"), "two").unwrap();
+
+        let config = ClassifyConfig {
+            template: String::new(),
+            ..Default::default()
+        };
+        let source_dir = source.to_string_lossy().to_string();
+        let photos: Vec<PhotoInfo> = ["IMG_0001.JPG", "IMG_0002.JPG"]
+            .iter()
+            .map(|name| photo_info(&source.join(name), &source_dir, &config, false))
+            .collect();
+        let run = |target: &str, max_result_files: usize| {
+            let target_dir = dir.path().join(target).to_string_lossy().to_string();
+            let mut ctx = TransferContext::new(Arc::new(AtomicBool::new(false)), &source_dir, &target_dir, "");
+            ctx.history_path = dir.path().join("history.json");
+            ctx.index_cache_dir = dir.path().join("dedup-index");
+            ctx.max_result_files = max_result_files;
+            transfer_photos_v2(&ctx, &CapturingSink::default(), &photos, &target_dir, false).unwrap()
+        };
+
+        // 超过上限时结果中不含文件列表，只保存在历史记录中
+        assert!(run("small", 1).files.is_none());
+        assert_eq!(TransferHistory::load_from(&dir.path().join("history.json")).records[0].files.len(), 2);
+        assert_eq!(run("full", usize::MAX).files.unwrap().len(), 2);
     }
 }
@@ -0,0 +1,103 @@
+// 磁盘卷信息
+// 用于传输前检查目标磁盘的剩余空间
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+/// 路径所在磁盘卷的容量信息
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct VolumeInfo {
+    pub total_bytes: u64,
+    pub available_bytes: u64,
+    /// 卷名称（挂载点的文件夹名，如 NAS；系统根目录为挂载点路径本身）
+    pub volume_name: String,
+}
+
+/// 获取路径所在磁盘卷的信息。路径不存在时（如尚未创建的目标文件夹）使用最近的已存在上级目录
+pub fn volume_info(path: &str) -> Result<VolumeInfo, String> {
+    let existing = nearest_existing_ancestor(Path::new(path))
+        .ok_or_else(|| format!("找不到路径所在的磁盘: {}", path))?;
+
+    let total_bytes = fs2::total_space(&existing).map_err(|e| format!("读取磁盘容量失败: {}", e))?;
+    let available_bytes =
+        fs2::available_space(&existing).map_err(|e| format!("读取磁盘剩余空间失败: {}", e))?;
+
+    Ok(VolumeInfo {
+        total_bytes,
+        available_bytes,
+        volume_name: volume_name(&mount_point(&existing)),
+    })
+}
+
+/// 路径本身或最近的已存在上级目录（相对路径按当前目录解析）
+fn nearest_existing_ancestor(path: &Path) -> Option<PathBuf> {
+    let path = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        std::env::current_dir().ok()?.join(path)
+    };
+    path.ancestors().find(|p| p.exists()).map(Path::to_path_buf)
+}
+
+/// 路径所在卷的挂载点：向上查找直到设备号变化
+#[cfg(unix)]
+fn mount_point(path: &Path) -> PathBuf {
+    use std::os::unix::fs::MetadataExt;
+
+    let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    let Ok(device) = path.metadata().map(|m| m.dev()) else {
+        return path;
+    };
+    let mut mount = path.as_path();
+    while let Some(parent) = mount.parent() {
+        if parent.metadata().map(|m| m.dev()).ok() != Some(device) {
+            break;
+        }
+        mount = parent;
+    }
+    mount.to_path_buf()
+}
+
+/// 路径所在卷的挂载点：Windows 上为盘符或网络共享的根目录
+#[cfg(not(unix))]
+fn mount_point(path: &Path) -> PathBuf {
+    path.ancestors().last().map(Path::to_path_buf).unwrap_or_else(|| path.to_path_buf())
+}
+
+/// 挂载点的文件夹名，根目录（如 `/`、`C:\`）没有文件夹名时使用路径本身
+fn volume_name(mount: &Path) -> String {
+    mount
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| mount.to_string_lossy().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_volume_info_for_temp_dir() {
+        let dir = TempDir::new().unwrap();
+        let info = volume_info(&dir.path().to_string_lossy()).unwrap();
+        assert!(info.total_bytes > 0);
+        assert!(info.available_bytes <= info.total_bytes);
+        assert!(!info.volume_name.is_empty());
+    }
+
+    #[test]
+    fn test_volume_info_uses_existing_ancestor() {
+        let dir = TempDir::new().unwrap();
+        let missing = dir.path().join("尚未创建").join("2024");
+        let info = volume_info(&missing.to_string_lossy()).unwrap();
+        let parent = volume_info(&dir.path().to_string_lossy()).unwrap();
+        assert_eq!(info.total_bytes, parent.total_bytes);
+        assert_eq!(info.volume_name, parent.volume_name);
+    }
+
+    #[test]
+    fn test_volume_name() {
+        assert_eq!(volume_name(Path::new("/Volumes/NAS")), "NAS");
+        assert_eq!(volume_name(Path::new("/")), "/");
+    }
+}
@@ -71,6 +71,12 @@ fn test_classification_with_templates() {
         model: Some("Canon EOS R5".to_string()),
         make: Some("Canon".to_string()),
         mime_type: Some("image/x-canon-cr3".to_string()),
+        width: None,
+        height: None,
+        orientation: None,
+        rating: None,
+        label: None,
+        keywords: Vec::new(),
     };
     
     // 测试每个预设模板
@@ -78,11 +84,18 @@ fn test_classification_with_templates() {
         let config = ClassifyConfig {
             template: template.to_string(),
             fallback_folder: "未知日期".to_string(),
+            ..Default::default()
         };
         
         let path = config.generate_path(&metadata);
         println!("模板 '{}' ({}) -> {}", name, template, path);
         
+        // 不分类模板直接放在目标文件夹中
+        if config.is_flat() {
+            assert_eq!(path, "", "模板 {} 不应生成子目录", name);
+            continue;
+        }
+        
         // 路径不应该是 fallback（因为有日期）
         assert_ne!(path, "未知日期", "模板 {} 不应使用 fallback", name);
         
@@ -291,6 +304,7 @@ fn test_classify_config_serialization() {
     let config = ClassifyConfig {
         template: "{year}/{month}/{day}".to_string(),
         fallback_folder: "未分类照片".to_string(),
+        ..Default::default()
     };
     
     // 序列化
@@ -310,10 +324,21 @@ fn test_photo_info_serialization() {
         file_name: "photo.cr3".to_string(),
         file_size: 25_000_000,
         date_time: Some("2024:12:25 10:30:00".to_string()),
+        date_time_original: None,
+        create_date: None,
         camera: Some("Canon EOS R5".to_string()),
+        make: None,
         target_folder: "Canon/2024/12".to_string(),
         is_duplicate: false,
         duplicate_of: None,
+        width: None,
+        height: None,
+        relative_dir: String::new(),
+        suspect: false,
+        source_root: String::new(),
+        rating: None,
+        label: None,
+        keywords: Vec::new(),
     };
     
     // 序列化
@@ -337,12 +362,24 @@ fn test_scan_result_serialization() {
                 file_name: "photo1.jpg".to_string(),
                 file_size: 5_000_000,
                 date_time: None,
+                date_time_original: None,
+                create_date: None,
                 camera: None,
+                make: None,
                 target_folder: "未知日期".to_string(),
                 is_duplicate: false,
                 duplicate_of: None,
+                width: None,
+                height: None,
+                relative_dir: String::new(),
+                suspect: false,
+                source_root: String::new(),
+                rating: None,
+                label: None,
+                keywords: Vec::new(),
             }
         ],
+        skipped_unreadable: Vec::new(),
     };
     
     let json = serde_json::to_string(&result).unwrap();
@@ -359,17 +396,27 @@ fn test_multiple_scans() {
     let dir = TempDir::new().unwrap();
     
     create_test_file(&dir, "photo.jpg", b"content");
+    for i in 0..10 {
+        create_test_file(&dir, &format!("DCIM/{}/IMG_{:04}.JPG", 9 - i, i), b"content");
+    }
     
     let config = ClassifyConfig::default();
     let path = dir.path().to_string_lossy().to_string();
     
-    // 多次扫描应该得到相同结果
+    // 多次扫描应该得到相同结果，顺序也相同
     let result1 = scan_photos(&path, &config).unwrap();
     let result2 = scan_photos(&path, &config).unwrap();
     let result3 = scan_photos(&path, &config).unwrap();
     
     assert_eq!(result1.total_files, result2.total_files);
     assert_eq!(result2.total_files, result3.total_files);
+
+    let paths = |result: &ScanResult| result.photos.iter().map(|p| p.path.clone()).collect::<Vec<_>>();
+    assert_eq!(paths(&result1), paths(&result2));
+    assert_eq!(paths(&result2), paths(&result3));
+    let mut sorted = paths(&result1);
+    sorted.sort();
+    assert_eq!(paths(&result1), sorted);
 }
 
 #[test]